@@ -1,5 +1,5 @@
 use bevy::{prelude::*, render::camera::Camera};
-use bevy_tiled::TiledMapCenter;
+use bevy_tiled::MapAnchor;
 
 fn main() {
     App::build()
@@ -14,7 +14,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn(bevy_tiled::TiledMapComponents {
             map_asset: asset_server.load("assets/ortho-map.tmx").unwrap(),
-            center: TiledMapCenter(true),
+            anchor: MapAnchor::Center,
             ..Default::default()
         })
         .spawn(Camera2dComponents::default());
@@ -0,0 +1,76 @@
+use crate::{paths::trace, Map, PropertiesExt};
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// How a tile affects sound, from its tile's `occlusion` and `reverb` float properties in the
+/// tileset. Tiles with the class `wall` block sound unless they say otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TileAcoustics {
+    /// Fraction of sound blocked passing through the tile, from 0 to 1.
+    pub occlusion: f32,
+    /// How reverberant the space around the tile is, from 0 to 1.
+    pub reverb: f32,
+}
+
+/// Reads the acoustics of every tile that has any, keyed by gid.
+pub(crate) fn read_tile_acoustics(map: &tiled::Map) -> HashMap<u32, TileAcoustics> {
+    let mut acoustics = HashMap::new();
+    for tileset in map.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            let wall = tile.tile_type.as_deref() == Some("wall");
+            let tile_acoustics = TileAcoustics {
+                occlusion: tile
+                    .properties
+                    .float_property("occlusion")
+                    .unwrap_or(if wall { 1.0 } else { 0.0 })
+                    .max(0.0)
+                    .min(1.0),
+                reverb: tile
+                    .properties
+                    .float_property("reverb")
+                    .unwrap_or(0.0)
+                    .max(0.0)
+                    .min(1.0),
+            };
+            if tile_acoustics != TileAcoustics::default() {
+                acoustics.insert(tileset.first_gid + tile.id, tile_acoustics);
+            }
+        }
+    }
+    acoustics
+}
+
+impl Map {
+    /// The acoustics of the tile at `x`, `y`: the most occluding and most reverberant of the
+    /// tiles on every layer there.
+    pub fn acoustics_at(&self, x: u32, y: u32) -> TileAcoustics {
+        let mut acoustics = TileAcoustics::default();
+        for layer in 0..self.map.layers.len() {
+            let tile = match self.tile(layer, x, y) {
+                Some(tile) => self.tile_acoustics.get(&tile.gid),
+                None => None,
+            };
+            if let Some(tile) = tile {
+                acoustics.occlusion = acoustics.occlusion.max(tile.occlusion);
+                acoustics.reverb = acoustics.reverb.max(tile.reverb);
+            }
+        }
+        acoustics
+    }
+
+    /// Fraction of sound blocked between two points in tile coordinates, from 0 to 1.
+    ///
+    /// Each tile the straight line between them passes through blocks its `occlusion` of what's
+    /// left, so two half-occluding walls block three quarters. The tile `a` is in doesn't count,
+    /// so a sound isn't muffled by the wall it's standing against.
+    pub fn occlusion_between(&self, a: Vec2, b: Vec2) -> f32 {
+        let (width, height) = (self.map.width as i32, self.map.height as i32);
+        let passed = trace(a, b)
+            .into_iter()
+            .skip(1)
+            .filter(|(x, y)| *x >= 0 && *y >= 0 && *x < width && *y < height)
+            .map(|(x, y)| 1.0 - self.acoustics_at(x as u32, y as u32).occlusion)
+            .product::<f32>();
+        1.0 - passed
+    }
+}
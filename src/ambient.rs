@@ -0,0 +1,87 @@
+use crate::{
+    layer_pipelines::layer_of, ChunkPool, LayerChanges, Map, PropertiesExt, TileMapChunk,
+    TiledChunk, TiledImageLayer, TiledObject,
+};
+use bevy::prelude::*;
+
+/// Light every map is drawn in, multiplied into the color of each chunk, tile object and image
+/// layer, for day and night cycles without touching their materials. Layers with the
+/// `ignore_ambient` bool property set, such as lit windows or a UI overlay, are drawn as they
+/// are.
+#[derive(Debug, Clone)]
+pub struct MapAmbient {
+    pub color: Color,
+    pub intensity: f32,
+}
+
+impl Default for MapAmbient {
+    fn default() -> Self {
+        Self {
+            color: Color::WHITE,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl MapAmbient {
+    /// What the tile shader multiplies colors by.
+    pub fn tint(&self) -> Color {
+        self.color * self.intensity
+    }
+}
+
+#[derive(Default)]
+pub struct MapAmbientState {
+    layers: LayerChanges<()>,
+    /// The tint last handed out.
+    tint: Option<Color>,
+}
+
+/// Hands chunks, tile objects and image layers the `MapAmbient` tint, or white for layers
+/// that ignore it. Merged meshes draw every layer, so they always take it.
+pub fn apply_map_ambient(
+    ambient: Res<MapAmbient>,
+    maps: Res<Assets<Map>>,
+    pool: Res<ChunkPool>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut state: Local<MapAmbientState>,
+    mut query: Query<(
+        Entity,
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&TiledImageLayer>,
+        &mut TileMapChunk,
+    )>,
+) {
+    let state = &mut *state;
+    let tint = ambient.tint();
+    let tint_changed = state.tint != Some(tint);
+    state.tint = Some(tint);
+    state.layers.update(&map_events, Vec::new());
+    for (entity, chunk, object, image_layer, mut tile_map_chunk) in &mut query.iter() {
+        // Checked first so every entity is remembered as seen.
+        let stale = state
+            .layers
+            .is_stale(&pool, entity, chunk, object, image_layer);
+        if !stale && !tint_changed {
+            continue;
+        }
+        let map_handle = match (object, image_layer) {
+            (Some(object), _) => &object.map,
+            (None, Some(image_layer)) => &image_layer.map,
+            (None, None) => &chunk.map,
+        };
+        // Overlays without a map, such as the debug ones, are left as they are.
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let ignored = layer_of(map, chunk, object, image_layer)
+            .and_then(|(_, properties)| properties.bool_property("ignore_ambient"))
+            .unwrap_or(false);
+        let drawn = if ignored { Color::WHITE } else { tint };
+        if tile_map_chunk.ambient != drawn {
+            tile_map_chunk.ambient = drawn;
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{loader::TiledMapLoader, Map, TileMapChunk, TileRect, TiledChunk, TiledObject};
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{VertexAttribute, VertexAttributeValues},
+        renderer::{RenderResource, RenderResources},
+        shader::ShaderDefs,
+        texture::TextureFormat,
+    },
+};
+use std::collections::{HashMap, HashSet};
+
+/// Frames of an animated tile as (gid, duration in milliseconds), from the `<animation>` of its
+/// tile in the tileset.
+pub type TileAnimation = Vec<(u32, u32)>;
+
+/// Reads the animation of every animated tile, keyed by gid.
+pub(crate) fn read_tile_animations(map: &tiled::Map) -> HashMap<u32, TileAnimation> {
+    let mut animations = HashMap::new();
+    for tileset in map.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            let frames = match &tile.animation {
+                Some(frames) if !frames.is_empty() => frames,
+                _ => continue,
+            };
+            let frames = frames
+                .iter()
+                .map(|frame| (tileset.first_gid + frame.tile_id, frame.duration))
+                .collect();
+            animations.insert(tileset.first_gid + tile.id, frames);
+        }
+    }
+    animations
+}
+
+/// Rows of animated tiles in `Map::animation_texture`, keyed by gid, in gid order.
+pub(crate) fn animation_rows(animations: &HashMap<u32, TileAnimation>) -> HashMap<u32, u32> {
+    let mut gids = animations.keys().copied().collect::<Vec<_>>();
+    gids.sort();
+    gids.into_iter()
+        .enumerate()
+        .map(|(row, gid)| (gid, row as u32))
+        .collect()
+}
+
+/// The `Vertex_Animation` attribute of the tile pipeline, with the `Map::animation_vertex` of
+/// each vertex.
+pub(crate) fn animation_attribute(animations: Vec<f32>) -> VertexAttribute {
+    VertexAttribute {
+        name: "Vertex_Animation".into(),
+        values: VertexAttributeValues::Float(animations),
+    }
+}
+
+/// The gid an animation shows `millis` milliseconds after it started, looping forever.
+fn animation_frame(frames: &[(u32, u32)], millis: u64) -> u32 {
+    let length = frames
+        .iter()
+        .map(|(_, duration)| *duration as u64)
+        .sum::<u64>();
+    if length == 0 {
+        return frames[0].0;
+    }
+    let mut time = millis % length;
+    for (gid, duration) in frames.iter() {
+        if time < *duration as u64 {
+            return *gid;
+        }
+        time -= *duration as u64;
+    }
+    frames[0].0
+}
+
+impl Map {
+    /// What the vertices of a tile drawing `gid` (flip flags included) tell the tile shader
+    /// about its animation: its row in `animation_texture` plus one, or 0 if it isn't animated.
+    pub(crate) fn animation_vertex(&self, gid: u32) -> f32 {
+        let gid = TiledMapLoader::remove_tile_flags(gid);
+        self.animation_rows
+            .get(&gid)
+            .map_or(0.0, |row| (*row + 1) as f32)
+    }
+
+    /// UVs of the tile drawing `gid`.
+    fn gid_uv(&self, gid: u32) -> Vec4 {
+        match self.tileset_for_gid(gid) {
+            Some(tileset) => self.tileset_uv(tileset, gid - tileset.first_gid),
+            None => Vec4::zero(),
+        }
+    }
+
+    /// The frames of every animated tile as a texture the tile shader plays them from, or
+    /// `None` if the map has no animated tiles.
+    ///
+    /// Row `animation_rows[gid]` has a texel per frame of `gid`: the UV offset from the tile to
+    /// the frame, the millisecond the frame ends at and the length of the animation. Rows of
+    /// shorter animations repeat their last frame.
+    pub fn animation_texture(&self) -> Option<Texture> {
+        if self.animation_rows.is_empty() {
+            return None;
+        }
+        let width = self
+            .tile_animations
+            .values()
+            .map(|frames| frames.len())
+            .max()
+            .unwrap_or(1);
+        let height = self.animation_rows.len();
+        let mut texels = vec![0.0f32; width * height * 4];
+        for (gid, row) in self.animation_rows.iter() {
+            let frames = &self.tile_animations[gid];
+            let uv = self.gid_uv(*gid);
+            let length = frames.iter().map(|(_, duration)| *duration).sum::<u32>() as f32;
+            let mut end = 0;
+            for column in 0..width {
+                let (frame, duration) = frames[column.min(frames.len() - 1)];
+                if column < frames.len() {
+                    end += duration;
+                }
+                let offset = self.gid_uv(frame) - uv;
+                let texel = (*row as usize * width + column) * 4;
+                texels[texel..texel + 4].copy_from_slice(&[
+                    offset.x(),
+                    offset.y(),
+                    end as f32,
+                    length,
+                ]);
+            }
+        }
+        let data = texels
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        Some(Texture::new(
+            Vec2::new(width as f32, height as f32),
+            data,
+            TextureFormat::Rgba32Float,
+        ))
+    }
+
+    /// Frames animated tiles show `millis` milliseconds after startup, keyed by animated gid,
+    /// for the ones that differ from what's drawn now.
+    fn changed_animation_frames(&self, millis: u64) -> HashMap<u32, u32> {
+        self.tile_animations
+            .iter()
+            .map(|(gid, frames)| (*gid, animation_frame(frames, millis)))
+            .filter(|(gid, frame)| self.animation_frames.get(gid) != Some(frame))
+            .collect()
+    }
+
+    /// Whether any animated tile would change frame at `millis`.
+    pub fn animations_due(&self, millis: u64) -> bool {
+        !self.changed_animation_frames(millis).is_empty()
+    }
+
+    /// Moves animated tiles to the frames they show `millis` milliseconds after startup and
+    /// marks the chunks drawing them dirty. Returns whether any tile changed frame.
+    pub fn advance_animations(&mut self, millis: u64) -> bool {
+        let changed = self.changed_animation_frames(millis);
+        if changed.is_empty() {
+            return false;
+        }
+
+        let (width, height) = self.chunk_size;
+        let mut chunks = HashSet::new();
+        for layer in self.layers.iter() {
+            let tiles = match &self.map.layers[layer.layer_index].tiles {
+                tiled::LayerData::Finite(tiles) => tiles,
+                _ => continue,
+            };
+            for (y, row) in tiles.iter().enumerate() {
+                for (x, tile) in row.iter().enumerate() {
+                    let gid = self.unanimated_gid(layer.layer_index, tile.gid);
+                    if changed.contains_key(&gid) {
+                        chunks.insert((layer.layer_index, x / width, y / height));
+                    }
+                }
+            }
+        }
+
+        self.animation_frames.extend(changed);
+        for (layer, chunk_x, chunk_y) in chunks {
+            self.invalidate_region(
+                layer,
+                TileRect {
+                    x: (chunk_x * width) as u32,
+                    y: (chunk_y * height) as u32,
+                    width: width as u32,
+                    height: height as u32,
+                },
+            );
+        }
+        true
+    }
+}
+
+/// Plays the `<animation>`s of tiles on the CPU, rebuilding the chunks drawing them when they
+/// change frame.
+///
+/// Animated tiles are played by the tile shader, see `TileAnimations`, so this isn't added by
+/// `TiledMapPlugin`. It's kept for renderers without float textures; add it in place of
+/// `advance_tile_animations`.
+pub fn animate_tiles(time: Res<Time>, mut maps: ResMut<Assets<Map>>) {
+    let millis = (time.seconds_since_startup * 1000.0) as u64;
+    // Only touch maps with frames to change, since `get_mut` marks them modified.
+    let due = maps
+        .iter()
+        .filter(|(_, map)| map.animations_due(millis))
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for map_handle in due {
+        if let Some(map) = maps.get_mut(&map_handle) {
+            map.advance_animations(millis);
+        }
+    }
+}
+
+/// The frame texture of the map a chunk or tile object belongs to, which the tile shader plays
+/// animated tiles from. Kept up to date by `sync_tile_animations`.
+#[derive(Debug, Clone, Default, RenderResources, ShaderDefs)]
+pub struct TileAnimations {
+    #[shader_def]
+    pub frames: Option<Handle<Texture>>,
+}
+
+#[derive(Default)]
+pub struct TileAnimationState {
+    map_event_reader: EventReader<AssetEvent<Map>>,
+    /// The frame texture of each map with animated tiles, along with the generation of the map
+    /// it was built for and whether that was drawn from an atlas.
+    textures: HashMap<Handle<Map>, (Handle<Texture>, u32, bool)>,
+}
+
+/// Builds the `animation_texture` of maps as they load and hands it to their chunks and tile
+/// objects. It's built again when a map is reloaded or packed into an atlas, which moves the
+/// frames.
+pub fn sync_tile_animations(
+    mut state: Local<TileAnimationState>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    maps: Res<Assets<Map>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut query: Query<(&TiledChunk, Option<&TiledObject>, &mut TileAnimations)>,
+) {
+    let state = &mut *state;
+    for event in state.map_event_reader.iter(&map_events) {
+        let handle = match event {
+            AssetEvent::Created { handle }
+            | AssetEvent::Modified { handle }
+            | AssetEvent::Removed { handle } => handle,
+        };
+        let map = maps.get(handle);
+        let built_for = map.map(|map| (map.generation, map.atlas.is_some()));
+        if let Some((_, generation, atlas)) = state.textures.get(handle) {
+            if Some((*generation, *atlas)) == built_for {
+                continue;
+            }
+        }
+        if let Some((texture, _, _)) = state.textures.remove(handle) {
+            textures.remove(&texture);
+        }
+        if let Some(map) = map {
+            if let Some(texture) = map.animation_texture() {
+                let texture = textures.add(texture);
+                state
+                    .textures
+                    .insert(*handle, (texture, map.generation, map.atlas.is_some()));
+            }
+        }
+    }
+
+    for (chunk, object, mut animations) in &mut query.iter() {
+        let map = object.map_or(&chunk.map, |object| &object.map);
+        let frames = state.textures.get(map).map(|(texture, _, _)| *texture);
+        if animations.frames != frames {
+            animations.frames = frames;
+        }
+    }
+}
+
+/// Advances the `time` the tile shader plays animated tiles at, on chunks and tile objects with
+/// a frame texture.
+pub fn advance_tile_animations(
+    time: Res<Time>,
+    mut query: Query<(&TileAnimations, &mut TileMapChunk)>,
+) {
+    // Wrapped hourly so an f32 keeps millisecond precision, at the cost of a skipped frame.
+    let seconds = (time.seconds_since_startup % 3600.0) as f32;
+    for (animations, mut chunk) in &mut query.iter() {
+        if animations.frames.is_some() {
+            chunk.time = seconds;
+        }
+    }
+}
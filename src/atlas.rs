@@ -0,0 +1,407 @@
+use crate::{ImageCollections, Map, MapSettings, TiledChunk, Tileset, TransparentColorKeys};
+use bevy::{asset::LoadState, prelude::*, render::texture::TextureFormat};
+use std::{collections::HashMap, sync::Arc};
+
+/// Pixels left between packed images, so filtering doesn't bleed one tileset into another.
+const ATLAS_PADDING: u32 = 1;
+
+/// The tileset images of a map packed into one texture, see `MapSettings::atlas_tilesets`.
+#[derive(Debug, Clone)]
+pub struct TilesetAtlas {
+    pub texture: Handle<Texture>,
+    /// Draws every tile layer and tile object of the map.
+    pub material: Handle<ColorMaterial>,
+    /// Where each tileset's image, or its extruded tiles, sits in the atlas, keyed by first
+    /// gid, as (start u, start v, end u, end v).
+    pub regions: HashMap<u32, Vec4>,
+    /// Pixels each tile's border was extruded by, see `MapSettings::atlas_extrusion`.
+    pub extrusion: u32,
+}
+
+/// How the tiles of a tileset image are laid out, for extruding them one by one.
+#[derive(Debug, Clone, Copy)]
+struct TileGrid {
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    count: u32,
+}
+
+impl TileGrid {
+    fn new(tileset: &tiled::Tileset) -> Option<Self> {
+        let image = tileset.images.first()?;
+        let (tile_width, tile_height) = (tileset.tile_width, tileset.tile_height);
+        let columns = (image.width.max(0) as u32 + tileset.spacing)
+            .saturating_sub(2 * tileset.margin)
+            / (tile_width + tileset.spacing).max(1);
+        let count = tileset.tilecount.unwrap_or(0);
+        if tile_width == 0 || tile_height == 0 || columns == 0 || count == 0 {
+            return None;
+        }
+        Some(Self {
+            tile_width,
+            tile_height,
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            columns,
+            count,
+        })
+    }
+
+    /// Top left pixel of the `tile`th tile in the tileset image.
+    fn source(&self, tile: u32) -> (u32, u32) {
+        (
+            self.margin + tile % self.columns * (self.tile_width + self.spacing),
+            self.margin + tile / self.columns * (self.tile_height + self.spacing),
+        )
+    }
+
+    /// Top left pixel of the `tile`th tile's cell once laid out with `extrusion` pixels
+    /// around each tile, relative to the first cell.
+    fn cell(&self, tile: u32, extrusion: u32) -> (u32, u32) {
+        (
+            tile % self.columns * (self.tile_width + 2 * extrusion),
+            tile / self.columns * (self.tile_height + 2 * extrusion),
+        )
+    }
+
+    /// Size of the tiles laid out with `extrusion` pixels around each.
+    fn extruded_size(&self, extrusion: u32) -> (u32, u32) {
+        let rows = (self.count + self.columns - 1) / self.columns;
+        (
+            self.columns * (self.tile_width + 2 * extrusion),
+            rows * (self.tile_height + 2 * extrusion),
+        )
+    }
+}
+
+/// An atlas built by `build_atlas`, before its texture is added.
+struct PackedAtlas {
+    texture: Texture,
+    regions: HashMap<u32, Vec4>,
+    /// UVs of every tile of the tilesets that were extruded, keyed by first gid.
+    tile_uvs: HashMap<u32, Vec<Vec4>>,
+}
+
+/// Where each image goes in an atlas of the returned width and height, packing them in rows
+/// from the tallest down.
+fn pack(sizes: &[(u32, u32)]) -> (Vec<(u32, u32)>, u32, u32) {
+    let area = sizes
+        .iter()
+        .map(|(width, height)| (width + ATLAS_PADDING) * (height + ATLAS_PADDING))
+        .sum::<u32>();
+    let widest = sizes.iter().map(|(width, _)| *width).max().unwrap_or(1);
+    let atlas_width = ((area as f32).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
+
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|index| std::cmp::Reverse(sizes[*index].1));
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for index in order {
+        let (width, height) = sizes[index];
+        if x > 0 && x + width > atlas_width {
+            x = 0;
+            y += row_height + ATLAS_PADDING;
+            row_height = 0;
+        }
+        positions[index] = (x, y);
+        x += width + ATLAS_PADDING;
+        row_height = row_height.max(height);
+    }
+    (positions, atlas_width, (y + row_height).max(1))
+}
+
+/// Packs `images`, keyed by first gid, into one texture. Only 8 bit RGBA textures are packed.
+///
+/// With an `extrusion`, images with a tile grid are packed tile by tile instead, each
+/// surrounded by copies of its own border pixels `extrusion` deep.
+fn build_atlas(
+    images: &[(u32, &Texture, Option<TileGrid>)],
+    extrusion: u32,
+) -> Option<PackedAtlas> {
+    let format = images.first()?.1.format;
+    let grids = images
+        .iter()
+        .map(|(_, _, grid)| grid.filter(|_| extrusion > 0))
+        .collect::<Vec<_>>();
+    let sizes = images
+        .iter()
+        .zip(grids.iter())
+        .map(|((_, texture, _), grid)| match texture.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+                if texture.format == format =>
+            {
+                Some(match grid {
+                    Some(grid) => grid.extruded_size(extrusion),
+                    None => (texture.size.x() as u32, texture.size.y() as u32),
+                })
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let (positions, atlas_width, atlas_height) = pack(&sizes);
+    let atlas_size = Vec2::new(atlas_width as f32, atlas_height as f32);
+
+    let mut data = vec![0; (atlas_width * atlas_height * 4) as usize];
+    let mut regions = HashMap::new();
+    let mut tile_uvs = HashMap::new();
+    for ((((first_gid, texture, _), grid), (width, height)), (x, y)) in
+        images.iter().zip(grids).zip(sizes).zip(positions)
+    {
+        if let Some(grid) = grid {
+            let (texture_width, texture_height) =
+                (texture.size.x() as u32, texture.size.y() as u32);
+            let mut uvs = Vec::new();
+            for tile in 0..grid.count {
+                let (source_x, source_y) = grid.source(tile);
+                let (cell_x, cell_y) = grid.cell(tile, extrusion);
+                let (cell_x, cell_y) = (x + cell_x, y + cell_y);
+                for dy in 0..grid.tile_height + 2 * extrusion {
+                    // Pixels outside the tile repeat its nearest edge.
+                    let pixel_y = dy.saturating_sub(extrusion).min(grid.tile_height - 1);
+                    let pixel_y = (source_y + pixel_y).min(texture_height - 1);
+                    for dx in 0..grid.tile_width + 2 * extrusion {
+                        let pixel_x = dx.saturating_sub(extrusion).min(grid.tile_width - 1);
+                        let pixel_x = (source_x + pixel_x).min(texture_width - 1);
+                        let source = ((pixel_y * texture_width + pixel_x) * 4) as usize;
+                        let target = (((cell_y + dy) * atlas_width + cell_x + dx) * 4) as usize;
+                        data[target..target + 4].copy_from_slice(&texture.data[source..source + 4]);
+                    }
+                }
+                let start = Vec2::new((cell_x + extrusion) as f32, (cell_y + extrusion) as f32);
+                let end = start + Vec2::new(grid.tile_width as f32, grid.tile_height as f32);
+                let (start, end) = (start / atlas_size, end / atlas_size);
+                uvs.push(Vec4::new(start.x(), start.y(), end.x(), end.y()));
+            }
+            tile_uvs.insert(*first_gid, uvs);
+        } else {
+            let row_bytes = (width * 4) as usize;
+            for row in 0..height {
+                let source = (row * width * 4) as usize;
+                let target = (((y + row) * atlas_width + x) * 4) as usize;
+                data[target..target + row_bytes]
+                    .copy_from_slice(&texture.data[source..source + row_bytes]);
+            }
+        }
+        regions.insert(
+            *first_gid,
+            Vec4::new(
+                x as f32 / atlas_width as f32,
+                y as f32 / atlas_height as f32,
+                (x + width) as f32 / atlas_width as f32,
+                (y + height) as f32 / atlas_height as f32,
+            ),
+        );
+    }
+    Some(PackedAtlas {
+        texture: Texture::new(atlas_size, data, format),
+        regions,
+        tile_uvs,
+    })
+}
+
+/// UVs within a tileset image moved into its `region` of an atlas.
+fn atlas_uv(uv: Vec4, region: Vec4) -> Vec4 {
+    let size = Vec2::new(region.z() - region.x(), region.w() - region.y());
+    Vec4::new(
+        region.x() + uv.x() * size.x(),
+        region.y() + uv.y() * size.y(),
+        region.x() + uv.z() * size.x(),
+        region.y() + uv.w() * size.y(),
+    )
+}
+
+impl Map {
+    /// Draws the map from `atlas`: moves each tileset's UVs into its region, or takes those of
+    /// its extruded tiles from `tile_uvs`, and rebuilds the chunk meshes and tile objects with
+    /// them.
+    fn use_atlas(&mut self, atlas: TilesetAtlas, mut tile_uvs: HashMap<u32, Vec<Vec4>>) {
+        for (first_gid, region) in atlas.regions.iter() {
+            if let Some(uvs) = tile_uvs.remove(first_gid) {
+                self.tile_uvs.insert(*first_gid, Arc::new(uvs));
+            } else if let Some(uvs) = self.tile_uvs.get(first_gid) {
+                let uvs = uvs.iter().map(|uv| atlas_uv(*uv, *region)).collect();
+                // The old table is shared with other maps drawing the same image.
+                self.tile_uvs.insert(*first_gid, Arc::new(uvs));
+            }
+        }
+        let keys = self.chunk_meshes.keys().copied().collect::<Vec<_>>();
+        self.dirty_chunks.extend(keys);
+        self.objects_revision += 1;
+        self.atlas = Some(atlas);
+    }
+}
+
+#[derive(Default)]
+pub struct TilesetAtlasState {
+    map_event_reader: EventReader<AssetEvent<Map>>,
+    /// Maps waiting for their tileset textures to load, by generation.
+    pending: HashMap<Handle<Map>, u32>,
+    /// The atlas texture and material of each map, removed along with the map.
+    atlases: HashMap<Handle<Map>, (Handle<Texture>, Handle<ColorMaterial>)>,
+}
+
+/// Packs the tileset images of maps into one texture once they've all loaded, when
+/// `MapSettings::atlas_tilesets` is set, and draws every chunk and tile object of those maps
+/// with its material.
+///
+/// Maps with a tileset that failed to load, or isn't 8 bit RGBA, keep a material per tileset.
+/// `TilesetSubstitution` swaps the per-tileset materials, so it doesn't apply to atlased maps.
+pub fn pack_tileset_atlases(
+    settings: Res<MapSettings>,
+    mut state: Local<TilesetAtlasState>,
+    asset_server: Res<AssetServer>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    color_keys: Res<TransparentColorKeys>,
+    image_collections: Res<ImageCollections>,
+    tilesets: Res<Assets<Tileset>>,
+    mut maps: ResMut<Assets<Map>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map_query: Query<(&Handle<Map>, &mut HashMap<u32, Handle<ColorMaterial>>)>,
+    mut chunk_query: Query<(&TiledChunk, &mut Handle<ColorMaterial>)>,
+) {
+    let state = &mut *state;
+    for event in state.map_event_reader.iter(&map_events) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                if !settings.atlas_tilesets {
+                    continue;
+                }
+                if let Some(map) = maps.get(handle) {
+                    // A single tileset is only worth packing to extrude its tiles.
+                    let tilesets = map.map.tilesets.len();
+                    if map.atlas.is_none() && (tilesets > 1 || settings.atlas_extrusion > 0) {
+                        state.pending.insert(*handle, map.generation);
+                    }
+                }
+            }
+            AssetEvent::Removed { handle } => {
+                state.pending.remove(handle);
+                if let Some((texture, material)) = state.atlases.remove(handle) {
+                    textures.remove(&texture);
+                    materials.remove(&material);
+                }
+            }
+        }
+    }
+
+    let mut packed = Vec::new();
+    let mut abandoned = Vec::new();
+    for (map_handle, generation) in state.pending.iter() {
+        let map = match maps.get(map_handle) {
+            Some(map) if map.generation == *generation => map,
+            _ => {
+                abandoned.push(*map_handle);
+                continue;
+            }
+        };
+        // Filled in once `process_loaded_tile_maps` has requested the images.
+        if map.tileset_assets.len() < map.map.tilesets.len() {
+            continue;
+        }
+        let mut images = Vec::new();
+        let (mut loading, mut failed) = (false, false);
+        for (first_gid, tileset_handle) in map.tileset_assets.iter() {
+            let texture = match tilesets.get(tileset_handle).and_then(|t| t.texture) {
+                Some(texture) => texture,
+                None => {
+                    failed = true;
+                    break;
+                }
+            };
+            if let Some(LoadState::Failed(_)) = asset_server.get_load_state(texture) {
+                failed = true;
+                break;
+            }
+            let grid = map
+                .map
+                .tilesets
+                .iter()
+                .find(|tileset| tileset.first_gid == *first_gid)
+                .and_then(TileGrid::new);
+            match textures.get(&texture) {
+                // Wait for the transparent color to be keyed out, or the collection stacked,
+                // first.
+                Some(image)
+                    if !color_keys.is_pending(texture)
+                        && !image_collections.is_pending(texture) =>
+                {
+                    images.push((*first_gid, image, grid))
+                }
+                _ => loading = true,
+            }
+        }
+        if failed {
+            abandoned.push(*map_handle);
+            continue;
+        }
+        if loading {
+            continue;
+        }
+        images.sort_by_key(|(first_gid, _, _)| *first_gid);
+        match build_atlas(&images, settings.atlas_extrusion) {
+            Some(atlas) => packed.push((*map_handle, atlas)),
+            None => abandoned.push(*map_handle),
+        }
+    }
+
+    for map_handle in abandoned {
+        state.pending.remove(&map_handle);
+    }
+    for (map_handle, atlas) in packed {
+        state.pending.remove(&map_handle);
+        let texture = textures.add(atlas.texture);
+        let material = materials.add(texture.into());
+        if let Some((texture, material)) = state.atlases.insert(map_handle, (texture, material)) {
+            textures.remove(&texture);
+            materials.remove(&material);
+        }
+        maps.get_mut(&map_handle).unwrap().use_atlas(
+            TilesetAtlas {
+                texture,
+                material,
+                regions: atlas.regions,
+                extrusion: settings.atlas_extrusion,
+            },
+            atlas.tile_uvs,
+        );
+    }
+
+    // Chunks of map entities spawned before or after their map was packed are switched over
+    // as they show up. Tile objects follow `materials_map` when they're respawned.
+    let mut atlas_materials = HashMap::new();
+    for (map_handle, mut materials_map) in &mut map_query.iter() {
+        let atlas = match maps.get(map_handle).and_then(|map| map.atlas.as_ref()) {
+            Some(atlas) => atlas,
+            None => continue,
+        };
+        atlas_materials.insert(*map_handle, atlas.material);
+        if materials_map
+            .values()
+            .any(|material| *material != atlas.material)
+        {
+            for material in materials_map.values_mut() {
+                *material = atlas.material;
+            }
+        }
+    }
+    if atlas_materials.is_empty() {
+        return;
+    }
+    for (chunk, mut material) in &mut chunk_query.iter() {
+        // The background quad has a material of its own.
+        if chunk.key.tileset_guid == 0 {
+            continue;
+        }
+        if let Some(atlas_material) = atlas_materials.get(&chunk.map) {
+            if *material != *atlas_material {
+                *material = *atlas_material;
+            }
+        }
+    }
+}
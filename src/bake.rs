@@ -0,0 +1,520 @@
+use crate::{
+    error::validate, loader::ChunkVertices, map::Chunk, ChunkKey, Layer, LayerMask, Map, MapError,
+    MapLoadOptions, NavData, NavGrid, NavRegions, TileRect, TilesetLayer,
+};
+use glam::Vec2;
+use std::convert::TryInto;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Starts every baked map, followed by `VERSION`.
+const MAGIC: &[u8; 4] = b"TMXB";
+const VERSION: u32 = 6;
+
+/// Whether `bytes` are a baked map rather than a `.tmx` file.
+pub(crate) fn is_baked(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// What to precompute into a baked map besides its chunk meshes.
+#[derive(Debug, Clone, Default)]
+pub struct BakeOptions {
+    /// Bakes the `NavData` of these layers, which `Map::nav_grid` and `Map::nav_data` then
+    /// return without computing it.
+    pub nav: Option<LayerMask>,
+    /// Width and height of the baked chunks in tiles, or `None` for `CHUNK_SIZE`.
+    pub chunk_size: Option<(usize, usize)>,
+    /// Whether the baked chunks are y-sorted, see `Map::set_y_sort`.
+    pub y_sort: bool,
+}
+
+impl BakeOptions {
+    /// Builds `map` at the baked chunk size and sorting.
+    fn apply_chunk_layout(&self, map: &mut Map) {
+        let chunk_size = self.chunk_size.map_or(map.chunk_size, |(width, height)| {
+            (width.max(1), height.max(1))
+        });
+        map.rebuild_with(chunk_size, self.y_sort);
+    }
+}
+
+/// Bakes the contents of a `.tmx` file into a `.tmxb` map with its chunk meshes already built.
+///
+/// Loading a baked map still parses the TMX, which it carries for tiles, objects and
+/// properties, but skips projecting every tile and building the meshes, the bulk of loading a
+/// large map. Maps are baked with the options given here, so external tilesets are read
+/// relative to `options.path`.
+pub fn bake_map(
+    bytes: &[u8],
+    options: MapLoadOptions,
+    bake_options: BakeOptions,
+) -> Result<Vec<u8>, MapError> {
+    let mut map = Map::try_from_bytes(bytes, options)?;
+    bake_options.apply_chunk_layout(&mut map);
+    Ok(bake(&map, bytes, &bake_options))
+}
+
+/// Bakes `map`, parsed from the TMX `bytes`.
+fn bake(map: &Map, bytes: &[u8], bake_options: &BakeOptions) -> Vec<u8> {
+    bake_version(map, bytes, bake_options, VERSION)
+}
+
+/// Bakes `map` in the layout of `version`, leaving out what older versions lack as
+/// `Map::try_from_baked` expects.
+fn bake_version(map: &Map, bytes: &[u8], bake_options: &BakeOptions, version: u32) -> Vec<u8> {
+    let mut baked = Vec::new();
+    baked.extend_from_slice(MAGIC);
+    write_u32(&mut baked, version);
+    write_u32(&mut baked, bytes.len() as u32);
+    baked.extend_from_slice(bytes);
+    if version >= 3 {
+        write_u32(&mut baked, map.chunk_size.0 as u32);
+        write_u32(&mut baked, map.chunk_size.1 as u32);
+    }
+    if version >= 5 {
+        write_u32(&mut baked, map.y_sort as u32);
+    }
+
+    write_u32(&mut baked, map.layers.len() as u32);
+    for layer in map.layers.iter() {
+        write_u32(&mut baked, layer.layer_index as u32);
+    }
+
+    let mut chunks = Vec::new();
+    for (layer_id, layer) in map.layers.iter().enumerate() {
+        for tileset_layer in layer.tileset_layers.iter() {
+            for (x, chunks_y) in tileset_layer.chunks.iter().enumerate() {
+                for (y, chunk) in chunks_y.iter().enumerate() {
+                    let vertices = map.chunk_vertices(
+                        layer.layer_index,
+                        tileset_layer.tileset_guid,
+                        chunk,
+                        None,
+                    );
+                    if let Some(vertices) = vertices {
+                        chunks.push((layer_id, tileset_layer.tileset_guid, (x, y), vertices));
+                    }
+                }
+            }
+        }
+    }
+    write_u32(&mut baked, chunks.len() as u32);
+    for (layer_id, tileset_guid, (x, y), vertices) in chunks {
+        write_u32(&mut baked, layer_id as u32);
+        write_u32(&mut baked, tileset_guid);
+        write_u32(&mut baked, x as u32);
+        write_u32(&mut baked, y as u32);
+        write_u32(&mut baked, vertices.positions.len() as u32);
+        let vertex_data = vertices
+            .positions
+            .iter()
+            .zip(vertices.uvs.iter())
+            .zip(vertices.animations.iter());
+        for ((position, uv), animation) in vertex_data {
+            let animation = if version >= 4 { Some(animation) } else { None };
+            for value in position.iter().chain(uv.iter()).chain(animation) {
+                baked.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        write_u32(&mut baked, vertices.indices.len() as u32);
+        let short = version >= 6 && short_indices(vertices.positions.len());
+        for index in vertices.indices.iter() {
+            if short {
+                baked.extend_from_slice(&(*index as u16).to_le_bytes());
+            } else {
+                write_u32(&mut baked, *index);
+            }
+        }
+    }
+
+    match bake_options.nav {
+        _ if version < 2 => {}
+        Some(mask) => {
+            write_u32(&mut baked, 1);
+            write_nav(&mut baked, &map.nav_data(mask));
+        }
+        None => write_u32(&mut baked, 0),
+    }
+    baked
+}
+
+fn write_nav(baked: &mut Vec<u8>, nav: &NavData) {
+    baked.extend_from_slice(&nav.mask.0.to_le_bytes());
+    let grid = &nav.grid;
+    write_u32(baked, grid.width);
+    write_u32(baked, grid.height);
+    for y in 0..grid.height as i32 {
+        for x in 0..grid.width as i32 {
+            baked.push(grid.is_walkable(x, y) as u8);
+        }
+    }
+    write_u32(baked, nav.colliders.len() as u32);
+    for collider in nav.colliders.iter() {
+        for value in [collider.x, collider.y, collider.width, collider.height].iter() {
+            write_u32(baked, *value);
+        }
+    }
+    write_u32(baked, nav.regions.count);
+    for label in nav.regions.labels.iter() {
+        write_u32(baked, *label);
+    }
+}
+
+fn read_nav(reader: &mut Reader) -> Result<NavData, MapError> {
+    let mask = LayerMask(u64::from_le_bytes(reader.take(8)?.try_into().unwrap()));
+    let (width, height) = (reader.u32()?, reader.u32()?);
+    let cells = width
+        .checked_mul(height)
+        .ok_or_else(|| MapError::Baked("nav grid is too big".to_string()))?
+        as usize;
+    // Read before the grid is allocated, so a bad size fails rather than allocating it.
+    let walkable = reader.take(cells)?;
+    let mut grid = NavGrid::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            grid.set_walkable(x, y, walkable[(y * width + x) as usize] != 0);
+        }
+    }
+    let mut colliders = Vec::new();
+    for _ in 0..reader.u32()? {
+        colliders.push(TileRect {
+            x: reader.u32()?,
+            y: reader.u32()?,
+            width: reader.u32()?,
+            height: reader.u32()?,
+        });
+    }
+    let count = reader.u32()?;
+    let label_bytes = cells
+        .checked_mul(4)
+        .ok_or_else(|| MapError::Baked("nav grid is too big".to_string()))?;
+    let labels = reader
+        .take(label_bytes)?
+        .chunks(4)
+        .map(|label| u32::from_le_bytes(label.try_into().unwrap()))
+        .collect();
+    Ok(NavData {
+        mask,
+        grid,
+        colliders,
+        regions: NavRegions {
+            width,
+            height,
+            labels,
+            count,
+        },
+    })
+}
+
+/// A map preprocessed by `preprocess`.
+#[derive(Debug, Clone)]
+pub struct BakedMap {
+    /// The `.tmxb` file.
+    pub bytes: Vec<u8>,
+    /// Every image the map draws, relative to whatever the `.tmx` path it was preprocessed
+    /// from is relative to, such as for copying or packing alongside it.
+    pub images: Vec<PathBuf>,
+    /// The nav data baked in, if `BakeOptions::nav` was set.
+    pub nav: Option<NavData>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl BakedMap {
+    /// Writes the `.tmxb` file to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        std::fs::write(path, &self.bytes).map_err(|err| MapError::Baked(err.to_string()))
+    }
+}
+
+/// Parses and bakes the `.tmx` file at `tmx_path` with the same code the loader runs, for
+/// build scripts and asset tools:
+///
+/// ```ignore
+/// let baked = bevy_tiled::preprocess("assets/maps/world.tmx", BakeOptions::default())?;
+/// baked.write("assets/maps/world.tmxb")?;
+/// println!("cargo:rerun-if-changed=assets/maps/world.tmx");
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub fn preprocess(
+    tmx_path: impl AsRef<Path>,
+    bake_options: BakeOptions,
+) -> Result<BakedMap, MapError> {
+    let tmx_path = tmx_path.as_ref();
+    let bytes = std::fs::read(tmx_path).map_err(|err| MapError::Baked(err.to_string()))?;
+    let mut map = Map::try_from_bytes(
+        &bytes,
+        MapLoadOptions {
+            path: Some(tmx_path.to_path_buf()),
+            ..Default::default()
+        },
+    )?;
+    bake_options.apply_chunk_layout(&mut map);
+    Ok(BakedMap {
+        bytes: bake(&map, &bytes, &bake_options),
+        images: map.image_dependencies(),
+        nav: bake_options.nav.map(|mask| map.nav_data(mask)),
+    })
+}
+
+/// Bakes the `.tmx` file at `tmx_path` into `out_path`, see `preprocess`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn bake_file(
+    tmx_path: impl AsRef<Path>,
+    out_path: impl AsRef<Path>,
+    bake_options: BakeOptions,
+) -> Result<(), MapError> {
+    preprocess(tmx_path, bake_options)?.write(out_path)
+}
+
+impl Map {
+    /// Loads a map baked with `bake_map`.
+    ///
+    /// Its chunks start without tile data, which is built as they're edited.
+    pub fn try_from_baked(bytes: &[u8], options: MapLoadOptions) -> Result<Map, MapError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err(MapError::Baked("not a baked map".to_string()));
+        }
+        let version = reader.u32()?;
+        // Version 1 maps lack the nav data section, versions before 3 the chunk size, before 4
+        // the animation of each vertex and before 5 whether they're y-sorted. Before 6, indices
+        // are always 32 bit.
+        if version == 0 || version > VERSION {
+            return Err(MapError::Baked(format!(
+                "baked with version {}, expected at most {}",
+                version, VERSION
+            )));
+        }
+        let tmx_len = reader.u32()? as usize;
+        let mut map = Map::parse(reader.take(tmx_len)?, options)?;
+        validate(&map.map)?;
+        if version >= 3 {
+            let (width, height) = (reader.u32()? as usize, reader.u32()? as usize);
+            if width == 0 || height == 0 {
+                return Err(MapError::Baked("chunks have no size".to_string()));
+            }
+            map.chunk_size = (width, height);
+        }
+        if version >= 5 {
+            map.y_sort = reader.u32()? != 0;
+        }
+
+        let (chunk_count_x, chunk_count_y) = map.chunk_count();
+        let layer_count = reader.u32()?;
+        for _ in 0..layer_count {
+            let layer_index = reader.u32()? as usize;
+            if map.map.layers.get(layer_index).is_none() {
+                return Err(MapError::Baked("baked from a different map".to_string()));
+            }
+            let tileset_layers = map
+                .map
+                .tilesets
+                .iter()
+                .map(|tileset| TilesetLayer {
+                    tile_size: Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
+                    chunks: (0..chunk_count_x)
+                        .map(|x| {
+                            (0..chunk_count_y)
+                                .map(|y| Chunk::empty(x, y, map.chunk_size))
+                                .collect()
+                        })
+                        .collect(),
+                    tileset_guid: tileset.first_gid,
+                })
+                .collect();
+            map.layers.push(Layer {
+                layer_index,
+                tileset_layers,
+            });
+        }
+
+        let chunk_count = reader.u32()?;
+        for _ in 0..chunk_count {
+            let key = ChunkKey {
+                layer_id: reader.u32()?,
+                tileset_guid: reader.u32()?,
+                chunk: (reader.u32()? as usize, reader.u32()? as usize),
+                part: None,
+            };
+            let (x, y) = key.chunk;
+            let known_tileset = map
+                .map
+                .tilesets
+                .iter()
+                .any(|tileset| tileset.first_gid == key.tileset_guid);
+            if key.layer_id as usize >= map.layers.len()
+                || !known_tileset
+                || x >= chunk_count_x
+                || y >= chunk_count_y
+            {
+                return Err(MapError::Baked("baked from a different map".to_string()));
+            }
+            let mut vertices = ChunkVertices::default();
+            let vertex_count = reader.u32()?;
+            for _ in 0..vertex_count {
+                vertices
+                    .positions
+                    .push([reader.f32()?, reader.f32()?, reader.f32()?]);
+                vertices.uvs.push([reader.f32()?, reader.f32()?]);
+                if version >= 4 {
+                    vertices.animations.push(reader.f32()?);
+                }
+            }
+            let short = version >= 6 && short_indices(vertex_count as usize);
+            for _ in 0..reader.u32()? {
+                let index = if short {
+                    reader.u16()? as u32
+                } else {
+                    reader.u32()?
+                };
+                if index >= vertex_count {
+                    return Err(MapError::Baked("index out of range".to_string()));
+                }
+                vertices.indices.push(index);
+            }
+            map.meshes.push((key, vertices.into_mesh()));
+        }
+        if version >= 2 && reader.u32()? != 0 {
+            map.baked_nav = Some(read_nav(&mut reader)?);
+        }
+
+        map.build_object_index();
+        Ok(map)
+    }
+}
+
+/// Whether the indices of a chunk with `vertex_count` vertices are baked as 16 bit. They're
+/// widened back to 32 bit on load, so this only shrinks the file.
+fn short_indices(vertex_count: usize) -> bool {
+    vertex_count <= u16::MAX as usize + 1
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MapError> {
+        let truncated = || MapError::Baked("baked map is truncated".to_string());
+        let end = self.offset.checked_add(len).ok_or_else(truncated)?;
+        let bytes = self.bytes.get(self.offset..end).ok_or_else(truncated)?;
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, MapError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, MapError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, MapError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &[&[u8]] = &[
+        include_bytes!("../assets/ortho-map.tmx"),
+        include_bytes!("../assets/iso-map.tmx"),
+    ];
+
+    fn meshes(map: &Map) -> Vec<String> {
+        map.meshes
+            .iter()
+            .map(|(key, mesh)| format!("{:?} {:?}", key, mesh))
+            .collect()
+    }
+
+    fn round_trip(version: u32, bake_options: BakeOptions) {
+        for bytes in MAPS {
+            let mut built = Map::try_from_bytes(bytes, MapLoadOptions::default()).unwrap();
+            bake_options.apply_chunk_layout(&mut built);
+            let baked = bake_version(&built, bytes, &bake_options, version);
+            let loaded = Map::try_from_baked(&baked, MapLoadOptions::default()).unwrap();
+            assert_eq!(meshes(&loaded), meshes(&built), "version {}", version);
+            assert_eq!(loaded.chunk_size, built.chunk_size);
+            let nav = bake_options.nav.filter(|_| version >= 2);
+            assert_eq!(loaded.baked_nav, nav.map(|mask| built.nav_data(mask)));
+        }
+    }
+
+    #[test]
+    fn baked_maps_load_as_built() {
+        round_trip(
+            VERSION,
+            BakeOptions {
+                nav: Some(LayerMask::ALL),
+                chunk_size: Some((8, 16)),
+                y_sort: false,
+            },
+        );
+    }
+
+    #[test]
+    fn older_versions_load_as_built() {
+        for version in 1..VERSION {
+            round_trip(
+                version,
+                BakeOptions {
+                    nav: Some(LayerMask::ALL),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    fn baked_ortho() -> Vec<u8> {
+        bake_map(MAPS[0], MapLoadOptions::default(), BakeOptions::default()).unwrap()
+    }
+
+    /// Offset of the first baked chunk key.
+    fn first_chunk(baked: &[u8]) -> usize {
+        let tmx_len = u32::from_le_bytes(baked[8..12].try_into().unwrap()) as usize;
+        let layers_at = 12 + tmx_len + 12;
+        let layer_count = u32::from_le_bytes(baked[layers_at..layers_at + 4].try_into().unwrap());
+        layers_at + 4 + layer_count as usize * 4 + 4
+    }
+
+    #[test]
+    fn chunk_keys_from_other_maps_are_rejected() {
+        let baked = baked_ortho();
+        let chunk = first_chunk(&baked);
+        // Layer id, tileset guid and chunk coordinates.
+        for field in 0..4 {
+            let mut corrupted = baked.clone();
+            let at = chunk + field * 4;
+            corrupted[at..at + 4].copy_from_slice(&9999u32.to_le_bytes());
+            match Map::try_from_baked(&corrupted, MapLoadOptions::default()) {
+                Err(MapError::Baked(_)) => {}
+                other => panic!("field {} accepted: {:?}", field, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_nav_grids_are_rejected() {
+        let mut baked = baked_ortho();
+        // Replace the empty nav section with a grid whose cell count overflows.
+        baked.truncate(baked.len() - 4);
+        write_u32(&mut baked, 1);
+        baked.extend_from_slice(&LayerMask::ALL.0.to_le_bytes());
+        write_u32(&mut baked, u32::MAX);
+        write_u32(&mut baked, u32::MAX);
+        match Map::try_from_baked(&baked, MapLoadOptions::default()) {
+            Err(MapError::Baked(_)) => {}
+            other => panic!("accepted: {:?}", other.map(|_| ())),
+        }
+    }
+}
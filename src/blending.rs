@@ -0,0 +1,51 @@
+use crate::{
+    PropertiesExt, TILE_MAP_ADDITIVE_PIPELINE_HANDLE, TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+    TILE_MAP_PIPELINE_HANDLE,
+};
+use bevy::{
+    prelude::*,
+    render::{pipeline::PipelineDescriptor, shader::ShaderDefs},
+};
+
+/// How a layer's pixels are combined with what's drawn behind them, from the layer's `blend`
+/// property: `add` for light and glow overlays, `multiply` for shadows, anything else or
+/// nothing for regular alpha blending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Alpha,
+    Additive,
+    Multiply,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Alpha
+    }
+}
+
+impl BlendMode {
+    pub fn from_properties(properties: &tiled::Properties) -> Self {
+        match properties.string_property("blend").as_deref() {
+            Some("add") | Some("additive") => BlendMode::Additive,
+            Some("multiply") => BlendMode::Multiply,
+            _ => BlendMode::Alpha,
+        }
+    }
+
+    /// The built-in pipeline drawing layers blended this way.
+    pub fn pipeline(self) -> Handle<PipelineDescriptor> {
+        match self {
+            BlendMode::Alpha => TILE_MAP_PIPELINE_HANDLE,
+            BlendMode::Additive => TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+            BlendMode::Multiply => TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+        }
+    }
+}
+
+/// Tells the tile shader to fade multiplied layers towards white where they're see-through,
+/// since the multiply pipeline ignores alpha. Kept up to date by `apply_layer_pipelines`.
+#[derive(Debug, Clone, Default, ShaderDefs)]
+pub struct TileBlending {
+    #[shader_def]
+    pub multiply: bool,
+}
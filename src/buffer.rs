@@ -0,0 +1,134 @@
+use crate::{
+    loader::{FLIPPED_DIAGONALLY_FLAG, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG},
+    Map,
+};
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// A gameplay side copy of a map's tile layers, for decoupling simulation from rendering.
+///
+/// Add it to a map entity to opt in. Simulation code edits the buffer, for example on its own
+/// fixed timestep, and calls `publish` once a step is complete. Only published steps are copied
+/// into the `Map`, so the renderer always draws a whole step rather than a partial update.
+#[derive(Debug, Default)]
+pub struct TileBuffer {
+    width: u32,
+    height: u32,
+    layers: Vec<Vec<u32>>,
+    changed: HashSet<(usize, u32, u32)>,
+    published: Vec<(usize, u32, u32, u32)>,
+    /// `Map::generation` of the map the buffer was filled from.
+    generation: u32,
+}
+
+impl TileBuffer {
+    /// Whether the buffer has been filled from its map yet.
+    pub fn is_ready(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// The gid (with flip flags) at `x`, `y` of `layer`, an index into the map's layers.
+    pub fn tile(&self, layer: usize, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.layers
+            .get(layer)
+            .map(|tiles| tiles[(y * self.width + x) as usize])
+    }
+
+    /// Sets a tile in the buffer; the map only sees it after the next `publish`.
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) -> bool {
+        if x >= self.width || y >= self.height || layer >= self.layers.len() {
+            return false;
+        }
+        self.layers[layer][(y * self.width + x) as usize] = gid;
+        self.changed.insert((layer, x, y));
+        true
+    }
+
+    /// Snapshots every tile changed since the last publish for the map to pick up.
+    pub fn publish(&mut self) {
+        for (layer, x, y) in self.changed.drain() {
+            let gid = self.layers[layer][(y * self.width + x) as usize];
+            self.published.push((layer, x, y, gid));
+        }
+    }
+
+    /// Fills the buffer from `map`, dropping edits that haven't reached it.
+    fn load(&mut self, map: &Map) {
+        self.changed.clear();
+        self.published.clear();
+        self.generation = map.generation;
+        self.width = map.map.width;
+        self.height = map.map.height;
+        self.layers = map
+            .map
+            .layers
+            .iter()
+            .map(|layer| match &layer.tiles {
+                tiled::LayerData::Finite(rows) => rows
+                    .iter()
+                    .flat_map(|row| row.iter())
+                    .map(|tile| {
+                        let mut gid = tile.gid;
+                        if tile.flip_h {
+                            gid |= FLIPPED_HORIZONTALLY_FLAG;
+                        }
+                        if tile.flip_v {
+                            gid |= FLIPPED_VERTICALLY_FLAG;
+                        }
+                        if tile.flip_d {
+                            gid |= FLIPPED_DIAGONALLY_FLAG;
+                        }
+                        gid
+                    })
+                    .collect(),
+                _ => vec![0; (self.width * self.height) as usize],
+            })
+            .collect();
+    }
+}
+
+#[derive(Default)]
+pub struct TileBufferState {
+    map_event_reader: EventReader<AssetEvent<Map>>,
+}
+
+/// Fills new tile buffers from their maps and copies published steps into the maps. Buffers
+/// of maps reloaded from their file are filled again, at the new size, with unpublished edits
+/// dropped.
+pub fn sync_tile_buffers(
+    mut state: Local<TileBufferState>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut maps: ResMut<Assets<Map>>,
+    mut query: Query<(&Handle<Map>, &mut TileBuffer)>,
+) {
+    let mut modified = HashSet::new();
+    for event in state.map_event_reader.iter(&map_events) {
+        if let AssetEvent::Modified { handle } = event {
+            modified.insert(*handle);
+        }
+    }
+    for (map_handle, mut buffer) in &mut query.iter() {
+        // Published edits modify the map too, so only a new generation means it was reloaded.
+        let reloaded = modified.contains(map_handle)
+            && maps
+                .get(map_handle)
+                .map_or(false, |map| map.generation != buffer.generation);
+        if !buffer.is_ready() || reloaded {
+            if let Some(map) = maps.get(map_handle) {
+                buffer.load(map);
+            }
+            continue;
+        }
+        if buffer.published.is_empty() {
+            continue;
+        }
+        if let Some(map) = maps.get_mut(map_handle) {
+            for (layer, x, y, gid) in buffer.published.drain(..) {
+                map.set_tile(layer, x, y, gid);
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{instancing::instance_rects, TileMapChunk, TiledChunk};
+use bevy::{
+    prelude::*,
+    render::{
+        camera::OrthographicProjection,
+        mesh::{VertexAttribute, VertexAttributeValues},
+    },
+    sprite::Rect,
+};
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// Hides chunk entities outside the view of the 2d camera, so large maps only draw what's on
+/// screen.
+#[derive(Debug, Clone)]
+pub struct ChunkCulling {
+    pub enabled: bool,
+    /// World units the view is grown by on every side before culling, hiding the frame the
+    /// culling lags behind a moving camera.
+    pub margin: f32,
+}
+
+impl Default for ChunkCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            margin: 64.0,
+        }
+    }
+}
+
+/// World-space bounds of a chunk entity, kept up to date by `cull_chunks`. Empty until the end
+/// of the frame the chunk is spawned in.
+#[derive(Debug, Clone)]
+pub struct ChunkBounds {
+    pub min: Vec2,
+    pub max: Vec2,
+    /// Whether `cull_chunks` hid the chunk.
+    culled: bool,
+}
+
+impl Default for ChunkBounds {
+    fn default() -> Self {
+        Self {
+            min: Vec2::new(std::f32::MAX, std::f32::MAX),
+            max: Vec2::new(std::f32::MIN, std::f32::MIN),
+            culled: false,
+        }
+    }
+}
+
+impl ChunkBounds {
+    /// Whether the bounds haven't been worked out yet, or the chunk's mesh is empty.
+    pub fn is_empty(&self) -> bool {
+        self.min.x() > self.max.x() || self.min.y() > self.max.y()
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            min: self.min,
+            max: self.max,
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x() >= self.min.x()
+            && point.x() <= self.max.x()
+            && point.y() >= self.min.y()
+            && point.y() <= self.max.y()
+    }
+
+    /// Whether the chunk is hidden for being out of view.
+    pub fn is_culled(&self) -> bool {
+        self.culled
+    }
+
+    pub fn intersects(&self, rect: &Rect) -> bool {
+        self.min.x() <= rect.max.x()
+            && self.max.x() >= rect.min.x()
+            && self.min.y() <= rect.max.y()
+            && self.max.y() >= rect.min.y()
+    }
+}
+
+/// The chunk entities whose bounds intersect `rect`, in world space, such as for custom culling
+/// or the chunks a minimap covers. Chunks spawned this frame aren't found until the next.
+pub fn chunks_in_rect(
+    query: &mut Query<(Entity, &TiledChunk, &ChunkBounds)>,
+    rect: &Rect,
+) -> Vec<(Entity, TiledChunk)> {
+    query
+        .iter()
+        .iter()
+        .filter(|(_, _, bounds)| bounds.intersects(rect))
+        .map(|(entity, chunk, _)| (entity, chunk.clone()))
+        .collect()
+}
+
+/// The chunk entities whose bounds contain a world position.
+pub fn chunks_at(
+    query: &mut Query<(Entity, &TiledChunk, &ChunkBounds)>,
+    point: Vec2,
+) -> Vec<(Entity, TiledChunk)> {
+    query
+        .iter()
+        .iter()
+        .filter(|(_, _, bounds)| bounds.contains(point))
+        .map(|(entity, chunk, _)| (entity, chunk.clone()))
+        .collect()
+}
+
+/// Bounds of a mesh's vertex positions, or `None` if it has none.
+fn mesh_bounds(mesh: &Mesh) -> Option<(Vec2, Vec2)> {
+    if let Some(rects) = instance_rects(mesh) {
+        return rects
+            .iter()
+            .fold(None, |bounds, [left, bottom, right, top]| {
+                let (rect_min, rect_max) = (Vec2::new(*left, *bottom), Vec2::new(*right, *top));
+                Some(match bounds {
+                    Some((min, max)) => (rect_min.min(min), rect_max.max(max)),
+                    None => (rect_min, rect_max),
+                })
+            });
+    }
+    let positions = mesh
+        .attributes
+        .iter()
+        .find(|attribute| attribute.name == VertexAttribute::POSITION)?;
+    let positions = match &positions.values {
+        VertexAttributeValues::Float3(positions) => positions,
+        _ => return None,
+    };
+    let mut bounds: Option<(Vec2, Vec2)> = None;
+    for [x, y, _] in positions.iter() {
+        let position = Vec2::new(*x, *y);
+        bounds = Some(match bounds {
+            Some((min, max)) => (min.min(position), max.max(position)),
+            None => (position, position),
+        });
+    }
+    bounds
+}
+
+#[derive(Default)]
+pub struct ChunkCullingState {
+    mesh_event_reader: EventReader<AssetEvent<Mesh>>,
+    /// Local bounds of each chunk mesh, dropped when the mesh changes.
+    mesh_bounds: HashMap<Handle<Mesh>, (Vec2, Vec2)>,
+}
+
+/// Shows the chunks `cull_chunks` hid last frame again, before anything else decides whether
+/// they're visible.
+pub fn uncull_chunks(mut query: Query<(&mut ChunkBounds, &mut Draw)>) {
+    for (mut bounds, mut draw) in &mut query.iter() {
+        if bounds.culled {
+            bounds.culled = false;
+            draw.is_visible = true;
+        }
+    }
+}
+
+/// Updates the `ChunkBounds` of chunk entities and, with `ChunkCulling` enabled, hides those
+/// outside the view of the first 2d camera. Runs after everything else that shows or hides
+/// chunks, such as `apply_layer_variant`.
+pub fn cull_chunks(
+    settings: Res<ChunkCulling>,
+    mut state: Local<ChunkCullingState>,
+    mesh_events: Res<Events<AssetEvent<Mesh>>>,
+    meshes: Res<Assets<Mesh>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    mut chunk_query: Query<(
+        &Handle<Mesh>,
+        &GlobalTransform,
+        &TileMapChunk,
+        &mut ChunkBounds,
+        &mut Draw,
+    )>,
+) {
+    let state = &mut *state;
+    for event in state.mesh_event_reader.iter(&mesh_events) {
+        match event {
+            AssetEvent::Modified { handle } | AssetEvent::Removed { handle } => {
+                state.mesh_bounds.remove(handle);
+            }
+            AssetEvent::Created { .. } => {}
+        }
+    }
+
+    let mut cameras = camera_query.iter();
+    let view =
+        cameras
+            .iter()
+            .next()
+            .filter(|_| settings.enabled)
+            .map(|(_, transform, projection)| {
+                let corners = [
+                    Vec3::new(projection.left, projection.bottom, 0.0),
+                    Vec3::new(projection.right, projection.top, 0.0),
+                ];
+                let mut view = Rect {
+                    min: Vec2::new(std::f32::MAX, std::f32::MAX),
+                    max: Vec2::new(std::f32::MIN, std::f32::MIN),
+                };
+                for corner in corners.iter() {
+                    let world = transform.value().transform_point3(*corner).truncate();
+                    view.min = view.min.min(world);
+                    view.max = view.max.max(world);
+                }
+                let margin = Vec2::new(settings.margin, settings.margin);
+                Rect {
+                    min: view.min - margin,
+                    max: view.max + margin,
+                }
+            });
+
+    for (mesh, transform, chunk, mut bounds, mut draw) in &mut chunk_query.iter() {
+        let (min, max) = match state.mesh_bounds.get(mesh) {
+            Some(local) => *local,
+            None => match meshes.get(mesh).and_then(mesh_bounds) {
+                Some(local) => {
+                    state.mesh_bounds.insert(*mesh, local);
+                    local
+                }
+                None => continue,
+            },
+        };
+        // Chunk vertices are scaled up in tile_map.vert before the model transform.
+        let (min, max) = (min * chunk.scale, max * chunk.scale);
+        let corners = [
+            Vec3::new(min.x(), min.y(), 0.0),
+            Vec3::new(min.x(), max.y(), 0.0),
+            Vec3::new(max.x(), min.y(), 0.0),
+            Vec3::new(max.x(), max.y(), 0.0),
+        ];
+        let mut world_min = Vec2::new(std::f32::MAX, std::f32::MAX);
+        let mut world_max = Vec2::new(std::f32::MIN, std::f32::MIN);
+        for corner in corners.iter() {
+            let world = transform.value().transform_point3(*corner).truncate();
+            world_min = world_min.min(world);
+            world_max = world_max.max(world);
+        }
+        if bounds.min != world_min || bounds.max != world_max {
+            bounds.min = world_min;
+            bounds.max = world_max;
+        }
+
+        if let Some(view) = &view {
+            if draw.is_visible && !bounds.intersects(view) {
+                draw.is_visible = false;
+                bounds.culled = true;
+            }
+        }
+    }
+}
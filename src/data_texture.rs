@@ -0,0 +1,211 @@
+use crate::{
+    loader::{ChunkVertices, TiledMapLoader, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG},
+    map::Chunk,
+    LodChunk, Map, TiledChunk,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderResource, RenderResources},
+        shader::ShaderDefs,
+        texture::TextureFormat,
+    },
+};
+
+/// The data texture of a chunk of a map drawn from data textures, which the tile shader reads
+/// the chunk's tiles from, see `Map::set_data_texture`. Kept up to date by `sync_tile_data_textures`.
+#[derive(Debug, Clone, Default, RenderResources, ShaderDefs)]
+pub struct TileDataTexture {
+    #[shader_def]
+    pub cells: Option<Handle<Texture>>,
+}
+
+impl Map {
+    /// Whether the map can be drawn from data textures: it's orthogonal, not y-sorted and every
+    /// tileset has tiles the size of the map's, so each tile fills exactly its cell.
+    pub fn can_use_data_texture(&self) -> bool {
+        self.map.orientation == tiled::Orientation::Orthogonal
+            && !self.y_sort
+            && self.map.tilesets.iter().all(|tileset| {
+                (tileset.tile_width, tileset.tile_height)
+                    == (self.map.tile_width, self.map.tile_height)
+            })
+    }
+
+    /// Draws each chunk as one quad, with its tiles as texels of a cell texture the tile shader
+    /// looks up, rather than as a quad per tile. Chunk meshes then take a few hundred bytes
+    /// however many tiles they hold, and an edit only rewrites one small texture.
+    ///
+    /// Maps that can't be drawn this way, see `can_use_data_texture`, and merged ones keep
+    /// their meshes. Like `set_chunk_size`, this is meant for maps that aren't in `Assets<Map>`
+    /// yet; loaded maps get `MapSettings::data_texture`.
+    pub fn set_data_texture(&mut self, data_texture: bool) {
+        let data_texture = data_texture && self.can_use_data_texture() && !self.merged;
+        if data_texture == self.data_texture || self.is_prebuilt() {
+            return;
+        }
+        self.data_texture = data_texture;
+        self.instanced = self.instanced && !data_texture;
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.build_layers();
+    }
+
+    /// The map-local left, top, right and bottom edges of the cells of `chunk`, a chunk of
+    /// `map.layers[layer_index]` drawn with the tileset starting at `tileset_guid`, on an
+    /// orthogonal map.
+    pub(crate) fn chunk_rect(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+    ) -> (f32, f32, f32, f32) {
+        let tile_width = self.map.tile_width as f32;
+        let tile_height = self.map.tile_height as f32;
+        // Tiled's offsets are y down, the mesh is y up.
+        let offset = self
+            .tile_offsets
+            .get(&tileset_guid)
+            .copied()
+            .unwrap_or_else(Vec2::zero)
+            + self.layer_offset(layer_index);
+        let (width, height) = chunk.size;
+        let first = Vec2::new(
+            chunk.position.x() * width as f32,
+            chunk.position.y() * height as f32,
+        );
+        let center =
+            Map::project_ortho(first, tile_width, tile_height) + Vec2::new(offset.x(), -offset.y());
+        let (left, top) = (
+            center.x() - tile_width / 2.0,
+            center.y() + tile_height / 2.0,
+        );
+        (
+            left,
+            top,
+            left + width as f32 * tile_width,
+            top - height as f32 * tile_height,
+        )
+    }
+
+    /// The quad a data texture chunk is drawn with, covering its cells, whose uv is the position
+    /// in cells from its top left corner. `None` if the chunk draws nothing.
+    pub(crate) fn data_texture_quad(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+    ) -> Option<Mesh> {
+        if chunk.gids.iter().all(|gid| *gid == 0) {
+            return None;
+        }
+        let (width, height) = (chunk.size.0 as f32, chunk.size.1 as f32);
+        let rect = self.chunk_rect(layer_index, tileset_guid, chunk);
+        Some(chunk_quad(rect, (width, height)))
+    }
+
+    /// The cell texture of a data texture chunk, or `None` if it draws nothing.
+    ///
+    /// It's as wide as the chunk and three times as tall, with a texel per cell in each third:
+    /// the UVs of the tile, swapped when flipped, or zero where the cell is empty; its tint;
+    /// and its `animation_vertex`.
+    pub(crate) fn data_texture_cells(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+    ) -> Option<Texture> {
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.first_gid == tileset_guid)?;
+        if chunk.gids.iter().all(|gid| *gid == 0) {
+            return None;
+        }
+        let (width, height) = chunk.size;
+        let mut texels = vec![0.0f32; width * height * 3 * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let gid = chunk.gid(x, y);
+                if gid == 0 {
+                    continue;
+                }
+                let tile_x = chunk.position.x() as usize * width + x;
+                let tile_y = chunk.position.y() as usize * height + y;
+                let uv = self.tileset_uv(
+                    tileset,
+                    TiledMapLoader::remove_tile_flags(gid) - tileset.first_gid,
+                );
+                let (mut start_u, mut start_v, mut end_u, mut end_v) =
+                    (uv.x(), uv.y(), uv.z(), uv.w());
+                if gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_u, &mut end_u);
+                }
+                if gid & FLIPPED_VERTICALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_v, &mut end_v);
+                }
+                let tint = self.tint_vertex(layer_index, tile_x as u32, tile_y as u32);
+                let animation = self.animation_vertex(gid);
+                let cells = [
+                    [start_u, start_v, end_u, end_v],
+                    tint,
+                    [animation, 0.0, 0.0, 0.0],
+                ];
+                for (third, cell) in cells.iter().enumerate() {
+                    let texel = ((third * height + y) * width + x) * 4;
+                    texels[texel..texel + 4].copy_from_slice(cell);
+                }
+            }
+        }
+        let data = texels
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        Some(Texture::new(
+            Vec2::new(width as f32, (height * 3) as f32),
+            data,
+            TextureFormat::Rgba32Float,
+        ))
+    }
+}
+
+/// A quad over `rect`, as left, top, right and bottom, with uvs from 0 at its top left to
+/// `uv_size` at its bottom right.
+pub(crate) fn chunk_quad(
+    (left, top, right, bottom): (f32, f32, f32, f32),
+    uv_size: (f32, f32),
+) -> Mesh {
+    let (u, v) = uv_size;
+    let vertices = ChunkVertices {
+        positions: vec![
+            [left, bottom, 0.0],
+            [left, top, 0.0],
+            [right, top, 0.0],
+            [right, bottom, 0.0],
+        ],
+        uvs: vec![[0.0, v], [0.0, 0.0], [u, 0.0], [u, v]],
+        indices: vec![0, 2, 1, 0, 3, 2],
+        ..Default::default()
+    };
+    vertices.into_mesh()
+}
+
+/// Hands chunks of maps drawn from data textures their cell textures, which are replaced as tiles are edited.
+pub fn sync_tile_data_textures(
+    maps: Res<Assets<Map>>,
+    mut query: Query<(&TiledChunk, Option<&LodChunk>, &mut TileDataTexture)>,
+) {
+    for (chunk, lod, mut data_texture) in &mut query.iter() {
+        // Level of detail chunks are drawn from their own texture.
+        if lod.is_some() {
+            continue;
+        }
+        let cells = maps
+            .get(&chunk.map)
+            .and_then(|map| map.chunk_cells.get(&chunk.key).copied());
+        if data_texture.cells != cells {
+            data_texture.cells = cells;
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{ChunkComponents, ChunkKey, LayerMask, Map, MapAnchor, TileMapChunk, TiledChunk};
+use bevy::prelude::*;
+use std::collections::HashSet;
+
+/// Colors the regions overlay cycles through.
+const REGION_COLORS: [(f32, f32, f32); 6] = [
+    (0.9, 0.3, 0.3),
+    (0.3, 0.8, 0.3),
+    (0.3, 0.5, 0.9),
+    (0.9, 0.8, 0.2),
+    (0.8, 0.3, 0.9),
+    (0.2, 0.8, 0.8),
+];
+
+/// Number of colors the cost heatmap is split into.
+const COST_BANDS: usize = 8;
+
+/// Draws the `MapDebugOverlays` over a map, for tuning AI on tilemaps.
+#[derive(Default)]
+pub struct MapDebugPlugin;
+
+impl Plugin for MapDebugPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MapDebugOverlays>()
+            .add_system(draw_map_debug_overlays.system());
+    }
+}
+
+/// Which overlays are drawn over a map entity. They're redrawn whenever this or the map
+/// changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapDebugOverlays {
+    /// The map entity the overlays are drawn over, or `None` for no overlays.
+    pub map: Option<Entity>,
+    /// The layers `Map::nav_data` is computed from.
+    pub mask: LayerMask,
+    /// Marks the tiles that can't be walked.
+    pub nav_grid: bool,
+    /// Colors each connected region of walkable tiles, cycling through a handful of colors.
+    pub regions: bool,
+    /// Cost of each tile, row by row, drawn as a heatmap from blue for the cheapest to red for
+    /// the most expensive.
+    pub costs: Option<Vec<f32>>,
+    /// Paths to draw, such as the last ones `NavGrid::find_path` returned.
+    pub paths: Vec<Vec<(u32, u32)>>,
+}
+
+impl Default for MapDebugOverlays {
+    fn default() -> Self {
+        Self {
+            map: None,
+            mask: LayerMask::ALL,
+            nav_grid: true,
+            regions: false,
+            costs: None,
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl MapDebugOverlays {
+    /// The tiles to draw in each color, bottom overlay first.
+    fn tile_sets(&self, map: &Map) -> Vec<(Color, HashSet<(u32, u32)>)> {
+        let mut sets = Vec::new();
+        let (width, height) = (map.map.width, map.map.height);
+        let tiles = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)));
+
+        if let Some(costs) = &self.costs {
+            let min = costs.iter().copied().fold(std::f32::MAX, f32::min);
+            let max = costs.iter().copied().fold(std::f32::MIN, f32::max);
+            let mut bands = vec![HashSet::new(); COST_BANDS];
+            for (x, y) in tiles() {
+                if let Some(cost) = costs.get((y * width + x) as usize) {
+                    let t = if max > min {
+                        (cost - min) / (max - min)
+                    } else {
+                        0.0
+                    };
+                    let band = ((t * COST_BANDS as f32) as usize).min(COST_BANDS - 1);
+                    bands[band].insert((x, y));
+                }
+            }
+            for (band, tiles) in bands.into_iter().enumerate() {
+                let t = band as f32 / (COST_BANDS - 1) as f32;
+                sets.push((Color::rgba(t, 0.2, 1.0 - t, 0.4), tiles));
+            }
+        }
+
+        if self.nav_grid || self.regions {
+            let nav = map.nav_data(self.mask);
+            if self.regions {
+                let mut regions = vec![HashSet::new(); REGION_COLORS.len()];
+                for (x, y) in tiles() {
+                    if let Some(region) = nav.regions.region_at(x as i32, y as i32) {
+                        regions[region as usize % REGION_COLORS.len()].insert((x, y));
+                    }
+                }
+                for ((r, g, b), tiles) in REGION_COLORS.iter().zip(regions) {
+                    sets.push((Color::rgba(*r, *g, *b, 0.35), tiles));
+                }
+            }
+            if self.nav_grid {
+                let blocked = tiles()
+                    .filter(|(x, y)| !nav.grid.is_walkable(*x as i32, *y as i32))
+                    .collect();
+                sets.push((Color::rgba(0.1, 0.1, 0.1, 0.5), blocked));
+            }
+        }
+
+        let path_tiles = self.paths.iter().flatten().copied().collect();
+        sets.push((Color::rgba(1.0, 0.9, 0.2, 0.6), path_tiles));
+        sets.retain(|(_, tiles)| !tiles.is_empty());
+        sets
+    }
+}
+
+#[derive(Default)]
+pub struct MapDebugState {
+    drawn: Option<(MapDebugOverlays, u32, Vec3)>,
+    entities: Vec<(Entity, Handle<Mesh>, Handle<ColorMaterial>)>,
+}
+
+/// Redraws the `MapDebugOverlays` when they or their map change.
+pub fn draw_map_debug_overlays(
+    mut commands: Commands,
+    mut state: Local<MapDebugState>,
+    overlays: Res<MapDebugOverlays>,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    let target = overlays.map.and_then(|entity| {
+        let (map_handle, anchor, origin) = match (
+            map_query.get::<Handle<Map>>(entity),
+            map_query.get::<MapAnchor>(entity),
+            map_query.get::<Transform>(entity),
+        ) {
+            (Ok(map_handle), Ok(anchor), Ok(origin)) => (map_handle, anchor, origin),
+            _ => return None,
+        };
+        let map = maps.get(&map_handle)?;
+        Some((map, map.translation(&anchor, origin.translation())))
+    });
+    let drawn = target.map(|(map, translation)| (overlays.clone(), map.generation, translation));
+    if state.drawn == drawn {
+        return;
+    }
+
+    for (entity, mesh, material) in state.entities.drain(..) {
+        commands.despawn(entity);
+        meshes.remove(&mesh);
+        materials.remove(&material);
+    }
+    state.drawn = drawn;
+    let (map, translation) = match target {
+        Some(target) => target,
+        None => return,
+    };
+
+    // Above the tile layers and object groups.
+    let base = map.stack_depth((map.layers.len() + map.map.object_groups.len()) as f32);
+    for (index, (color, tiles)) in overlays.tile_sets(map).into_iter().enumerate() {
+        let mesh = meshes.add(map.tiles_mesh(&tiles));
+        let material = materials.add(color.into());
+        commands.spawn(ChunkComponents {
+            chunk: TileMapChunk {
+                layer_id: base + index as f32 * 0.01 * map.layer_depths.spacing,
+                scale: map.scale,
+                ..Default::default()
+            },
+            tiled_chunk: TiledChunk {
+                map: Handle::default(),
+                key: ChunkKey::default(),
+                map_entity: None,
+            },
+            material,
+            mesh,
+            transform: Transform::from_translation(translation),
+            ..Default::default()
+        });
+        if let Some(entity) = commands.current_entity() {
+            state.entities.push((entity, mesh, material));
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use crate::{
+    loader::{FLIPPED_DIAGONALLY_FLAG, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG},
+    screen_to_world, Map, MapAnchor, MapError, TiledChunk,
+};
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+    window::CursorMoved,
+};
+use glam::Vec2;
+use std::path::PathBuf;
+
+/// A basic in-game level editor: paints, erases and places objects on one map entity with
+/// the mouse, with undo, a layer panel and saving back to TMX.
+///
+/// Games draw their own UI over `EditorState`, which holds everything the editor needs.
+/// Ctrl+Z undoes, Ctrl+Y redoes and Ctrl+S saves to `EditorState::save_path`.
+#[derive(Default)]
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<EditorState>()
+            .add_system(apply_editor_tools.system())
+            .add_system(apply_layer_panel.system());
+    }
+}
+
+/// What the mouse does over the map.
+#[derive(Debug, Clone)]
+pub enum EditorTool {
+    /// Paints `gid` (flip flags included) onto the active layer.
+    Brush { gid: u32 },
+    /// Clears tiles of the active layer.
+    Eraser,
+    /// Places a copy of `template` in the object group `group` on click, such as a spawn point
+    /// or a tile object.
+    PlaceObject {
+        group: usize,
+        template: tiled::Object,
+    },
+}
+
+/// Editor state of one tile layer, for a layer panel.
+#[derive(Debug, Clone)]
+pub struct EditorLayer {
+    pub name: String,
+    /// Hides the layer's chunks while editing, without changing the map.
+    pub visible: bool,
+    /// Locked layers can't be painted or erased.
+    pub locked: bool,
+}
+
+/// One undoable change.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// A tile went from `before` to `after`, both gids with flip flags.
+    Tile {
+        layer: usize,
+        x: u32,
+        y: u32,
+        before: u32,
+        after: u32,
+    },
+    /// An object was added to the object group `group`.
+    AddObject { group: usize, object: tiled::Object },
+}
+
+/// The state of the `EditorPlugin`.
+#[derive(Debug)]
+pub struct EditorState {
+    /// The map entity being edited, or `None` to turn the editor off.
+    pub map: Option<Entity>,
+    pub tool: Option<EditorTool>,
+    /// Index into `tiled::Map::layers` that the brush and eraser work on.
+    pub active_layer: usize,
+    /// One per tile layer, filled in when the map is first edited.
+    pub layers: Vec<EditorLayer>,
+    /// The mouse button tools are used with.
+    pub button: MouseButton,
+    /// Where Ctrl+S writes the map.
+    pub save_path: Option<PathBuf>,
+    /// Why the last save failed, if it did.
+    pub save_error: Option<MapError>,
+    /// Strokes that can be undone, most recent last. A stroke is everything done between
+    /// pressing and releasing the button.
+    history: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    stroke: Vec<Edit>,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self {
+            map: None,
+            tool: None,
+            active_layer: 0,
+            layers: Vec::new(),
+            button: MouseButton::Left,
+            save_path: None,
+            save_error: None,
+            history: Vec::new(),
+            redo: Vec::new(),
+            stroke: Vec::new(),
+        }
+    }
+}
+
+/// A layer tile's gid with its flip flags.
+fn tile_gid(map: &Map, layer: usize, x: u32, y: u32) -> Option<u32> {
+    let tile = map.tile(layer, x, y)?;
+    let mut gid = tile.gid;
+    if tile.flip_h {
+        gid |= FLIPPED_HORIZONTALLY_FLAG;
+    }
+    if tile.flip_v {
+        gid |= FLIPPED_VERTICALLY_FLAG;
+    }
+    if tile.flip_d {
+        gid |= FLIPPED_DIAGONALLY_FLAG;
+    }
+    Some(gid)
+}
+
+impl EditorState {
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Reverts the last stroke.
+    pub fn undo(&mut self, map: &mut Map) {
+        if let Some(mut stroke) = self.history.pop() {
+            for edit in stroke.iter_mut().rev() {
+                match edit {
+                    Edit::Tile {
+                        layer,
+                        x,
+                        y,
+                        before,
+                        ..
+                    } => {
+                        map.set_tile(*layer, *x, *y, *before);
+                    }
+                    Edit::AddObject { object, .. } => {
+                        map.remove_object(object.id);
+                    }
+                }
+            }
+            self.redo.push(stroke);
+        }
+    }
+
+    /// Applies the last undone stroke again.
+    pub fn redo(&mut self, map: &mut Map) {
+        if let Some(mut stroke) = self.redo.pop() {
+            for edit in stroke.iter_mut() {
+                match edit {
+                    Edit::Tile {
+                        layer, x, y, after, ..
+                    } => {
+                        map.set_tile(*layer, *x, *y, *after);
+                    }
+                    Edit::AddObject { group, object } => {
+                        if let Some(id) = map.add_object(*group, object.clone()) {
+                            object.id = id;
+                        }
+                    }
+                }
+            }
+            self.history.push(stroke);
+        }
+    }
+
+    /// Sets a tile of `map` as part of the current stroke.
+    pub fn set_tile(&mut self, map: &mut Map, layer: usize, x: u32, y: u32, gid: u32) -> bool {
+        if self.layers.get(layer).map_or(false, |layer| layer.locked) {
+            return false;
+        }
+        let before = match tile_gid(map, layer, x, y) {
+            Some(before) if before != gid => before,
+            _ => return false,
+        };
+        if !map.set_tile(layer, x, y, gid) {
+            return false;
+        }
+        self.stroke.push(Edit::Tile {
+            layer,
+            x,
+            y,
+            before,
+            after: gid,
+        });
+        true
+    }
+
+    /// Adds an object to `map` as part of the current stroke, returning its id.
+    pub fn add_object(
+        &mut self,
+        map: &mut Map,
+        group: usize,
+        object: tiled::Object,
+    ) -> Option<u32> {
+        let id = map.add_object(group, object.clone())?;
+        self.stroke.push(Edit::AddObject {
+            group,
+            object: tiled::Object { id, ..object },
+        });
+        Some(id)
+    }
+
+    /// Ends the current stroke, making it undoable.
+    pub fn end_stroke(&mut self) {
+        if !self.stroke.is_empty() {
+            self.history.push(std::mem::take(&mut self.stroke));
+            self.redo.clear();
+        }
+    }
+
+    fn sync_layers(&mut self, map: &Map) {
+        if self.layers.len() != map.map.layers.len() {
+            self.layers = map
+                .map
+                .layers
+                .iter()
+                .map(|layer| EditorLayer {
+                    name: layer.name.clone(),
+                    visible: true,
+                    locked: false,
+                })
+                .collect();
+        }
+    }
+}
+
+impl Map {
+    /// The cell of `layer` at a map-local position, offsets included, whether or not it
+    /// holds a tile.
+    fn cell_at(&self, local: Vec2, layer: usize) -> Option<(u32, u32)> {
+        let offset = self.layer_offset(layer);
+        let tile = self.local_to_tile(local - Vec2::new(offset.x(), -offset.y()));
+        if tile.x() < 0.0 || tile.y() < 0.0 {
+            return None;
+        }
+        let (x, y) = (tile.x() as u32, tile.y() as u32);
+        if x >= self.map.width || y >= self.map.height {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    /// The position in Tiled's object pixel space of a map-local position, the inverse of
+    /// `object_to_world`.
+    fn world_to_object(&self, local: Vec2) -> Vec2 {
+        let tile = self.local_to_tile(local);
+        let tile_height = self.map.tile_height as f32;
+        match self.map.orientation {
+            tiled::Orientation::Isometric => tile * tile_height,
+            _ => Vec2::new(
+                tile.x() * self.map.tile_width as f32,
+                tile.y() * tile_height,
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EditorCursor {
+    cursor_reader: EventReader<CursorMoved>,
+    cursor: Option<Vec2>,
+}
+
+/// Uses the `EditorState` tool under the cursor and handles the editor shortcuts.
+pub fn apply_editor_tools(
+    mut state: ResMut<EditorState>,
+    mut cursor: Local<EditorCursor>,
+    cursor_events: Res<Events<CursorMoved>>,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut maps: ResMut<Assets<Map>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    if let Some(event) = cursor.cursor_reader.latest(&cursor_events) {
+        cursor.cursor = Some(event.position);
+    }
+    let map_entity = match state.map {
+        Some(map_entity) => map_entity,
+        None => return,
+    };
+    let (map_handle, anchor, origin) = match (
+        map_query.get::<Handle<Map>>(map_entity),
+        map_query.get::<MapAnchor>(map_entity),
+        map_query.get::<Transform>(map_entity),
+    ) {
+        (Ok(map_handle), Ok(anchor), Ok(origin)) => (*map_handle, anchor, origin),
+        _ => return,
+    };
+    let (translation, scale) = match maps.get(&map_handle) {
+        Some(map) => {
+            state.sync_layers(map);
+            (map.translation(&anchor, origin.translation()), map.scale)
+        }
+        None => return,
+    };
+
+    let control = keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+    if control && keys.just_pressed(KeyCode::Z) && state.can_undo() {
+        state.undo(maps.get_mut(&map_handle).unwrap());
+    }
+    if control && keys.just_pressed(KeyCode::Y) && state.can_redo() {
+        state.redo(maps.get_mut(&map_handle).unwrap());
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if control && keys.just_pressed(KeyCode::S) {
+            if let Some(path) = state.save_path.clone() {
+                state.save_error = maps.get(&map_handle).unwrap().save_tmx(path).err();
+            }
+        }
+    }
+
+    if mouse.just_released(state.button) || !mouse.pressed(state.button) {
+        state.end_stroke();
+        return;
+    }
+    let tool = match &state.tool {
+        Some(tool) => tool.clone(),
+        None => return,
+    };
+    let (window, cursor) = match (windows.get_primary(), cursor.cursor) {
+        (Some(window), Some(cursor)) => (window, cursor),
+        _ => return,
+    };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let mut cameras = camera_query.iter();
+    let world = match cameras.iter().next() {
+        Some((camera, camera_transform, _)) => {
+            screen_to_world(cursor, window_size, camera, camera_transform)
+        }
+        None => return,
+    };
+    // Undo the map translation and the scale applied in tile_map.vert.
+    let local = (world - translation.truncate()) / scale;
+
+    match tool {
+        EditorTool::Brush { .. } | EditorTool::Eraser => {
+            let gid = match tool {
+                EditorTool::Brush { gid } => gid,
+                _ => 0,
+            };
+            let layer = state.active_layer;
+            let cell = maps.get(&map_handle).unwrap().cell_at(local, layer);
+            let unchanged = cell.map_or(true, |(x, y)| {
+                tile_gid(maps.get(&map_handle).unwrap(), layer, x, y) == Some(gid)
+            });
+            // `get_mut` marks the map modified, so only when the tile changes.
+            if let (Some((x, y)), false) = (cell, unchanged) {
+                state.set_tile(maps.get_mut(&map_handle).unwrap(), layer, x, y, gid);
+            }
+        }
+        EditorTool::PlaceObject { group, template } => {
+            if mouse.just_pressed(state.button) {
+                let map = maps.get_mut(&map_handle).unwrap();
+                let pos = map.world_to_object(local);
+                let object = tiled::Object {
+                    x: pos.x(),
+                    y: pos.y(),
+                    ..template
+                };
+                state.add_object(map, group, object);
+            }
+        }
+    }
+}
+
+/// Shows and hides the chunks of the edited map's layers to match `EditorState::layers`.
+pub fn apply_layer_panel(
+    state: Res<EditorState>,
+    maps: Res<Assets<Map>>,
+    map_query: Query<&Handle<Map>>,
+    mut chunk_query: Query<(&TiledChunk, &mut Draw)>,
+) {
+    let map_handle = match state
+        .map
+        .and_then(|entity| map_query.get::<Handle<Map>>(entity).ok())
+    {
+        Some(map_handle) => *map_handle,
+        None => return,
+    };
+    let map = match maps.get(&map_handle) {
+        Some(map) => map,
+        None => return,
+    };
+    for (chunk, mut draw) in &mut chunk_query.iter() {
+        if chunk.map != map_handle || chunk.key.tileset_guid == 0 {
+            continue;
+        }
+        let visible = map
+            .layers
+            .get(chunk.key.layer_id as usize)
+            .and_then(|layer| state.layers.get(layer.layer_index))
+            .map_or(true, |layer| layer.visible);
+        if draw.is_visible != visible {
+            draw.is_visible = visible;
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{LodChunk, Map, PropertiesExt, TiledChunk, TiledObject, Tileset};
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderResource, RenderResources},
+        shader::ShaderDefs,
+        texture::TextureFormat,
+    },
+};
+use std::collections::HashMap;
+
+/// How strongly a tile or tileset glows from its `emissive` property: `true` for fully, or a
+/// float strength from 0 to 1.
+fn emission(properties: &tiled::Properties) -> Option<f32> {
+    properties
+        .float_property("emissive")
+        .or_else(|| {
+            properties
+                .bool_property("emissive")
+                .map(|emissive| if emissive { 1.0 } else { 0.0 })
+        })
+        .map(|strength| strength.max(0.0).min(1.0))
+}
+
+/// Reads how strongly tiles glow, keyed by gid, from the `emissive` property of the tile or
+/// else of its tileset. Tiles that don't glow are left out.
+pub(crate) fn read_emissive_tiles(map: &tiled::Map) -> HashMap<u32, f32> {
+    let mut emissive = HashMap::new();
+    for tileset in map.tilesets.iter() {
+        let whole = emission(&tileset.properties).unwrap_or(0.0);
+        let ids = tileset.tiles.iter().map(|tile| tile.id + 1).max();
+        for id in 0..tileset.tilecount.unwrap_or(0).max(ids.unwrap_or(0)) {
+            let tile = tileset.tiles.iter().find(|tile| tile.id == id);
+            let strength = tile
+                .and_then(|tile| emission(&tile.properties))
+                .unwrap_or(whole);
+            if strength > 0.0 {
+                emissive.insert(tileset.first_gid + id, strength);
+            }
+        }
+    }
+    emissive
+}
+
+/// A tile that glows, see `Map::emissive_tiles`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmissiveTile {
+    /// Index of the tile's layer in `map.layers`.
+    pub layer: usize,
+    pub x: u32,
+    pub y: u32,
+    /// Center of the tile in map-local space, layer offset included.
+    pub position: Vec2,
+    pub strength: f32,
+}
+
+impl Map {
+    /// How strongly tiles drawn as `gid` glow, from 0 for not at all to 1.
+    pub fn emission(&self, gid: u32) -> f32 {
+        self.emissive_tiles.get(&gid).copied().unwrap_or(0.0)
+    }
+
+    /// Every glowing tile on the map's visible layers, for lights, a bloom pass or anything
+    /// else lighting the world around lava, windows and neon.
+    pub fn emissive_tiles(&self) -> Vec<EmissiveTile> {
+        let mut emissive = Vec::new();
+        if self.emissive_tiles.is_empty() {
+            return emissive;
+        }
+        for (layer_index, layer) in self.map.layers.iter().enumerate() {
+            let tiles = match &layer.tiles {
+                tiled::LayerData::Finite(tiles) if layer.visible => tiles,
+                _ => continue,
+            };
+            // Tiled's offsets are y down, map-local space is y up.
+            let offset = self.layer_offset(layer_index);
+            let offset = Vec2::new(offset.x(), -offset.y());
+            for (y, row) in tiles.iter().enumerate() {
+                for (x, tile) in row.iter().enumerate() {
+                    let strength = self.emission(tile.gid);
+                    if strength <= 0.0 {
+                        continue;
+                    }
+                    let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    emissive.push(EmissiveTile {
+                        layer: layer_index,
+                        x: x as u32,
+                        y: y as u32,
+                        position: self.tile_to_local(center) + offset,
+                        strength,
+                    });
+                }
+            }
+        }
+        emissive
+    }
+
+    /// Path of the emissive map drawn with the tileset's image, from its `emissive_map`
+    /// property, found like the normal map with `_e` added instead, see
+    /// `tileset_normal_map_path`.
+    pub fn tileset_emissive_map_path(&self, tileset: &tiled::Tileset) -> Option<String> {
+        self.companion_image_path(tileset, "emissive_map", "_e")
+    }
+
+    /// An emissive map for a tileset without one, `size` pixels like its image, white as far
+    /// as each tile glows. `None` if none of its tiles do.
+    pub(crate) fn emissive_mask(
+        &self,
+        tileset: &tiled::Tileset,
+        size: (u32, u32),
+    ) -> Option<Texture> {
+        let (width, height) = size;
+        let ids = tileset.tiles.iter().map(|tile| tile.id + 1).max();
+        let count = tileset.tilecount.unwrap_or(0).max(ids.unwrap_or(0));
+        let glows = (0..count).any(|id| self.emission(tileset.first_gid + id) > 0.0);
+        if !glows || width == 0 || height == 0 {
+            return None;
+        }
+        let mut data = vec![0; (width * height * 4) as usize];
+        for id in 0..count {
+            let strength = self.emission(tileset.first_gid + id);
+            if strength <= 0.0 {
+                continue;
+            }
+            let uv = Map::tile_uv(tileset, id);
+            let value = (strength * 255.0).round() as u8;
+            let x_range = (uv.x() * width as f32) as u32..(uv.z() * width as f32).ceil() as u32;
+            for y in (uv.y() * height as f32) as u32..(uv.w() * height as f32).ceil() as u32 {
+                for x in x_range.clone() {
+                    if x >= width || y >= height {
+                        continue;
+                    }
+                    let texel = ((y * width + x) * 4) as usize;
+                    data[texel..texel + 4].copy_from_slice(&[value, value, value, 255]);
+                }
+            }
+        }
+        Some(Texture::new(
+            Vec2::new(width as f32, height as f32),
+            data,
+            TextureFormat::Rgba8Unorm,
+        ))
+    }
+}
+
+/// How much brighter than their lit color glowing pixels are drawn. At 1 they keep the color
+/// of their image whatever the `TileLight`; above it they're drawn brighter still.
+#[derive(Debug, Clone)]
+pub struct TileGlow {
+    pub intensity: f32,
+}
+
+impl Default for TileGlow {
+    fn default() -> Self {
+        Self { intensity: 1.0 }
+    }
+}
+
+/// The emissive map of the tileset a chunk draws, and the `TileGlow` intensity it's drawn
+/// with. Kept up to date by `sync_tile_emission`.
+#[derive(Debug, Clone, Default, RenderResources, ShaderDefs)]
+pub struct TileEmission {
+    #[shader_def]
+    pub emissive_map: Option<Handle<Texture>>,
+    pub intensity: f32,
+}
+
+/// Hands chunks the emissive maps of their tilesets and the current `TileGlow`.
+///
+/// Like normal maps, emissive maps share the tileset image's layout, so maps packed into an
+/// atlas, tile objects and level of detail chunks are drawn without them.
+pub fn sync_tile_emission(
+    glow: Res<TileGlow>,
+    maps: Res<Assets<Map>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut query: Query<(
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&LodChunk>,
+        &mut TileEmission,
+    )>,
+) {
+    for (chunk, object, lod, mut emission) in &mut query.iter() {
+        let emissive_map = maps
+            .get(&chunk.map)
+            .filter(|map| map.atlas.is_none() && object.is_none() && lod.is_none())
+            .and_then(|map| map.tileset_assets.get(&chunk.key.tileset_guid))
+            .and_then(|tileset| tilesets.get(tileset))
+            .and_then(|tileset| tileset.emissive_map);
+        if emission.emissive_map != emissive_map {
+            emission.emissive_map = emissive_map;
+        }
+        if emission.intensity != glow.intensity {
+            emission.intensity = glow.intensity;
+        }
+    }
+}
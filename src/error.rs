@@ -0,0 +1,101 @@
+use crate::{image_collections::is_image_collection, Map};
+use bevy::prelude::*;
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Why a map couldn't be loaded or built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    /// The TMX is malformed, or uses something the tiled crate can't read.
+    Parse(String),
+    /// Only orthogonal and isometric maps are supported.
+    UnsupportedOrientation(tiled::Orientation),
+    /// Infinite maps aren't supported; holds the name of the first infinite layer.
+    InfiniteLayer(String),
+    /// A tileset is missing its image or tile count; holds the tileset name.
+    InvalidTileset(String),
+    /// A baked map is truncated, from another version, or can't be read or written.
+    Baked(String),
+    /// An untrusted map breaks a `SandboxProfile` limit.
+    Rejected(String),
+    /// A map couldn't be written out as TMX.
+    Export(String),
+    /// A generator was set up with something it can't generate from.
+    Generate(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Parse(message) => write!(f, "failed to parse map: {}", message),
+            MapError::UnsupportedOrientation(orientation) => {
+                write!(f, "unsupported orientation {:?}", orientation)
+            }
+            MapError::InfiniteLayer(name) => {
+                write!(f, "layer {:?} is infinite, which isn't supported", name)
+            }
+            MapError::InvalidTileset(name) => {
+                write!(f, "tileset {:?} has no image or tile count", name)
+            }
+            MapError::Baked(message) => write!(f, "invalid baked map: {}", message),
+            MapError::Rejected(message) => write!(f, "map rejected: {}", message),
+            MapError::Export(message) => write!(f, "failed to export map: {}", message),
+            MapError::Generate(message) => write!(f, "failed to generate map: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// Checks that a map only uses what can be drawn.
+pub(crate) fn validate(map: &tiled::Map) -> Result<(), MapError> {
+    match map.orientation {
+        tiled::Orientation::Orthogonal | tiled::Orientation::Isometric => {}
+        orientation => return Err(MapError::UnsupportedOrientation(orientation)),
+    }
+    for layer in map.layers.iter() {
+        if let tiled::LayerData::Infinite(_) = layer.tiles {
+            return Err(MapError::InfiniteLayer(layer.name.clone()));
+        }
+    }
+    for tileset in map.tilesets.iter() {
+        let drawable = !tileset.images.is_empty() || is_image_collection(tileset);
+        if !drawable || tileset.tilecount.is_none() {
+            return Err(MapError::InvalidTileset(tileset.name.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Sent when a map loaded through the `AssetServer` fails to load.
+#[derive(Debug, Clone)]
+pub struct MapLoadFailed {
+    pub map: Handle<Map>,
+    pub path: PathBuf,
+    pub error: MapError,
+}
+
+/// Failures reported by the map loader, which runs off the main thread, waiting to be sent as
+/// `MapLoadFailed` events.
+#[derive(Debug, Clone, Default)]
+pub struct MapLoadErrors(pub(crate) Arc<Mutex<Vec<(PathBuf, MapError)>>>);
+
+/// Sends a `MapLoadFailed` for every map the loader failed to load.
+pub fn send_map_load_failures(
+    asset_server: Res<AssetServer>,
+    errors: Res<MapLoadErrors>,
+    mut events: ResMut<Events<MapLoadFailed>>,
+) {
+    let failed = match errors.0.lock() {
+        Ok(mut failed) => failed.drain(..).collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    for (path, error) in failed {
+        if let Some(map) = asset_server.get_handle(&path) {
+            events.send(MapLoadFailed { map, path, error });
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use crate::{
+    loader::{FLIPPED_DIAGONALLY_FLAG, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG},
+    Map, MapError, ObjectDrawOrder, PropertyOwner, RenderOrder, TilesetSource,
+};
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+type Writer<'a> = EventWriter<&'a mut Vec<u8>>;
+
+/// Writes an element with `attributes`, leaving it open.
+fn start(
+    writer: &mut Writer,
+    name: &str,
+    attributes: &[(&str, String)],
+) -> xml::writer::Result<()> {
+    let mut element = XmlEvent::start_element(name);
+    for (key, value) in attributes {
+        element = element.attr(*key, value.as_str());
+    }
+    writer.write(element)
+}
+
+fn end(writer: &mut Writer) -> xml::writer::Result<()> {
+    writer.write(XmlEvent::end_element())
+}
+
+fn colour(colour: &tiled::Colour) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.red, colour.green, colour.blue)
+}
+
+fn bool_attribute(value: bool) -> String {
+    if value { "1" } else { "0" }.to_string()
+}
+
+/// Writes `properties`, with the ones in `files` typed `file` again.
+fn write_properties(
+    writer: &mut Writer,
+    properties: &tiled::Properties,
+    files: &HashMap<String, String>,
+) -> xml::writer::Result<()> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+    let mut names = properties.keys().collect::<Vec<_>>();
+    names.sort();
+    start(writer, "properties", &[])?;
+    for name in names {
+        let (kind, value) = match &properties[name] {
+            tiled::PropertyValue::BoolValue(value) => ("bool", value.to_string()),
+            tiled::PropertyValue::FloatValue(value) => ("float", value.to_string()),
+            tiled::PropertyValue::IntValue(value) => ("int", value.to_string()),
+            tiled::PropertyValue::ColorValue(value) => ("color", format!("#{:08x}", value)),
+            tiled::PropertyValue::StringValue(value) => match files.get(name) {
+                Some(file) => ("file", file.clone()),
+                None => ("string", value.clone()),
+            },
+        };
+        start(
+            writer,
+            "property",
+            &[
+                ("name", name.clone()),
+                ("type", kind.to_string()),
+                ("value", value),
+            ],
+        )?;
+        end(writer)?;
+    }
+    end(writer)
+}
+
+fn write_image(writer: &mut Writer, image: &tiled::Image) -> xml::writer::Result<()> {
+    let mut attributes = vec![
+        ("source", image.source.clone()),
+        ("width", image.width.to_string()),
+        ("height", image.height.to_string()),
+    ];
+    if let Some(transparent) = &image.transparent_colour {
+        attributes.push(("trans", colour(transparent)[1..].to_string()));
+    }
+    start(writer, "image", &attributes)?;
+    end(writer)
+}
+
+fn points(points: &[(f32, f32)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Map {
+    /// The `file` properties of `owner`, keyed by name, with the paths they're written with.
+    fn property_files(
+        &self,
+        owner: PropertyOwner,
+        properties: &tiled::Properties,
+    ) -> HashMap<String, String> {
+        let mut files = HashMap::new();
+        for name in self.file_properties.get(&owner).into_iter().flatten() {
+            if let Some(tiled::PropertyValue::StringValue(file)) = properties.get(name) {
+                files.insert(name.clone(), file.clone());
+            }
+        }
+        // Object files are stored resolved against the map folder, and may have been edited.
+        if let PropertyOwner::Object(id) = owner {
+            for (name, path) in self.object_files.get(&id).into_iter().flatten() {
+                let path = path.strip_prefix(&self.map_folder).unwrap_or(path);
+                files.insert(name.clone(), path.to_string_lossy().into_owned());
+            }
+        }
+        files
+    }
+
+    /// Writes the map out as a `.tmx` file, edits included, such as for saving a level made
+    /// in game.
+    ///
+    /// Tilesets loaded from `.tsx` files are referenced rather than embedded. Object groups
+    /// are written after the tile and image layers, which is where they're drawn, and wang
+    /// sets and tile collision shapes aren't written.
+    pub fn to_tmx(&self) -> Result<Vec<u8>, MapError> {
+        let mut bytes = Vec::new();
+        self.write_tmx(&mut bytes)
+            .map_err(|err| MapError::Export(err.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// Writes `to_tmx` to `path`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_tmx(&self, path: impl AsRef<Path>) -> Result<(), MapError> {
+        std::fs::write(path, self.to_tmx()?).map_err(|err| MapError::Export(err.to_string()))
+    }
+
+    fn write_tmx(&self, bytes: &mut Vec<u8>) -> xml::writer::Result<()> {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(bytes);
+        let map = &self.map;
+        let orientation = match map.orientation {
+            tiled::Orientation::Orthogonal => "orthogonal",
+            tiled::Orientation::Isometric => "isometric",
+            tiled::Orientation::Staggered => "staggered",
+            tiled::Orientation::Hexagonal => "hexagonal",
+        };
+        let render_order = match self.render_order {
+            RenderOrder::RightDown => "right-down",
+            RenderOrder::RightUp => "right-up",
+            RenderOrder::LeftDown => "left-down",
+            RenderOrder::LeftUp => "left-up",
+        };
+        let mut attributes = vec![
+            ("version", "1.2".to_string()),
+            ("orientation", orientation.to_string()),
+            ("renderorder", render_order.to_string()),
+            ("width", map.width.to_string()),
+            ("height", map.height.to_string()),
+            ("tilewidth", map.tile_width.to_string()),
+            ("tileheight", map.tile_height.to_string()),
+            ("infinite", "0".to_string()),
+        ];
+        if let Some(background) = &map.background_colour {
+            attributes.push(("backgroundcolor", colour(background)));
+        }
+        start(&mut writer, "map", &attributes)?;
+        let files = self.property_files(PropertyOwner::Map, &map.properties);
+        write_properties(&mut writer, &map.properties, &files)?;
+
+        for tileset in map.tilesets.iter() {
+            self.write_tileset(&mut writer, tileset)?;
+        }
+
+        // Tile and image layers, in the order they were loaded.
+        let mut layers = map
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(index, layer)| (layer.layer_index, Some(index), None))
+            .chain(
+                map.image_layers
+                    .iter()
+                    .enumerate()
+                    .map(|(index, layer)| (layer.layer_index, None, Some(index))),
+            )
+            .collect::<Vec<_>>();
+        layers.sort_by_key(|(layer_index, _, _)| *layer_index);
+        for (_, tile_layer, image_layer) in layers {
+            if let Some(index) = tile_layer {
+                self.write_tile_layer(&mut writer, index)?;
+            }
+            if let Some(index) = image_layer {
+                self.write_image_layer(&mut writer, index)?;
+            }
+        }
+        for index in 0..map.object_groups.len() {
+            self.write_object_group(&mut writer, index)?;
+        }
+        end(&mut writer)
+    }
+
+    fn write_tileset(
+        &self,
+        writer: &mut Writer,
+        tileset: &tiled::Tileset,
+    ) -> xml::writer::Result<()> {
+        let first_gid = ("firstgid", tileset.first_gid.to_string());
+        if let Some(TilesetSource::External(path)) = self.tileset_sources.get(&tileset.first_gid) {
+            start(
+                writer,
+                "tileset",
+                &[first_gid, ("source", path.to_string_lossy().into_owned())],
+            )?;
+            return end(writer);
+        }
+
+        let mut attributes = vec![
+            first_gid,
+            ("name", tileset.name.clone()),
+            ("tilewidth", tileset.tile_width.to_string()),
+            ("tileheight", tileset.tile_height.to_string()),
+            ("spacing", tileset.spacing.to_string()),
+            ("margin", tileset.margin.to_string()),
+        ];
+        if let Some(tilecount) = tileset.tilecount {
+            attributes.push(("tilecount", tilecount.to_string()));
+        }
+        start(writer, "tileset", &attributes)?;
+        if let Some(offset) = self.tile_offsets.get(&tileset.first_gid) {
+            start(
+                writer,
+                "tileoffset",
+                &[("x", offset.x().to_string()), ("y", offset.y().to_string())],
+            )?;
+            end(writer)?;
+        }
+        let owner = PropertyOwner::Tileset(tileset.first_gid);
+        let files = self.property_files(owner, &tileset.properties);
+        write_properties(writer, &tileset.properties, &files)?;
+        for image in tileset.images.iter() {
+            write_image(writer, image)?;
+        }
+        for tile in tileset.tiles.iter() {
+            let mut attributes = vec![("id", tile.id.to_string())];
+            if let Some(tile_type) = &tile.tile_type {
+                attributes.push(("type", tile_type.clone()));
+            }
+            start(writer, "tile", &attributes)?;
+            let owner = PropertyOwner::Tile(tileset.first_gid, tile.id);
+            let files = self.property_files(owner, &tile.properties);
+            write_properties(writer, &tile.properties, &files)?;
+            if let Some(frames) = &tile.animation {
+                start(writer, "animation", &[])?;
+                for frame in frames.iter() {
+                    start(
+                        writer,
+                        "frame",
+                        &[
+                            ("tileid", frame.tile_id.to_string()),
+                            ("duration", frame.duration.to_string()),
+                        ],
+                    )?;
+                    end(writer)?;
+                }
+                end(writer)?;
+            }
+            end(writer)?;
+        }
+        end(writer)
+    }
+
+    fn write_tile_layer(&self, writer: &mut Writer, index: usize) -> xml::writer::Result<()> {
+        let layer = &self.map.layers[index];
+        let offset = self.layer_offset(index);
+        start(
+            writer,
+            "layer",
+            &[
+                ("name", layer.name.clone()),
+                ("width", self.map.width.to_string()),
+                ("height", self.map.height.to_string()),
+                ("opacity", layer.opacity.to_string()),
+                ("visible", bool_attribute(layer.visible)),
+                ("offsetx", offset.x().to_string()),
+                ("offsety", offset.y().to_string()),
+            ],
+        )?;
+        let files = self.property_files(PropertyOwner::Layer(index), &layer.properties);
+        write_properties(writer, &layer.properties, &files)?;
+        start(writer, "data", &[("encoding", "csv".to_string())])?;
+        if let tiled::LayerData::Finite(rows) = &layer.tiles {
+            let csv = rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|tile| {
+                            let mut gid = tile.gid;
+                            if tile.flip_h {
+                                gid |= FLIPPED_HORIZONTALLY_FLAG;
+                            }
+                            if tile.flip_v {
+                                gid |= FLIPPED_VERTICALLY_FLAG;
+                            }
+                            if tile.flip_d {
+                                gid |= FLIPPED_DIAGONALLY_FLAG;
+                            }
+                            gid.to_string()
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>()
+                .join(",\n");
+            writer.write(XmlEvent::characters(&format!("\n{}\n", csv)))?;
+        }
+        end(writer)?;
+        end(writer)
+    }
+
+    fn write_image_layer(&self, writer: &mut Writer, index: usize) -> xml::writer::Result<()> {
+        let layer = &self.map.image_layers[index];
+        let repeat = self
+            .image_layer_repeats
+            .get(index)
+            .copied()
+            .unwrap_or_default();
+        start(
+            writer,
+            "imagelayer",
+            &[
+                ("name", layer.name.clone()),
+                ("opacity", layer.opacity.to_string()),
+                ("visible", bool_attribute(layer.visible)),
+                ("offsetx", layer.offset_x.to_string()),
+                ("offsety", layer.offset_y.to_string()),
+                ("repeatx", bool_attribute(repeat.x)),
+                ("repeaty", bool_attribute(repeat.y)),
+            ],
+        )?;
+        let files = self.property_files(PropertyOwner::ImageLayer(index), &layer.properties);
+        write_properties(writer, &layer.properties, &files)?;
+        if let Some(image) = &layer.image {
+            write_image(writer, image)?;
+        }
+        end(writer)
+    }
+
+    fn write_object_group(&self, writer: &mut Writer, index: usize) -> xml::writer::Result<()> {
+        let group = &self.map.object_groups[index];
+        let draw_order = match self
+            .object_draw_orders
+            .get(index)
+            .copied()
+            .unwrap_or_default()
+        {
+            ObjectDrawOrder::TopDown => "topdown",
+            ObjectDrawOrder::Index => "index",
+        };
+        let mut attributes = vec![
+            ("name", group.name.clone()),
+            ("opacity", group.opacity.to_string()),
+            ("visible", bool_attribute(group.visible)),
+            ("draworder", draw_order.to_string()),
+        ];
+        if let Some(color) = &group.colour {
+            attributes.push(("color", colour(color)));
+        }
+        start(writer, "objectgroup", &attributes)?;
+        let files = self.property_files(PropertyOwner::ObjectGroup(index), &group.properties);
+        write_properties(writer, &group.properties, &files)?;
+        for object in group.objects.iter() {
+            let mut attributes = vec![
+                ("id", object.id.to_string()),
+                ("name", object.name.clone()),
+                ("type", object.obj_type.clone()),
+                ("x", object.x.to_string()),
+                ("y", object.y.to_string()),
+                ("rotation", object.rotation.to_string()),
+                ("visible", bool_attribute(object.visible)),
+            ];
+            if object.gid != 0 {
+                attributes.push(("gid", object.gid.to_string()));
+            }
+            match &object.shape {
+                tiled::ObjectShape::Rect { width, height }
+                | tiled::ObjectShape::Ellipse { width, height } => {
+                    attributes.push(("width", width.to_string()));
+                    attributes.push(("height", height.to_string()));
+                }
+                _ => {}
+            }
+            start(writer, "object", &attributes)?;
+            let files = self.property_files(PropertyOwner::Object(object.id), &object.properties);
+            write_properties(writer, &object.properties, &files)?;
+            match &object.shape {
+                tiled::ObjectShape::Ellipse { .. } => {
+                    start(writer, "ellipse", &[])?;
+                    end(writer)?;
+                }
+                tiled::ObjectShape::Point(_, _) => {
+                    start(writer, "point", &[])?;
+                    end(writer)?;
+                }
+                tiled::ObjectShape::Polyline { points: shape } => {
+                    start(writer, "polyline", &[("points", points(shape))])?;
+                    end(writer)?;
+                }
+                tiled::ObjectShape::Polygon { points: shape } => {
+                    start(writer, "polygon", &[("points", points(shape))])?;
+                    end(writer)?;
+                }
+                tiled::ObjectShape::Rect { .. } => {}
+            }
+            end(writer)?;
+        }
+        end(writer)
+    }
+}
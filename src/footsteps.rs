@@ -0,0 +1,100 @@
+use crate::{Map, MapAnchor, PropertiesExt};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Reads the `footstep` string property of every tile that has one, keyed by gid.
+pub(crate) fn read_tile_footsteps(map: &tiled::Map) -> HashMap<u32, String> {
+    let mut footsteps = HashMap::new();
+    for tileset in map.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            if let Some(surface) = tile.properties.string_property("footstep") {
+                footsteps.insert(tileset.first_gid + tile.id, surface);
+            }
+        }
+    }
+    footsteps
+}
+
+impl Map {
+    /// The footstep surface of the tile at `x`, `y`, such as `"grass"` or `"wood"`, from the
+    /// `footstep` property of its tile in the tileset. Where layers overlap the topmost tile
+    /// with a surface wins, so a rug sounds like a rug rather than the floor under it.
+    pub fn footstep_at(&self, x: u32, y: u32) -> Option<&str> {
+        (0..self.map.layers.len()).rev().find_map(|layer| {
+            self.tile(layer, x, y)
+                .and_then(|tile| self.tile_footsteps.get(&tile.gid))
+                .map(String::as_str)
+        })
+    }
+}
+
+/// Tracks the footstep surface under an entity, sending a `FootstepSurface` whenever it
+/// changes.
+#[derive(Debug, Clone)]
+pub struct FootstepTracker {
+    /// The map entity the tracked entity walks on.
+    pub map: Entity,
+    surface: Option<String>,
+}
+
+impl FootstepTracker {
+    pub fn new(map: Entity) -> Self {
+        Self { map, surface: None }
+    }
+
+    /// The surface the entity is on, as of the last update.
+    pub fn surface(&self) -> Option<&str> {
+        self.surface.as_deref()
+    }
+}
+
+/// Sent when an entity with a `FootstepTracker` moves onto a tile with a different footstep
+/// surface, including onto or off tiles without one.
+#[derive(Debug, Clone)]
+pub struct FootstepSurface {
+    pub entity: Entity,
+    pub map: Handle<Map>,
+    pub previous: Option<String>,
+    pub surface: Option<String>,
+}
+
+/// Updates each `FootstepTracker` with the surface under its entity.
+pub fn track_footstep_surfaces(
+    maps: Res<Assets<Map>>,
+    mut events: ResMut<Events<FootstepSurface>>,
+    mut tracker_query: Query<(Entity, &mut FootstepTracker, &GlobalTransform)>,
+    map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    for (entity, mut tracker, transform) in &mut tracker_query.iter() {
+        let (map_handle, anchor, origin) = match (
+            map_query.get::<Handle<Map>>(tracker.map),
+            map_query.get::<MapAnchor>(tracker.map),
+            map_query.get::<Transform>(tracker.map),
+        ) {
+            (Ok(map_handle), Ok(anchor), Ok(origin)) => (map_handle, anchor, origin),
+            _ => continue,
+        };
+        let map = match maps.get(&map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let translation = map.translation(&anchor, origin.translation());
+        let local = map.world_to_local(translation, transform.translation().truncate());
+        let tile = map.local_to_tile(local);
+        let surface = if tile.x() >= 0.0 && tile.y() >= 0.0 {
+            map.footstep_at(tile.x() as u32, tile.y() as u32)
+        } else {
+            None
+        };
+        if tracker.surface.as_deref() != surface {
+            let surface = surface.map(str::to_string);
+            let previous = std::mem::replace(&mut tracker.surface, surface.clone());
+            events.send(FootstepSurface {
+                entity,
+                map: *map_handle,
+                previous,
+                surface,
+            });
+        }
+    }
+}
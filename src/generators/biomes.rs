@@ -0,0 +1,139 @@
+use crate::{Map, WangSet};
+
+/// Transition tiles for `Map::blend_biomes`.
+#[derive(Debug, Clone, Default)]
+pub struct BiomeBlend {
+    /// The wang color name of each biome id. Later biomes have priority and spread over the
+    /// corners they share with earlier ones.
+    pub biomes: Vec<String>,
+    /// Sets holding the transitions; each transition comes from the first set that has it.
+    pub wang_sets: Vec<WangSet>,
+}
+
+impl BiomeBlend {
+    /// The gid of a tile whose corners have these biomes, from the first set with one.
+    fn transition(&self, corners: [usize; 4]) -> Option<u32> {
+        self.wang_sets.iter().find_map(|wang_set| {
+            let mut colors = [0; 4];
+            for (color, biome) in colors.iter_mut().zip(corners.iter()) {
+                *color = wang_set.color(self.biomes.get(*biome)?)?;
+            }
+            wang_set.corner_tile(colors)
+        })
+    }
+}
+
+impl Map {
+    /// Replaces tiles of `layer` along biome boundaries with transition tiles.
+    ///
+    /// `biomes` holds a biome id per tile, in rows like `tiled::LayerData::Finite`. Every tile
+    /// with a differing neighbour gets the transition whose corners are the highest priority
+    /// biome among the tiles sharing each corner. Tiles without a transition keep their tile.
+    /// Returns the number of tiles replaced.
+    pub fn blend_biomes(
+        &mut self,
+        layer: usize,
+        biomes: &[Vec<usize>],
+        blend: &BiomeBlend,
+    ) -> usize {
+        let biome = |x: i32, y: i32| -> Option<usize> {
+            if x < 0 || y < 0 {
+                return None;
+            }
+            biomes.get(y as usize)?.get(x as usize).copied()
+        };
+
+        let mut placements = Vec::new();
+        for (y, row) in biomes.iter().enumerate() {
+            for (x, own) in row.iter().enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                // The tiles sharing each corner, as offsets.
+                let corners = [(-1, -1), (0, -1), (0, 0), (-1, 0)];
+                let mut colors = [*own; 4];
+                for (color, (cx, cy)) in colors.iter_mut().zip(corners.iter()) {
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+                        if let Some(other) = biome(x + cx + dx, y + cy + dy) {
+                            *color = (*color).max(other);
+                        }
+                    }
+                }
+                if colors.iter().all(|color| color == own) {
+                    continue;
+                }
+                if let Some(gid) = blend.transition(colors) {
+                    placements.push((x as u32, y as u32, gid));
+                }
+            }
+        }
+
+        placements
+            .into_iter()
+            .filter(|(x, y, gid)| self.set_tile(layer, *x, *y, *gid))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WangTile;
+
+    const FILL: u32 = 16;
+
+    /// A tile of the wang set with corners [top left, top right, bottom right, bottom left].
+    fn transition(tile_id: u32, corners: [u8; 4]) -> WangTile {
+        let mut wang_id = [0; 8];
+        wang_id[7] = corners[0];
+        wang_id[1] = corners[1];
+        wang_id[3] = corners[2];
+        wang_id[5] = corners[3];
+        WangTile { tile_id, wang_id }
+    }
+
+    #[test]
+    fn transitions_are_picked_from_the_neighbouring_biomes() {
+        let tmx = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="3" height="3" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="16" columns="4">
+  <image source="tiles.png" width="64" height="64"/>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="3">
+  <data encoding="csv">{0},{0},{0},
+{0},{0},{0},
+{0},{0},{0}</data>
+ </layer>
+</map>
+"#,
+            FILL
+        );
+        let mut map = Map::try_from_bytes(tmx.as_bytes(), Default::default()).unwrap();
+        // Sand in the middle of grass. The top left corner transition is missing.
+        let blend = BiomeBlend {
+            biomes: vec!["grass".to_string(), "sand".to_string()],
+            wang_sets: vec![WangSet {
+                name: "ground".to_string(),
+                first_gid: 1,
+                colors: vec!["grass".to_string(), "sand".to_string()],
+                tiles: vec![
+                    transition(0, [1, 1, 2, 1]),
+                    transition(1, [1, 1, 2, 2]),
+                    transition(2, [1, 1, 1, 2]),
+                    transition(3, [1, 2, 2, 1]),
+                    transition(4, [2, 1, 1, 2]),
+                    transition(5, [1, 2, 1, 1]),
+                    transition(6, [2, 2, 1, 1]),
+                ],
+            }],
+        };
+        let biomes = vec![vec![0, 0, 0], vec![0, 1, 0], vec![0, 0, 0]];
+        assert_eq!(map.blend_biomes(0, &biomes, &blend), 7);
+        let gids = (0..3)
+            .map(|y| (0..3).map(|x| map.tile(0, x, y).unwrap().gid).collect())
+            .collect::<Vec<Vec<u32>>>();
+        assert_eq!(
+            gids,
+            vec![vec![1, 2, 3], vec![4, FILL, 5], vec![6, 7, FILL]]
+        );
+    }
+}
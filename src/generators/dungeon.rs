@@ -0,0 +1,466 @@
+use super::{empty_tiled_map, push_marker_group, push_tile_layer};
+use crate::{rng::SplitMix64, Map, MapError, TileRect};
+use std::{
+    collections::{HashSet, VecDeque},
+    path::Path,
+};
+
+/// How a `DungeonGenerator` carves floor out of solid wall.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DungeonAlgorithm {
+    /// Rooms in the leaves of a binary space partition, joined by corridors between siblings.
+    /// Corridor openings into rooms get `door` markers.
+    Bsp {
+        /// Smallest room width or height, in tiles.
+        min_room_size: u32,
+        /// How many times the map is split.
+        max_depth: u32,
+    },
+    /// Random fill smoothed by a cellular automaton, keeping only the largest cave.
+    CellularCaves {
+        /// Chance of a tile starting as wall.
+        wall_chance: f32,
+        iterations: u32,
+    },
+    /// A random walk from the center until the given fraction of the map is floor.
+    DrunkardsWalk { floor_ratio: f32 },
+}
+
+/// Generates a single layer dungeon of floor and wall tiles, plus a `markers` object group with
+/// a `spawn` point object and any `door` point objects.
+///
+/// The outermost ring of tiles is always wall.
+#[derive(Debug, Clone)]
+pub struct DungeonGenerator {
+    pub width: u32,
+    pub height: u32,
+    /// The same seed always generates the same map; take it from a `MapRng` stream.
+    pub seed: u64,
+    pub algorithm: DungeonAlgorithm,
+    pub floor_gid: u32,
+    pub wall_gid: u32,
+    pub tileset: tiled::Tileset,
+}
+
+impl DungeonGenerator {
+    /// Generates the dungeon as Tiled data.
+    pub fn generate(&self) -> tiled::Map {
+        let mut grid = Grid::new(self.width, self.height);
+        let mut rng = SplitMix64::new(self.seed);
+        let mut markers = Vec::new();
+        match self.algorithm {
+            DungeonAlgorithm::Bsp {
+                min_room_size,
+                max_depth,
+            } => {
+                let bounds = TileRect {
+                    x: 1,
+                    y: 1,
+                    width: self.width.saturating_sub(2),
+                    height: self.height.saturating_sub(2),
+                };
+                let mut rooms = Vec::new();
+                grid.split(
+                    bounds,
+                    max_depth,
+                    min_room_size.max(1),
+                    &mut rng,
+                    &mut rooms,
+                );
+                for (x, y) in grid.doors(&rooms) {
+                    markers.push(("door", x, y));
+                }
+                if let Some(room) = rooms.first() {
+                    markers.push(("spawn", room.x + room.width / 2, room.y + room.height / 2));
+                }
+            }
+            DungeonAlgorithm::CellularCaves {
+                wall_chance,
+                iterations,
+            } => {
+                grid.caves(wall_chance, iterations, &mut rng);
+                if let Some((x, y)) = grid.first_floor() {
+                    markers.push(("spawn", x, y));
+                }
+            }
+            DungeonAlgorithm::DrunkardsWalk { floor_ratio } => {
+                let start = grid.walk(floor_ratio, &mut rng);
+                markers.extend(start.map(|(x, y)| ("spawn", x, y)));
+            }
+        }
+
+        let rows = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        if grid.is_floor(x as i32, y as i32) {
+                            self.floor_gid
+                        } else {
+                            self.wall_gid
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut map = empty_tiled_map(self.width, self.height, &self.tileset);
+        push_tile_layer(&mut map, "dungeon", rows);
+        push_marker_group(&mut map, "markers", &markers);
+        map
+    }
+
+    /// Generates the dungeon as a map asset; tileset images are relative to `map_folder`.
+    pub fn generate_map(&self, map_folder: impl AsRef<Path>) -> Result<Map, MapError> {
+        Map::from_tiled(self.generate(), map_folder)
+    }
+}
+
+fn range(rng: &mut SplitMix64, min: u32, max: u32) -> u32 {
+    min + (rng.next_u64() % (max - min + 1) as u64) as u32
+}
+
+/// Floor tiles of a map, with everything else wall.
+struct Grid {
+    width: u32,
+    height: u32,
+    floor: Vec<bool>,
+}
+
+impl Grid {
+    fn new(width: u32, height: u32) -> Self {
+        Grid {
+            width,
+            height,
+            floor: vec![false; (width * height) as usize],
+        }
+    }
+
+    /// Whether `(x, y)` can be carved, which excludes the border.
+    fn inner(&self, x: i32, y: i32) -> bool {
+        x > 0 && y > 0 && x < self.width as i32 - 1 && y < self.height as i32 - 1
+    }
+
+    fn is_floor(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && x < self.width as i32
+            && y < self.height as i32
+            && self.floor[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    fn set_floor(&mut self, x: i32, y: i32, floor: bool) {
+        if self.inner(x, y) {
+            self.floor[(y as u32 * self.width + x as u32) as usize] = floor;
+        }
+    }
+
+    /// Partitions `rect`, carving a room in each leaf and joining siblings with corridors.
+    /// Returns the center of a room within `rect`.
+    fn split(
+        &mut self,
+        rect: TileRect,
+        depth: u32,
+        min_size: u32,
+        rng: &mut SplitMix64,
+        rooms: &mut Vec<TileRect>,
+    ) -> Option<(u32, u32)> {
+        // Each half needs room for a room plus a wall on either side.
+        let min_leaf = min_size + 2;
+        let can_split_x = rect.width >= min_leaf * 2;
+        let can_split_y = rect.height >= min_leaf * 2;
+        if depth == 0 || !(can_split_x || can_split_y) {
+            return self.room(rect, min_size, rng, rooms);
+        }
+
+        let split_x = if can_split_x && can_split_y {
+            rect.width > rect.height || (rect.width == rect.height && rng.next_u64() % 2 == 0)
+        } else {
+            can_split_x
+        };
+        let (first, second) = if split_x {
+            let at = range(rng, min_leaf, rect.width - min_leaf);
+            (
+                TileRect { width: at, ..rect },
+                TileRect {
+                    x: rect.x + at,
+                    width: rect.width - at,
+                    ..rect
+                },
+            )
+        } else {
+            let at = range(rng, min_leaf, rect.height - min_leaf);
+            (
+                TileRect { height: at, ..rect },
+                TileRect {
+                    y: rect.y + at,
+                    height: rect.height - at,
+                    ..rect
+                },
+            )
+        };
+
+        let a = self.split(first, depth - 1, min_size, rng, rooms);
+        let b = self.split(second, depth - 1, min_size, rng, rooms);
+        if let (Some(a), Some(b)) = (a, b) {
+            self.corridor(a, b, rng.next_u64() % 2 == 0);
+        }
+        a.or(b)
+    }
+
+    fn room(
+        &mut self,
+        leaf: TileRect,
+        min_size: u32,
+        rng: &mut SplitMix64,
+        rooms: &mut Vec<TileRect>,
+    ) -> Option<(u32, u32)> {
+        if leaf.width < min_size + 2 || leaf.height < min_size + 2 {
+            return None;
+        }
+        let width = range(rng, min_size, leaf.width - 2);
+        let height = range(rng, min_size, leaf.height - 2);
+        let room = TileRect {
+            x: leaf.x + range(rng, 1, leaf.width - width - 1),
+            y: leaf.y + range(rng, 1, leaf.height - height - 1),
+            width,
+            height,
+        };
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                self.set_floor(x as i32, y as i32, true);
+            }
+        }
+        rooms.push(room);
+        Some((room.x + room.width / 2, room.y + room.height / 2))
+    }
+
+    /// Carves an L shaped corridor, going along x first or y first.
+    fn corridor(&mut self, from: (u32, u32), to: (u32, u32), x_first: bool) {
+        let corner = if x_first {
+            (to.0, from.1)
+        } else {
+            (from.0, to.1)
+        };
+        for (a, b) in [(from, corner), (corner, to)].iter() {
+            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                for y in a.1.min(b.1)..=a.1.max(b.1) {
+                    self.set_floor(x as i32, y as i32, true);
+                }
+            }
+        }
+    }
+
+    /// Floor tiles just outside a room, where a corridor leads into it.
+    fn doors(&self, rooms: &[TileRect]) -> Vec<(u32, u32)> {
+        let in_room = |x: i32, y: i32| {
+            rooms.iter().any(|room| {
+                x >= room.x as i32
+                    && y >= room.y as i32
+                    && x < (room.x + room.width) as i32
+                    && y < (room.y + room.height) as i32
+            })
+        };
+        let mut doors = Vec::new();
+        let mut seen = HashSet::new();
+        for room in rooms {
+            let (left, top) = (room.x as i32 - 1, room.y as i32 - 1);
+            let (right, bottom) = ((room.x + room.width) as i32, (room.y + room.height) as i32);
+            let ring = (left + 1..right)
+                .flat_map(|x| vec![(x, top), (x, bottom)])
+                .chain((top + 1..bottom).flat_map(|y| vec![(left, y), (right, y)]));
+            for (x, y) in ring {
+                if self.is_floor(x, y) && !in_room(x, y) && seen.insert((x, y)) {
+                    doors.push((x as u32, y as u32));
+                }
+            }
+        }
+        doors
+    }
+
+    fn caves(&mut self, wall_chance: f32, iterations: u32, rng: &mut SplitMix64) {
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let floor = rng.next_f32() >= wall_chance;
+                self.set_floor(x, y, floor);
+            }
+        }
+        for _ in 0..iterations {
+            let mut next = Grid::new(self.width, self.height);
+            for y in 0..self.height as i32 {
+                for x in 0..self.width as i32 {
+                    let walls = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|&(dx, dy)| (dx, dy) != (0, 0) && !self.is_floor(x + dx, y + dy))
+                        .count();
+                    next.set_floor(x, y, walls < 5 && (walls < 4 || self.is_floor(x, y)));
+                }
+            }
+            self.floor = next.floor;
+        }
+        self.keep_largest_region();
+    }
+
+    /// Fills every floor region but the largest, so caves are always connected.
+    fn keep_largest_region(&mut self) {
+        let mut region_of = vec![usize::MAX; self.floor.len()];
+        let mut sizes = Vec::new();
+        for start in 0..self.floor.len() {
+            if !self.floor[start] || region_of[start] != usize::MAX {
+                continue;
+            }
+            let region = sizes.len();
+            let mut size = 0;
+            let mut queue = VecDeque::new();
+            region_of[start] = region;
+            queue.push_back(start);
+            while let Some(index) = queue.pop_front() {
+                size += 1;
+                let (x, y) = (
+                    (index as u32 % self.width) as i32,
+                    (index as u32 / self.width) as i32,
+                );
+                for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter() {
+                    if self.is_floor(*nx, *ny) {
+                        let neighbor = (*ny as u32 * self.width + *nx as u32) as usize;
+                        if region_of[neighbor] == usize::MAX {
+                            region_of[neighbor] = region;
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        let largest = (0..sizes.len()).max_by_key(|region| sizes[*region]);
+        for (floor, region) in self.floor.iter_mut().zip(region_of) {
+            *floor = *floor && Some(region) == largest;
+        }
+    }
+
+    fn first_floor(&self) -> Option<(u32, u32)> {
+        let index = self.floor.iter().position(|floor| *floor)? as u32;
+        Some((index % self.width, index / self.width))
+    }
+
+    /// Returns where the walk started, or `None` when the map has no room to walk in.
+    fn walk(&mut self, floor_ratio: f32, rng: &mut SplitMix64) -> Option<(u32, u32)> {
+        let start = (self.width as i32 / 2, self.height as i32 / 2);
+        if !self.inner(start.0, start.1) {
+            return None;
+        }
+        let inner_tiles = (self.width - 2) * (self.height - 2);
+        let target = ((inner_tiles as f32 * floor_ratio.max(0.0).min(1.0)) as u32).max(1);
+        let (mut x, mut y) = start;
+        let mut carved = 0;
+        // Bounded, in case the ratio can't be reached.
+        for _ in 0..inner_tiles.saturating_mul(64) {
+            if !self.is_floor(x, y) {
+                self.set_floor(x, y, true);
+                carved += 1;
+                if carved >= target {
+                    break;
+                }
+            }
+            let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][(rng.next_u64() % 4) as usize];
+            if self.inner(x + dx, y + dy) {
+                x += dx;
+                y += dy;
+            }
+        }
+        Some((start.0 as u32, start.1 as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLOOR: u32 = 1;
+    const WALL: u32 = 2;
+
+    const ALGORITHMS: &[DungeonAlgorithm] = &[
+        DungeonAlgorithm::Bsp {
+            min_room_size: 4,
+            max_depth: 4,
+        },
+        DungeonAlgorithm::CellularCaves {
+            wall_chance: 0.45,
+            iterations: 4,
+        },
+        DungeonAlgorithm::DrunkardsWalk { floor_ratio: 0.4 },
+    ];
+
+    fn generate(algorithm: DungeonAlgorithm, seed: u64) -> tiled::Map {
+        let map = Map::try_from_bytes(
+            include_bytes!("../../assets/ortho-map.tmx"),
+            Default::default(),
+        )
+        .unwrap();
+        DungeonGenerator {
+            width: 48,
+            height: 32,
+            seed,
+            algorithm,
+            floor_gid: FLOOR,
+            wall_gid: WALL,
+            tileset: map.map.tilesets[0].clone(),
+        }
+        .generate()
+    }
+
+    fn rows(map: &tiled::Map) -> Vec<Vec<u32>> {
+        match &map.layers[0].tiles {
+            tiled::LayerData::Finite(rows) => rows
+                .iter()
+                .map(|row| row.iter().map(|tile| tile.gid).collect())
+                .collect(),
+            tiled::LayerData::Infinite(_) => unreachable!(),
+        }
+    }
+
+    fn markers(map: &tiled::Map) -> Vec<(String, f32, f32)> {
+        map.object_groups[0]
+            .objects
+            .iter()
+            .map(|object| (object.name.clone(), object.x, object.y))
+            .collect()
+    }
+
+    #[test]
+    fn every_floor_tile_is_reachable() {
+        for algorithm in ALGORITHMS {
+            let rows = rows(&generate(*algorithm, 3));
+            let floor = |x: i32, y: i32| {
+                y >= 0
+                    && x >= 0
+                    && rows.get(y as usize).and_then(|row| row.get(x as usize)) == Some(&FLOOR)
+            };
+            let floors = (0..rows.len() as i32)
+                .flat_map(|y| (0..rows[0].len() as i32).map(move |x| (x, y)))
+                .filter(|(x, y)| floor(*x, *y))
+                .collect::<HashSet<_>>();
+            let start = *floors.iter().next().expect("no floor");
+            let mut reached = HashSet::new();
+            let mut queue = VecDeque::new();
+            reached.insert(start);
+            queue.push_back(start);
+            while let Some((x, y)) = queue.pop_front() {
+                for next in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter() {
+                    if floor(next.0, next.1) && reached.insert(*next) {
+                        queue.push_back(*next);
+                    }
+                }
+            }
+            assert_eq!(reached, floors, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_dungeon() {
+        for algorithm in ALGORITHMS {
+            let (a, b) = (generate(*algorithm, 3), generate(*algorithm, 3));
+            assert_eq!(rows(&a), rows(&b), "{:?}", algorithm);
+            assert_eq!(markers(&a), markers(&b), "{:?}", algorithm);
+            assert_ne!(rows(&a), rows(&generate(*algorithm, 4)), "{:?}", algorithm);
+        }
+    }
+}
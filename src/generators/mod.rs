@@ -0,0 +1,89 @@
+//! Procedural companions to the TMX loader, producing the same `Map` asset.
+
+mod biomes;
+pub use biomes::*;
+mod dungeon;
+pub use dungeon::*;
+mod noise;
+pub use noise::*;
+mod terrain;
+pub use terrain::*;
+
+/// An orthogonal `tiled::Map` with one tileset and no layers, sized in tiles.
+pub(crate) fn empty_tiled_map(width: u32, height: u32, tileset: &tiled::Tileset) -> tiled::Map {
+    tiled::Map {
+        version: "1.0".to_string(),
+        orientation: tiled::Orientation::Orthogonal,
+        width,
+        height,
+        tile_width: tileset.tile_width,
+        tile_height: tileset.tile_height,
+        tilesets: vec![tileset.clone()],
+        layers: Vec::new(),
+        image_layers: Vec::new(),
+        object_groups: Vec::new(),
+        properties: Default::default(),
+        background_colour: None,
+    }
+}
+
+/// Appends a finite tile layer built from rows of gids.
+pub(crate) fn push_tile_layer(map: &mut tiled::Map, name: &str, rows: Vec<Vec<u32>>) {
+    let layer_index = (map.layers.len() + map.object_groups.len()) as u32;
+    map.layers.push(tiled::Layer {
+        name: name.to_string(),
+        opacity: 1.0,
+        visible: true,
+        tiles: tiled::LayerData::Finite(
+            rows.into_iter()
+                .map(|row| row.into_iter().map(tiled::LayerTile::new).collect())
+                .collect(),
+        ),
+        properties: Default::default(),
+        layer_index,
+    });
+}
+
+/// Appends an object group of point objects placed at the centers of tiles, given as
+/// `(name, x, y)`. The name doubles as the object's type.
+pub(crate) fn push_marker_group(map: &mut tiled::Map, name: &str, markers: &[(&str, u32, u32)]) {
+    let layer_index = (map.layers.len() + map.object_groups.len()) as u32;
+    let (tile_width, tile_height) = (map.tile_width as f32, map.tile_height as f32);
+    let first_id = map
+        .object_groups
+        .iter()
+        .flat_map(|group| group.objects.iter())
+        .map(|object| object.id + 1)
+        .max()
+        .unwrap_or(1);
+    let objects = markers
+        .iter()
+        .enumerate()
+        .map(|(i, (name, x, y))| {
+            let x = (*x as f32 + 0.5) * tile_width;
+            let y = (*y as f32 + 0.5) * tile_height;
+            tiled::Object {
+                id: first_id + i as u32,
+                gid: 0,
+                name: name.to_string(),
+                obj_type: name.to_string(),
+                width: 0.0,
+                height: 0.0,
+                x,
+                y,
+                rotation: 0.0,
+                visible: true,
+                shape: tiled::ObjectShape::Point(x, y),
+                properties: Default::default(),
+            }
+        })
+        .collect();
+    map.object_groups.push(tiled::ObjectGroup {
+        name: name.to_string(),
+        opacity: 1.0,
+        visible: true,
+        objects,
+        colour: None,
+        layer_index,
+    });
+}
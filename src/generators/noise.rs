@@ -0,0 +1,80 @@
+use crate::rng::SplitMix64;
+
+/// Seeded 2d Perlin noise.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    permutation: Vec<u8>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SplitMix64::new(seed);
+        let mut permutation = (0..=255).map(|i| i as u8).collect::<Vec<_>>();
+        for i in (1..permutation.len()).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            permutation.swap(i, j);
+        }
+        let repeated = permutation.clone();
+        permutation.extend(repeated);
+        Perlin { permutation }
+    }
+
+    /// Noise in roughly `[-1, 1]`.
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (xf, yf) = (x - x0, y - y0);
+        let (xi, yi) = ((x0 as i32 & 255) as usize, (y0 as i32 & 255) as usize);
+        let p = &self.permutation;
+        let hash = |x: usize, y: usize| p[p[x] as usize + y];
+
+        let (u, v) = (fade(xf), fade(yf));
+        let top = lerp(
+            gradient(hash(xi, yi), xf, yf),
+            gradient(hash(xi + 1, yi), xf - 1.0, yf),
+            u,
+        );
+        let bottom = lerp(
+            gradient(hash(xi, yi + 1), xf, yf - 1.0),
+            gradient(hash(xi + 1, yi + 1), xf - 1.0, yf - 1.0),
+            u,
+        );
+        lerp(top, bottom, v)
+    }
+
+    /// Fractal noise summing `octaves` layers, each twice the frequency and `persistence`
+    /// times the amplitude of the last, normalized to `[0, 1]`.
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, persistence: f32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+        for _ in 0..octaves.max(1) {
+            total += self.noise(x * frequency, y * frequency) * amplitude;
+            max += amplitude;
+            amplitude *= persistence;
+            frequency *= 2.0;
+        }
+        ((total / max + 1.0) / 2.0).max(0.0).min(1.0)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
@@ -0,0 +1,195 @@
+use super::{empty_tiled_map, push_tile_layer};
+use crate::{rng::SplitMix64, Map, MapError, Perlin, WangSet};
+use std::path::Path;
+
+/// A terrain drawn where the height is at least `min_height`.
+#[derive(Debug, Clone)]
+pub struct TerrainBand {
+    /// Name of the band's color in the wang set.
+    pub color: String,
+    pub min_height: f32,
+}
+
+/// Generates terrain from fractal noise, one layer per band with wang set transitions.
+///
+/// Heights are sampled at tile corners. The first band fills the bottom layer; each following
+/// band gets its own layer whose tiles are picked by corner from `wang_set`, with corners below
+/// the band using the previous band's color. This expects the usual layered autotile art where
+/// the lower color of a transition tile is transparent, so lower layers show through.
+#[derive(Debug, Clone)]
+pub struct TerrainGenerator {
+    pub width: u32,
+    pub height: u32,
+    /// The same seed always generates the same map; take it from a `MapRng` stream.
+    pub seed: u64,
+    /// Size of a noise feature, in tiles.
+    pub scale: f32,
+    pub octaves: u32,
+    pub persistence: f32,
+    /// Bands in ascending `min_height` order, e.g. water, sand, grass, rock.
+    pub bands: Vec<TerrainBand>,
+    pub tileset: tiled::Tileset,
+    pub wang_set: WangSet,
+}
+
+impl TerrainGenerator {
+    /// Generates the terrain as Tiled data.
+    pub fn generate(&self) -> Result<tiled::Map, MapError> {
+        let colors = self
+            .bands
+            .iter()
+            .map(|band| {
+                self.wang_set.color(&band.color).ok_or_else(|| {
+                    MapError::Generate(format!("wang set has no color {:?}", band.color))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if colors.is_empty() {
+            return Err(MapError::Generate(
+                "terrain needs at least one band".to_string(),
+            ));
+        }
+
+        let perlin = Perlin::new(self.seed);
+        let scale = self.scale.max(std::f32::EPSILON);
+        // Perlin noise is 0 on its integer lattice, which corners land on at every octave when
+        // `scale` is a power of two, so they're sampled half a tile in and then at an offset
+        // picked by the seed.
+        let mut rng = SplitMix64::new(self.seed);
+        let offset = (0.5 + rng.next_f32(), 0.5 + rng.next_f32());
+        // The band index of every tile corner.
+        let corner_bands = (0..=self.height)
+            .map(|y| {
+                (0..=self.width)
+                    .map(|x| {
+                        let height = perlin.fbm(
+                            (x as f32 + offset.0) / scale,
+                            (y as f32 + offset.1) / scale,
+                            self.octaves,
+                            self.persistence,
+                        );
+                        self.bands
+                            .iter()
+                            .rposition(|band| band.min_height <= height)
+                            .unwrap_or(0)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut map = empty_tiled_map(self.width, self.height, &self.tileset);
+        for (band, band_color) in colors.iter().enumerate() {
+            let lower_color = if band == 0 { 0 } else { colors[band - 1] };
+            let mut rows = Vec::new();
+            for y in 0..self.height as usize {
+                let mut row = Vec::new();
+                for x in 0..self.width as usize {
+                    let corners = [
+                        corner_bands[y][x],
+                        corner_bands[y][x + 1],
+                        corner_bands[y + 1][x + 1],
+                        corner_bands[y + 1][x],
+                    ];
+                    let covered = corners.iter().filter(|corner| **corner >= band).count();
+                    let gid = if band == 0 {
+                        self.pick(&[*band_color; 4], x, y, band)
+                    } else if covered == 0 {
+                        0
+                    } else {
+                        let mut wang_corners = [lower_color; 4];
+                        for (i, corner) in corners.iter().enumerate() {
+                            if *corner >= band {
+                                wang_corners[i] = *band_color;
+                            }
+                        }
+                        let gid = self.pick(&wang_corners, x, y, band);
+                        // Without a transition tile, round to whichever color covers more.
+                        if gid == 0 && covered >= 2 {
+                            self.pick(&[*band_color; 4], x, y, band)
+                        } else {
+                            gid
+                        }
+                    };
+                    row.push(gid);
+                }
+                rows.push(row);
+            }
+            push_tile_layer(&mut map, &self.bands[band].color, rows);
+        }
+        Ok(map)
+    }
+
+    /// Generates the terrain as a map asset; tileset images are relative to `map_folder`.
+    pub fn generate_map(&self, map_folder: impl AsRef<Path>) -> Result<Map, MapError> {
+        let mut map = Map::new(self.generate()?, map_folder);
+        map.wang_sets.push(self.wang_set.clone());
+        map.build()?;
+        Ok(map)
+    }
+
+    /// A tile with the given corners, choosing between variations by position, or 0.
+    fn pick(&self, corners: &[u8; 4], x: usize, y: usize, band: usize) -> u32 {
+        let candidates = self.wang_set.corner_tiles(*corners).collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return 0;
+        }
+        let mut rng = SplitMix64::for_tile(self.seed ^ band as u64, x as u32, y as u32);
+        candidates[(rng.next_u64() % candidates.len() as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WangTile;
+
+    fn generator(scale: f32) -> TerrainGenerator {
+        let map = Map::try_from_bytes(
+            include_bytes!("../../assets/ortho-map.tmx"),
+            Default::default(),
+        )
+        .unwrap();
+        let tileset = map.map.tilesets[0].clone();
+        let solid = |tile_id, color| WangTile {
+            tile_id,
+            wang_id: [color; 8],
+        };
+        TerrainGenerator {
+            width: 16,
+            height: 16,
+            seed: 7,
+            scale,
+            octaves: 3,
+            persistence: 0.5,
+            bands: vec![
+                TerrainBand {
+                    color: "water".to_string(),
+                    min_height: 0.0,
+                },
+                TerrainBand {
+                    color: "grass".to_string(),
+                    min_height: 0.5,
+                },
+            ],
+            wang_set: WangSet {
+                name: "terrain".to_string(),
+                first_gid: tileset.first_gid,
+                colors: vec!["water".to_string(), "grass".to_string()],
+                tiles: vec![solid(171, 1), solid(150, 2)],
+            },
+            tileset,
+        }
+    }
+
+    #[test]
+    fn unit_scale_terrain_has_more_than_one_band() {
+        let map = generator(1.0).generate().unwrap();
+        let grass = match &map.layers[1].tiles {
+            tiled::LayerData::Finite(rows) => rows.iter().flatten().map(|tile| tile.gid),
+            tiled::LayerData::Infinite(_) => unreachable!(),
+        }
+        .collect::<Vec<_>>();
+        assert!(grass.iter().any(|gid| *gid != 0));
+        assert!(grass.iter().any(|gid| *gid == 0));
+    }
+}
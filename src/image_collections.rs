@@ -0,0 +1,156 @@
+use crate::{Map, TilesetSource};
+use bevy::{asset::LoadState, prelude::*, render::texture::TextureFormat};
+use std::collections::HashMap;
+
+/// Whether a tileset is a collection of images, one per tile, rather than a single image, and
+/// can be drawn: every tile has an image and they're all the same size.
+pub(crate) fn is_image_collection(tileset: &tiled::Tileset) -> bool {
+    let mut sizes = tileset
+        .tiles
+        .iter()
+        .map(|tile| tile.images.first().map(|image| (image.width, image.height)));
+    let first = match sizes.next() {
+        Some(Some(size)) => size,
+        _ => return false,
+    };
+    tileset.images.is_empty() && sizes.all(|size| size == Some(first))
+}
+
+/// UVs of tile `tile` of an image collection tileset, in the texture `ImageCollections`
+/// stacks its images into, a layer per tile in the order they're listed.
+pub(crate) fn collection_tile_uv(tileset: &tiled::Tileset, tile: u32) -> Vec4 {
+    let layers = tileset.tiles.len() as f32;
+    match tileset
+        .tiles
+        .iter()
+        .position(|candidate| candidate.id == tile)
+    {
+        Some(layer) => Vec4::new(0.0, layer as f32 / layers, 1.0, (layer + 1) as f32 / layers),
+        None => Vec4::zero(),
+    }
+}
+
+impl Map {
+    /// Paths of the images of an image collection tileset, a layer per tile in the order
+    /// they're listed, or `None` if it isn't one.
+    pub fn collection_image_paths(&self, tileset: &tiled::Tileset) -> Option<Vec<String>> {
+        if !is_image_collection(tileset) {
+            return None;
+        }
+        let declared = self
+            .tileset_sources
+            .get(&tileset.first_gid)
+            .unwrap_or(&TilesetSource::Embedded);
+        tileset
+            .tiles
+            .iter()
+            .map(|tile| {
+                let image = tile.images.first()?;
+                let path =
+                    self.resolve_image(declared.resolve_image(&self.map_folder, &image.source));
+                path.to_str().map(String::from)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct PendingCollection {
+    layers: Vec<Option<Handle<Texture>>>,
+    layer_size: (u32, u32),
+}
+
+/// Image collection tilesets waiting for their images to load, to be stacked into the
+/// texture they're drawn from.
+///
+/// Bevy 0.2 has no array textures, so the layers of the array are stacked top to bottom in
+/// one texture instead, and tiles pick theirs through their UVs.
+#[derive(Debug, Default)]
+pub struct ImageCollections {
+    pending: HashMap<Handle<Texture>, PendingCollection>,
+}
+
+impl ImageCollections {
+    /// Requests the images at `paths` and returns the texture they'll be stacked into once
+    /// they've loaded, transparent until then. Images that can't be requested, or don't load
+    /// as `layer_size` 8 bit RGBA images, leave their layer transparent.
+    pub fn load(
+        &mut self,
+        asset_server: &AssetServer,
+        textures: &mut Assets<Texture>,
+        paths: &[String],
+        layer_size: (u32, u32),
+    ) -> Handle<Texture> {
+        let (width, height) = (layer_size.0.max(1), layer_size.1.max(1));
+        let layers = paths.len().max(1) as u32;
+        let texture = textures.add(Texture::new(
+            Vec2::new(width as f32, (height * layers) as f32),
+            vec![0; (width * height * layers * 4) as usize],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        let layers = paths
+            .iter()
+            .map(|path| asset_server.load(path.as_str()).ok())
+            .collect();
+        self.pending.insert(
+            texture,
+            PendingCollection {
+                layers,
+                layer_size: (width, height),
+            },
+        );
+        texture
+    }
+
+    /// Whether `texture` is still waiting for its images.
+    pub(crate) fn is_pending(&self, texture: Handle<Texture>) -> bool {
+        self.pending.contains_key(&texture)
+    }
+}
+
+/// Stacks the images of image collection tilesets into their textures once they've all
+/// loaded or failed to.
+pub fn stack_image_collections(
+    asset_server: Res<AssetServer>,
+    mut collections: ResMut<ImageCollections>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    let mut stacked = Vec::new();
+    for (texture, collection) in collections.pending.iter() {
+        let loading = collection.layers.iter().flatten().any(|layer| {
+            textures.get(layer).is_none()
+                && match asset_server.get_load_state(*layer) {
+                    Some(LoadState::Failed(_)) => false,
+                    _ => true,
+                }
+        });
+        if loading {
+            continue;
+        }
+        let (width, height) = collection.layer_size;
+        let layer_bytes = (width * height * 4) as usize;
+        let mut data = vec![0; layer_bytes * collection.layers.len()];
+        for (index, layer) in collection.layers.iter().enumerate() {
+            let image = match layer.as_ref().and_then(|layer| textures.get(layer)) {
+                Some(image) => image,
+                None => continue,
+            };
+            let fits = image.size == Vec2::new(width as f32, height as f32)
+                && match image.format {
+                    TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => true,
+                    _ => false,
+                };
+            if fits {
+                data[index * layer_bytes..(index + 1) * layer_bytes]
+                    .copy_from_slice(&image.data[..layer_bytes]);
+            }
+        }
+        stacked.push((*texture, data));
+    }
+    for (texture, data) in stacked {
+        collections.pending.remove(&texture);
+        if let Some(target) = textures.get_mut(&texture) {
+            target.data = data;
+        }
+    }
+}
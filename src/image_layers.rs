@@ -0,0 +1,300 @@
+use crate::{
+    animation::animation_attribute, tints::tint_attribute, ChunkComponents, Map, MapAnchor,
+    TileMapChunk, TransparentColorKeys,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{Camera, OrthographicProjection},
+        mesh::VertexAttribute,
+        pipeline::PrimitiveTopology,
+    },
+    sprite::Rect,
+};
+use glam::Vec2;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Most copies of a repeating image drawn along one axis, however far the camera zooms out.
+const MAX_REPEATS: i32 = 256;
+
+/// Which axes an image layer repeats along, from its `repeatx` and `repeaty` attributes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLayerRepeat {
+    pub x: bool,
+    pub y: bool,
+}
+
+/// Copies of an image drawn by an image layer's mesh: columns `min.0..=max.0` and rows
+/// `min.1..=max.1`, where copy (0, 0) is the image at its offset and rows count upwards.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImageCopies {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+/// An entity drawing an image layer of a map entity.
+#[derive(Debug, Clone)]
+pub struct TiledImageLayer {
+    pub map: Handle<Map>,
+    pub map_entity: Entity,
+    /// Index into `map.image_layers`.
+    pub index: usize,
+    pub copies: ImageCopies,
+}
+
+impl Map {
+    /// Path of an image layer's image, relative to the asset folder.
+    pub fn image_layer_path(&self, index: usize) -> Option<PathBuf> {
+        let image = self.map.image_layers.get(index)?.image.as_ref()?;
+        Some(self.resolve_image(self.map_folder.join(&image.source)))
+    }
+
+    /// Map-local rect of an image layer's image at its offset, before any repeating.
+    pub fn image_layer_rect(&self, index: usize) -> Option<Rect> {
+        let layer = self.map.image_layers.get(index)?;
+        let image = layer.image.as_ref()?;
+        // Image layers aren't projected; they're placed in pixels from the top left of the map.
+        let bounds = self.world_bounds();
+        let top_left = Vec2::new(
+            bounds.min.x() + layer.offset_x,
+            bounds.max.y() - layer.offset_y,
+        );
+        Some(Rect {
+            min: top_left - Vec2::new(0.0, image.height as f32),
+            max: top_left + Vec2::new(image.width as f32, 0.0),
+        })
+    }
+
+    /// Copies of a repeating image layer needed to cover `view`, a map-local rect. Axes that
+    /// don't repeat only have copy 0.
+    pub fn image_layer_copies(&self, index: usize, view: &Rect) -> Option<ImageCopies> {
+        let rect = self.image_layer_rect(index)?;
+        let repeat = self
+            .image_layer_repeats
+            .get(index)
+            .copied()
+            .unwrap_or_default();
+        let size = rect.max - rect.min;
+        let range = |repeats: bool, min: f32, max: f32, start: f32, size: f32| {
+            if !repeats || size <= 0.0 {
+                return (0, 0);
+            }
+            let first = ((min - start) / size).floor() as i32;
+            let last = ((max - start) / size).floor() as i32;
+            (first, last.min(first + MAX_REPEATS - 1))
+        };
+        let x = range(repeat.x, view.min.x(), view.max.x(), rect.min.x(), size.x());
+        let y = range(repeat.y, view.min.y(), view.max.y(), rect.min.y(), size.y());
+        Some(ImageCopies {
+            min: (x.0, y.0),
+            max: (x.1, y.1),
+        })
+    }
+
+    /// A quad per copy of an image layer's image.
+    pub(crate) fn image_layer_mesh(&self, index: usize, copies: ImageCopies) -> Option<Mesh> {
+        let rect = self.image_layer_rect(index)?;
+        let size = rect.max - rect.min;
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        for y in copies.min.1..=copies.max.1 {
+            for x in copies.min.0..=copies.max.0 {
+                let offset = Vec2::new(x as f32 * size.x(), y as f32 * size.y());
+                let (min, max) = (rect.min + offset, rect.max + offset);
+                let first = positions.len() as u32;
+                positions.extend_from_slice(&[
+                    [min.x(), min.y(), 0.0],
+                    [min.x(), max.y(), 0.0],
+                    [max.x(), max.y(), 0.0],
+                    [max.x(), min.y(), 0.0],
+                ]);
+                uvs.extend_from_slice(&[[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+                indices.extend_from_slice(&[
+                    first,
+                    first + 2,
+                    first + 1,
+                    first,
+                    first + 3,
+                    first + 2,
+                ]);
+            }
+        }
+        Some(Mesh {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(positions.clone()),
+                VertexAttribute::normal(vec![[0.0, 0.0, 1.0]; positions.len()]),
+                VertexAttribute::uv(uvs),
+                animation_attribute(vec![0.0; positions.len()]),
+                tint_attribute(vec![[1.0; 4]; positions.len()]),
+            ],
+            indices: Some(indices),
+        })
+    }
+
+    /// The `layer_id` an image layer is drawn at: between the tile layers around it in Tiled's
+    /// layer order, and just in front of a background quad, unless its depth is overridden.
+    pub fn image_layer_depth(&self, index: usize) -> f32 {
+        let image_layer = match self.map.image_layers.get(index) {
+            Some(layer) => layer,
+            None => return self.layer_depths.base,
+        };
+        if let Some(z) = self.depth_override(&image_layer.name, &image_layer.properties) {
+            return z;
+        }
+        let layer_index = image_layer.layer_index;
+        let below = self
+            .layers
+            .iter()
+            .filter(|layer| self.map.layers[layer.layer_index].layer_index < layer_index)
+            .count();
+        self.stack_depth(below as f32 - 0.005)
+    }
+}
+
+/// Map-local rect of `map` seen by a 2d camera, for the map placed at `translation`.
+fn camera_view(
+    map: &Map,
+    camera_transform: &GlobalTransform,
+    projection: &OrthographicProjection,
+    translation: Vec3,
+) -> Rect {
+    let corners = [
+        Vec3::new(projection.left, projection.bottom, 0.0),
+        Vec3::new(projection.right, projection.top, 0.0),
+    ];
+    let mut view = Rect {
+        min: Vec2::new(std::f32::MAX, std::f32::MAX),
+        max: Vec2::new(std::f32::MIN, std::f32::MIN),
+    };
+    for corner in corners.iter() {
+        let world = camera_transform.value().transform_point3(*corner);
+        let local = map.world_to_local(translation, world.truncate());
+        view.min = view.min.min(local);
+        view.max = view.max.max(local);
+    }
+    view
+}
+
+/// Spawns the image layers of map entities, replacing them when their map is reloaded, and
+/// keeps repeating ones covering the camera view.
+pub fn sync_image_layers(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut color_keys: ResMut<TransparentColorKeys>,
+    mut spawned: Local<HashMap<Entity, u32>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    mut map_query: Query<(Entity, &Handle<Map>, &MapAnchor, &Transform)>,
+    mut layer_query: Query<(
+        Entity,
+        &mut TiledImageLayer,
+        &Handle<Mesh>,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let mut cameras = camera_query.iter();
+    let camera = cameras
+        .iter()
+        .next()
+        .map(|(_, transform, projection)| (*transform, projection.clone()));
+
+    for (map_entity, map_handle, anchor, origin) in &mut map_query.iter() {
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let translation = map.translation(anchor, origin.translation());
+        let view = camera
+            .as_ref()
+            .map(|(transform, projection)| camera_view(map, transform, projection, translation));
+
+        if spawned.insert(map_entity, map.generation) != Some(map.generation) {
+            // Replace the layers of a reloaded map, overwriting their meshes in place.
+            let mut spawned_meshes = HashMap::new();
+            for (entity, layer, mesh, material) in &mut layer_query.iter() {
+                if layer.map_entity == map_entity {
+                    commands.despawn(entity);
+                    spawned_meshes.insert(layer.index, *mesh);
+                    materials.remove(material);
+                }
+            }
+            for (index, layer) in map.map.image_layers.iter().enumerate() {
+                let image = match &layer.image {
+                    Some(image) if layer.visible => image,
+                    _ => continue,
+                };
+                let copies = view
+                    .as_ref()
+                    .and_then(|view| map.image_layer_copies(index, view))
+                    .unwrap_or_default();
+                let mesh = match map.image_layer_mesh(index, copies) {
+                    Some(mesh) => mesh,
+                    None => continue,
+                };
+                let texture_handle = match map
+                    .image_layer_path(index)
+                    .map(|texture_path| asset_server.load(texture_path))
+                {
+                    Some(Ok(texture_handle)) => texture_handle,
+                    _ => continue,
+                };
+                if let Some(color) = &image.transparent_colour {
+                    color_keys.insert(texture_handle, color);
+                }
+                let mesh = match spawned_meshes.remove(&index) {
+                    Some(handle) => {
+                        meshes.set(handle, mesh);
+                        handle
+                    }
+                    None => meshes.add(mesh),
+                };
+                commands
+                    .spawn(ChunkComponents {
+                        chunk: TileMapChunk {
+                            layer_id: map.image_layer_depth(index),
+                            ..Default::default()
+                        },
+                        material: materials.add(texture_handle.into()),
+                        mesh,
+                        transform: Transform::from_translation(translation),
+                        ..Default::default()
+                    })
+                    .with(TiledImageLayer {
+                        map: *map_handle,
+                        map_entity,
+                        index,
+                        copies,
+                    });
+            }
+            // Whatever is left belongs to layers the reloaded map no longer draws.
+            for (_, mesh) in spawned_meshes {
+                meshes.remove(&mesh);
+            }
+            continue;
+        }
+
+        let view = match view {
+            Some(view) => view,
+            None => continue,
+        };
+        for (_, mut layer, mesh_handle, _) in &mut layer_query.iter() {
+            if layer.map_entity != map_entity {
+                continue;
+            }
+            let copies = match map.image_layer_copies(layer.index, &view) {
+                Some(copies) => copies,
+                None => continue,
+            };
+            if copies != layer.copies {
+                if let Some(mesh) = map.image_layer_mesh(layer.index, copies) {
+                    meshes.set(*mesh_handle, mesh);
+                    layer.copies = copies;
+                }
+            }
+        }
+    }
+}
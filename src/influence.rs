@@ -0,0 +1,259 @@
+use crate::{Map, MapAnchor, NavGrid};
+use bevy::prelude::*;
+use glam::Vec2;
+use std::collections::{HashMap, HashSet};
+
+/// Influence below this is dropped to 0 and stops being updated.
+const MIN_INFLUENCE: f32 = 0.001;
+
+/// A value per tile of a map that `InfluenceSource`s push into and that spreads out and fades
+/// over time, such as threat or territory for AI.
+///
+/// Spawn it on an entity of its own, one per kind of influence. It's sized to the map once the
+/// map has loaded, and only the tiles with influence and their neighbours are updated, so a
+/// mostly empty influence map costs little however large the map.
+#[derive(Debug, Clone)]
+pub struct InfluenceMap {
+    /// The map entity whose tiles the influence covers.
+    pub map: Entity,
+    /// How fast influence fades, as an exponential rate per second, so 0.5 loses about 40% a
+    /// second.
+    pub decay: f32,
+    /// How fast influence evens out with neighbouring tiles, per second.
+    pub spread: f32,
+    /// Tiles that influence can't spread into, such as from `Map::nav_grid`.
+    pub grid: Option<NavGrid>,
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+    /// Indices of tiles with influence.
+    active: HashSet<usize>,
+}
+
+impl InfluenceMap {
+    pub fn new(map: Entity) -> Self {
+        Self {
+            map,
+            decay: 0.5,
+            spread: 2.0,
+            grid: None,
+            width: 0,
+            height: 0,
+            values: Vec::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Clears the influence and sizes it to `width` by `height` tiles.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.values = vec![0.0; (width * height) as usize];
+        self.active.clear();
+    }
+
+    pub fn clear(&mut self) {
+        for index in self.active.drain() {
+            self.values[index] = 0.0;
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some((y as u32 * self.width + x as u32) as usize)
+    }
+
+    /// The influence at the tile `x`, `y`, or 0 off the map.
+    pub fn get(&self, x: i32, y: i32) -> f32 {
+        self.index(x, y).map_or(0.0, |index| self.values[index])
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, value: f32) {
+        if let Some(index) = self.index(x, y) {
+            self.values[index] = value;
+            if value.abs() < MIN_INFLUENCE {
+                self.values[index] = 0.0;
+                self.active.remove(&index);
+            } else {
+                self.active.insert(index);
+            }
+        }
+    }
+
+    /// Number of tiles with influence.
+    pub fn active_tiles(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Which way influence rises at the tile `x`, `y`, in tile coordinates with y down, for
+    /// steering towards it. Negate it to steer away.
+    pub fn gradient(&self, x: i32, y: i32) -> Vec2 {
+        Vec2::new(
+            self.get(x + 1, y) - self.get(x - 1, y),
+            self.get(x, y + 1) - self.get(x, y - 1),
+        ) / 2.0
+    }
+
+    fn can_spread_to(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some()
+            && self
+                .grid
+                .as_ref()
+                .map_or(true, |grid| grid.is_walkable(x, y))
+    }
+
+    /// Spreads and fades the influence over `seconds`.
+    pub fn step(&mut self, seconds: f32) {
+        let keep = (-self.decay * seconds).exp();
+        let spread = (self.spread * seconds).min(1.0);
+
+        let mut tiles = HashSet::new();
+        for index in self.active.iter() {
+            let (x, y) = (
+                (*index as u32 % self.width) as i32,
+                (*index as u32 / self.width) as i32,
+            );
+            tiles.insert((x, y));
+            for (x, y) in [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].iter() {
+                if self.can_spread_to(*x, *y) {
+                    tiles.insert((*x, *y));
+                }
+            }
+        }
+
+        let updated = tiles
+            .into_iter()
+            .map(|(x, y)| {
+                let neighbours = [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+                    .iter()
+                    .filter(|(x, y)| self.can_spread_to(*x, *y))
+                    .map(|(x, y)| self.get(*x, *y))
+                    .collect::<Vec<_>>();
+                let value = self.get(x, y);
+                let average = if neighbours.is_empty() {
+                    value
+                } else {
+                    neighbours.iter().sum::<f32>() / neighbours.len() as f32
+                };
+                (x, y, (value + (average - value) * spread) * keep)
+            })
+            .collect::<Vec<_>>();
+        for (x, y, value) in updated {
+            self.set(x, y, value);
+        }
+    }
+}
+
+/// Pushes influence into an `InfluenceMap` at the tile under the entity, overriding what's
+/// there. Negative strengths, such as for enemies, work too.
+#[derive(Debug, Clone, Copy)]
+pub struct InfluenceSource {
+    /// The entity with the `InfluenceMap`.
+    pub influence: Entity,
+    pub strength: f32,
+}
+
+/// Applies `InfluenceSource`s to their `InfluenceMap`s, then spreads and fades them.
+pub fn update_influence_maps(
+    time: Res<Time>,
+    maps: Res<Assets<Map>>,
+    mut source_query: Query<(&InfluenceSource, &GlobalTransform)>,
+    mut influence_query: Query<(Entity, &mut InfluenceMap)>,
+    map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    let mut sources = HashMap::<Entity, Vec<(Vec2, f32)>>::new();
+    for (source, transform) in &mut source_query.iter() {
+        sources
+            .entry(source.influence)
+            .or_insert_with(Vec::new)
+            .push((transform.translation().truncate(), source.strength));
+    }
+
+    for (entity, mut influence) in &mut influence_query.iter() {
+        let (map_handle, anchor, origin) = match (
+            map_query.get::<Handle<Map>>(influence.map),
+            map_query.get::<MapAnchor>(influence.map),
+            map_query.get::<Transform>(influence.map),
+        ) {
+            (Ok(map_handle), Ok(anchor), Ok(origin)) => (map_handle, anchor, origin),
+            _ => continue,
+        };
+        let map = match maps.get(&map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        if influence.width() != map.map.width || influence.height() != map.map.height {
+            influence.resize(map.map.width, map.map.height);
+        }
+
+        let translation = map.translation(&anchor, origin.translation());
+        for (position, strength) in sources.get(&entity).into_iter().flatten() {
+            let tile = map.local_to_tile(map.world_to_local(translation, *position));
+            influence.set(tile.x().floor() as i32, tile.y().floor() as i32, *strength);
+        }
+        influence.step(time.delta_seconds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn influence_map(grid: Option<NavGrid>) -> InfluenceMap {
+        let mut influence = InfluenceMap::new(World::new().spawn((0u8,)));
+        influence.decay = 0.0;
+        influence.grid = grid;
+        influence.resize(9, 9);
+        influence
+    }
+
+    #[test]
+    fn influence_falls_off_away_from_a_source() {
+        let mut influence = influence_map(None);
+        influence.set(4, 4, 1.0);
+        for _ in 0..3 {
+            influence.step(0.25);
+        }
+        let along = (4..=7).map(|x| influence.get(x, 4)).collect::<Vec<_>>();
+        assert!(along[0] > along[1] && along[1] > along[2] && along[2] > 0.0);
+        // Three steps spread three tiles at most.
+        assert_eq!(along[3], 0.0);
+        assert!((influence.get(5, 4) - influence.get(3, 4)).abs() < 1e-6);
+        assert!(influence.gradient(5, 4).x() < 0.0);
+
+        influence.spread = 0.0;
+        influence.decay = 1.0;
+        let before = influence.get(4, 4);
+        influence.step(1.0);
+        assert!((influence.get(4, 4) - before * (-1.0f32).exp()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn influence_does_not_spread_into_blocked_tiles() {
+        let mut grid = NavGrid::new(9, 9);
+        for y in 0..9 {
+            grid.set_walkable(5, y, false);
+        }
+        let mut influence = influence_map(Some(grid));
+        influence.set(4, 4, 1.0);
+        for _ in 0..20 {
+            influence.step(0.25);
+        }
+        assert!(influence.get(3, 4) > 0.0);
+        for y in 0..9 {
+            for x in 5..9 {
+                assert_eq!(influence.get(x, y), 0.0, "{}, {}", x, y);
+            }
+        }
+    }
+}
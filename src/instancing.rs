@@ -0,0 +1,230 @@
+use crate::{
+    loader::TileQuad, map::Chunk, Map, TiledChunk, TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+    TILE_MAP_INSTANCED_ADDITIVE_PIPELINE_HANDLE, TILE_MAP_INSTANCED_MULTIPLY_PIPELINE_HANDLE,
+    TILE_MAP_INSTANCED_PIPELINE_HANDLE, TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+    TILE_MAP_PIPELINE_HANDLE,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        draw::RenderCommand,
+        mesh::{VertexAttribute, VertexAttributeValues},
+        pipeline::{PipelineDescriptor, PrimitiveTopology},
+    },
+};
+
+/// Per-instance attributes of `tile_map_instanced.vert`. The `I_` prefix makes Bevy step
+/// through them once per instance rather than once per vertex.
+const TILE_RECT: &str = "I_Vertex_Rect";
+const TILE_UV: &str = "I_Vertex_Uv";
+const TILE_COLOR: &str = "I_Vertex_Color";
+const TILE_DEPTH: &str = "I_Vertex_Depth";
+const TILE_ANIMATION: &str = "I_Vertex_Animation";
+
+impl Map {
+    /// Draws each chunk as GPU instances of one quad, a tile each, with the tile's position,
+    /// uv rect, tint, depth and animation as per-instance data, rather than four vertices and
+    /// six indices per tile. Chunk meshes then take 56 bytes a tile, and an edit only rewrites
+    /// its chunk's instances.
+    ///
+    /// Merged and data texture maps aren't instanced. Like `set_chunk_size`, this is meant for
+    /// maps that aren't in `Assets<Map>` yet; loaded maps get `MapSettings::instanced`.
+    pub fn set_instanced(&mut self, instanced: bool) {
+        let instanced = instanced && !self.merged && !self.data_texture;
+        if instanced == self.instanced || self.is_prebuilt() {
+            return;
+        }
+        self.instanced = instanced;
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.build_layers();
+    }
+
+    /// The instance mesh of `chunk`, a chunk of `map.layers[layer_index]` drawn with the
+    /// tileset starting at `tileset_guid`, or `None` if it draws nothing.
+    pub(crate) fn instance_mesh(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+    ) -> Option<Mesh> {
+        let quads = self.chunk_quads(layer_index, tileset_guid, chunk, None)?;
+        Some(instance_mesh(&quads))
+    }
+}
+
+/// A mesh of one instance per quad, drawn with `TILE_MAP_INSTANCED_PIPELINE_HANDLE`. Its
+/// indices draw the two triangles of an instance, whose corners `tile_map_instanced.vert`
+/// places from the vertex index.
+pub(crate) fn instance_mesh(quads: &[TileQuad]) -> Mesh {
+    let attribute = |name: &'static str, values| VertexAttribute {
+        name: name.into(),
+        values,
+    };
+    Mesh {
+        primitive_topology: PrimitiveTopology::TriangleList,
+        attributes: vec![
+            attribute(
+                TILE_RECT,
+                VertexAttributeValues::Float4(quads.iter().map(|quad| quad.rect).collect()),
+            ),
+            attribute(
+                TILE_UV,
+                VertexAttributeValues::Float4(quads.iter().map(|quad| quad.uv).collect()),
+            ),
+            attribute(
+                TILE_COLOR,
+                VertexAttributeValues::Float4(quads.iter().map(|quad| quad.color).collect()),
+            ),
+            attribute(
+                TILE_DEPTH,
+                VertexAttributeValues::Float(quads.iter().map(|quad| quad.depth).collect()),
+            ),
+            attribute(
+                TILE_ANIMATION,
+                VertexAttributeValues::Float(quads.iter().map(|quad| quad.animation).collect()),
+            ),
+        ],
+        indices: Some(vec![0, 2, 1, 0, 3, 2]),
+    }
+}
+
+/// The instance rects of an instance mesh, as left, bottom, right and top edges, or `None`
+/// for other meshes.
+pub(crate) fn instance_rects(mesh: &Mesh) -> Option<&[[f32; 4]]> {
+    let rects = mesh
+        .attributes
+        .iter()
+        .find(|attribute| attribute.name == TILE_RECT)?;
+    match &rects.values {
+        VertexAttributeValues::Float4(rects) => Some(rects),
+        _ => None,
+    }
+}
+
+/// The instanced variant of a built-in tile pipeline, or the other way around when
+/// `instanced` is false. Other pipelines are left alone.
+pub fn tile_pipeline_variant(
+    pipeline: Handle<PipelineDescriptor>,
+    instanced: bool,
+) -> Handle<PipelineDescriptor> {
+    let pairs = [
+        (TILE_MAP_PIPELINE_HANDLE, TILE_MAP_INSTANCED_PIPELINE_HANDLE),
+        (
+            TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+            TILE_MAP_INSTANCED_ADDITIVE_PIPELINE_HANDLE,
+        ),
+        (
+            TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+            TILE_MAP_INSTANCED_MULTIPLY_PIPELINE_HANDLE,
+        ),
+    ];
+    pairs
+        .iter()
+        .find(|(meshed, instanced)| pipeline == *meshed || pipeline == *instanced)
+        .map_or(pipeline, |(meshed, instanced_pipeline)| {
+            if instanced {
+                *instanced_pipeline
+            } else {
+                *meshed
+            }
+        })
+}
+
+/// Draws chunks whose mesh is an instance mesh with the instanced variant of their built-in
+/// pipeline, and other chunks with the regular one. Runs after `apply_layer_pipelines`, which
+/// picks the regular ones.
+pub fn apply_tile_instancing(
+    meshes: Res<Assets<Mesh>>,
+    mut query: Query<(&TiledChunk, &Handle<Mesh>, &mut RenderPipelines)>,
+) {
+    for (_, mesh, mut render_pipelines) in &mut query.iter() {
+        let instanced = match meshes.get(mesh) {
+            Some(mesh) => instance_rects(mesh).is_some(),
+            None => continue,
+        };
+        let current = match render_pipelines.pipelines.first() {
+            Some(render_pipeline) => render_pipeline.pipeline,
+            None => continue,
+        };
+        let pipeline = tile_pipeline_variant(current, instanced);
+        if pipeline != current {
+            render_pipelines.pipelines[0].pipeline = pipeline;
+        }
+    }
+}
+
+/// Draws an instance of the quad for each tile of an instanced chunk. Bevy's
+/// `draw_render_pipelines_system`, which runs before it in the draw stage, draws one.
+pub fn draw_tile_instances(
+    meshes: Res<Assets<Mesh>>,
+    mut query: Query<(&TiledChunk, &Handle<Mesh>, &mut Draw)>,
+) {
+    for (_, mesh, mut draw) in &mut query.iter() {
+        let count = match meshes.get(mesh).and_then(instance_rects) {
+            Some(rects) => rects.len() as u32,
+            None => continue,
+        };
+        for command in draw.render_commands.iter_mut() {
+            if let RenderCommand::DrawIndexed { instances, .. } = command {
+                *instances = 0..count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_match_the_quads_of_the_meshed_chunk() {
+        let mut map = Map::try_from_bytes(
+            include_bytes!("../assets/ortho-map.tmx"),
+            Default::default(),
+        )
+        .unwrap();
+        let layer = &map.layers[0];
+        let tileset_layer = &layer.tileset_layers[0];
+        let chunk = &tileset_layer.chunks[0][0];
+        let quads = map
+            .chunk_quads(layer.layer_index, tileset_layer.tileset_guid, chunk, None)
+            .unwrap();
+        let mesh = map
+            .instance_mesh(layer.layer_index, tileset_layer.tileset_guid, chunk)
+            .unwrap();
+        let rects = instance_rects(&mesh).unwrap();
+        assert_eq!(rects.len(), quads.len());
+        assert!(rects
+            .iter()
+            .zip(quads.iter())
+            .all(|(rect, quad)| *rect == quad.rect));
+
+        let chunks = map.meshes.len();
+        map.set_instanced(true);
+        assert!(map.instanced);
+        assert_eq!(map.meshes.len(), chunks);
+        assert!(map
+            .meshes
+            .iter()
+            .all(|(_, mesh)| instance_rects(mesh).is_some()));
+    }
+
+    #[test]
+    fn pipelines_swap_between_their_instanced_variants() {
+        for meshed in [
+            TILE_MAP_PIPELINE_HANDLE,
+            TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+            TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+        ]
+        .iter()
+        {
+            let instanced = tile_pipeline_variant(*meshed, true);
+            assert_ne!(instanced, *meshed);
+            assert_eq!(tile_pipeline_variant(instanced, true), instanced);
+            assert_eq!(tile_pipeline_variant(instanced, false), *meshed);
+        }
+        let custom = Handle::from_u128(1);
+        assert_eq!(tile_pipeline_variant(custom, true), custom);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    BlendMode, ChunkKey, ChunkPool, Map, TileBlending, TiledChunk, TiledImageLayer, TiledObject,
+    MERGED_LAYER, TILE_MAP_MULTIPLY_PIPELINE_HANDLE, TILE_MAP_PIPELINE_HANDLE,
+};
+use bevy::{prelude::*, render::pipeline::PipelineDescriptor};
+use std::collections::{HashMap, HashSet};
+
+/// Pipelines a map entity's layers are drawn with in place of `TILE_MAP_PIPELINE_HANDLE`, for
+/// effects such as distortion or palette cycling on some layers.
+///
+/// The pipelines get the same meshes, materials and bindings as the built-in one, so their
+/// shaders should take the vertex attributes and uniforms of tile_map.vert and tile_map.frag.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerPipelines {
+    /// Draws every layer without a pipeline of its own.
+    pub map: Option<Handle<PipelineDescriptor>>,
+    /// Pipelines of tile layers, object groups and image layers, by layer name.
+    pub layers: HashMap<String, Handle<PipelineDescriptor>>,
+}
+
+impl LayerPipelines {
+    pub fn map(mut self, pipeline: Handle<PipelineDescriptor>) -> Self {
+        self.map = Some(pipeline);
+        self
+    }
+
+    pub fn layer(mut self, name: impl Into<String>, pipeline: Handle<PipelineDescriptor>) -> Self {
+        self.layers.insert(name.into(), pipeline);
+        self
+    }
+
+    /// The pipeline a layer named `name` is drawn with.
+    pub fn pipeline(&self, name: &str) -> Handle<PipelineDescriptor> {
+        self.layers
+            .get(name)
+            .copied()
+            .or(self.map)
+            .unwrap_or(TILE_MAP_PIPELINE_HANDLE)
+    }
+}
+
+/// The name and properties of the layer a chunk, tile object or image layer entity draws, if it
+/// draws one.
+pub(crate) fn layer_of<'a>(
+    map: &'a Map,
+    chunk: &TiledChunk,
+    object: Option<&TiledObject>,
+    image_layer: Option<&TiledImageLayer>,
+) -> Option<(&'a str, &'a tiled::Properties)> {
+    if let Some(object) = object {
+        let id = map.object_id(object.id)?;
+        let group = map.map.object_groups.get(id.group)?;
+        return Some((&group.name, &group.properties));
+    }
+    if let Some(image_layer) = image_layer {
+        let image_layer = map.map.image_layers.get(image_layer.index)?;
+        return Some((&image_layer.name, &image_layer.properties));
+    }
+    // The background quad isn't a layer.
+    if chunk.key.tileset_guid == 0 {
+        return None;
+    }
+    let layer = map.layers.get(chunk.key.layer_id as usize)?;
+    let layer = map.map.layers.get(layer.layer_index)?;
+    Some((&layer.name, &layer.properties))
+}
+
+/// What a chunk, tile object or image layer entity draws, as far as `layer_of` goes, and how
+/// many times `ChunkPool` has spawned it, since reusing it resets its components.
+type DrawnLayer = (
+    Handle<Map>,
+    Option<Entity>,
+    ChunkKey,
+    Option<u32>,
+    Option<usize>,
+    u32,
+);
+
+/// What the systems applying per-layer settings such as `LayerPipelines` last saw, so they
+/// only look again at entities of maps that changed, of map entities whose settings `S`
+/// changed, or that were just spawned or reused for another chunk.
+pub struct LayerChanges<S> {
+    map_event_reader: EventReader<AssetEvent<Map>>,
+    settings: HashMap<Entity, S>,
+    drawn: HashMap<Entity, DrawnLayer>,
+    seen: HashSet<Entity>,
+    changed_maps: HashSet<Handle<Map>>,
+    changed_settings: HashSet<Entity>,
+}
+
+impl<S> Default for LayerChanges<S> {
+    fn default() -> Self {
+        Self {
+            map_event_reader: Default::default(),
+            settings: HashMap::new(),
+            drawn: HashMap::new(),
+            seen: HashSet::new(),
+            changed_maps: HashSet::new(),
+            changed_settings: HashSet::new(),
+        }
+    }
+}
+
+impl<S: PartialEq> LayerChanges<S> {
+    /// Reads the maps changed since the last frame, and the current settings of each map
+    /// entity. Entities not looked at last frame are forgotten.
+    pub(crate) fn update(
+        &mut self,
+        map_events: &Events<AssetEvent<Map>>,
+        settings: impl IntoIterator<Item = (Entity, S)>,
+    ) {
+        self.changed_maps.clear();
+        for event in self.map_event_reader.iter(map_events) {
+            match event {
+                AssetEvent::Created { handle }
+                | AssetEvent::Modified { handle }
+                | AssetEvent::Removed { handle } => {
+                    self.changed_maps.insert(*handle);
+                }
+            }
+        }
+        self.changed_settings.clear();
+        let mut current = HashMap::new();
+        for (entity, settings) in settings {
+            if self.settings.get(&entity) != Some(&settings) {
+                self.changed_settings.insert(entity);
+            }
+            current.insert(entity, settings);
+        }
+        // Map entities that lost their settings changed too.
+        self.changed_settings.extend(
+            self.settings
+                .keys()
+                .filter(|entity| !current.contains_key(entity)),
+        );
+        self.settings = current;
+        let seen = std::mem::take(&mut self.seen);
+        self.drawn.retain(|entity, _| seen.contains(entity));
+    }
+
+    /// Whether the layer settings of `entity`, drawing `chunk` and maybe a tile object or
+    /// image layer, need applying again.
+    pub(crate) fn is_stale(
+        &mut self,
+        pool: &ChunkPool,
+        entity: Entity,
+        chunk: &TiledChunk,
+        object: Option<&TiledObject>,
+        image_layer: Option<&TiledImageLayer>,
+    ) -> bool {
+        let spawns = pool.spawns(entity);
+        let drawn = match (object, image_layer) {
+            (Some(object), _) => (
+                object.map,
+                Some(object.map_entity),
+                chunk.key,
+                Some(object.id),
+                None,
+                spawns,
+            ),
+            (None, Some(image_layer)) => (
+                image_layer.map,
+                Some(image_layer.map_entity),
+                chunk.key,
+                None,
+                Some(image_layer.index),
+                spawns,
+            ),
+            (None, None) => (chunk.map, chunk.map_entity, chunk.key, None, None, spawns),
+        };
+        self.seen.insert(entity);
+        let stale = self.changed_maps.contains(&drawn.0)
+            || drawn.1.map_or(false, |map_entity| {
+                self.changed_settings.contains(&map_entity)
+            })
+            || self.drawn.get(&entity) != Some(&drawn);
+        self.drawn.insert(entity, drawn);
+        stale
+    }
+}
+
+/// Draws the chunks, tile objects and image layers of map entities with the pipelines set for
+/// them in their `LayerPipelines`, or else with the built-in pipeline of their layer's
+/// `BlendMode`. Merged meshes draw every layer, so they're always alpha blended.
+pub fn apply_layer_pipelines(
+    maps: Res<Assets<Map>>,
+    pool: Res<ChunkPool>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut changes: Local<LayerChanges<LayerPipelines>>,
+    pipelines_query: Query<(Entity, &LayerPipelines)>,
+    mut query: Query<(
+        Entity,
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&TiledImageLayer>,
+        &mut RenderPipelines,
+        Option<&mut TileBlending>,
+    )>,
+) {
+    let pipelines = pipelines_query
+        .iter()
+        .iter()
+        .map(|(entity, pipelines)| (entity, (*pipelines).clone()))
+        .collect::<Vec<_>>();
+    changes.update(&map_events, pipelines);
+    for (entity, chunk, object, image_layer, mut render_pipelines, blending) in &mut query.iter() {
+        if !changes.is_stale(&pool, entity, chunk, object, image_layer) {
+            continue;
+        }
+        let (map_handle, map_entity) = match (object, image_layer) {
+            (Some(object), _) => (&object.map, Some(object.map_entity)),
+            (None, Some(image_layer)) => (&image_layer.map, Some(image_layer.map_entity)),
+            (None, None) => (&chunk.map, chunk.map_entity),
+        };
+        let pipelines =
+            map_entity.and_then(|entity| pipelines_query.get::<LayerPipelines>(entity).ok());
+        let merged =
+            chunk.key.layer_id == MERGED_LAYER && object.is_none() && image_layer.is_none();
+        let layer = maps
+            .get(map_handle)
+            .and_then(|map| layer_of(map, chunk, object, image_layer));
+        let blend = layer.map_or(BlendMode::Alpha, |(_, properties)| {
+            BlendMode::from_properties(properties)
+        });
+        let pipeline = match (layer, pipelines) {
+            (Some((name, _)), Some(pipelines)) => pipelines
+                .layers
+                .get(name)
+                .copied()
+                .or(pipelines.map)
+                .unwrap_or_else(|| blend.pipeline()),
+            (Some(_), None) => blend.pipeline(),
+            // Merged meshes draw every layer, so only the map's pipeline applies.
+            (None, Some(pipelines)) if merged => pipelines.map.unwrap_or(TILE_MAP_PIPELINE_HANDLE),
+            (None, _) => continue,
+        };
+        let current = render_pipelines
+            .pipelines
+            .first()
+            .map(|render_pipeline| render_pipeline.pipeline);
+        if current.map_or(false, |current| current != pipeline) {
+            // Keeps the specialization and bindings set up for the built-in pipeline.
+            render_pipelines.pipelines[0].pipeline = pipeline;
+        }
+        let multiply = pipeline == TILE_MAP_MULTIPLY_PIPELINE_HANDLE;
+        if let Some(mut blending) = blending {
+            if blending.multiply != multiply {
+                blending.multiply = multiply;
+            }
+        }
+    }
+}
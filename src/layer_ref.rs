@@ -0,0 +1,45 @@
+use crate::Map;
+use anyhow::{anyhow, Result};
+use bevy::prelude::*;
+
+/// A tile layer of a map asset, named with a label such as `level1.tmx#layer:Foreground`.
+///
+/// Bevy 0.2 has no labeled sub-assets, so the map is loaded as a whole and the layer is
+/// looked up by name once it has loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerRef {
+    pub map: Handle<Map>,
+    /// The layer's name in Tiled.
+    pub name: String,
+}
+
+impl LayerRef {
+    /// Splits `path#layer:Name` into the map path and layer name.
+    pub fn parse(labeled_path: &str) -> Option<(&str, &str)> {
+        let hash = labeled_path.rfind('#')?;
+        let name = labeled_path[hash + 1..].strip_prefix("layer:")?;
+        Some((&labeled_path[..hash], name))
+    }
+
+    /// Loads the map of a `path#layer:Name` label through the asset server.
+    pub fn load(asset_server: &AssetServer, labeled_path: &str) -> Result<LayerRef> {
+        let (path, name) = LayerRef::parse(labeled_path)
+            .ok_or_else(|| anyhow!("{:?} isn't labeled with #layer:<name>", labeled_path))?;
+        Ok(LayerRef {
+            map: asset_server.load(path)?,
+            name: name.to_string(),
+        })
+    }
+
+    /// The layer's index into `map.layers`, once the map has loaded and if it has such a layer.
+    pub fn index(&self, maps: &Assets<Map>) -> Option<usize> {
+        maps.get(&self.map)?.layer_index(&self.name)
+    }
+}
+
+impl Map {
+    /// The index into `map.layers` of the first tile layer called `name`.
+    pub fn layer_index(&self, name: &str) -> Option<usize> {
+        self.map.layers.iter().position(|layer| layer.name == name)
+    }
+}
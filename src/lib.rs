@@ -1,12 +1,130 @@
-use bevy::{prelude::*, render::render_graph::RenderGraph};
+use bevy::{
+    prelude::*,
+    render::{render_graph::RenderGraph, shader::shader_defs_system, stage as render_stage},
+};
 
+/// Enters a tracing span until the end of the enclosing block, with the `trace` feature.
+macro_rules! profile_span {
+    ($name:expr) => {
+        #[cfg(feature = "trace")]
+        let _span = tracing::info_span!($name);
+        #[cfg(feature = "trace")]
+        let _guard = _span.enter();
+    };
+}
+
+mod acoustics;
+pub use acoustics::*;
+mod ambient;
+pub use ambient::*;
+mod animation;
+pub use animation::*;
+mod atlas;
+pub use atlas::*;
+mod bake;
+pub use bake::*;
+mod blending;
+pub use blending::*;
+mod buffer;
+pub use buffer::*;
+mod culling;
+pub use culling::*;
+mod data_texture;
+pub use data_texture::*;
+mod debug;
+pub use debug::*;
+#[cfg(feature = "editor")]
+mod editor;
+#[cfg(feature = "editor")]
+pub use editor::*;
+mod emission;
+pub use emission::*;
+mod error;
+pub use error::*;
+mod export;
+pub use export::*;
+mod footsteps;
+pub use footsteps::*;
+#[cfg(feature = "procgen")]
+mod generators;
+#[cfg(feature = "procgen")]
+pub use generators::*;
+mod image_collections;
+pub use image_collections::*;
+mod image_layers;
+pub use image_layers::*;
+mod influence;
+pub use influence::*;
+mod instancing;
+pub use instancing::*;
+mod layer_pipelines;
+pub use layer_pipelines::*;
+mod layer_ref;
+pub use layer_ref::*;
+mod lighting;
+pub use lighting::*;
 mod loader;
+mod lod;
+pub use lod::*;
 mod map;
 pub use map::*;
+mod meshing;
+pub use meshing::*;
+mod merging;
+pub use merging::*;
+mod navigation;
+pub use navigation::*;
+mod objects;
+pub use objects::*;
+mod occlusion;
+pub use occlusion::*;
+mod palettes;
+pub use palettes::*;
+mod paths;
+mod picking;
+pub use picking::*;
 mod pipeline;
 pub use pipeline::*;
+mod pixels;
+pub use pixels::*;
+mod pooling;
+pub use pooling::*;
+mod progress;
+pub use progress::*;
+mod properties;
+pub use properties::*;
+mod rng;
+pub use rng::MapRng;
+mod sandbox;
+pub use sandbox::*;
+mod scatter;
+mod see_through;
+pub use see_through::*;
+mod shoreline;
+pub use shoreline::*;
+mod splitting;
+pub use splitting::*;
+mod streaming;
+pub use streaming::*;
+mod substitution;
+pub use substitution::*;
+mod thumbnails;
+pub use thumbnails::*;
 mod tile_map;
 pub use tile_map::*;
+mod tileset;
+pub use tileset::*;
+mod tints;
+mod tmx;
+pub mod v1;
+mod variants;
+pub use variants::*;
+mod vision;
+pub use vision::*;
+mod wang;
+pub use wang::*;
+mod y_sort;
+pub use y_sort::*;
 
 /// Adds support for GLTF file loading to Apps
 #[derive(Default)]
@@ -14,9 +132,107 @@ pub struct TiledMapPlugin;
 
 impl Plugin for TiledMapPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_asset::<map::Map>()
+        // The loader reports its failures through `MapLoadErrors` and reads whether to mesh in
+        // the background from `LoaderMeshing`, so they go in first.
+        app.init_resource::<MapLoadErrors>()
+            .init_resource::<LoaderMeshing>()
+            .add_asset::<map::Map>()
             .add_asset_loader::<map::Map, loader::TiledMapLoader>()
-            .add_system(process_loaded_tile_maps.system());
+            .add_asset::<Tileset>()
+            .init_resource::<SharedTilesets>()
+            .init_resource::<TransparentColorKeys>()
+            .init_resource::<MissingTextures>()
+            .add_event::<MissingTexture>()
+            .add_event::<MapReadyEvent>()
+            .add_event::<ObjectSpawnedEvent>()
+            .add_event::<MapLoadFailed>()
+            .add_event::<FootstepSurface>()
+            .init_resource::<LayerVariant>()
+            .init_resource::<TilesetSubstitution>()
+            .init_resource::<MapRng>()
+            .init_resource::<ChunkSplitting>()
+            .init_resource::<LoadingProgress>()
+            .init_resource::<MapSettings>()
+            .init_resource::<ChunkCulling>()
+            .init_resource::<ChunkLod>()
+            .init_resource::<PixelPerfect>()
+            .init_resource::<SnappedChunks>()
+            .init_resource::<TileLight>()
+            .init_resource::<TileGlow>()
+            .init_resource::<MapAmbient>()
+            .init_resource::<MapThumbnails>()
+            .init_resource::<LodHiddenChunks>()
+            .init_resource::<ImageCollections>()
+            .init_resource::<ChunkPool>()
+            .add_startup_system(share_background_meshing.system())
+            .add_system_to_stage(stage::PRE_UPDATE, share_background_meshing.system())
+            .add_system_to_stage(stage::PRE_UPDATE, sync_tile_buffers.system())
+            .add_system_to_stage(stage::PRE_UPDATE, uncull_chunks.system())
+            .add_system_to_stage(stage::PRE_UPDATE, show_lod_hidden_chunks.system())
+            .add_system_to_stage(stage::PRE_UPDATE, unsnap_chunks.system())
+            .add_system_to_stage(stage::POST_UPDATE, apply_chunk_lods.system())
+            .add_system_to_stage(stage::POST_UPDATE, snap_chunks_to_pixels.system())
+            .add_system_to_stage(stage::POST_UPDATE, cull_chunks.system())
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TileAnimations>.system(),
+            )
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TileDataTexture>.system(),
+            )
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TileLighting>.system(),
+            )
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TileEmission>.system(),
+            )
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TileBlending>.system(),
+            )
+            .add_system_to_stage(
+                stage::POST_UPDATE,
+                shader_defs_system::<TilePalette>.system(),
+            )
+            .add_system(send_map_load_failures.system())
+            .add_system(despawn_removed_maps.system())
+            .add_system(despawn_tiled_maps.system())
+            .add_system(apply_map_settings.system())
+            .add_system(process_loaded_tile_maps.system())
+            .add_system(pack_tileset_atlases.system())
+            .add_system(release_map_sources.system())
+            .add_system(apply_transparent_color_keys.system())
+            .add_system(stack_image_collections.system())
+            .add_system(apply_missing_textures.system())
+            .add_system(sync_tile_animations.system())
+            .add_system(advance_tile_animations.system())
+            .add_system(split_hot_chunks.system())
+            .add_system(mesh_chunks_in_background.system())
+            .add_system(rebuild_dirty_chunks.system())
+            .add_system(sync_tile_data_textures.system())
+            .add_system(sync_tile_lighting.system())
+            .add_system(sync_tile_emission.system())
+            .add_system(apply_map_ambient.system())
+            .add_system(sync_map_scales.system())
+            .add_system(render_map_thumbnails.system())
+            .add_system(sync_map_objects.system())
+            .add_system(apply_y_sort.system())
+            .add_system(sync_image_layers.system())
+            .add_system(apply_layer_variant.system())
+            .add_system(apply_layer_pipelines.system())
+            .add_system(apply_tile_instancing.system())
+            .add_system_to_stage(render_stage::DRAW, draw_tile_instances.system())
+            .add_system(apply_layer_palettes.system())
+            .add_system(apply_tile_sampling.system())
+            .add_system(apply_see_through.system())
+            .add_system(draw_vision_cones.system())
+            .add_system(update_influence_maps.system())
+            .add_system(track_footstep_surfaces.system())
+            .add_system(apply_tileset_substitution.system())
+            .add_system(update_loading_progress.system());
 
         let resources = app.resources();
         let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
@@ -0,0 +1,120 @@
+use crate::{LodChunk, Map, PropertiesExt, TiledChunk, TiledObject, Tileset, TilesetSource};
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderResource, RenderResources},
+        shader::ShaderDefs,
+    },
+};
+use std::path::Path;
+
+/// The light the tile shader shades chunks with normal maps by. Chunks without a normal map
+/// are drawn as they are.
+#[derive(Debug, Clone)]
+pub struct TileLight {
+    /// Direction towards the light, with z pointing out of the screen.
+    pub direction: Vec3,
+    pub color: Color,
+    /// Light reaching every pixel whichever way it faces.
+    pub ambient: Color,
+}
+
+impl Default for TileLight {
+    fn default() -> Self {
+        // Straight on, flat normals leave the tiles as they are.
+        Self {
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            color: Color::WHITE,
+            ambient: Color::BLACK,
+        }
+    }
+}
+
+/// The normal map of the tileset a chunk draws, and the `TileLight` it's shaded by. Kept up to
+/// date by `sync_tile_lighting`.
+#[derive(Debug, Clone, Default, RenderResources, ShaderDefs)]
+pub struct TileLighting {
+    #[shader_def]
+    pub normal_map: Option<Handle<Texture>>,
+    pub light_direction: Vec4,
+    pub light_color: Color,
+    pub ambient: Color,
+}
+
+impl Map {
+    /// Path of the normal map drawn with the tileset's image, from its `normal_map` property:
+    /// a path resolved like the image's source, or `true` for the image's path with `_n` added
+    /// to its name, so `tiles.png` goes with `tiles_n.png`.
+    pub fn tileset_normal_map_path(&self, tileset: &tiled::Tileset) -> Option<String> {
+        self.companion_image_path(tileset, "normal_map", "_n")
+    }
+
+    /// Path of an image drawn alongside the tileset's image, from its `property` property: a
+    /// path, or `true` for the image's path with `suffix` added to its name.
+    pub(crate) fn companion_image_path(
+        &self,
+        tileset: &tiled::Tileset,
+        property: &str,
+        suffix: &str,
+    ) -> Option<String> {
+        let image_path = self.tileset_image_path(tileset)?;
+        if let Some(true) = tileset.properties.bool_property(property) {
+            let image_path = Path::new(&image_path);
+            let stem = image_path.file_stem()?.to_str()?;
+            let name = match image_path
+                .extension()
+                .and_then(|extension| extension.to_str())
+            {
+                Some(extension) => format!("{}{}.{}", stem, suffix, extension),
+                None => format!("{}{}", stem, suffix),
+            };
+            return image_path.with_file_name(name).to_str().map(String::from);
+        }
+        let source = tileset.properties.string_property(property)?;
+        let declared = self
+            .tileset_sources
+            .get(&tileset.first_gid)
+            .unwrap_or(&TilesetSource::Embedded);
+        let path = self.resolve_image(declared.resolve_image(&self.map_folder, &source));
+        path.to_str().map(String::from)
+    }
+}
+
+/// Hands chunks the normal maps of their tilesets and the current `TileLight`.
+///
+/// Maps packed into an atlas draw with UVs into the atlas rather than the tileset image, so
+/// they're drawn without normal maps.
+pub fn sync_tile_lighting(
+    light: Res<TileLight>,
+    maps: Res<Assets<Map>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut query: Query<(
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&LodChunk>,
+        &mut TileLighting,
+    )>,
+) {
+    let direction = light.direction.normalize().extend(0.0);
+    for (chunk, object, lod, mut lighting) in &mut query.iter() {
+        let normal_map = maps
+            .get(&chunk.map)
+            .filter(|map| map.atlas.is_none() && object.is_none() && lod.is_none())
+            .and_then(|map| map.tileset_assets.get(&chunk.key.tileset_guid))
+            .and_then(|tileset| tilesets.get(tileset))
+            .and_then(|tileset| tileset.normal_map);
+        if lighting.normal_map != normal_map {
+            lighting.normal_map = normal_map;
+        }
+        if (
+            lighting.light_direction,
+            lighting.light_color,
+            lighting.ambient,
+        ) != (direction, light.color, light.ambient)
+        {
+            lighting.light_direction = direction;
+            lighting.light_color = light.color;
+            lighting.ambient = light.ambient;
+        }
+    }
+}
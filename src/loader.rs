@@ -1,288 +1,958 @@
 use crate::{
-    map::{Chunk, Map},
-    Layer, Tile, TilesetLayer,
+    acoustics::read_tile_acoustics,
+    animation::{animation_attribute, animation_rows, read_tile_animations},
+    bake::is_baked,
+    emission::read_emissive_tiles,
+    error::validate,
+    footsteps::read_tile_footsteps,
+    image_collections::collection_tile_uv,
+    map::{next_generation, Chunk, Map},
+    occlusion::read_occluding_tiles,
+    properties::make_rgb_colors_opaque,
+    tileset::{read_external_tilesets, read_tileset_extras, tile_uv_table, tileset_uv_scale},
+    tints::tint_attribute,
+    tmx::{prepare_for_tiled, read_tmx_extras},
+    ChunkKey, EmptyTile, ImageResolver, Layer, LoaderMeshing, MapError, MapLoadErrors,
+    MapLoadOptions, PropertiesExt, PropertyOwner, RenderOrder, TilesetLayer, TilesetReader,
+    CHUNK_PARTS, CHUNK_SIZE, MAP_SCALE,
 };
 use anyhow::Result;
 use bevy::{
     asset::AssetLoader,
+    ecs::{FromResources, Resources},
     prelude::Mesh,
     render::{mesh::VertexAttribute, pipeline::PrimitiveTopology},
 };
 use glam::{Vec2, Vec4};
 
-use std::{io::BufReader, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
 
-#[derive(Default)]
-pub struct TiledMapLoader;
+pub struct TiledMapLoader {
+    errors: MapLoadErrors,
+    background_meshing: LoaderMeshing,
+}
+
+impl FromResources for TiledMapLoader {
+    fn from_resources(resources: &Resources) -> Self {
+        Self {
+            errors: resources
+                .get::<MapLoadErrors>()
+                .map(|errors| errors.clone())
+                .unwrap_or_default(),
+            background_meshing: resources
+                .get::<LoaderMeshing>()
+                .map(|meshing| meshing.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
 
 impl TiledMapLoader {
-    fn remove_tile_flags(tile: u32) -> u32 {
+    pub(crate) fn remove_tile_flags(tile: u32) -> u32 {
         let tile = tile & !ALL_FLIP_FLAGS;
         tile
     }
 }
 
-const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
-const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
-const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
+pub(crate) const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
+pub(crate) const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
+pub(crate) const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
 const ALL_FLIP_FLAGS: u32 =
     FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG | FLIPPED_DIAGONALLY_FLAG;
 
 impl AssetLoader<Map> for TiledMapLoader {
     fn from_bytes(&self, asset_path: &Path, bytes: Vec<u8>) -> Result<Map> {
-        let map = tiled::parse_with_path(BufReader::new(bytes.as_slice()), asset_path).unwrap();
+        let options = MapLoadOptions {
+            path: Some(asset_path.to_path_buf()),
+            background_meshing: self.background_meshing.0.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+        let map = if is_baked(&bytes) {
+            Map::try_from_baked(&bytes, options)
+        } else {
+            Map::try_from_bytes(&bytes, options)
+        };
+        if let Err(error) = &map {
+            if let Ok(mut errors) = self.errors.0.lock() {
+                errors.push((asset_path.to_path_buf(), error.clone()));
+            }
+        }
+        Ok(map?)
+    }
 
-        let mut layers = Vec::new();
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["tmx", "tmxb"];
+        EXTENSIONS
+    }
+}
 
-        let target_chunk_x = 32;
-        let target_chunk_y = 32;
+impl Map {
+    /// Parses and builds a map from the contents of a `.tmx` file, such as one bundled with
+    /// `include_bytes!`, downloaded, or written by a tool.
+    pub fn try_from_bytes(bytes: &[u8], options: MapLoadOptions) -> Result<Map, MapError> {
+        let mut map = Map::parse(bytes, options)?;
+        map.build()?;
+        Ok(map)
+    }
+
+    /// Builds a map from the contents of a `.tmx` file that isn't in the asset folder, such as
+    /// one downloaded at runtime, with its images loaded from wherever `resolve_image` says.
+    ///
+    /// `resolve_image` gets each image path relative to the map, and returns the path the
+    /// `AssetServer` loads it from, such as the cache folder the images were downloaded into.
+    /// The map can then be added to `Assets<Map>` and spawned like any other.
+    pub fn from_tmx_bytes(
+        bytes: &[u8],
+        resolve_image: impl Fn(&Path) -> PathBuf + Send + Sync + 'static,
+    ) -> Result<Map, MapError> {
+        let mut map = Map::try_from_bytes(bytes, MapLoadOptions::default())?;
+        map.image_resolver = Some(ImageResolver::new(resolve_image));
+        Ok(map)
+    }
+
+    /// Like `from_tmx_bytes`, with the map's external tilesets read by `read_tileset` rather
+    /// than from the filesystem, such as out of the zip or pak archive the map came from.
+    ///
+    /// `read_tileset` gets each `.tsx` path relative to the map.
+    pub fn from_tmx_bytes_with_tilesets(
+        bytes: &[u8],
+        read_tileset: impl Fn(&Path) -> std::io::Result<Vec<u8>> + Send + Sync + 'static,
+        resolve_image: impl Fn(&Path) -> PathBuf + Send + Sync + 'static,
+    ) -> Result<Map, MapError> {
+        let options = MapLoadOptions {
+            tileset_reader: Some(TilesetReader::new(read_tileset)),
+            ..Default::default()
+        };
+        let mut map = Map::try_from_bytes(bytes, options)?;
+        map.image_resolver = Some(ImageResolver::new(resolve_image));
+        Ok(map)
+    }
+
+    /// Parses a `.tmx` file and the data the tiled crate skips, without building any chunks.
+    pub(crate) fn parse(bytes: &[u8], options: MapLoadOptions) -> Result<Map, MapError> {
+        let path = options.path.as_deref();
+        let map_folder = path.and_then(Path::parent).unwrap_or_else(|| Path::new(""));
+        let extras = read_tmx_extras(bytes);
+        let external = read_external_tilesets(
+            &extras.tileset_sources,
+            map_folder,
+            path,
+            options.tileset_reader.as_ref(),
+        )?;
+        let tileset_extras = read_tileset_extras(extras.tileset_extras, &external);
+        // Without the `zstd` feature, zstd-compressed layers fail here instead of panicking.
+        let mut map = {
+            profile_span!("parse_tmx");
+            // External tilesets are embedded before the tiled crate sees the map, so it never
+            // reads the filesystem itself.
+            let rewritten = if extras.file_typed || !external.is_empty() {
+                prepare_for_tiled(bytes, &external)
+            } else {
+                None
+            };
+            let reader = BufReader::new(rewritten.as_deref().unwrap_or(bytes));
+            tiled::parse(reader).map_err(|err| MapError::Parse(err.to_string()))?
+        };
+        let mut rgb_colors = extras.rgb_colors;
+        for (first_gid, extras) in tileset_extras.iter() {
+            for (tile, name) in extras.rgb_colors.iter() {
+                let owner = match tile {
+                    Some(tile) => PropertyOwner::Tile(*first_gid, *tile),
+                    None => PropertyOwner::Tileset(*first_gid),
+                };
+                rgb_colors
+                    .entry(owner)
+                    .or_insert_with(HashSet::new)
+                    .insert(name.clone());
+            }
+        }
+        make_rgb_colors_opaque(&mut map, &rgb_colors);
+
+        let mut map = Map::new(map, map_folder);
+        map.tileset_sources = extras.tileset_sources;
+        for (first_gid, extras) in tileset_extras {
+            map.tile_offsets.insert(first_gid, extras.tile_offset);
+            map.wang_sets.extend(extras.wang_sets);
+        }
+        map.layer_offsets = extras.layer_offsets;
+        map.render_order = extras.render_order;
+        map.image_layer_repeats = extras.image_layer_repeats;
+        map.object_draw_orders = extras.object_draw_orders;
+        for (id, files) in extras.object_files {
+            let files = files
+                .into_iter()
+                .map(|(name, file)| (name, map_folder.join(file)))
+                .collect::<HashMap<_, PathBuf>>();
+            map.object_files.insert(id, files);
+        }
+        map.file_properties = extras.file_properties;
+        map.background_meshing = options.background_meshing;
+        Ok(map)
+    }
 
-        let chunk_size_x = (map.width as f32 / target_chunk_x as f32).ceil().max(1.0) as usize;
-        let chunk_size_y = (map.height as f32 / target_chunk_y as f32).ceil().max(1.0) as usize;
+    /// Wraps parsed or generated Tiled data without building any chunks yet. Images are looked
+    /// up relative to `map_folder`.
+    #[allow(deprecated)]
+    pub fn new(map: tiled::Map, map_folder: impl AsRef<Path>) -> Self {
+        let map_folder = map_folder.as_ref().to_path_buf();
         let tile_size = Vec2::new(map.tile_width as f32, map.tile_height as f32);
+        let empty_tiles = map
+            .layers
+            .iter()
+            .map(
+                |layer| match layer.properties.int_property("default_tile") {
+                    Some(gid) if gid > 0 => EmptyTile::Fill(gid as u32),
+                    _ => EmptyTile::Empty,
+                },
+            )
+            .collect();
+        let tile_acoustics = read_tile_acoustics(&map);
+        let tile_footsteps = read_tile_footsteps(&map);
+        let tile_uvs = map
+            .tilesets
+            .iter()
+            .map(|tileset| (tileset.first_gid, Arc::new(tile_uv_table(tileset))))
+            .collect();
+        let tile_animations = read_tile_animations(&map);
+        let occluding_tiles = read_occluding_tiles(&map);
+        let emissive_tiles = read_emissive_tiles(&map);
+        let animation_rows = animation_rows(&tile_animations);
+        Map {
+            map,
+            meshes: Vec::new(),
+            layers: Vec::new(),
+            tile_size,
+            image_folder: map_folder.to_string_lossy().into_owned(),
+            map_folder,
+            image_resolver: None,
+            tileset_sources: HashMap::new(),
+            tile_offsets: HashMap::new(),
+            wang_sets: Vec::new(),
+            empty_tiles,
+            gid_overrides: HashMap::new(),
+            tile_acoustics,
+            tile_footsteps,
+            tile_uvs,
+            tileset_assets: HashMap::new(),
+            tileset_textures: HashMap::new(),
+            tile_animations,
+            animation_rows,
+            animation_frames: HashMap::new(),
+            shorelines: Vec::new(),
+            layer_offsets: Vec::new(),
+            render_order: RenderOrder::default(),
+            image_layer_repeats: Vec::new(),
+            object_draw_orders: Vec::new(),
+            object_files: HashMap::new(),
+            file_properties: HashMap::new(),
+            object_index: Default::default(),
+            generation: next_generation(),
+            objects_revision: 0,
+            tiles_revision: 0,
+            chunk_meshes: HashMap::new(),
+            dirty_chunks: HashSet::new(),
+            chunk_splits: Default::default(),
+            baked_nav: None,
+            source_released: false,
+            atlas: None,
+            chunk_size: (CHUNK_SIZE, CHUNK_SIZE),
+            y_sort: false,
+            layer_depths: Default::default(),
+            tile_tints: HashMap::new(),
+            merged: false,
+            data_texture: false,
+            instanced: false,
+            cells: Vec::new(),
+            chunk_cells: HashMap::new(),
+            streamed: false,
+            occluding_tiles,
+            emissive_tiles,
+            skip_covered: false,
+            background_meshing: false,
+            unmeshed: Vec::new(),
+            meshing: Default::default(),
+            meshed: Vec::new(),
+            scale: MAP_SCALE,
+        }
+    }
+
+    /// Builds a map asset from Tiled data, such as a generated map. Tileset images are looked
+    /// up relative to `map_folder`.
+    pub fn from_tiled(map: tiled::Map, map_folder: impl AsRef<Path>) -> Result<Self, MapError> {
+        let mut map = Map::new(map, map_folder);
+        map.build()?;
+        Ok(map)
+    }
+
+    /// Builds the chunks, chunk meshes and object index, failing if the map uses something that
+    /// can't be drawn.
+    pub(crate) fn build(&mut self) -> Result<(), MapError> {
+        validate(&self.map)?;
+        self.build_layers();
+        self.build_object_index();
+        Ok(())
+    }
 
-        for layer in map.layers.iter() {
+    /// Builds the chunks of every visible layer, and meshes for the ones that aren't empty.
+    pub(crate) fn build_layers(&mut self) {
+        let (chunk_count_x, chunk_count_y) = self.chunk_count();
+
+        let mut layers = Vec::new();
+        for (layer_index, layer) in self.map.layers.iter().enumerate() {
             if !layer.visible {
                 continue;
             }
+            profile_span!("project_layer");
             let mut tileset_layers = Vec::new();
 
-            for tileset in map.tilesets.iter() {
-                let tile_width = tileset.tile_width as f32;
-                let tile_height = tileset.tile_height as f32;
-                let image = tileset.images.first().unwrap();
-                let texture_width = image.width as f32;
-                let texture_height = image.height as f32;
-                let columns = (texture_width / tile_width).floor();
-
+            for tileset in self.map.tilesets.iter() {
                 let mut chunks = Vec::new();
                 // 32 x 32 tile chunk sizes
-                for chunk_x in 0..chunk_size_x {
+                for chunk_x in 0..chunk_count_x {
                     let mut chunks_y = Vec::new();
-                    for chunk_y in 0..chunk_size_y {
-                        let mut tiles = Vec::new();
-
-                        for tile_x in 0..target_chunk_x {
-                            let mut tiles_y = Vec::new();
-                            for tile_y in 0..target_chunk_y {
-                                let lookup_x = (chunk_x * target_chunk_x) + tile_x;
-                                let lookup_y = (chunk_y * target_chunk_y) + tile_y;
-
-                                // Get chunk tile.
-                                let chunk_tile = if lookup_x < map.width as usize
-                                    && lookup_y < map.height as usize
-                                {
-                                    // New Tiled crate code:
-                                    let map_tile = match &layer.tiles {
-                                        tiled::LayerData::Finite(tiles) => {
-                                            &tiles[lookup_y][lookup_x]
-                                        }
-                                        _ => panic!("Infinte maps not supported"),
-                                    };
-
-                                    let tile = map_tile.gid;
-                                    if tile < tileset.first_gid
-                                        || tile >= tileset.first_gid + tileset.tilecount.unwrap()
-                                    {
-                                        continue;
-                                    }
-
-                                    let tile = (Self::remove_tile_flags(tile) as f32)
-                                        - tileset.first_gid as f32;
-
-                                    // This calculation is much simpler we only care about getting the remainder
-                                    // and multiplying that by the tile width.
-                                    let sprite_sheet_x: f32 = (tile % columns * tile_width).floor();
-
-                                    // Calculation here is (tile / columns).round_down * tile_height
-                                    // Example: tile 30 / 28 columns = 1.0714 rounded down to 1 * 16 tile_height = 16 Y
-                                    // which is the 2nd row in the sprite sheet.
-                                    // Example2: tile 10 / 28 columns = 0.3571 rounded down to 0 * 16 tile_height = 0 Y
-                                    // which is the 1st row in the sprite sheet.
-                                    let sprite_sheet_y: f32 =
-                                        (tile / columns).floor() * tile_height;
-
-                                    // Calculate positions
-                                    let (start_x, end_x, start_y, end_y) = match map.orientation {
-                                        tiled::Orientation::Orthogonal => {
-                                            let center = Map::project_ortho(
-                                                Vec2::new(lookup_x as f32, lookup_y as f32),
-                                                tile_width,
-                                                tile_height,
-                                            );
-
-                                            let start = Vec2::new(
-                                                center.x() - tile_width / 2.0,
-                                                center.y() - tile_height / 2.0,
-                                            );
-
-                                            let end = Vec2::new(
-                                                center.x() + tile_width / 2.0,
-                                                center.y() + tile_height / 2.0,
-                                            );
-
-                                            (start.x(), end.x(), start.y(), end.y())
-                                        }
-                                        tiled::Orientation::Isometric => {
-                                            let center = Map::project_iso(
-                                                Vec2::new(lookup_x as f32, lookup_y as f32),
-                                                tile_width,
-                                                tile_height,
-                                            );
-
-                                            let start = Vec2::new(
-                                                center.x() - tile_width / 2.0,
-                                                center.y() - tile_height / 2.0,
-                                            );
-
-                                            let end = Vec2::new(
-                                                center.x() + tile_width / 2.0,
-                                                center.y() + tile_height / 2.0,
-                                            );
-
-                                            (start.x(), end.x(), start.y(), end.y())
-                                        }
-                                        _ => {
-                                            panic!("Unsupported orientation {:?}", map.orientation)
-                                        }
-                                    };
-
-                                    // Calculate UV:
-                                    let mut start_u: f32 = sprite_sheet_x / texture_width;
-                                    let mut end_u: f32 =
-                                        (sprite_sheet_x + tile_width) / texture_width;
-                                    let mut start_v: f32 = sprite_sheet_y / texture_height;
-                                    let mut end_v: f32 =
-                                        (sprite_sheet_y + tile_height) / texture_height;
-
-                                    if map_tile.flip_h {
-                                        let temp_startu = start_u;
-                                        start_u = end_u;
-                                        end_u = temp_startu;
-                                    }
-                                    if map_tile.flip_v {
-                                        let temp_startv = start_v;
-                                        start_v = end_v;
-                                        end_v = temp_startv;
-                                    }
-
-                                    Tile {
-                                        tile_id: map_tile.gid,
-                                        pos: Vec2::new(tile_x as f32, tile_y as f32),
-                                        vertex: Vec4::new(start_x, start_y, end_x, end_y),
-                                        uv: Vec4::new(start_u, start_v, end_u, end_v),
-                                    }
-                                } else {
-                                    // Empty tile
-                                    Tile {
-                                        tile_id: 0,
-                                        pos: Vec2::new(tile_x as f32, tile_y as f32),
-                                        vertex: Vec4::new(0.0, 0.0, 0.0, 0.0),
-                                        uv: Vec4::new(0.0, 0.0, 0.0, 0.0),
-                                    }
-                                };
-
-                                tiles_y.push(chunk_tile);
-                            }
-                            tiles.push(tiles_y);
-                        }
-
-                        let chunk = Chunk {
-                            position: Vec2::new(chunk_x as f32, chunk_y as f32),
-                            tiles,
-                        };
-                        chunks_y.push(chunk);
+                    for chunk_y in 0..chunk_count_y {
+                        chunks_y.push(self.build_chunk(
+                            layer_index,
+                            tileset,
+                            chunk_x,
+                            chunk_y,
+                            None,
+                        ));
                     }
                     chunks.push(chunks_y);
                 }
 
                 let tileset_layer = TilesetLayer {
-                    tile_size: Vec2::new(tile_width, tile_height),
+                    tile_size: Vec2::new(tileset.tile_width as f32, tileset.tile_height as f32),
                     chunks,
                     tileset_guid: tileset.first_gid,
                 };
                 tileset_layers.push(tileset_layer);
             }
 
-            let layer = Layer { tileset_layers };
+            let layer = Layer {
+                layer_index,
+                tileset_layers,
+            };
             layers.push(layer);
         }
 
         let mut meshes = Vec::new();
-        for (layer_id, layer) in layers.iter().enumerate() {
+        // Streamed maps build their meshes as anchors come near, and background meshed ones
+        // a batch at a time.
+        let background = self.background_meshing && !self.streamed && !self.merged;
+        let layers_to_mesh = if self.streamed || background {
+            &[][..]
+        } else {
+            &layers[..]
+        };
+        for (layer_id, layer) in layers_to_mesh.iter().enumerate() {
+            profile_span!("build_layer_meshes");
             for tileset_layer in layer.tileset_layers.iter() {
                 for x in 0..tileset_layer.chunks.len() {
                     let chunk_x = &tileset_layer.chunks[x];
                     for y in 0..chunk_x.len() {
-                        let chunk = &chunk_x[y];
-
-                        let mut positions = Vec::new();
-                        let mut normals = Vec::new();
-                        let mut uvs = Vec::new();
-                        let mut indices = Vec::new();
-
-                        let mut i = 0;
-                        for tile in chunk.tiles.iter().flat_map(|tiles_y| tiles_y.iter()) {
-                            if tile.tile_id < tileset_layer.tileset_guid {
-                                continue;
-                            }
-
-                            // X, Y
-                            positions.push([tile.vertex.x(), tile.vertex.y(), 0.0]);
-                            normals.push([0.0, 0.0, 1.0]);
-                            uvs.push([tile.uv.x(), tile.uv.w()]);
-
-                            // X, Y + 1
-                            positions.push([tile.vertex.x(), tile.vertex.w(), 0.0]);
-                            normals.push([0.0, 0.0, 1.0]);
-                            uvs.push([tile.uv.x(), tile.uv.y()]);
-
-                            // X + 1, Y + 1
-                            positions.push([tile.vertex.z(), tile.vertex.w(), 0.0]);
-                            normals.push([0.0, 0.0, 1.0]);
-                            uvs.push([tile.uv.z(), tile.uv.y()]);
-
-                            // X + 1, Y
-                            positions.push([tile.vertex.z(), tile.vertex.y(), 0.0]);
-                            normals.push([0.0, 0.0, 1.0]);
-                            uvs.push([tile.uv.z(), tile.uv.w()]);
-
-                            let mut new_indices = vec![i + 0, i + 2, i + 1, i + 0, i + 3, i + 2];
-                            indices.append(&mut new_indices);
-
-                            i += 4;
-                        }
-
-                        if positions.len() > 0 {
-                            let mesh = Mesh {
-                                primitive_topology: PrimitiveTopology::TriangleList,
-                                attributes: vec![
-                                    VertexAttribute::position(positions),
-                                    VertexAttribute::normal(normals),
-                                    VertexAttribute::uv(uvs),
-                                ],
-                                indices: Some(indices),
+                        let mesh = self.chunk_mesh(
+                            layer.layer_index,
+                            tileset_layer.tileset_guid,
+                            &chunk_x[y],
+                            None,
+                        );
+                        if let Some(mesh) = mesh {
+                            let key = ChunkKey {
+                                layer_id: layer_id as u32,
+                                tileset_guid: tileset_layer.tileset_guid,
+                                chunk: (x, y),
+                                part: None,
                             };
-                            meshes.push((layer_id as u32, tileset_layer.tileset_guid, mesh));
+                            meshes.push((key, mesh));
                         }
                     }
                 }
             }
         }
 
-        let map = Map {
-            map,
-            meshes,
-            layers,
-            tile_size,
-            image_folder: asset_path.parent().unwrap().to_str().unwrap().to_string(),
+        self.layers = layers;
+        self.meshes = meshes;
+        self.unmeshed.clear();
+        self.meshing = Default::default();
+        self.meshed.clear();
+        if background {
+            self.queue_unmeshed();
+        } else if self.merged {
+            self.meshes = self.merged_meshes();
+        } else if self.data_texture {
+            self.cells = self
+                .meshes
+                .iter()
+                .filter_map(|(key, _)| {
+                    let layer = &self.layers[key.layer_id as usize];
+                    let tileset_layer = layer
+                        .tileset_layers
+                        .iter()
+                        .find(|tileset_layer| tileset_layer.tileset_guid == key.tileset_guid)?;
+                    let chunk = &tileset_layer.chunks[key.chunk.0][key.chunk.1];
+                    let cells =
+                        self.data_texture_cells(layer.layer_index, key.tileset_guid, chunk)?;
+                    Some((*key, cells))
+                })
+                .collect();
+        }
+    }
+
+    /// Builds the tiles of one chunk of `map.layers[layer_index]` which belong to `tileset`,
+    /// or only those in one `part` of it. Chunks where the tileset draws nothing come back
+    /// `Chunk::empty`, so sparse layers don't keep a cell per tile of every tileset.
+    pub(crate) fn build_chunk(
+        &self,
+        layer_index: usize,
+        tileset: &tiled::Tileset,
+        chunk_x: usize,
+        chunk_y: usize,
+        part: Option<(usize, usize)>,
+    ) -> Chunk {
+        let map = &self.map;
+        let layer = &map.layers[layer_index];
+        let (width, height) = self.chunk_size;
+        let mut gids = vec![0; width * height];
+        let mut drawn = false;
+        for tile_y in 0..height {
+            for tile_x in 0..width {
+                if !in_part(part, self.chunk_size, tile_x, tile_y) {
+                    continue;
+                }
+                let lookup_x = (chunk_x * width) + tile_x;
+                let lookup_y = (chunk_y * height) + tile_y;
+                if lookup_x >= map.width as usize || lookup_y >= map.height as usize {
+                    continue;
+                }
+                let map_tile = match &layer.tiles {
+                    tiled::LayerData::Finite(tiles) => &tiles[lookup_y][lookup_x],
+                    // Infinite maps are rejected by `validate`.
+                    _ => continue,
+                };
+
+                let gid = self.drawn_gid(layer_index, map_tile.gid);
+                if gid < tileset.first_gid
+                    || gid >= tileset.first_gid + tileset.tilecount.unwrap_or(0)
+                    || self.is_tile_covered(layer_index, lookup_x as u32, lookup_y as u32)
+                {
+                    continue;
+                }
+                let mut flags = 0;
+                if map_tile.flip_h {
+                    flags |= FLIPPED_HORIZONTALLY_FLAG;
+                }
+                if map_tile.flip_v {
+                    flags |= FLIPPED_VERTICALLY_FLAG;
+                }
+                gids[tile_y * width + tile_x] = gid | flags;
+                drawn = true;
+            }
+        }
+
+        if !drawn {
+            return Chunk::empty(chunk_x, chunk_y, self.chunk_size);
+        }
+        Chunk {
+            position: Vec2::new(chunk_x as f32, chunk_y as f32),
+            size: self.chunk_size,
+            gids,
+        }
+    }
+
+    /// The quads of `chunk`, a chunk of `map.layers[layer_index]`, for the tiles of the
+    /// tileset starting at `tileset_guid`, or `None` if it has no such tiles. With a `part`,
+    /// only that part's tiles are included.
+    ///
+    /// Quads are in `tile_draw_order`, so overlapping tiles overdraw as they do in Tiled.
+    pub(crate) fn chunk_quads(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+        part: Option<(usize, usize)>,
+    ) -> Option<Vec<TileQuad>> {
+        if chunk.gids.is_empty() {
+            return None;
+        }
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.first_gid == tileset_guid)?;
+        let tile_width = tileset.tile_width as f32;
+        let tile_height = tileset.tile_height as f32;
+        // Tiled's offsets are y down, the mesh is y up.
+        let tile_offset = self
+            .tile_offsets
+            .get(&tileset.first_gid)
+            .copied()
+            .unwrap_or_else(Vec2::zero)
+            + self.layer_offset(layer_index);
+        let tile_offset = Vec2::new(tile_offset.x(), -tile_offset.y());
+        let project = match self.map.orientation {
+            tiled::Orientation::Orthogonal => Map::project_ortho,
+            tiled::Orientation::Isometric => Map::project_iso,
+            _ => return None,
         };
 
-        Ok(map)
+        let render_order = self.tile_draw_order();
+        let (width, height) = chunk.size;
+        let mut tiles = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|(x, y)| in_part(part, chunk.size, *x, *y) && chunk.gid(*x, *y) != 0)
+            .collect::<Vec<_>>();
+        tiles.sort_by_key(|(x, y)| render_order.sort_key(*x as i32, *y as i32));
+
+        let quads = tiles
+            .into_iter()
+            .map(|(x, y)| {
+                let gid = chunk.gid(x, y);
+                let tile_pos = Vec2::new(
+                    chunk.position.x() * width as f32 + x as f32,
+                    chunk.position.y() * height as f32 + y as f32,
+                );
+                let center = project(tile_pos, tile_width, tile_height) + tile_offset;
+                let (start_x, start_y) = (
+                    center.x() - tile_width / 2.0,
+                    center.y() - tile_height / 2.0,
+                );
+                let (end_x, end_y) = (
+                    center.x() + tile_width / 2.0,
+                    center.y() + tile_height / 2.0,
+                );
+
+                let uv = self.tileset_uv(
+                    tileset,
+                    TiledMapLoader::remove_tile_flags(gid) - tileset.first_gid,
+                );
+                let (mut start_u, mut start_v, mut end_u, mut end_v) =
+                    (uv.x(), uv.y(), uv.z(), uv.w());
+                if gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_u, &mut end_u);
+                }
+                if gid & FLIPPED_VERTICALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_v, &mut end_v);
+                }
+
+                let depth = if self.y_sort {
+                    self.y_sort_depth(start_y)
+                } else {
+                    0.0
+                };
+                TileQuad {
+                    rect: [start_x, start_y, end_x, end_y],
+                    uv: [start_u, start_v, end_u, end_v],
+                    color: self.tint_vertex(layer_index, tile_pos.x() as u32, tile_pos.y() as u32),
+                    depth,
+                    animation: self.animation_vertex(gid),
+                }
+            })
+            .collect::<Vec<_>>();
+        if quads.is_empty() {
+            None
+        } else {
+            Some(quads)
+        }
     }
 
-    fn extensions(&self) -> &[&str] {
-        static EXTENSIONS: &[&str] = &["tmx"];
-        EXTENSIONS
+    /// Builds the vertices of the `chunk_quads` of `chunk`, or `None` if it has no tiles of
+    /// the tileset starting at `tileset_guid`.
+    pub(crate) fn chunk_vertices(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+        part: Option<(usize, usize)>,
+    ) -> Option<ChunkVertices> {
+        let quads = self.chunk_quads(layer_index, tileset_guid, chunk, part)?;
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut animations = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        let mut i = 0;
+        for quad in quads {
+            let [start_x, start_y, end_x, end_y] = quad.rect;
+            let [start_u, start_v, end_u, end_v] = quad.uv;
+            let depth = quad.depth;
+            animations.extend_from_slice(&[quad.animation; 4]);
+            colors.extend_from_slice(&[quad.color; 4]);
+
+            // X, Y
+            positions.push([start_x, start_y, depth]);
+            uvs.push([start_u, end_v]);
+
+            // X, Y + 1
+            positions.push([start_x, end_y, depth]);
+            uvs.push([start_u, start_v]);
+
+            // X + 1, Y + 1
+            positions.push([end_x, end_y, depth]);
+            uvs.push([end_u, start_v]);
+
+            // X + 1, Y
+            positions.push([end_x, start_y, depth]);
+            uvs.push([end_u, end_v]);
+
+            indices.extend_from_slice(&[i, i + 2, i + 1, i, i + 3, i + 2]);
+            i += 4;
+        }
+
+        let mut vertices = ChunkVertices {
+            positions,
+            uvs,
+            animations,
+            colors,
+            indices,
+        };
+        vertices.weld();
+        Some(vertices)
+    }
+
+    /// The mesh of `chunk_vertices`, the `data_texture_quad` of data texture maps, or the
+    /// `instance_mesh` of instanced ones.
+    pub(crate) fn chunk_mesh(
+        &self,
+        layer_index: usize,
+        tileset_guid: u32,
+        chunk: &Chunk,
+        part: Option<(usize, usize)>,
+    ) -> Option<Mesh> {
+        if self.data_texture && part.is_none() {
+            return self.data_texture_quad(layer_index, tileset_guid, chunk);
+        }
+        if self.instanced && part.is_none() {
+            return self.instance_mesh(layer_index, tileset_guid, chunk);
+        }
+        self.chunk_vertices(layer_index, tileset_guid, chunk, part)
+            .map(ChunkVertices::into_mesh)
+    }
+}
+
+impl Map {
+    /// The tileset `gid` (flip flags included) belongs to.
+    pub fn tileset_for_gid(&self, gid: u32) -> Option<&tiled::Tileset> {
+        let gid = TiledMapLoader::remove_tile_flags(gid);
+        self.map
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.first_gid <= gid)
+            .max_by_key(|tileset| tileset.first_gid)
+    }
+
+    /// UVs of the `tile`th tile of a tileset, from its UV table in `tile_uvs`.
+    pub fn tileset_uv(&self, tileset: &tiled::Tileset, tile: u32) -> Vec4 {
+        self.tile_uvs
+            .get(&tileset.first_gid)
+            .and_then(|uvs| uvs.get(tile as usize))
+            .copied()
+            .unwrap_or_else(|| Map::tile_uv(tileset, tile))
+    }
+
+    /// UVs of the `tile`th tile of a tileset image, as (start u, start v, end u, end v),
+    /// scaled by `tileset_uv_scale`.
+    pub fn tile_uv(tileset: &tiled::Tileset, tile: u32) -> Vec4 {
+        let image = match tileset.images.first() {
+            Some(image) => image,
+            None => return collection_tile_uv(tileset, tile),
+        };
+        let tile = tile as f32;
+        let tile_width = tileset.tile_width as f32;
+        let tile_height = tileset.tile_height as f32;
+        let texture_width = image.width as f32;
+        let texture_height = image.height as f32;
+        let margin = tileset.margin as f32;
+        let spacing = tileset.spacing as f32;
+        // Tiles sit `margin` pixels in from the image edge with `spacing` pixels between them.
+        let columns = ((texture_width - 2.0 * margin + spacing) / (tile_width + spacing)).floor();
+
+        // This calculation is much simpler we only care about getting the remainder
+        // and multiplying that by the tile width plus spacing.
+        let sprite_sheet_x: f32 = margin + (tile % columns).floor() * (tile_width + spacing);
+
+        // Calculation here is (tile / columns).round_down * (tile_height + spacing)
+        // Example: tile 30 / 28 columns = 1.0714 rounded down to 1 * 16 tile_height = 16 Y
+        // which is the 2nd row in the sprite sheet.
+        // Example2: tile 10 / 28 columns = 0.3571 rounded down to 0 * 16 tile_height = 0 Y
+        // which is the 1st row in the sprite sheet.
+        let sprite_sheet_y: f32 = margin + (tile / columns).floor() * (tile_height + spacing);
+
+        let scale = tileset_uv_scale(tileset);
+        Vec4::new(
+            sprite_sheet_x / texture_width * scale.x(),
+            sprite_sheet_y / texture_height * scale.y(),
+            (sprite_sheet_x + tile_width) / texture_width * scale.x(),
+            (sprite_sheet_y + tile_height) / texture_height * scale.y(),
+        )
+    }
+
+    /// A quad drawing a tile object, with its origin at the object's bottom left corner as in
+    /// Tiled. Returns the tileset's first gid along with the mesh.
+    pub(crate) fn tile_object_mesh(&self, object: &tiled::Object) -> Option<(u32, Mesh)> {
+        let tileset = self.tileset_for_gid(object.gid)?;
+        let tile = TiledMapLoader::remove_tile_flags(object.gid) - tileset.first_gid;
+        let uv = self.tileset_uv(tileset, tile);
+        let (mut start_u, mut start_v, mut end_u, mut end_v) = (uv.x(), uv.y(), uv.z(), uv.w());
+        if object.gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+            std::mem::swap(&mut start_u, &mut end_u);
+        }
+        if object.gid & FLIPPED_VERTICALLY_FLAG != 0 {
+            std::mem::swap(&mut start_v, &mut end_v);
+        }
+
+        let (width, height) = (object.width, object.height);
+        let mesh = Mesh {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(vec![
+                    [0.0, 0.0, 0.0],
+                    [0.0, height, 0.0],
+                    [width, height, 0.0],
+                    [width, 0.0, 0.0],
+                ]),
+                VertexAttribute::normal(vec![[0.0, 0.0, 1.0]; 4]),
+                VertexAttribute::uv(vec![
+                    [start_u, end_v],
+                    [start_u, start_v],
+                    [end_u, start_v],
+                    [end_u, end_v],
+                ]),
+                animation_attribute(vec![self.animation_vertex(object.gid); 4]),
+                tint_attribute(vec![[1.0; 4]; 4]),
+            ],
+            indices: Some(vec![0, 2, 1, 0, 3, 2]),
+        };
+        Some((tileset.first_gid, mesh))
+    }
+}
+
+/// Width and height in tiles of the parts a chunk of `chunk_size` is split into, rounded up
+/// so there are never more than `CHUNK_PARTS` along an axis.
+pub(crate) fn part_size(chunk_size: (usize, usize)) -> (usize, usize) {
+    let parts = |size: usize| ((size + CHUNK_PARTS - 1) / CHUNK_PARTS).max(1);
+    (parts(chunk_size.0), parts(chunk_size.1))
+}
+
+/// Whether the chunk-local tile at `x`, `y` of a chunk of `chunk_size` is in `part`, or any
+/// tile when there's no part.
+fn in_part(part: Option<(usize, usize)>, chunk_size: (usize, usize), x: usize, y: usize) -> bool {
+    let (part_width, part_height) = part_size(chunk_size);
+    part.map_or(true, |part| part == (x / part_width, y / part_height))
+}
+
+impl Chunk {
+    /// A chunk of `size` without any tiles.
+    pub(crate) fn empty(chunk_x: usize, chunk_y: usize, size: (usize, usize)) -> Chunk {
+        Chunk {
+            position: Vec2::new(chunk_x as f32, chunk_y as f32),
+            size,
+            gids: Vec::new(),
+        }
+    }
+
+    /// The gid (with flip flags) drawn at the chunk-local tile `x`, `y`, or 0 for none.
+    pub fn gid(&self, x: usize, y: usize) -> u32 {
+        if x >= self.size.0 {
+            return 0;
+        }
+        self.gids.get(y * self.size.0 + x).copied().unwrap_or(0)
+    }
+
+    /// Swaps the tiles of one part of the chunk for those of `chunk`, built for just that part.
+    pub(crate) fn replace_part(&mut self, part: (usize, usize), chunk: &Chunk) {
+        let (width, height) = self.size;
+        if self.gids.is_empty() {
+            self.gids = vec![0; width * height];
+        }
+        for y in 0..height {
+            for x in 0..width {
+                if in_part(Some(part), self.size, x, y) {
+                    self.gids[y * width + x] = chunk.gid(x, y);
+                }
+            }
+        }
+        if self.gids.iter().all(|gid| *gid == 0) {
+            self.gids.clear();
+        }
+    }
+}
+
+/// A tile of a chunk, as drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct TileQuad {
+    /// Map-local left, bottom, right and top edges, y up.
+    pub rect: [f32; 4],
+    /// Start u, start v, end u and end v in the tileset image, swapped where the tile is
+    /// flipped.
+    pub uv: [f32; 4],
+    /// `Map::tint_vertex` of the tile.
+    pub color: [f32; 4],
+    /// How far in front of its layer the tile is drawn, see `Map::y_sort_depth`.
+    pub depth: f32,
+    /// `Map::animation_vertex` of the tile.
+    pub animation: f32,
+}
+
+/// The vertices of a chunk mesh.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ChunkVertices {
+    pub positions: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    /// `Map::animation_vertex` of each vertex.
+    pub animations: Vec<f32>,
+    /// `Map::tint_vertex` of each vertex.
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkVertices {
+    /// Shares the corners of quads that meet with the same uv, animation and tint, such as
+    /// tiles drawn from neighbouring cells of a tileset image, so they're uploaded once.
+    /// Corners whose uvs differ are never shared, since the tiles would bleed into each other.
+    pub fn weld(&mut self) {
+        let mut welded = ChunkVertices::default();
+        let mut shared = HashMap::new();
+        let remap = (0..self.positions.len())
+            .map(|i| {
+                let position = self.positions[i];
+                let uv = self.uvs[i];
+                let animation = self.animations.get(i).copied().unwrap_or(0.0);
+                let color = self.colors.get(i).copied().unwrap_or([1.0; 4]);
+                let mut bits = [0; 10];
+                let values = position
+                    .iter()
+                    .chain(uv.iter())
+                    .chain(Some(&animation))
+                    .chain(color.iter());
+                for (bits, value) in bits.iter_mut().zip(values) {
+                    *bits = value.to_bits();
+                }
+                *shared.entry(bits).or_insert_with(|| {
+                    welded.positions.push(position);
+                    welded.uvs.push(uv);
+                    welded.animations.push(animation);
+                    welded.colors.push(color);
+                    welded.positions.len() as u32 - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        welded.indices = self
+            .indices
+            .iter()
+            .map(|index| remap[*index as usize])
+            .collect();
+        *self = welded;
+    }
+
+    pub fn into_mesh(mut self) -> Mesh {
+        let normals = vec![[0.0, 0.0, 1.0]; self.positions.len()];
+        self.animations.resize(self.positions.len(), 0.0);
+        self.colors.resize(self.positions.len(), [1.0; 4]);
+        Mesh {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(self.positions),
+                VertexAttribute::normal(normals),
+                VertexAttribute::uv(self.uvs),
+                animation_attribute(self.animations),
+                tint_attribute(self.colors),
+            ],
+            indices: Some(self.indices),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_quads_follow_the_render_order() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="16" columns="4">
+  <image source="tiles.png" width="64" height="64"/>
+ </tileset>
+ <layer id="1" name="ground" width="2" height="2">
+  <data encoding="csv">1,6,
+11,16</data>
+ </layer>
+</map>
+"#;
+        let mut map = Map::try_from_bytes(tmx.as_bytes(), Default::default()).unwrap();
+        // Diagonal cells of the tileset, so no corners are welded and each tile keeps its 4.
+        let chunk = Chunk {
+            position: Vec2::zero(),
+            size: (2, 2),
+            gids: vec![1, 6, 11, 16],
+        };
+        let orders = [
+            (RenderOrder::RightDown, [1, 6, 11, 16]),
+            (RenderOrder::RightUp, [11, 16, 1, 6]),
+            (RenderOrder::LeftDown, [6, 1, 16, 11]),
+            (RenderOrder::LeftUp, [16, 11, 6, 1]),
+        ];
+        for (order, gids) in orders.iter() {
+            map.render_order = *order;
+            let vertices = map.chunk_vertices(0, 1, &chunk, None).unwrap();
+            assert_eq!(vertices.positions.len(), 16);
+            let tileset = &map.map.tilesets[0];
+            // The first corner of each quad is its bottom left, with the tile's start u and
+            // end v.
+            let drawn = (0..4)
+                .map(|quad| vertices.uvs[quad * 4])
+                .collect::<Vec<_>>();
+            let expected = gids
+                .iter()
+                .map(|gid| {
+                    let uv = map.tileset_uv(tileset, gid - 1);
+                    [uv.x(), uv.w()]
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(drawn, expected, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn tile_uvs_skip_margin_and_spacing() {
+        // 4 columns and 2 rows of 16 pixel tiles, 1 pixel in from the edges and 2 apart.
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" spacing="2" margin="1" tilecount="8" columns="4">
+  <image source="tiles.png" width="72" height="36"/>
+ </tileset>
+ <layer id="1" name="ground" width="1" height="1">
+  <data encoding="csv">1</data>
+ </layer>
+</map>
+"#;
+        let map = tiled::parse(tmx.as_bytes()).unwrap();
+        let tileset = &map.tilesets[0];
+        let uv =
+            |x: f32, y: f32| Vec4::new(x / 72.0, y / 36.0, (x + 16.0) / 72.0, (y + 16.0) / 36.0);
+        assert_eq!(Map::tile_uv(tileset, 0), uv(1.0, 1.0));
+        assert_eq!(Map::tile_uv(tileset, 3), uv(55.0, 1.0));
+        assert_eq!(Map::tile_uv(tileset, 4), uv(1.0, 19.0));
+    }
+
+    #[test]
+    fn tileset_textures_are_not_image_dependencies() {
+        let mut map = Map::try_from_bytes(
+            include_bytes!("../assets/ortho-map.tmx"),
+            Default::default(),
+        )
+        .unwrap();
+        let image = map.tileset_image_path(&map.map.tilesets[0]).unwrap();
+        assert!(map.image_dependencies().contains(&PathBuf::from(&image)));
+
+        map.set_tileset_texture(image.clone(), bevy::prelude::Handle::from_u128(1));
+        assert!(!map.image_dependencies().contains(&PathBuf::from(&image)));
     }
 }
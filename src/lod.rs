@@ -0,0 +1,351 @@
+use crate::{
+    data_texture::chunk_quad,
+    loader::{TiledMapLoader, FLIPPED_HORIZONTALLY_FLAG, FLIPPED_VERTICALLY_FLAG},
+    map::Chunk,
+    ChunkComponents, ChunkKey, Map, TileMapChunk, TiledChunk, TiledImageLayer, TiledObject,
+    Tileset, MERGED_LAYER,
+};
+use bevy::{
+    prelude::*,
+    render::{camera::OrthographicProjection, texture::TextureFormat},
+};
+use std::collections::{HashMap, HashSet};
+
+/// Draws chunks of orthogonal maps as a single quad with a small prerendered texture once the
+/// 2d camera is zoomed out far enough, so zoomed out views of big maps stay cheap.
+#[derive(Debug, Clone)]
+pub struct ChunkLod {
+    pub enabled: bool,
+    /// Width of a tile on screen in pixels at or below which chunks are drawn from their
+    /// prerendered texture.
+    pub max_tile_pixels: f32,
+    /// Pixels each tile is shrunk to in the prerendered textures.
+    pub pixels_per_tile: u32,
+}
+
+impl Default for ChunkLod {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_tile_pixels: 8.0,
+            pixels_per_tile: 4,
+        }
+    }
+}
+
+/// Marks the entity drawing the prerendered texture of a chunk, spawned next to it by
+/// `apply_chunk_lods` with the same `TiledChunk`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LodChunk {
+    /// The chunk entity it stands in for.
+    pub chunk: Option<Entity>,
+}
+
+/// Chunk and level of detail entities `apply_chunk_lods` hid, shown again by
+/// `show_lod_hidden_chunks` before anything else decides whether they're visible.
+#[derive(Debug, Default)]
+pub struct LodHiddenChunks(HashSet<Entity>);
+
+/// The prerendered mesh, texture and material of a chunk mesh.
+struct ChunkLodAssets {
+    mesh: Handle<Mesh>,
+    texture: Handle<Texture>,
+    material: Handle<ColorMaterial>,
+}
+
+#[derive(Default)]
+pub struct ChunkLodState {
+    mesh_event_reader: EventReader<AssetEvent<Mesh>>,
+    /// Prerendered assets, by the chunk mesh they were rendered from.
+    built: HashMap<Handle<Mesh>, ChunkLodAssets>,
+    /// The level of detail entity of each chunk entity, with the chunk mesh it draws.
+    spawned: HashMap<Entity, (Entity, Handle<Mesh>)>,
+}
+
+/// Reads the pixel at `uv` of an 8 bit RGBA texture.
+pub(crate) fn texel(texture: &Texture, u: f32, v: f32) -> [f32; 4] {
+    let (width, height) = (texture.size.x() as usize, texture.size.y() as usize);
+    let x = ((u * width as f32) as usize).min(width.saturating_sub(1));
+    let y = ((v * height as f32) as usize).min(height.saturating_sub(1));
+    let index = (y * width + x) * 4;
+    match texture.data.get(index..index + 4) {
+        Some(pixel) => [
+            pixel[0] as f32,
+            pixel[1] as f32,
+            pixel[2] as f32,
+            pixel[3] as f32,
+        ],
+        None => [0.0; 4],
+    }
+}
+
+impl Map {
+    /// Whether `apply_chunk_lods` can draw the map's chunks from prerendered textures, which
+    /// are laid out on the orthogonal grid.
+    pub fn can_lod(&self) -> bool {
+        self.map.orientation == tiled::Orientation::Orthogonal && !self.y_sort
+    }
+
+    /// The chunk `key` prerendered from `source`, the texture its tiles are drawn from, at
+    /// `pixels_per_tile` pixels a tile, with each tile's flips and tint. Tiles bigger than the
+    /// map's grid are shrunk into their cell and animated tiles show their first frame.
+    pub fn lod_texture(
+        &self,
+        key: &ChunkKey,
+        source: &Texture,
+        pixels_per_tile: u32,
+    ) -> Option<Texture> {
+        match source.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+            _ => return None,
+        }
+        let layer = self.layers.get(key.layer_id as usize)?;
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.first_gid == key.tileset_guid)?;
+        let chunk = self.build_chunk(
+            layer.layer_index,
+            tileset,
+            key.chunk.0,
+            key.chunk.1,
+            key.part,
+        );
+        let pixels = pixels_per_tile.max(1) as usize;
+        // Each pixel averages a few texels, so detail thinner than a pixel still shows.
+        let samples = (tileset.tile_width as usize / pixels).max(1).min(4);
+        let (width, height) = chunk.size;
+        let row = width * pixels;
+        let mut data = vec![0u8; row * height * pixels * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let gid = chunk.gid(x, y);
+                if gid == 0 {
+                    continue;
+                }
+                let uv = self.tileset_uv(
+                    tileset,
+                    TiledMapLoader::remove_tile_flags(gid) - tileset.first_gid,
+                );
+                let (mut start_u, mut start_v, mut end_u, mut end_v) =
+                    (uv.x(), uv.y(), uv.z(), uv.w());
+                if gid & FLIPPED_HORIZONTALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_u, &mut end_u);
+                }
+                if gid & FLIPPED_VERTICALLY_FLAG != 0 {
+                    std::mem::swap(&mut start_v, &mut end_v);
+                }
+                let tile_x = (chunk.position.x() as usize * width + x) as u32;
+                let tile_y = (chunk.position.y() as usize * height + y) as u32;
+                let tint = self.tint_vertex(layer.layer_index, tile_x, tile_y);
+                for pixel_y in 0..pixels {
+                    for pixel_x in 0..pixels {
+                        let mut sum = [0.0; 4];
+                        for sample_y in 0..samples {
+                            for sample_x in 0..samples {
+                                let s = (pixel_x as f32 + (sample_x as f32 + 0.5) / samples as f32)
+                                    / pixels as f32;
+                                let t = (pixel_y as f32 + (sample_y as f32 + 0.5) / samples as f32)
+                                    / pixels as f32;
+                                let color = texel(
+                                    source,
+                                    start_u + (end_u - start_u) * s,
+                                    start_v + (end_v - start_v) * t,
+                                );
+                                for channel in 0..4 {
+                                    sum[channel] += color[channel];
+                                }
+                            }
+                        }
+                        let count = (samples * samples) as f32;
+                        let index = ((y * pixels + pixel_y) * row + x * pixels + pixel_x) * 4;
+                        for channel in 0..4 {
+                            data[index + channel] =
+                                (sum[channel] / count * tint[channel]).min(255.0) as u8;
+                        }
+                    }
+                }
+            }
+        }
+        Some(Texture::new(
+            Vec2::new(row as f32, (height * pixels) as f32),
+            data,
+            source.format,
+        ))
+    }
+
+    /// The quad a prerendered chunk is drawn with.
+    fn lod_mesh(&self, key: &ChunkKey) -> Option<Mesh> {
+        let layer = self.layers.get(key.layer_id as usize)?;
+        let chunk = Chunk::empty(key.chunk.0, key.chunk.1, self.chunk_size);
+        let rect = self.chunk_rect(layer.layer_index, key.tileset_guid, &chunk);
+        Some(chunk_quad(rect, (1.0, 1.0)))
+    }
+
+    /// The texture the chunks drawing the tileset starting at `tileset_guid` sample.
+    pub(crate) fn chunk_source_texture(
+        &self,
+        tileset_guid: u32,
+        tilesets: &Assets<Tileset>,
+    ) -> Option<Handle<Texture>> {
+        match &self.atlas {
+            Some(atlas) => Some(atlas.texture),
+            None => {
+                tilesets
+                    .get(self.tileset_assets.get(&tileset_guid)?)?
+                    .texture
+            }
+        }
+    }
+}
+
+/// Shows the chunks `apply_chunk_lods` hid last frame again.
+pub fn show_lod_hidden_chunks(mut hidden: ResMut<LodHiddenChunks>, mut query: Query<&mut Draw>) {
+    for entity in hidden.0.drain() {
+        if let Ok(mut draw) = query.get_mut::<Draw>(entity) {
+            draw.is_visible = true;
+        }
+    }
+}
+
+/// Swaps the chunks of orthogonal maps for their prerendered textures while the camera is
+/// zoomed out past `ChunkLod::max_tile_pixels`, and back as it zooms in. Textures are rendered
+/// the first time they're needed, and again when their chunk is rebuilt.
+pub fn apply_chunk_lods(
+    mut commands: Commands,
+    settings: Res<ChunkLod>,
+    mut state: Local<ChunkLodState>,
+    mut hidden: ResMut<LodHiddenChunks>,
+    mesh_events: Res<Events<AssetEvent<Mesh>>>,
+    maps: Res<Assets<Map>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    mut chunk_query: Query<(
+        Entity,
+        &TiledChunk,
+        &TileMapChunk,
+        &Transform,
+        &Handle<Mesh>,
+        Option<&TiledObject>,
+        Option<&TiledImageLayer>,
+    )>,
+    mut lod_query: Query<(Entity, &LodChunk, &mut Draw)>,
+    mut draw_query: Query<&mut Draw>,
+) {
+    let state = &mut *state;
+    // Rebuilt chunks are rendered again.
+    let mut stale = HashSet::new();
+    for event in state.mesh_event_reader.iter(&mesh_events) {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            stale.insert(*handle);
+        }
+    }
+    for handle in stale {
+        if let Some(assets) = state.built.remove(&handle) {
+            meshes.remove(&assets.mesh);
+            textures.remove(&assets.texture);
+            materials.remove(&assets.material);
+        }
+    }
+
+    // How far the first 2d camera is zoomed out, in world units per screen pixel.
+    let mut cameras = camera_query.iter();
+    let zoom = cameras
+        .iter()
+        .next()
+        .map(|(_, transform, _)| transform.value().x_axis().truncate().length());
+
+    let mut chunks = HashSet::new();
+    for (entity, chunk, tile_map_chunk, transform, mesh, object, image_layer) in
+        &mut chunk_query.iter()
+    {
+        // Only tile layer chunks, not tile objects, image layers, backgrounds or stand-ins.
+        if object.is_some() || image_layer.is_some() || chunk.key.tileset_guid == 0 {
+            continue;
+        }
+        if lod_query.get::<LodChunk>(entity).is_ok() {
+            continue;
+        }
+        chunks.insert(entity);
+        let map = match maps.get(&chunk.map) {
+            Some(map) if map.can_lod() && chunk.key.layer_id != MERGED_LAYER => map,
+            _ => continue,
+        };
+        // Chunk vertices are scaled up in tile_map.vert.
+        let tile_pixels = zoom.map_or(std::f32::MAX, |zoom| {
+            map.map.tile_width as f32 * map.scale / zoom.max(std::f32::EPSILON)
+        });
+        if !settings.enabled || tile_pixels > settings.max_tile_pixels {
+            continue;
+        }
+
+        if !state.built.contains_key(mesh) {
+            let source = map
+                .chunk_source_texture(chunk.key.tileset_guid, &tilesets)
+                .and_then(|texture| textures.get(&texture));
+            let texture = source
+                .and_then(|source| map.lod_texture(&chunk.key, source, settings.pixels_per_tile));
+            let (texture, lod_mesh) = match (texture, map.lod_mesh(&chunk.key)) {
+                (Some(texture), Some(lod_mesh)) => (texture, lod_mesh),
+                _ => continue,
+            };
+            let texture = textures.add(texture);
+            let assets = ChunkLodAssets {
+                mesh: meshes.add(lod_mesh),
+                texture,
+                material: materials.add(texture.into()),
+            };
+            state.built.insert(*mesh, assets);
+        }
+        let assets = &state.built[mesh];
+
+        let spawned = state.spawned.get(&entity);
+        if spawned.map_or(true, |(_, drawn)| drawn != mesh) {
+            if let Some((lod_entity, _)) = spawned {
+                commands.despawn(*lod_entity);
+            }
+            commands.spawn(ChunkComponents::new(
+                chunk.map,
+                chunk.map_entity,
+                chunk.key,
+                tile_map_chunk.layer_id,
+                assets.mesh,
+                assets.material,
+                transform.translation(),
+            ));
+            commands.with(LodChunk {
+                chunk: Some(entity),
+            });
+            if let Some(lod_entity) = commands.current_entity() {
+                state.spawned.insert(entity, (lod_entity, *mesh));
+            }
+        }
+        if let Ok(mut draw) = draw_query.get_mut::<Draw>(entity) {
+            if draw.is_visible {
+                draw.is_visible = false;
+                hidden.0.insert(entity);
+            }
+        }
+    }
+
+    // Stand-ins of despawned chunks go with them, and the rest only show when their chunk
+    // was swapped out this frame.
+    let spawned = &mut state.spawned;
+    spawned.retain(|chunk, (lod_entity, _)| {
+        if !chunks.contains(chunk) {
+            commands.despawn(*lod_entity);
+        }
+        chunks.contains(chunk)
+    });
+    for (entity, lod, mut draw) in &mut lod_query.iter() {
+        let shown = lod.chunk.map_or(false, |chunk| hidden.0.contains(&chunk));
+        if !shown && draw.is_visible {
+            draw.is_visible = false;
+            hidden.0.insert(entity);
+        }
+    }
+}
@@ -1,52 +1,608 @@
 use bevy::{
     prelude::*,
     render::{
-        pipeline::{DynamicBinding, PipelineSpecialization, RenderPipeline},
+        mesh::VertexAttribute,
+        pipeline::{DynamicBinding, PipelineSpecialization, PrimitiveTopology, RenderPipeline},
         render_graph::base::MainPass,
     },
+    sprite::Rect,
 };
 
-use crate::{TileMapChunk, TILE_MAP_PIPELINE_HANDLE};
+use crate::{
+    animation::animation_attribute, loader::part_size, merging::merged_key,
+    objects::object_pixel_bounds, placeholder_texture, tileset::tile_uv_table,
+    tints::tint_attribute, ChunkBounds, ChunkPool, ChunkSplits, ImageCollections, ImageLayerRepeat,
+    LayerPalettes, LayerPipelines, MapProperties, MeshingTasks, MissingTexture, MissingTextures,
+    NavData, ObjectId, ObjectIndex, PreloadObjectFiles, SharedTilesets, Shoreline, TileAcoustics,
+    TileAnimation, TileAnimations, TileBlending, TileDataTexture, TileEmission, TileLighting,
+    TileMapChunk, TilePalette, TiledImageLayer, TiledObject, Tileset, TilesetAtlas, TilesetSource,
+    TransparentColorKeys, WangSet, MERGED_LAYER, TILE_MAP_PIPELINE_HANDLE,
+};
 use glam::Vec2;
-use std::collections::{HashMap, HashSet};
-
-#[derive(Debug)]
-pub struct Tile {
-    pub tile_id: u32,
-    pub pos: Vec2,
-    pub vertex: Vec4,
-    pub uv: Vec4,
-}
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
 
-#[derive(Debug)]
+/// The tiles one tileset draws in a chunk of a layer. Quads are generated from them when the
+/// chunk mesh is built.
+#[derive(Debug, Clone)]
 pub struct Chunk {
+    /// Chunk coordinates, in chunks.
     pub position: Vec2,
-    pub tiles: Vec<Vec<Tile>>,
+    /// Width and height in tiles, the map's `chunk_size`.
+    pub size: (usize, usize),
+    /// Gids with flip flags, row by row, 0 where the tileset draws nothing. Empty for chunks
+    /// without tile data, such as those of baked maps, and where the tileset draws no tiles.
+    pub gids: Vec<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TilesetLayer {
     pub tile_size: Vec2,
     pub chunks: Vec<Vec<Chunk>>,
     pub tileset_guid: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Layer {
+    /// Index of the source layer in `map.layers`.
+    pub layer_index: usize,
     pub tileset_layers: Vec<TilesetLayer>,
 }
 
+/// The order Tiled draws tiles in, from the map's `renderorder` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderOrder {
+    RightDown,
+    RightUp,
+    LeftDown,
+    LeftUp,
+}
+
+impl Default for RenderOrder {
+    fn default() -> Self {
+        RenderOrder::RightDown
+    }
+}
+
+impl RenderOrder {
+    pub fn from_attribute(value: &str) -> Option<Self> {
+        match value {
+            "right-down" => Some(RenderOrder::RightDown),
+            "right-up" => Some(RenderOrder::RightUp),
+            "left-down" => Some(RenderOrder::LeftDown),
+            "left-up" => Some(RenderOrder::LeftUp),
+            _ => None,
+        }
+    }
+
+    /// Sort key drawing tiles row by row in this order.
+    pub fn sort_key(self, x: i32, y: i32) -> (i32, i32) {
+        match self {
+            RenderOrder::RightDown => (y, x),
+            RenderOrder::RightUp => (-y, x),
+            RenderOrder::LeftDown => (y, -x),
+            RenderOrder::LeftUp => (-y, -x),
+        }
+    }
+}
+
+/// The order objects of an object group are drawn in, from its `draworder` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectDrawOrder {
+    /// Objects lower down the map are drawn over the ones above them.
+    TopDown,
+    /// Objects are drawn in the order they appear in the group.
+    Index,
+}
+
+impl Default for ObjectDrawOrder {
+    fn default() -> Self {
+        ObjectDrawOrder::TopDown
+    }
+}
+
+impl ObjectDrawOrder {
+    pub fn from_attribute(value: &str) -> Option<Self> {
+        match value {
+            "topdown" => Some(ObjectDrawOrder::TopDown),
+            "index" => Some(ObjectDrawOrder::Index),
+            _ => None,
+        }
+    }
+}
+
+/// What a property belongs to, see `Map::file_properties`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyOwner {
+    Map,
+    /// Index into `map.layers`.
+    Layer(usize),
+    /// Index into `map.image_layers`.
+    ImageLayer(usize),
+    /// Index into `map.object_groups`.
+    ObjectGroup(usize),
+    /// Object id.
+    Object(u32),
+    /// First gid of the tileset.
+    Tileset(u32),
+    /// First gid of the tileset and the tile's id in it.
+    Tile(u32, u32),
+}
+
+/// What the empty (gid 0) cells of a layer draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyTile {
+    /// Nothing, which is what Tiled means by gid 0.
+    Empty,
+    /// The tile with this gid. Fills unpainted cells with a base tile, or maps gid 0 onto the
+    /// tile meant by exporters that wrote it for a real tile.
+    Fill(u32),
+}
+
+impl Default for EmptyTile {
+    fn default() -> Self {
+        EmptyTile::Empty
+    }
+}
+
+/// A generation for a new map asset.
+pub(crate) fn next_generation() -> u32 {
+    static NEXT_GENERATION: AtomicU32 = AtomicU32::new(0);
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Default width and height of a chunk in tiles, see `MapSettings::chunk_size`.
+pub const CHUNK_SIZE: usize = 32;
+
+/// Default world units each map pixel is drawn across, see `MapSettings::scale`.
+pub const MAP_SCALE: f32 = 4.0;
+
+/// Parts along each axis that a frequently edited chunk is split into.
+pub const CHUNK_PARTS: usize = 4;
+
+/// Identifies one chunk mesh: a chunk of one layer drawn with one tileset.
+///
+/// No tileset starts at gid 0, so a `tileset_guid` of 0 marks the background quad.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkKey {
+    /// Index into `Map::layers`.
+    pub layer_id: u32,
+    pub tileset_guid: u32,
+    pub chunk: (usize, usize),
+    /// The part of a split chunk, each a `CHUNK_PARTS`th of the chunk across, or `None` for
+    /// the whole chunk.
+    pub part: Option<(usize, usize)>,
+}
+
+/// A rectangle of tiles, in tile coordinates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where the layers of a map are drawn along z, see `Map::layer_depth`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerDepths {
+    /// z of the first tile layer.
+    pub base: f32,
+    /// z between one layer and the next. Objects and y-sorted tiles are spread within it.
+    pub spacing: f32,
+    /// z of layers by name, in place of their spot in the stack. Negative values put a layer
+    /// behind the first. A `z` float property on a layer does the same from Tiled.
+    pub overrides: HashMap<String, f32>,
+}
+
+impl Default for LayerDepths {
+    fn default() -> Self {
+        Self {
+            base: 0.0,
+            spacing: 1.0,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl LayerDepths {
+    pub fn base(mut self, base: f32) -> Self {
+        self.base = base;
+        self
+    }
+
+    pub fn spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_override(mut self, layer: impl Into<String>, z: f32) -> Self {
+        self.overrides.insert(layer.into(), z);
+        self
+    }
+}
+
+/// How loaded maps are kept in memory.
+#[derive(Debug, Clone)]
+pub struct MapSettings {
+    /// Whether maps keep the gid grids their chunk meshes were built from, see
+    /// `Map::release_source`.
+    pub retain_source: bool,
+    /// Width and height of the chunks maps are split into, in tiles. Bigger chunks mean fewer
+    /// entities and draw calls, smaller ones finer culling, streaming and edit rebuilds.
+    pub chunk_size: (usize, usize),
+    /// Whether the tileset images of maps with several tilesets are packed into one texture
+    /// once loaded, so every layer is drawn with a single material, see `TilesetAtlas`.
+    pub atlas_tilesets: bool,
+    /// Pixels each tile's border is extruded by in the atlas, so sampling at fractional zoom
+    /// picks up the tile's own edge rather than its neighbour's and leaves no seams. Maps with
+    /// one tileset are packed too when this is set.
+    pub atlas_extrusion: u32,
+    /// Whether tiles are drawn at a depth by how far down the map they stand rather than all
+    /// at their layer's, see `Map::set_y_sort`.
+    pub y_sort: bool,
+    /// Where layers are drawn along z.
+    pub layer_depths: LayerDepths,
+    /// Whether tileset and image layer textures are drawn with nearest filtering, for pixel
+    /// art, see `tile_sampler`.
+    pub nearest_filtering: bool,
+    /// Whether each tileset of a map is drawn with one mesh across all of its layers, for
+    /// maps that are never edited, see `Map::merge_layers`.
+    pub merge_layers: bool,
+    /// Whether chunks are drawn as one quad each from a texture of their tiles, for huge or
+    /// often edited maps, see `Map::set_data_texture`.
+    pub data_texture: bool,
+    /// Whether chunks are drawn as a GPU instance per tile rather than a quad per tile, for
+    /// huge or often edited maps, see `Map::set_instanced`.
+    pub instanced: bool,
+    /// Whether tiles hidden behind tiles with the `occludes` property are left out of the chunk
+    /// meshes, see `Map::set_skip_covered_tiles`.
+    pub skip_covered_tiles: bool,
+    /// World units each map pixel is drawn across, see `Map::set_scale`.
+    pub scale: f32,
+    /// Whether the chunks of loaded maps, and of maps rebuilt by these settings, are meshed
+    /// over the next frames on the `AsyncComputeTaskPool`, see `Map::set_background_meshing`.
+    pub background_meshing: bool,
+    /// Textures drawn for tileset images instead of loading them, such as block compressed
+    /// copies, keyed by image path, see `Map::set_tileset_texture`.
+    pub tileset_textures: HashMap<String, Handle<Texture>>,
+}
+
+impl Default for MapSettings {
+    fn default() -> Self {
+        Self {
+            retain_source: true,
+            chunk_size: (CHUNK_SIZE, CHUNK_SIZE),
+            atlas_tilesets: false,
+            atlas_extrusion: 0,
+            y_sort: false,
+            layer_depths: LayerDepths::default(),
+            nearest_filtering: false,
+            merge_layers: false,
+            data_texture: false,
+            instanced: false,
+            skip_covered_tiles: false,
+            scale: MAP_SCALE,
+            background_meshing: false,
+            tileset_textures: HashMap::new(),
+        }
+    }
+}
+
+impl MapSettings {
+    pub fn retain_source(mut self, retain_source: bool) -> Self {
+        self.retain_source = retain_source;
+        self
+    }
+
+    pub fn chunk_size(mut self, width: usize, height: usize) -> Self {
+        self.chunk_size = (width.max(1), height.max(1));
+        self
+    }
+
+    pub fn atlas_tilesets(mut self, atlas_tilesets: bool) -> Self {
+        self.atlas_tilesets = atlas_tilesets;
+        self
+    }
+
+    pub fn atlas_extrusion(mut self, atlas_extrusion: u32) -> Self {
+        self.atlas_extrusion = atlas_extrusion;
+        self
+    }
+
+    pub fn y_sort(mut self, y_sort: bool) -> Self {
+        self.y_sort = y_sort;
+        self
+    }
+
+    pub fn layer_depths(mut self, layer_depths: LayerDepths) -> Self {
+        self.layer_depths = layer_depths;
+        self
+    }
+
+    pub fn nearest_filtering(mut self, nearest_filtering: bool) -> Self {
+        self.nearest_filtering = nearest_filtering;
+        self
+    }
+
+    pub fn merge_layers(mut self, merge_layers: bool) -> Self {
+        self.merge_layers = merge_layers;
+        self
+    }
+
+    pub fn data_texture(mut self, data_texture: bool) -> Self {
+        self.data_texture = data_texture;
+        self
+    }
+
+    pub fn instanced(mut self, instanced: bool) -> Self {
+        self.instanced = instanced;
+        self
+    }
+
+    pub fn skip_covered_tiles(mut self, skip_covered_tiles: bool) -> Self {
+        self.skip_covered_tiles = skip_covered_tiles;
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn background_meshing(mut self, background_meshing: bool) -> Self {
+        self.background_meshing = background_meshing;
+        self
+    }
+
+    pub fn tileset_texture(mut self, image: impl Into<String>, texture: Handle<Texture>) -> Self {
+        self.tileset_textures.insert(image.into(), texture);
+        self
+    }
+}
+
+/// Options for `Map::try_from_bytes`.
+#[derive(Debug, Clone, Default)]
+pub struct MapLoadOptions {
+    /// Where the map file is, or would be. External tilesets are read relative to it, and images
+    /// and files are resolved against its folder. Without a path or a `tileset_reader`, maps
+    /// can only use embedded tilesets and their images are resolved against the asset folder.
+    pub path: Option<PathBuf>,
+    /// Reads external tilesets instead of the filesystem.
+    pub tileset_reader: Option<TilesetReader>,
+    /// Whether the map's chunks are meshed in the background, see
+    /// `Map::set_background_meshing`.
+    pub background_meshing: bool,
+}
+
+/// Reads the `.tsx` file of an external tileset, given its path resolved against the map as
+/// usual, such as out of the zip or pak archive the map came from, see
+/// `Map::from_tmx_bytes_with_tilesets`.
+#[derive(Clone)]
+pub struct TilesetReader(Arc<dyn Fn(&Path) -> std::io::Result<Vec<u8>> + Send + Sync>);
+
+impl TilesetReader {
+    pub fn new(read: impl Fn(&Path) -> std::io::Result<Vec<u8>> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(read))
+    }
+
+    pub fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        (self.0)(path)
+    }
+}
+
+impl std::fmt::Debug for TilesetReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TilesetReader")
+    }
+}
+
+/// Maps the path of an image a map draws, resolved against the map as usual, to the path it's
+/// loaded from through the `AssetServer`, see `Map::from_tmx_bytes`.
+#[derive(Clone)]
+pub struct ImageResolver(Arc<dyn Fn(&Path) -> PathBuf + Send + Sync>);
+
+impl ImageResolver {
+    pub fn new(resolve: impl Fn(&Path) -> PathBuf + Send + Sync + 'static) -> Self {
+        Self(Arc::new(resolve))
+    }
+
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        (self.0)(path)
+    }
+}
+
+impl std::fmt::Debug for ImageResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ImageResolver")
+    }
+}
+
 // An asset for maps
 #[derive(Debug)]
 pub struct Map {
     pub map: tiled::Map,
-    pub meshes: Vec<(u32, u32, Mesh)>,
+    pub meshes: Vec<(ChunkKey, Mesh)>,
     pub layers: Vec<Layer>,
     pub tile_size: Vec2,
+    /// Folder of the map file, relative to the asset folder. Tileset images, image layers and
+    /// files are resolved against it, or against the `.tsx` file that declared a tileset.
+    pub map_folder: PathBuf,
+    /// Where tileset and image layer images are loaded from instead of next to the map, such
+    /// as for maps downloaded at runtime.
+    pub image_resolver: Option<ImageResolver>,
+    #[deprecated(note = "images are resolved against `map_folder` and their `.tsx` files")]
     pub image_folder: String,
+    /// Where each tileset (keyed by first gid) was declared.
+    pub tileset_sources: HashMap<u32, TilesetSource>,
+    /// Each tileset's `<tileoffset>` in pixels (y down), keyed by first gid.
+    pub tile_offsets: HashMap<u32, Vec2>,
+    /// Wang sets of every tileset.
+    pub wang_sets: Vec<WangSet>,
+    /// What empty cells of each tile layer draw, indexed like `map.layers`. Read from a layer's
+    /// `default_tile` int property.
+    pub empty_tiles: Vec<EmptyTile>,
+    /// Gids drawn in place of others, such as by a `TilesetSubstitution`.
+    pub gid_overrides: HashMap<u32, u32>,
+    /// Each tileset's UV table, keyed by first gid. Shared with the `Tileset` asset, and other
+    /// maps drawing the same image, once the map has been processed.
+    pub tile_uvs: HashMap<u32, Arc<Vec<Vec4>>>,
+    /// The shared `Tileset` asset of each tileset with an image, keyed by first gid. Filled in
+    /// once the map has been processed.
+    pub tileset_assets: HashMap<u32, Handle<Tileset>>,
+    /// Textures drawn for tileset images instead of loading them, keyed by image path, see
+    /// `set_tileset_texture`.
+    pub tileset_textures: HashMap<String, Handle<Texture>>,
+    /// Acoustics of tiles that affect sound, keyed by gid.
+    pub tile_acoustics: HashMap<u32, TileAcoustics>,
+    /// Footstep surfaces of tiles with a `footstep` property, keyed by gid.
+    pub tile_footsteps: HashMap<u32, String>,
+    /// Animations of animated tiles, keyed by gid.
+    pub tile_animations: HashMap<u32, TileAnimation>,
+    /// Row of each animated tile in `animation_texture`, keyed by gid.
+    pub animation_rows: HashMap<u32, u32>,
+    /// The frame each animated tile is drawing when animated by `animate_tiles`, keyed by gid.
+    pub animation_frames: HashMap<u32, u32>,
+    /// Shorelines kept up to date as their terrain is edited.
+    pub shorelines: Vec<Shoreline>,
+    /// Each tile layer's offset in pixels (y down), indexed like `map.layers`.
+    pub layer_offsets: Vec<Vec2>,
+    pub render_order: RenderOrder,
+    /// Which axes each image layer repeats along, indexed like `map.image_layers`.
+    pub image_layer_repeats: Vec<ImageLayerRepeat>,
+    /// Each object group's draw order, indexed like `map.object_groups`.
+    pub object_draw_orders: Vec<ObjectDrawOrder>,
+    /// Paths of each object's `file` properties, relative to the asset folder, keyed by object
+    /// id then property name.
+    pub object_files: HashMap<u32, HashMap<String, PathBuf>>,
+    /// Names of the properties typed `file` in the `.tmx` file, which the tiled crate reads as
+    /// strings, so `to_tmx` can write them back as files.
+    pub file_properties: HashMap<PropertyOwner, HashSet<String>>,
+    /// Spatial and class lookup over every object in `map.object_groups`.
+    pub object_index: ObjectIndex,
+    /// Unique to each loaded or generated map asset, so a reloaded map can be told apart from
+    /// edits to the same one.
+    pub generation: u32,
+    /// Bumped whenever objects are added, moved or removed so their entities get re-synced.
+    pub objects_revision: u32,
+    /// Bumped whenever tiles are edited or drawn differently, so anything drawn from them can
+    /// be redrawn.
+    pub tiles_revision: u32,
+    /// Mesh handles of the chunks that have been added to `Assets<Mesh>`.
+    pub chunk_meshes: HashMap<ChunkKey, Handle<Mesh>>,
+    /// Chunks to rebuild next frame.
+    pub dirty_chunks: HashSet<ChunkKey>,
+    /// Which chunks are split into parts because they're edited often.
+    pub chunk_splits: ChunkSplits,
+    /// Nav data loaded with a baked map, dropped once its tiles are edited.
+    pub baked_nav: Option<NavData>,
+    /// Whether `release_source` dropped the gid grids of the chunks.
+    pub source_released: bool,
+    /// The atlas the map's tilesets were packed into, if `MapSettings::atlas_tilesets` is set.
+    pub atlas: Option<TilesetAtlas>,
+    /// Width and height of the map's chunks in tiles, see `set_chunk_size`.
+    pub chunk_size: (usize, usize),
+    /// Whether tiles are drawn at a depth by how far down the map they stand, see `set_y_sort`.
+    pub y_sort: bool,
+    /// Where the map's layers are drawn along z, from `MapSettings::layer_depths`.
+    pub layer_depths: LayerDepths,
+    /// Tints of tiles, keyed by layer (an index into `map.layers`), x and y, see
+    /// `set_tile_tint`.
+    pub tile_tints: HashMap<(usize, u32, u32), Color>,
+    /// Whether each tileset is drawn with one mesh across every layer, see `merge_layers`.
+    pub merged: bool,
+    /// Whether chunks are drawn as one quad each from a cell texture, see `set_data_texture`.
+    pub data_texture: bool,
+    /// Whether chunks are drawn as an instance per tile, see `set_instanced`.
+    pub instanced: bool,
+    /// Cell textures of data texture chunks, waiting to be added to `Assets<Texture>`.
+    pub cells: Vec<(ChunkKey, Texture)>,
+    /// Handles of the cell textures that have been added to `Assets<Texture>`.
+    pub chunk_cells: HashMap<ChunkKey, Handle<Texture>>,
+    /// Whether chunk meshes are only built near streaming anchors, see `set_streamed`.
+    pub streamed: bool,
+    /// World units each map pixel is drawn across, see `set_scale`.
+    pub scale: f32,
+    /// Gids of tiles with the `occludes` property, which hide the tiles behind them.
+    pub occluding_tiles: HashSet<u32>,
+    /// How strongly tiles with the `emissive` property glow, by gid, see `emission`.
+    pub emissive_tiles: HashMap<u32, f32>,
+    /// Whether tiles covered by occluding tiles are left out of the chunk meshes, see
+    /// `set_skip_covered_tiles`.
+    pub skip_covered: bool,
+    /// Whether rebuilt chunks are meshed a batch at a time over the next frames, see
+    /// `set_background_meshing`.
+    pub background_meshing: bool,
+    /// Chunks waiting to be meshed by `mesh_chunks_in_background`.
+    pub unmeshed: Vec<ChunkKey>,
+    /// Chunks being meshed on the `AsyncComputeTaskPool` by `mesh_chunks_in_background`.
+    pub meshing: MeshingTasks,
+    /// Meshes built by `mesh_chunks_in_background`, waiting to be added to `Assets<Mesh>`.
+    pub meshed: Vec<(ChunkKey, Mesh)>,
 }
 
 impl Map {
+    /// Every image the map draws, relative to the asset folder: tileset images followed by
+    /// image layer images.
+    ///
+    /// These load through the `AssetServer` once the map is processed; loading them up front
+    /// avoids a frame of missing textures.
+    pub fn image_dependencies(&self) -> Vec<PathBuf> {
+        let tileset_images = self
+            .map
+            .tilesets
+            .iter()
+            .flat_map(|tileset| {
+                self.collection_image_paths(tileset).unwrap_or_else(|| {
+                    self.tileset_image_path(tileset)
+                        .filter(|path| !self.tileset_textures.contains_key(path))
+                        .into_iter()
+                        .collect()
+                })
+            })
+            .map(PathBuf::from);
+        let layer_images =
+            (0..self.map.image_layers.len()).filter_map(|index| self.image_layer_path(index));
+        tileset_images.chain(layer_images).collect()
+    }
+
+    /// Passes an image path through the map's `image_resolver`, if it has one.
+    pub(crate) fn resolve_image(&self, path: PathBuf) -> PathBuf {
+        match &self.image_resolver {
+            Some(resolver) => resolver.resolve(&path),
+            None => path,
+        }
+    }
+
+    /// Draws the tilesets whose image is at `image`, as given by `tileset_image_path`, with
+    /// `texture` instead of loading the image through the `AssetServer`. This is how GPU
+    /// compressed textures, which Bevy 0.2 can't load itself, are handed to a map: decode or
+    /// upload them some other way and add them to `Assets<Texture>`.
+    ///
+    /// Tiles are still measured against the image's size in the tileset, so a texture padded
+    /// out to other dimensions needs the tileset's `uv_scale_x` and `uv_scale_y` properties,
+    /// see `tileset_uv_scale`. Takes effect when the map is next processed; loaded maps get
+    /// `MapSettings::tileset_textures`.
+    pub fn set_tileset_texture(&mut self, image: impl Into<String>, texture: Handle<Texture>) {
+        self.tileset_textures.insert(image.into(), texture);
+    }
+
+    /// Path of the tileset's image, resolved against the map or `.tsx` file that declared it.
+    pub fn tileset_image_path(&self, tileset: &tiled::Tileset) -> Option<String> {
+        let image = tileset.images.first()?;
+        let source = self
+            .tileset_sources
+            .get(&tileset.first_gid)
+            .unwrap_or(&TilesetSource::Embedded);
+        let path = self.resolve_image(source.resolve_image(&self.map_folder, &image.source));
+        path.to_str().map(|path| path.to_string())
+    }
     pub fn project_ortho(pos: Vec2, tile_width: f32, tile_height: f32) -> Vec2 {
         let x = tile_width * pos.x();
         let y = tile_height * pos.y();
@@ -69,42 +625,834 @@ impl Map {
         let y = ((-(pos.y()) / half_height) - (pos.x() / half_width)) / 2.0;
         Vec2::new(x.round(), y.round())
     }
-    pub fn center(&self, origin: Vec3) -> Vec3 {
+    /// Whether tiles are projected as orthogonal or isometric, the only orientations maps are
+    /// built and drawn with.
+    fn projected(&self) -> bool {
+        match self.map.orientation {
+            tiled::Orientation::Orthogonal | tiled::Orientation::Isometric => true,
+            _ => false,
+        }
+    }
+
+    /// Converts fractional tile coordinates, where (0, 0) is the top corner of the first tile,
+    /// into the map-local space the chunk meshes are built in. `None` for orientations other
+    /// than orthogonal and isometric.
+    pub fn tile_to_world(&self, tile: Vec2) -> Option<Vec2> {
+        if self.projected() {
+            Some(self.tile_to_local(tile))
+        } else {
+            None
+        }
+    }
+
+    /// Converts a map-local position back into fractional tile coordinates, the inverse of
+    /// `tile_to_world`. `None` for orientations other than orthogonal and isometric.
+    pub fn world_to_tile(&self, pos: Vec2) -> Option<Vec2> {
+        if self.projected() {
+            Some(self.local_to_tile(pos))
+        } else {
+            None
+        }
+    }
+
+    /// `tile_to_world` for maps that have been built, which are always orthogonal or
+    /// isometric. Anything else is projected as orthogonal.
+    pub(crate) fn tile_to_local(&self, tile: Vec2) -> Vec2 {
+        let tile_width = self.map.tile_width as f32;
+        let tile_height = self.map.tile_height as f32;
+        // Mesh quads are centered on their projected tile position.
+        let tile = tile - Vec2::new(0.5, 0.5);
+        match self.map.orientation {
+            tiled::Orientation::Isometric => Map::project_iso(tile, tile_width, tile_height),
+            _ => Map::project_ortho(tile, tile_width, tile_height),
+        }
+    }
+
+    /// `world_to_tile` for maps that have been built, see `tile_to_local`.
+    pub(crate) fn local_to_tile(&self, pos: Vec2) -> Vec2 {
+        let tile_width = self.map.tile_width as f32;
+        let tile_height = self.map.tile_height as f32;
+        let tile = match self.map.orientation {
+            tiled::Orientation::Isometric => {
+                let half_width = tile_width / 2.0;
+                let half_height = tile_height / 2.0;
+                Vec2::new(
+                    ((pos.x() / half_width) + (-(pos.y()) / half_height)) / 2.0,
+                    ((-(pos.y()) / half_height) - (pos.x() / half_width)) / 2.0,
+                )
+            }
+            _ => Map::unproject_ortho(pos, tile_width, tile_height),
+        };
+        tile + Vec2::new(0.5, 0.5)
+    }
+
+    /// The offset of `layer` (an index into `map.layers`) in pixels, y down.
+    pub fn layer_offset(&self, layer: usize) -> Vec2 {
+        self.layer_offsets
+            .get(layer)
+            .copied()
+            .unwrap_or_else(Vec2::zero)
+    }
+
+    /// Converts a position in Tiled's object pixel space into map-local space.
+    pub fn object_to_world(&self, pos: Vec2) -> Vec2 {
+        let tile_height = self.map.tile_height as f32;
+        let tile = match self.map.orientation {
+            // Isometric objects measure both axes in tile heights.
+            tiled::Orientation::Isometric => pos / tile_height,
+            _ => Vec2::new(pos.x() / self.map.tile_width as f32, pos.y() / tile_height),
+        };
+        self.tile_to_local(tile)
+    }
+
+    /// Map-local bounds of an object, covering its projected corners.
+    pub fn object_world_bounds(&self, object: &tiled::Object) -> Rect {
+        let (min, max) = object_pixel_bounds(object);
+        let corners = [
+            self.object_to_world(min),
+            self.object_to_world(Vec2::new(max.x(), min.y())),
+            self.object_to_world(max),
+            self.object_to_world(Vec2::new(min.x(), max.y())),
+        ];
+        let mut bounds = Rect {
+            min: corners[0],
+            max: corners[0],
+        };
+        for corner in corners.iter() {
+            bounds.min = bounds.min.min(*corner);
+            bounds.max = bounds.max.max(*corner);
+        }
+        bounds
+    }
+
+    pub fn object(&self, id: ObjectId) -> Option<&tiled::Object> {
+        self.map.object_groups.get(id.group)?.objects.get(id.index)
+    }
+
+    /// Objects whose bounds overlap `rect` (in map-local space), in group then index order.
+    pub fn objects_in_rect<'a>(
+        &'a self,
+        rect: Rect,
+    ) -> impl Iterator<Item = &'a tiled::Object> + 'a {
+        self.object_index
+            .query(&rect)
+            .into_iter()
+            .filter_map(move |id| self.object(id))
+    }
+
+    /// Objects of the given class (Tiled's object "type"), in group then index order.
+    pub fn objects_by_class<'a>(
+        &'a self,
+        class: &str,
+    ) -> impl Iterator<Item = &'a tiled::Object> + 'a {
+        self.object_index
+            .class(class)
+            .iter()
+            .filter_map(move |id| self.object(*id))
+    }
+
+    /// Looks an object up by its Tiled object id.
+    pub fn object_by_id(&self, id: u32) -> Option<&tiled::Object> {
+        self.object(self.object_id(id)?)
+    }
+
+    /// Where the object with the given Tiled object id is stored.
+    pub fn object_id(&self, id: u32) -> Option<ObjectId> {
+        self.object_index.id(id)
+    }
+
+    /// z of the `slot`th layer in the stack of tile layers followed by object groups, as set
+    /// by `layer_depths` for layers without an override.
+    pub fn stack_depth(&self, slot: f32) -> f32 {
+        self.layer_depths.base + slot * self.layer_depths.spacing
+    }
+
+    /// The z override of a layer, from `layer_depths` or else its `z` property.
+    pub(crate) fn depth_override(&self, name: &str, properties: &tiled::Properties) -> Option<f32> {
+        self.layer_depths
+            .overrides
+            .get(name)
+            .copied()
+            .or_else(|| properties.float_property("z"))
+    }
+
+    /// The `layer_id` the chunks of a tile layer are drawn at, by index into `layers`.
+    pub fn layer_depth(&self, layer_id: usize) -> f32 {
+        self.layers
+            .get(layer_id)
+            .and_then(|layer| {
+                let layer = &self.map.layers[layer.layer_index];
+                self.depth_override(&layer.name, &layer.properties)
+            })
+            .unwrap_or_else(|| self.stack_depth(layer_id as f32))
+    }
+
+    /// The `layer_id` the background quad is drawn at, just behind every layer.
+    pub fn background_depth(&self) -> f32 {
+        let lowest = (0..self.layers.len())
+            .map(|layer_id| self.layer_depth(layer_id))
+            .chain((0..self.map.image_layers.len()).map(|index| self.image_layer_depth(index)))
+            .fold(self.layer_depths.base, f32::min);
+        lowest - 0.01 * self.layer_depths.spacing
+    }
+
+    /// The `layer_id` an object is drawn at.
+    ///
+    /// Object groups stack above every tile layer, one layer apart, and objects are spread
+    /// within their group's layer by its draw order.
+    pub fn object_depth(&self, id: ObjectId) -> f32 {
+        let base = self
+            .map
+            .object_groups
+            .get(id.group)
+            .and_then(|group| self.depth_override(&group.name, &group.properties))
+            .unwrap_or_else(|| self.stack_depth((self.layers.len() + id.group) as f32));
+        let fraction = match self
+            .object_draw_orders
+            .get(id.group)
+            .copied()
+            .unwrap_or_default()
+        {
+            ObjectDrawOrder::TopDown => {
+                let height = (self.map.height * self.map.tile_height).max(1) as f32;
+                let y = self.object(id).map_or(0.0, |object| object.y);
+                (y / height).max(0.0).min(1.0)
+            }
+            ObjectDrawOrder::Index => {
+                let count = self
+                    .map
+                    .object_groups
+                    .get(id.group)
+                    .map_or(0, |group| group.objects.len());
+                id.index as f32 / count.max(1) as f32
+            }
+        };
+        // Keep clear of the next group's layer.
+        base + fraction * 0.99 * self.layer_depths.spacing
+    }
+
+    /// Moves an object to a new position in Tiled's object pixel space.
+    ///
+    /// Edits go straight to `self.map`, so anything serializing the source map keeps them.
+    pub fn move_object(&mut self, id: u32, pos: Vec2) -> bool {
+        let object = self
+            .map
+            .object_groups
+            .iter_mut()
+            .flat_map(|group| group.objects.iter_mut())
+            .find(|object| object.id == id);
+        match object {
+            Some(object) => {
+                object.x = pos.x();
+                object.y = pos.y();
+                self.objects_changed();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an object, despawning its entity on the next sync.
+    pub fn remove_object(&mut self, id: u32) -> Option<tiled::Object> {
+        for group in self.map.object_groups.iter_mut() {
+            if let Some(index) = group.objects.iter().position(|object| object.id == id) {
+                let object = group.objects.remove(index);
+                self.objects_changed();
+                return Some(object);
+            }
+        }
+        None
+    }
+
+    /// Adds an object to an object group, assigning it a fresh id which is returned.
+    pub fn add_object(&mut self, group: usize, mut object: tiled::Object) -> Option<u32> {
+        let next_id = self
+            .map
+            .object_groups
+            .iter()
+            .flat_map(|group| group.objects.iter())
+            .map(|object| object.id + 1)
+            .max()
+            .unwrap_or(1);
+        object.id = next_id;
+        self.map.object_groups.get_mut(group)?.objects.push(object);
+        self.objects_changed();
+        Some(next_id)
+    }
+
+    pub(crate) fn objects_changed(&mut self) {
+        self.build_object_index();
+        self.objects_revision += 1;
+    }
+
+    pub(crate) fn build_object_index(&mut self) {
+        let tile_size = self.map.tile_width.max(self.map.tile_height) as f32;
+        let mut index = ObjectIndex::new(tile_size * 8.0);
+        for (group, object_group) in self.map.object_groups.iter().enumerate() {
+            for (i, object) in object_group.objects.iter().enumerate() {
+                let id = ObjectId { group, index: i };
+                index.insert(
+                    id,
+                    object.id,
+                    &object.obj_type,
+                    self.object_world_bounds(object),
+                );
+            }
+        }
+        self.object_index = index;
+    }
+
+    /// Number of chunks along each axis.
+    pub fn chunk_count(&self) -> (usize, usize) {
+        let (width, height) = self.chunk_size;
+        let chunks_x = (self.map.width as f32 / width as f32).ceil().max(1.0) as usize;
+        let chunks_y = (self.map.height as f32 / height as f32).ceil().max(1.0) as usize;
+        (chunks_x, chunks_y)
+    }
+
+    /// Splits the map into chunks of `width` by `height` tiles and rebuilds them. Meant for
+    /// maps that haven't been added to `Assets<Map>` yet, or are only being added; maps loaded
+    /// through the `AssetServer` get `MapSettings::chunk_size`.
+    ///
+    /// Baked maps keep the chunks they were baked with.
+    pub fn set_chunk_size(&mut self, width: usize, height: usize) {
+        self.rebuild_with((width.max(1), height.max(1)), self.y_sort);
+    }
+
+    /// Draws each tile at a depth by how far down the map its bottom edge is, rather than all
+    /// tiles of a layer at one, so tall tiles and sprites placed with `y_sorted_z` overlap by
+    /// which stands in front. Like `set_chunk_size`, this rebuilds the map and is meant for
+    /// maps that aren't in `Assets<Map>` yet; loaded maps get `MapSettings::y_sort`.
+    ///
+    /// Baked maps keep the sorting they were baked with.
+    pub fn set_y_sort(&mut self, y_sort: bool) {
+        self.rebuild_with(self.chunk_size, y_sort);
+    }
+
+    /// Sets where the map's layers are drawn along z. Chunks spawned before keep their depth.
+    /// Y-sorted maps are rebuilt when the spacing changes, and merged ones when anything does,
+    /// unless they're baked.
+    pub fn set_layer_depths(&mut self, layer_depths: LayerDepths) {
+        let resort = (self.y_sort && layer_depths.spacing != self.layer_depths.spacing)
+            || ((self.merged || self.skip_covered) && layer_depths != self.layer_depths);
+        self.layer_depths = layer_depths;
+        if resort && !self.is_prebuilt() {
+            self.chunk_splits = Default::default();
+            self.dirty_chunks.clear();
+            self.build_layers();
+        }
+    }
+
+    /// Rebuilds the map's chunks at `chunk_size` and with `y_sort`, unless nothing changed.
+    pub(crate) fn rebuild_with(&mut self, chunk_size: (usize, usize), y_sort: bool) {
+        if (chunk_size, y_sort) == (self.chunk_size, self.y_sort) || self.is_prebuilt() {
+            return;
+        }
+        self.chunk_size = chunk_size;
+        self.y_sort = y_sort;
+        self.data_texture = self.data_texture && self.can_use_data_texture();
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.build_layers();
+    }
+
+    /// How far in front of its layer a tile is drawn on a y-sorted map, from the map-local y
+    /// of its bottom edge: from 0 at the top of the map to 0.5 at the bottom, keeping clear of
+    /// the next layer.
+    pub fn y_sort_depth(&self, y: f32) -> f32 {
+        let bounds = self.world_bounds();
+        let height = (bounds.max.y() - bounds.min.y()).max(1.0);
+        ((bounds.max.y() - y) / height).max(0.0).min(1.0) * 0.5 * self.layer_depths.spacing
+    }
+
+    /// The z to draw a sprite standing at `world` among the tiles of the `layer`th tile layer
+    /// (indexing `layers`), for a y-sorted map placed at `translation`.
+    pub fn y_sorted_z(&self, translation: Vec3, layer: usize, world: Vec2) -> f32 {
+        let local = self.world_to_local(translation, world);
+        translation.z() + self.layer_depth(layer) + self.y_sort_depth(local.y())
+    }
+
+    /// Whether the chunk meshes were loaded ready-made, as from a baked map, rather than built
+    /// from the tile layers.
+    pub(crate) fn is_prebuilt(&self) -> bool {
+        !self.meshes.is_empty()
+            && self
+                .layers
+                .iter()
+                .flat_map(|layer| layer.tileset_layers.iter())
+                .flat_map(|tileset_layer| tileset_layer.chunks.iter().flatten())
+                .all(|chunk| chunk.gids.is_empty())
+    }
+
+    /// The order tiles are drawn in within a chunk. Tiled only honors `render_order` on
+    /// orthogonal maps; isometric ones are always drawn back to front, row by row.
+    pub fn tile_draw_order(&self) -> RenderOrder {
+        match self.map.orientation {
+            tiled::Orientation::Isometric => RenderOrder::RightDown,
+            _ => self.render_order,
+        }
+    }
+
+    /// Translation of the entity drawing the chunk `key` for a map placed at `translation`.
+    ///
+    /// Chunk entities of a layer are drawn in order of distance from the camera, so on
+    /// isometric maps each is nudged towards the camera by its place back to front, by less
+    /// than the gap between layers. A tile can only be behind tiles further right or down, so
+    /// ordering chunks by the sum of their coordinates draws everything behind a chunk first,
+    /// and tall tiles overlap neighbouring chunks the way they overlap tiles within one. Whole
+    /// chunks next to split ones are ordered by their first part, so tall tiles along that
+    /// boundary can be drawn out of order until the split chunk merges again.
+    pub fn chunk_translation(&self, translation: Vec3, key: &ChunkKey) -> Vec3 {
+        // Y-sorted tiles and merged meshes carry their own depth.
+        if self.map.orientation != tiled::Orientation::Isometric
+            || self.y_sort
+            || key.layer_id == MERGED_LAYER
+        {
+            return translation;
+        }
+        let (chunks_x, chunks_y) = self.chunk_count();
+        let (part_x, part_y) = key.part.unwrap_or((0, 0));
+        let rank = key.chunk.0 * CHUNK_PARTS + part_x + key.chunk.1 * CHUNK_PARTS + part_y;
+        let ranks = (chunks_x + chunks_y) * CHUNK_PARTS;
+        let nudge = rank as f32 / ranks as f32 * 0.5 * self.layer_depths.spacing;
+        translation + Vec3::new(0.0, 0.0, nudge)
+    }
+
+    /// The gid drawn for a cell of `layer` holding `gid`, after filling empty cells, applying
+    /// `gid_overrides` and animating. `tile` and `set_tile` deal in the stored gid, where 0 is
+    /// always an empty cell.
+    pub fn drawn_gid(&self, layer: usize, gid: u32) -> u32 {
+        let gid = self.unanimated_gid(layer, gid);
+        self.animation_frames.get(&gid).copied().unwrap_or(gid)
+    }
+
+    /// `drawn_gid` before animating.
+    pub(crate) fn unanimated_gid(&self, layer: usize, gid: u32) -> u32 {
+        let gid = match self.empty_tiles.get(layer) {
+            Some(EmptyTile::Fill(fill)) if gid == 0 => *fill,
+            _ => gid,
+        };
+        self.gid_overrides.get(&gid).copied().unwrap_or(gid)
+    }
+
+    /// Sets what empty cells of `layer` draw, rebuilding its chunks.
+    pub fn set_empty_tile(&mut self, layer: usize, empty_tile: EmptyTile) {
+        if layer >= self.map.layers.len() {
+            return;
+        }
+        if self.empty_tiles.len() < self.map.layers.len() {
+            self.empty_tiles
+                .resize(self.map.layers.len(), EmptyTile::Empty);
+        }
+        self.empty_tiles[layer] = empty_tile;
+        self.invalidate_layer(layer);
+    }
+
+    /// Draws tiles of one gid as another across every layer, rebuilding the map's chunks.
+    pub fn set_gid_overrides(&mut self, gid_overrides: HashMap<u32, u32>) {
+        if gid_overrides == self.gid_overrides {
+            return;
+        }
+        self.gid_overrides = gid_overrides;
+        for layer in 0..self.map.layers.len() {
+            self.invalidate_layer(layer);
+        }
+    }
+
+    fn invalidate_layer(&mut self, layer: usize) {
+        let (width, height) = (self.map.width, self.map.height);
+        self.invalidate_region(
+            layer,
+            TileRect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        );
+    }
+
+    /// Marks the chunks of `layer` (an index into `map.layers`) overlapping `rect` dirty, so
+    /// edits made directly to the layer's tiles are rebuilt next frame.
+    pub fn invalidate_region(&mut self, layer: usize, rect: TileRect) {
+        self.invalidate_tilesets(layer, rect, None);
+    }
+
+    /// Marks dirty only the chunks of the cell at `x`, `y` of `layer` drawing the tilesets of
+    /// `gids`, such as the gids a cell changed between, so an edit rebuilds one mesh rather
+    /// than one per tileset.
+    pub(crate) fn invalidate_tile(&mut self, layer: usize, x: u32, y: u32, gids: &[u32]) {
+        let tilesets = gids
+            .iter()
+            .map(|gid| self.unanimated_gid(layer, *gid))
+            .filter(|gid| *gid != 0)
+            .filter_map(|gid| self.tileset_for_gid(gid))
+            .map(|tileset| tileset.first_gid)
+            .collect::<Vec<_>>();
+        let rect = TileRect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        self.invalidate_tilesets(layer, rect, Some(&tilesets));
+    }
+
+    /// `invalidate_region` for the tilesets starting at `tilesets`, or all of them.
+    fn invalidate_tilesets(&mut self, layer: usize, rect: TileRect, tilesets: Option<&[u32]>) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        self.tiles_revision += 1;
+        let layer_id = match self.layers.iter().position(|l| l.layer_index == layer) {
+            Some(layer_id) => layer_id,
+            // Hidden layers have no chunks.
+            None => return,
+        };
+        let (chunks_x, chunks_y) = self.chunk_count();
+        let (width, height) = self.chunk_size;
+        let first = (rect.x as usize / width, rect.y as usize / height);
+        let last = (
+            ((rect.x + rect.width - 1) as usize / width).min(chunks_x - 1),
+            ((rect.y + rect.height - 1) as usize / height).min(chunks_y - 1),
+        );
+        let (part_width, part_height) = part_size(self.chunk_size);
+        for tileset_layer in self.layers[layer_id].tileset_layers.iter() {
+            if let Some(tilesets) = tilesets {
+                if !tilesets.contains(&tileset_layer.tileset_guid) {
+                    continue;
+                }
+            }
+            if self.merged {
+                self.dirty_chunks
+                    .insert(merged_key(tileset_layer.tileset_guid));
+                continue;
+            }
+            for x in first.0..=last.0 {
+                for y in first.1..=last.1 {
+                    let key = ChunkKey {
+                        layer_id: layer_id as u32,
+                        tileset_guid: tileset_layer.tileset_guid,
+                        chunk: (x, y),
+                        part: None,
+                    };
+                    if !self.chunk_splits.is_split((key.layer_id, key.chunk)) {
+                        self.dirty_chunks.insert(key);
+                        continue;
+                    }
+                    // Only the parts of a split chunk overlapping `rect` are rebuilt.
+                    let origin = (x * width, y * height);
+                    let part = |tile: u32, origin: usize, size: usize, part_size: usize| {
+                        ((tile as usize).max(origin) - origin).min(size - 1) / part_size
+                    };
+                    let first_part = (
+                        part(rect.x, origin.0, width, part_width),
+                        part(rect.y, origin.1, height, part_height),
+                    );
+                    let last_part = (
+                        part(rect.x + rect.width - 1, origin.0, width, part_width),
+                        part(rect.y + rect.height - 1, origin.1, height, part_height),
+                    );
+                    for part_x in first_part.0..=last_part.0 {
+                        for part_y in first_part.1..=last_part.1 {
+                            self.dirty_chunks.insert(ChunkKey {
+                                part: Some((part_x, part_y)),
+                                ..key
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The tile at `x`, `y` of `layer` (an index into `map.layers`).
+    pub fn tile(&self, layer: usize, x: u32, y: u32) -> Option<&tiled::LayerTile> {
+        match &self.map.layers.get(layer)?.tiles {
+            tiled::LayerData::Finite(tiles) => tiles.get(y as usize)?.get(x as usize),
+            _ => None,
+        }
+    }
+
+    /// Sets the tile at `x`, `y` of `layer` to `gid` (flip flags included) and marks its chunk
+    /// dirty. Shorelines of `layer` are re-tiled around it.
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) -> bool {
+        if !self.write_tile(layer, x, y, gid) {
+            return false;
+        }
+        self.update_shorelines(
+            layer,
+            TileRect {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            },
+        );
+        true
+    }
+
+    /// `set_tile` without updating shorelines.
+    pub(crate) fn write_tile(&mut self, layer: usize, x: u32, y: u32, gid: u32) -> bool {
+        let tile = match self.map.layers.get_mut(layer).map(|layer| &mut layer.tiles) {
+            Some(tiled::LayerData::Finite(tiles)) => tiles
+                .get_mut(y as usize)
+                .and_then(|row| row.get_mut(x as usize)),
+            _ => None,
+        };
+        match tile {
+            Some(tile) => {
+                let previous = tile.gid;
+                *tile = tiled::LayerTile::new(gid);
+                self.baked_nav = None;
+                if let Some(layer_id) = self.layers.iter().position(|l| l.layer_index == layer) {
+                    let chunk = (
+                        x as usize / self.chunk_size.0,
+                        y as usize / self.chunk_size.1,
+                    );
+                    // Data texture chunks rewrite a texture, which is cheap enough whole, and
+                    // streamed ones are only built whole.
+                    if !self.data_texture && !self.instanced && !self.streamed {
+                        self.chunk_splits.edited((layer_id as u32, chunk));
+                    }
+                }
+                self.invalidate_tile(layer, x, y, &[previous, gid]);
+                self.invalidate_covered(layer, x, y);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rebuilds the dirty chunks from the source layers, returning their new meshes.
+    ///
+    /// Whole chunks that are split, and parts of chunks that aren't, get no mesh.
+    pub(crate) fn rebuild_dirty_chunks(&mut self) -> Vec<(ChunkKey, Option<Mesh>)> {
+        let mut rebuilt = self
+            .meshed
+            .drain(..)
+            .map(|(key, mesh)| (key, Some(mesh)))
+            .collect::<Vec<_>>();
+        for key in self.dirty_chunks.drain().collect::<Vec<_>>() {
+            if key.layer_id == MERGED_LAYER {
+                rebuilt.push((key, self.merged_mesh(key.tileset_guid)));
+                continue;
+            }
+            let layer_index = self.layers[key.layer_id as usize].layer_index;
+            let tileset = self
+                .map
+                .tilesets
+                .iter()
+                .find(|tileset| tileset.first_gid == key.tileset_guid);
+            let tileset = match tileset {
+                Some(tileset) => tileset,
+                None => continue,
+            };
+            let split = self.chunk_splits.is_split((key.layer_id, key.chunk));
+            if split != key.part.is_some() {
+                rebuilt.push((key, None));
+                continue;
+            }
+            let chunk = self.build_chunk(layer_index, tileset, key.chunk.0, key.chunk.1, key.part);
+            let mesh = self.chunk_mesh(layer_index, key.tileset_guid, &chunk, key.part);
+            if self.data_texture {
+                if let Some(cells) = self.data_texture_cells(layer_index, key.tileset_guid, &chunk)
+                {
+                    self.cells.push((key, cells));
+                }
+            }
+            if !self.source_released {
+                let tileset_layer = self.layers[key.layer_id as usize]
+                    .tileset_layers
+                    .iter_mut()
+                    .find(|tileset_layer| tileset_layer.tileset_guid == key.tileset_guid)
+                    .unwrap();
+                let stored = &mut tileset_layer.chunks[key.chunk.0][key.chunk.1];
+                match key.part {
+                    Some(part) => stored.replace_part(part, &chunk),
+                    None => *stored = chunk,
+                }
+            }
+            rebuilt.push((key, mesh));
+        }
+        rebuilt
+    }
+
+    /// The map's background color, if it has one.
+    pub fn background_color(&self) -> Option<Color> {
+        self.map.background_colour.map(|colour| {
+            Color::rgb(
+                colour.red as f32 / 255.0,
+                colour.green as f32 / 255.0,
+                colour.blue as f32 / 255.0,
+            )
+        })
+    }
+
+    /// Map-local bounds covering every tile of the map.
+    pub fn world_bounds(&self) -> Rect {
+        let size = Vec2::new(self.map.width as f32, self.map.height as f32);
+        let corners = [
+            self.tile_to_local(Vec2::zero()),
+            self.tile_to_local(Vec2::new(size.x(), 0.0)),
+            self.tile_to_local(size),
+            self.tile_to_local(Vec2::new(0.0, size.y())),
+        ];
+        let mut bounds = Rect {
+            min: corners[0],
+            max: corners[0],
+        };
+        for corner in corners.iter() {
+            bounds.min = bounds.min.min(*corner);
+            bounds.max = bounds.max.max(*corner);
+        }
+        bounds
+    }
+
+    /// A quad covering `world_bounds`, for drawing the background color.
+    pub(crate) fn background_mesh(&self) -> Mesh {
+        let bounds = self.world_bounds();
+        Mesh {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(vec![
+                    [bounds.min.x(), bounds.min.y(), 0.0],
+                    [bounds.min.x(), bounds.max.y(), 0.0],
+                    [bounds.max.x(), bounds.max.y(), 0.0],
+                    [bounds.max.x(), bounds.min.y(), 0.0],
+                ]),
+                VertexAttribute::normal(vec![[0.0, 0.0, 1.0]; 4]),
+                VertexAttribute::uv(vec![[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]),
+                animation_attribute(vec![0.0; 4]),
+                tint_attribute(vec![[1.0; 4]; 4]),
+            ],
+            indices: Some(vec![0, 2, 1, 0, 3, 2]),
+        }
+    }
+
+    /// Translation of the map's chunks given the map entity's origin, so the map's `anchor`
+    /// point lands on it.
+    pub fn translation(&self, anchor: &MapAnchor, origin: Vec3) -> Vec3 {
+        origin - (self.anchor_point(anchor) * self.scale).extend(0.0)
+    }
+
+    /// The map-local point of the map's `anchor`, measured on `world_bounds` so it's the same
+    /// corner of the map whatever its orientation.
+    pub fn anchor_point(&self, anchor: &MapAnchor) -> Vec2 {
+        let bounds = self.world_bounds();
+        let normalized = match anchor {
+            MapAnchor::Origin => return Vec2::zero(),
+            MapAnchor::TopLeft => Vec2::new(0.0, 1.0),
+            MapAnchor::BottomLeft => Vec2::zero(),
+            MapAnchor::Center => Vec2::new(0.5, 0.5),
+            MapAnchor::Custom(point) => *point,
+        };
+        bounds.min + (bounds.max - bounds.min) * normalized
+    }
+
+    /// Converts a map-local position into world space for a map placed at `translation`.
+    pub fn local_to_world(&self, translation: Vec3, local: Vec2) -> Vec3 {
+        // Matches the scale applied to chunk vertices in tile_map.vert.
+        translation + (local * self.scale).extend(0.0)
+    }
+
+    /// Converts a world position into map-local space for a map placed at `translation`.
+    pub fn world_to_local(&self, translation: Vec3, world: Vec2) -> Vec2 {
+        (world - translation.truncate()) / self.scale
+    }
+
+    /// Sets how many world units each map pixel is drawn across. Chunks, objects and image
+    /// layers already spawned are rescaled by `sync_map_scales`, but keep their translations,
+    /// so this is best set before the map is spawned, or through `MapSettings::scale`.
+    pub fn set_scale(&mut self, scale: f32) {
+        if scale > 0.0 {
+            self.scale = scale;
+        }
+    }
+
+    /// Translation placing the map's center on `origin`. `None` for orientations other than
+    /// orthogonal and isometric.
+    pub fn center(&self, origin: Vec3) -> Option<Vec3> {
         let tile_size = Vec2::new(self.map.tile_width as f32, self.map.tile_height as f32);
         let map_center = Vec2::new(self.map.width as f32 / 2.0, self.map.height as f32 / 2.0);
-        match self.map.orientation {
+        let center = match self.map.orientation {
             tiled::Orientation::Orthogonal => {
-                let center = Map::project_ortho(map_center, tile_size.x(), tile_size.y());
-                Vec3::new(
-                    origin.x() - center.x() * 4.0,
-                    origin.y() - center.y() * 4.0,
-                    origin.z(),
-                )
+                Map::project_ortho(map_center, tile_size.x(), tile_size.y())
             }
             tiled::Orientation::Isometric => {
-                let center = Map::project_iso(map_center, tile_size.x(), tile_size.y());
-                Vec3::new(
-                    origin.x() - center.x() * 4.0,
-                    origin.y() - center.y() * 4.0,
-                    origin.z(),
-                )
+                Map::project_iso(map_center, tile_size.x(), tile_size.y())
             }
+            _ => return None,
+        };
+        Some(origin - (center * self.scale).extend(0.0))
+    }
+}
 
-            _ => panic!("Unsupported orientation {:?}", self.map.orientation),
-        }
+/// Which point of a map is placed on its map entity's translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapAnchor {
+    /// The top corner of the first tile, the top left corner of orthogonal maps and the top
+    /// corner of isometric ones.
+    Origin,
+    TopLeft,
+    BottomLeft,
+    Center,
+    /// A point across the map's bounds, from (0, 0) at the bottom left to (1, 1) at the top
+    /// right.
+    Custom(Vec2),
+}
+
+impl Default for MapAnchor {
+    fn default() -> Self {
+        MapAnchor::Origin
     }
 }
 
-#[derive(Default)]
+/// What maps were centered with before `MapAnchor`. Converts to `MapAnchor::Center` when
+/// true and `MapAnchor::Origin` when false.
+#[deprecated(note = "use `MapAnchor::Center`, or `MapAnchor::Origin` for uncentered maps")]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct TiledMapCenter(pub bool);
 
+#[allow(deprecated)]
+impl From<TiledMapCenter> for MapAnchor {
+    fn from(center: TiledMapCenter) -> Self {
+        if center.0 {
+            MapAnchor::Center
+        } else {
+            MapAnchor::Origin
+        }
+    }
+}
+
+/// What to do with the map's background color when it's spawned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapBackground {
+    /// Ignore it.
+    None,
+    /// Set the `ClearColor` resource to it.
+    ClearColor,
+    /// Draw a quad of that color behind every layer.
+    Quad,
+}
+
+impl Default for MapBackground {
+    fn default() -> Self {
+        MapBackground::None
+    }
+}
+
 /// A bundle of tiled map entities.
 #[derive(Bundle)]
 pub struct TiledMapComponents {
     pub map_asset: Handle<Map>,
     pub materials: HashMap<u32, Handle<ColorMaterial>>,
     pub origin: Transform,
-    pub center: TiledMapCenter
+    pub anchor: MapAnchor,
+    pub background: MapBackground,
+    pub preload_files: PreloadObjectFiles,
+    pub pipelines: LayerPipelines,
+    pub palettes: LayerPalettes,
 }
 
 impl Default for TiledMapComponents {
@@ -112,20 +1460,49 @@ impl Default for TiledMapComponents {
         Self {
             map_asset: Handle::default(),
             materials: HashMap::default(),
-            center: TiledMapCenter::default(),
-            origin : Transform::default()
+            anchor: MapAnchor::default(),
+            origin: Transform::default(),
+            background: MapBackground::default(),
+            preload_files: PreloadObjectFiles::default(),
+            pipelines: LayerPipelines::default(),
+            palettes: LayerPalettes::default(),
         }
     }
 }
 
+/// Sent once the chunks and materials of a map entity have been spawned, and again each time
+/// its map is reloaded. The chunk entities exist from the next stage on.
+#[derive(Debug, Clone)]
+pub struct MapReadyEvent {
+    pub map: Handle<Map>,
+    /// The map entity.
+    pub entity: Entity,
+}
+
 #[derive(Default)]
 pub struct MapResourceProviderState {
     map_event_reader: EventReader<AssetEvent<Map>>,
+    /// The generation of each map last processed.
+    processed: HashMap<Handle<Map>, u32>,
+    /// The tileset materials of each processed map, keyed by first gid.
+    materials: HashMap<Handle<Map>, HashMap<u32, Handle<ColorMaterial>>>,
+    /// The map generation each map entity last spawned chunks for.
+    spawned: HashMap<Entity, u32>,
+}
+
+/// Identifies the map and chunk a chunk entity draws.
+#[derive(Debug, Default, Clone)]
+pub struct TiledChunk {
+    pub map: Handle<Map>,
+    pub key: ChunkKey,
+    /// The map entity the chunk was spawned for, or `None` for streamed chunks and overlays.
+    pub map_entity: Option<Entity>,
 }
 
 #[derive(Bundle)]
 pub struct ChunkComponents {
     pub chunk: TileMapChunk,
+    pub tiled_chunk: TiledChunk,
     pub main_pass: MainPass,
     pub material: Handle<ColorMaterial>,
     pub render_pipeline: RenderPipelines,
@@ -133,12 +1510,20 @@ pub struct ChunkComponents {
     pub mesh: Handle<Mesh>,
     pub transform: Transform,
     pub global_transform: GlobalTransform,
+    pub bounds: ChunkBounds,
+    pub animations: TileAnimations,
+    pub data_texture: TileDataTexture,
+    pub lighting: TileLighting,
+    pub emission: TileEmission,
+    pub blending: TileBlending,
+    pub palette: TilePalette,
 }
 
 impl Default for ChunkComponents {
     fn default() -> Self {
         Self {
             chunk: TileMapChunk::default(),
+            tiled_chunk: TiledChunk::default(),
             draw: Draw {
                 is_transparent: true,
                 ..Default::default()
@@ -166,26 +1551,74 @@ impl Default for ChunkComponents {
             )]),
             transform: Default::default(),
             global_transform: Default::default(),
+            bounds: Default::default(),
+            animations: Default::default(),
+            data_texture: Default::default(),
+            lighting: Default::default(),
+            emission: Default::default(),
+            blending: Default::default(),
+            palette: Default::default(),
+        }
+    }
+}
+
+impl ChunkComponents {
+    pub fn new(
+        map: Handle<Map>,
+        map_entity: Option<Entity>,
+        key: ChunkKey,
+        layer_depth: f32,
+        mesh: Handle<Mesh>,
+        material: Handle<ColorMaterial>,
+        translation: Vec3,
+    ) -> Self {
+        Self {
+            chunk: TileMapChunk {
+                layer_id: layer_depth,
+                ..Default::default()
+            },
+            tiled_chunk: TiledChunk {
+                map,
+                key,
+                map_entity,
+            },
+            material,
+            mesh,
+            transform: Transform::from_translation(translation),
+            ..Default::default()
         }
     }
 }
 
 pub fn process_loaded_tile_maps(
     mut commands: Commands,
+    mut chunk_pool: ResMut<ChunkPool>,
     asset_server: Res<AssetServer>,
     mut state: Local<MapResourceProviderState>,
     map_events: Res<Events<AssetEvent<Map>>>,
     mut maps: ResMut<Assets<Map>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut color_keys: ResMut<TransparentColorKeys>,
+    mut missing_textures: ResMut<MissingTextures>,
+    mut image_collections: ResMut<ImageCollections>,
+    mut missing_texture_events: ResMut<Events<MissingTexture>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut tilesets: ResMut<Assets<Tileset>>,
+    mut shared_tilesets: ResMut<SharedTilesets>,
+    mut ready_events: ResMut<Events<MapReadyEvent>>,
     mut query: Query<(
         Entity,
-        &TiledMapCenter,
+        &MapAnchor,
         &Handle<Map>,
         &mut HashMap<u32, Handle<ColorMaterial>>,
         &Transform,
+        &MapBackground,
     )>,
+    mut chunk_query: Query<(Entity, &TiledChunk, &Handle<Mesh>, Option<&TileDataTexture>)>,
 ) {
+    let state = &mut *state;
     let mut changed_maps = HashSet::<Handle<Map>>::new();
     for event in state.map_event_reader.iter(&map_events) {
         match event {
@@ -199,78 +1632,613 @@ pub fn process_loaded_tile_maps(
                 // if mesh was modified and removed in the same update, ignore the modification
                 // events are ordered so future modification events are ok
                 changed_maps.remove(handle);
+                state.materials.remove(handle);
+            }
+        }
+    }
+
+    // Edits also fire `Modified`, so only maps that were loaded again are processed.
+    let processed = &mut state.processed;
+    changed_maps.retain(|handle| match maps.get(handle) {
+        Some(map) => processed.insert(*handle, map.generation) != Some(map.generation),
+        None => false,
+    });
+
+    // Chunks already spawned for a reloaded map are replaced, so edits made in Tiled show up
+    // live. Their meshes and cell textures are overwritten in place so the old GPU buffers
+    // aren't left behind.
+    let mut spawned_meshes = HashMap::<(Handle<Map>, ChunkKey), Handle<Mesh>>::new();
+    let mut spawned_cells = HashMap::<(Handle<Map>, ChunkKey), Handle<Texture>>::new();
+    for (entity, chunk, mesh, data_texture) in &mut chunk_query.iter() {
+        if changed_maps.contains(&chunk.map) {
+            chunk_pool.release(&mut commands, entity);
+            if let Some(cells) = data_texture.and_then(|data_texture| data_texture.cells) {
+                spawned_cells.insert((chunk.map, chunk.key), cells);
+            }
+            if chunk.key.tileset_guid == 0 {
+                // The background quad is rebuilt from the new map size.
+                meshes.remove(mesh);
+            } else {
+                spawned_meshes.insert((chunk.map, chunk.key), *mesh);
             }
         }
     }
 
-    let mut new_meshes = HashMap::<&Handle<Map>, Vec<(u32, u32, Handle<Mesh>)>>::new();
     for changed_map in changed_maps.iter() {
         let map = maps.get_mut(changed_map).unwrap();
 
-        for (_, _, _, mut materials_map, _) in &mut query.iter() {
-            for tileset in &map.map.tilesets {
-                if !materials_map.contains_key(&tileset.first_gid) {
-                    let texture_path =
-                        map.image_folder.clone() + "/" + &tileset.images.first().unwrap().source;
-                    let texture_handle = asset_server.load(texture_path).unwrap();
-                    materials_map.insert(tileset.first_gid, materials.add(texture_handle.into()));
+        profile_span!("create_materials");
+        // Tilesets drawing the same image share a texture, material and UV table across maps.
+        let mut tileset_materials = HashMap::new();
+        for tileset in map.map.tilesets.iter() {
+            if let Some(paths) = map.collection_image_paths(tileset) {
+                // Shared like single images, keyed by all of the collection's images.
+                let key = paths.join("|");
+                let tileset_handle = match shared_tilesets.get(tileset, &key) {
+                    Some(tileset_handle) => tileset_handle,
+                    None => {
+                        let texture = image_collections.load(
+                            &asset_server,
+                            &mut textures,
+                            &paths,
+                            (tileset.tile_width, tileset.tile_height),
+                        );
+                        let layers = tileset.tiles.len() as u32;
+                        let emissive_map = map
+                            .emissive_mask(
+                                tileset,
+                                (tileset.tile_width, tileset.tile_height * layers),
+                            )
+                            .map(|mask| textures.add(mask));
+                        let tileset_handle = tilesets.add(Tileset {
+                            name: tileset.name.clone(),
+                            image_path: key.clone(),
+                            tile_width: tileset.tile_width,
+                            tile_height: tileset.tile_height,
+                            texture: Some(texture),
+                            material: materials.add(texture.into()),
+                            uvs: Arc::new(tile_uv_table(tileset)),
+                            normal_map: None,
+                            emissive_map,
+                        });
+                        shared_tilesets.insert(tileset, &key, tileset_handle);
+                        tileset_handle
+                    }
+                };
+                if let Some(shared) = tilesets.get(&tileset_handle) {
+                    map.tile_uvs.insert(tileset.first_gid, shared.uvs.clone());
+                    tileset_materials.insert(tileset.first_gid, shared.material);
                 }
+                map.tileset_assets.insert(tileset.first_gid, tileset_handle);
+                continue;
             }
+            // Textures load asynchronously; a tileset whose image can't be loaded is drawn with a
+            // placeholder rather than panicking, which matters on the web.
+            let (texture_path, image) =
+                match (map.tileset_image_path(tileset), tileset.images.first()) {
+                    (Some(texture_path), Some(image)) => (texture_path, image),
+                    _ => {
+                        tileset_materials
+                            .insert(tileset.first_gid, materials.add(ColorMaterial::default()));
+                        continue;
+                    }
+                };
+            let provided = map.tileset_textures.get(&texture_path).copied();
+            // Tilesets drawn with a handed over texture only share it with each other.
+            let shared_key = match provided {
+                Some(texture_handle) => format!("{}#{:?}", texture_path, texture_handle.id),
+                None => texture_path.clone(),
+            };
+            let tileset_handle = match shared_tilesets.get(tileset, &shared_key) {
+                Some(tileset_handle) => tileset_handle,
+                None => {
+                    let size = (image.width.max(0) as u32, image.height.max(0) as u32);
+                    let tile_size = tileset.tile_width.min(tileset.tile_height);
+                    let loaded = match provided {
+                        Some(texture_handle) => Ok(texture_handle),
+                        None => asset_server.load(texture_path.as_str()),
+                    };
+                    let (texture, material_handle) = match loaded {
+                        Ok(texture_handle) => {
+                            if let Some(color) = &image.transparent_colour {
+                                color_keys.insert(texture_handle, color);
+                            }
+                            let material_handle = materials.add(texture_handle.into());
+                            // Textures handed over with `set_tileset_texture` aren't loaded.
+                            if provided.is_none() {
+                                missing_textures.watch(
+                                    material_handle,
+                                    *changed_map,
+                                    texture_path.clone(),
+                                    texture_handle,
+                                    size,
+                                    tile_size,
+                                );
+                            }
+                            (Some(texture_handle), material_handle)
+                        }
+                        Err(_) => {
+                            let placeholder = placeholder_texture(size.0, size.1, tile_size / 2);
+                            missing_texture_events.send(MissingTexture {
+                                map: *changed_map,
+                                path: texture_path.clone(),
+                            });
+                            (None, materials.add(textures.add(placeholder).into()))
+                        }
+                    };
+                    let uvs = match map.tile_uvs.get(&tileset.first_gid) {
+                        Some(uvs) => uvs.clone(),
+                        None => Arc::new(tile_uv_table(tileset)),
+                    };
+                    let normal_map = map
+                        .tileset_normal_map_path(tileset)
+                        .and_then(|path| asset_server.load(path.as_str()).ok());
+                    let emissive_map = match map.tileset_emissive_map_path(tileset) {
+                        Some(path) => asset_server.load(path.as_str()).ok(),
+                        None => map
+                            .emissive_mask(tileset, size)
+                            .map(|mask| textures.add(mask)),
+                    };
+                    let tileset_handle = tilesets.add(Tileset {
+                        name: tileset.name.clone(),
+                        image_path: texture_path.clone(),
+                        tile_width: tileset.tile_width,
+                        tile_height: tileset.tile_height,
+                        texture,
+                        material: material_handle,
+                        uvs,
+                        normal_map,
+                        emissive_map,
+                    });
+                    shared_tilesets.insert(tileset, &shared_key, tileset_handle);
+                    tileset_handle
+                }
+            };
+            if let Some(shared) = tilesets.get(&tileset_handle) {
+                map.tile_uvs.insert(tileset.first_gid, shared.uvs.clone());
+                tileset_materials.insert(tileset.first_gid, shared.material);
+            }
+            map.tileset_assets.insert(tileset.first_gid, tileset_handle);
         }
 
-        for mesh in map.meshes.drain(0..map.meshes.len()) {
-            let handle = meshes.add(mesh.2);
-            if new_meshes.contains_key(changed_map) {
-                let mesh_list = new_meshes.get_mut(changed_map).unwrap();
-                mesh_list.push((mesh.0, mesh.1, handle));
-            } else {
-                let mut mesh_list = Vec::new();
-                mesh_list.push((mesh.0, mesh.1, handle));
-                new_meshes.insert(changed_map, mesh_list);
-            }
+        state.materials.insert(*changed_map, tileset_materials);
+
+        for (key, mesh) in map.meshes.drain(0..map.meshes.len()) {
+            let handle = match spawned_meshes.remove(&(*changed_map, key)) {
+                Some(handle) => {
+                    meshes.set(handle, mesh);
+                    handle
+                }
+                None => meshes.add(mesh),
+            };
+            map.chunk_meshes.insert(key, handle);
+        }
+        for (key, cells) in map.cells.drain(0..map.cells.len()) {
+            let handle = match spawned_cells.remove(&(*changed_map, key)) {
+                Some(handle) => {
+                    textures.set(handle, cells);
+                    handle
+                }
+                None => textures.add(cells),
+            };
+            map.chunk_cells.insert(key, handle);
         }
     }
 
-    for (_, center, map_handle, materials_map, origin) in &mut query.iter() {
-        if new_meshes.contains_key(map_handle) {
-            let map = maps.get(map_handle).unwrap();
+    // Whatever is left belongs to chunks the reloaded maps no longer have.
+    for (_, mesh) in spawned_meshes {
+        meshes.remove(&mesh);
+    }
+    for (_, cells) in spawned_cells {
+        textures.remove(&cells);
+    }
 
-            let translation = if center.0 {
-                map.center(origin.translation())
-            } else {
-                origin.translation()
-            };
+    for (entity, anchor, map_handle, mut materials_map, origin, background) in &mut query.iter() {
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let map_materials = match state.materials.get(map_handle) {
+            Some(map_materials) => map_materials,
+            None => continue,
+        };
+        // Chunks are spawned per map entity, once per map generation, so entities added after
+        // their map loaded and several entities drawing different maps each get their own.
+        if state.spawned.insert(entity, map.generation) == Some(map.generation) {
+            continue;
+        }
+        profile_span!("spawn_chunks");
+        // Only this map's materials, since other maps can have different tilesets at the same
+        // first gids.
+        *materials_map = map_materials.clone();
+        commands.insert_one(entity, MapProperties(map.map.properties.clone()));
+
+        let translation = map.translation(anchor, origin.translation());
 
-            let mesh_list = new_meshes.get_mut(map_handle).unwrap();
-
-            for (layer_id, layer) in map.layers.iter().enumerate() {
-                for tileset_layer in layer.tileset_layers.iter() {
-                    let material_handle = materials_map.get(&tileset_layer.tileset_guid).unwrap();
-                    // let mut mesh_list = mesh_list.iter_mut().filter(|(mesh_layer_id, _)| *mesh_layer_id == layer_id as u32).drain(0..mesh_list.len()).collect::<Vec<_>>();
-                    let chunk_mesh_list = mesh_list
-                        .iter()
-                        .filter(|(mesh_layer_id, tileset_guid, _)| {
-                            *mesh_layer_id == layer_id as u32
-                                && *tileset_guid == tileset_layer.tileset_guid
-                        })
-                        .collect::<Vec<_>>();
-                    for (_, _, mesh) in chunk_mesh_list.iter() {
-                        // TODO: Sadly bevy doesn't support multiple meshes on a single entity with multiple materials.
-                        // Change this once it does.
-
-                        // Instead for now spawn a new entity per chunk.
-                        commands.spawn(ChunkComponents {
-                            chunk: TileMapChunk {
-                                // TODO: Support more layers here..
-                                layer_id: layer_id as f32,
-                            },
-                            material: material_handle.clone(),
-                            mesh: mesh.clone(),
-                            transform: Transform::from_translation(translation),
+        if let Some(color) = map.background_color() {
+            match background {
+                MapBackground::None => {}
+                MapBackground::ClearColor => clear_color.0 = color,
+                MapBackground::Quad => {
+                    commands.spawn(ChunkComponents {
+                        chunk: TileMapChunk {
+                            layer_id: map.background_depth(),
                             ..Default::default()
-                        });
+                        },
+                        tiled_chunk: TiledChunk {
+                            map: *map_handle,
+                            key: ChunkKey::default(),
+                            map_entity: Some(entity),
+                        },
+                        material: materials.add(color.into()),
+                        mesh: meshes.add(map.background_mesh()),
+                        transform: Transform::from_translation(translation),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        let mut chunk_meshes = map.chunk_meshes.iter().collect::<Vec<_>>();
+        chunk_meshes.sort_by_key(|(key, _)| (key.layer_id, key.tileset_guid, key.chunk, key.part));
+        for (key, mesh) in chunk_meshes {
+            let material_handle = match materials_map.get(&key.tileset_guid) {
+                Some(material_handle) => material_handle,
+                None => continue,
+            };
+            // TODO: Sadly bevy doesn't support multiple meshes on a single entity with multiple materials.
+            // Change this once it does.
+
+            // Instead for now spawn a new entity per chunk.
+            chunk_pool.spawn(
+                &mut commands,
+                ChunkComponents::new(
+                    *map_handle,
+                    Some(entity),
+                    *key,
+                    map.chunk_depth(key),
+                    *mesh,
+                    *material_handle,
+                    map.chunk_translation(translation, key),
+                ),
+            );
+        }
+        ready_events.send(MapReadyEvent {
+            map: *map_handle,
+            entity,
+        });
+    }
+}
+
+impl Map {
+    /// Drops the gid grid of every chunk, one per tileset of each layer, once the chunk
+    /// meshes are built. Edits rebuild chunks straight from the tile layers, which stay, as do
+    /// objects, properties and everything read from them.
+    pub fn release_source(&mut self) {
+        for layer in self.layers.iter_mut() {
+            for tileset_layer in layer.tileset_layers.iter_mut() {
+                for (x, chunks_y) in tileset_layer.chunks.iter_mut().enumerate() {
+                    for (y, chunk) in chunks_y.iter_mut().enumerate() {
+                        *chunk = Chunk::empty(x, y, self.chunk_size);
+                    }
+                }
+            }
+        }
+        self.meshes = Vec::new();
+        self.source_released = true;
+    }
+}
+
+/// Rebuilds newly added maps at `MapSettings::chunk_size` and with `MapSettings::y_sort`,
+/// merging their layers with `MapSettings::merge_layers`, drawing them from data textures
+/// with `MapSettings::data_texture` or as instances with `MapSettings::instanced`, and gives
+/// them `MapSettings::layer_depths` and `MapSettings::tileset_textures`, before they're
+/// processed.
+pub fn apply_map_settings(
+    settings: Res<MapSettings>,
+    mut map_event_reader: Local<EventReader<AssetEvent<Map>>>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut maps: ResMut<Assets<Map>>,
+) {
+    let mut created = Vec::new();
+    for event in map_event_reader.iter(&map_events) {
+        if let AssetEvent::Created { handle } = event {
+            created.push(*handle);
+        }
+    }
+    for handle in created {
+        // `get_mut` fires `Modified`, so only maps that need it are touched.
+        let apply = maps.get(&handle).map_or(false, |map| {
+            ((map.chunk_size, map.y_sort) != (settings.chunk_size, settings.y_sort)
+                || map.layer_depths != settings.layer_depths
+                || (settings.merge_layers && !map.merged)
+                || (settings.data_texture && !map.data_texture && map.can_use_data_texture())
+                || (settings.instanced && !map.instanced)
+                || settings.skip_covered_tiles != map.skip_covered
+                || settings.scale != map.scale)
+                && map.chunk_meshes.is_empty()
+        });
+        if apply {
+            let map = maps.get_mut(&handle).unwrap();
+            map.set_background_meshing(settings.background_meshing);
+            map.set_layer_depths(settings.layer_depths.clone());
+            map.rebuild_with(settings.chunk_size, settings.y_sort);
+            map.set_skip_covered_tiles(settings.skip_covered_tiles);
+            if settings.merge_layers {
+                map.merge_layers();
+            }
+            map.set_data_texture(settings.data_texture);
+            map.set_instanced(settings.instanced);
+            map.set_scale(settings.scale);
+        }
+        let missing_textures = maps.get(&handle).map_or(false, |map| {
+            settings
+                .tileset_textures
+                .keys()
+                .any(|image| !map.tileset_textures.contains_key(image))
+        });
+        if missing_textures {
+            let map = maps.get_mut(&handle).unwrap();
+            for (image, texture) in settings.tileset_textures.iter() {
+                map.tileset_textures
+                    .entry(image.clone())
+                    .or_insert(*texture);
+            }
+        }
+    }
+}
+
+/// Releases the source data of maps once they're processed, unless `MapSettings` retains it.
+pub fn release_map_sources(
+    settings: Res<MapSettings>,
+    mut map_event_reader: Local<EventReader<AssetEvent<Map>>>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut maps: ResMut<Assets<Map>>,
+) {
+    let mut changed_maps = HashSet::new();
+    for event in map_event_reader.iter(&map_events) {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                changed_maps.insert(*handle);
+            }
+            AssetEvent::Removed { handle } => {
+                changed_maps.remove(handle);
+            }
+        }
+    }
+    if settings.retain_source {
+        return;
+    }
+    for handle in changed_maps {
+        // Only once the chunk meshes have been taken, and `get_mut` fires `Modified` again.
+        let ready = maps.get(&handle).map_or(false, |map| {
+            !map.source_released && map.meshes.is_empty() && map.unmeshed_chunks() == 0
+        });
+        if ready {
+            maps.get_mut(&handle).unwrap().release_source();
+        }
+    }
+}
+
+/// Despawns the chunk, object and image layer entities spawned for maps removed from
+/// `Assets<Map>`, along with the meshes and materials made for them. Tileset materials are
+/// shared between maps and stay.
+///
+/// Map entities themselves are left alone, so they draw their map again if it's added back.
+pub fn despawn_removed_maps(
+    mut commands: Commands,
+    mut chunk_pool: ResMut<ChunkPool>,
+    mut map_event_reader: Local<EventReader<AssetEvent<Map>>>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut chunk_query: Query<(
+        Entity,
+        &TiledChunk,
+        &Handle<Mesh>,
+        &Handle<ColorMaterial>,
+        Option<&TileDataTexture>,
+    )>,
+    mut object_query: Query<(Entity, &TiledObject, Option<&Handle<Mesh>>)>,
+    mut image_layer_query: Query<(
+        Entity,
+        &TiledImageLayer,
+        &Handle<Mesh>,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let mut removed_maps = HashSet::new();
+    for event in map_event_reader.iter(&map_events) {
+        match event {
+            AssetEvent::Removed { handle } => {
+                removed_maps.insert(*handle);
+            }
+            // A map added back under the same handle keeps its new entities.
+            AssetEvent::Created { handle } => {
+                removed_maps.remove(handle);
+            }
+            AssetEvent::Modified { .. } => {}
+        }
+    }
+    if removed_maps.is_empty() {
+        return;
+    }
+
+    for (entity, chunk, mesh, material, data_texture) in &mut chunk_query.iter() {
+        if removed_maps.contains(&chunk.map) {
+            chunk_pool.release(&mut commands, entity);
+            meshes.remove(mesh);
+            if let Some(cells) = data_texture.and_then(|data_texture| data_texture.cells) {
+                textures.remove(&cells);
+            }
+            if chunk.key.tileset_guid == 0 {
+                // The background quad has a material of its own.
+                materials.remove(material);
+            }
+        }
+    }
+    for (entity, object, mesh) in &mut object_query.iter() {
+        if removed_maps.contains(&object.map) {
+            commands.despawn(entity);
+            if let Some(mesh) = mesh {
+                meshes.remove(mesh);
+            }
+        }
+    }
+    for (entity, layer, mesh, material) in &mut image_layer_query.iter() {
+        if removed_maps.contains(&layer.map) {
+            commands.despawn(entity);
+            meshes.remove(mesh);
+            materials.remove(material);
+        }
+    }
+}
+
+/// Marks a map entity to be despawned along with the chunk, object and image layer entities
+/// spawned for it, see `v1::TiledCommands::despawn_tiled_map`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DespawnTiledMap;
+
+/// Despawns map entities marked with `DespawnTiledMap` and everything spawned for them. Chunk
+/// meshes are shared by every entity drawing the map and stay with it.
+pub fn despawn_tiled_maps(
+    mut commands: Commands,
+    mut chunk_pool: ResMut<ChunkPool>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map_query: Query<(Entity, &DespawnTiledMap)>,
+    mut chunk_query: Query<(Entity, &TiledChunk, &Handle<Mesh>, &Handle<ColorMaterial>)>,
+    mut object_query: Query<(Entity, &TiledObject, Option<&Handle<Mesh>>)>,
+    mut image_layer_query: Query<(
+        Entity,
+        &TiledImageLayer,
+        &Handle<Mesh>,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let despawned = map_query
+        .iter()
+        .iter()
+        .map(|(entity, _)| entity)
+        .collect::<HashSet<_>>();
+    if despawned.is_empty() {
+        return;
+    }
+
+    for entity in despawned.iter() {
+        commands.despawn(*entity);
+    }
+    for (entity, chunk, mesh, material) in &mut chunk_query.iter() {
+        let map_entity = match chunk.map_entity {
+            Some(map_entity) => map_entity,
+            None => continue,
+        };
+        if despawned.contains(&map_entity) {
+            chunk_pool.release(&mut commands, entity);
+            if chunk.key.tileset_guid == 0 {
+                // The background quad has a mesh and material of its own.
+                meshes.remove(mesh);
+                materials.remove(material);
+            }
+        }
+    }
+    for (entity, object, mesh) in &mut object_query.iter() {
+        if despawned.contains(&object.map_entity) {
+            commands.despawn(entity);
+            if let Some(mesh) = mesh {
+                meshes.remove(mesh);
+            }
+        }
+    }
+    for (entity, layer, mesh, material) in &mut image_layer_query.iter() {
+        if despawned.contains(&layer.map_entity) {
+            commands.despawn(entity);
+            meshes.remove(mesh);
+            materials.remove(material);
+        }
+    }
+}
+
+/// Rebuilds chunks marked with `Map::invalidate_region` and swaps in their new meshes.
+pub fn rebuild_dirty_chunks(
+    mut commands: Commands,
+    mut chunk_pool: ResMut<ChunkPool>,
+    mut maps: ResMut<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut map_query: Query<(
+        Entity,
+        &MapAnchor,
+        &Handle<Map>,
+        &HashMap<u32, Handle<ColorMaterial>>,
+        &Transform,
+    )>,
+    mut chunk_query: Query<(Entity, &TiledChunk)>,
+) {
+    let dirty_maps = maps
+        .iter()
+        .filter(|(_, map)| !map.dirty_chunks.is_empty() || !map.meshed.is_empty())
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+
+    for map_handle in dirty_maps {
+        let map = maps.get_mut(&map_handle).unwrap();
+        for (key, mesh) in map.rebuild_dirty_chunks() {
+            match (map.chunk_meshes.get(&key).copied(), mesh) {
+                (Some(mesh_handle), Some(mesh)) => {
+                    meshes.set(mesh_handle, mesh);
+                }
+                (Some(mesh_handle), None) => {
+                    // The chunk is empty now.
+                    map.chunk_meshes.remove(&key);
+                    meshes.remove(&mesh_handle);
+                    if let Some(cells) = map.chunk_cells.remove(&key) {
+                        textures.remove(&cells);
+                    }
+                    for (entity, chunk) in &mut chunk_query.iter() {
+                        if chunk.map == map_handle && chunk.key == key {
+                            chunk_pool.release(&mut commands, entity);
+                        }
+                    }
+                }
+                // Streamed chunks are built once an anchor comes near.
+                (None, Some(_)) if map.streamed => {}
+                (None, Some(mesh)) => {
+                    // The chunk was empty, so nothing has been spawned for it yet.
+                    let mesh_handle = meshes.add(mesh);
+                    map.chunk_meshes.insert(key, mesh_handle);
+                    for (entity, anchor, handle, materials_map, origin) in &mut map_query.iter() {
+                        if *handle != map_handle {
+                            continue;
+                        }
+                        if let Some(material_handle) = materials_map.get(&key.tileset_guid) {
+                            let translation = map.translation(anchor, origin.translation());
+                            chunk_pool.spawn(
+                                &mut commands,
+                                ChunkComponents::new(
+                                    map_handle,
+                                    Some(entity),
+                                    key,
+                                    map.chunk_depth(&key),
+                                    mesh_handle,
+                                    *material_handle,
+                                    map.chunk_translation(translation, &key),
+                                ),
+                            );
+                        }
                     }
                 }
+                (None, None) => {}
+            }
+        }
+        for (key, cells) in map.cells.drain(0..map.cells.len()).collect::<Vec<_>>() {
+            match map.chunk_cells.get(&key) {
+                Some(handle) => textures.set(*handle, cells),
+                None if map.streamed && !map.chunk_meshes.contains_key(&key) => {}
+                None => {
+                    map.chunk_cells.insert(key, textures.add(cells));
+                }
             }
         }
     }
@@ -0,0 +1,119 @@
+use crate::{loader::ChunkVertices, ChunkKey, Map};
+use bevy::prelude::*;
+
+/// The `layer_id` of the `ChunkKey` of a merged mesh, which draws one tileset across every
+/// chunk of every layer, see `Map::merge_layers`.
+pub const MERGED_LAYER: u32 = u32::MAX;
+
+impl ChunkVertices {
+    /// Appends `other`, moved `depth` along z.
+    fn append(&mut self, other: ChunkVertices, depth: f32) {
+        let start = self.positions.len() as u32;
+        self.positions.extend(
+            other
+                .positions
+                .into_iter()
+                .map(|[x, y, z]| [x, y, z + depth]),
+        );
+        self.uvs.extend(other.uvs);
+        self.animations.extend(other.animations);
+        self.colors.extend(other.colors);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + start));
+    }
+}
+
+impl Map {
+    /// Draws each tileset of the map with a single mesh across all of its layers and chunks,
+    /// rather than one per chunk of each layer, for maps such as menu backgrounds that are
+    /// never edited. Each layer's depth goes into the vertices, and the merged meshes are
+    /// culled and streamed as a whole.
+    ///
+    /// Edits still work but rebuild a whole tileset's mesh. Layers drawing different tilesets
+    /// only overlap by depth, so half transparent pixels can blend against the wrong layer;
+    /// maps with one tileset, or packed with `MapSettings::atlas_tilesets`, draw as before.
+    /// Like `set_chunk_size`, this is meant for maps that aren't in `Assets<Map>` yet; loaded
+    /// maps get `MapSettings::merge_layers`.
+    pub fn merge_layers(&mut self) {
+        if self.merged || !self.chunk_meshes.is_empty() {
+            return;
+        }
+        self.merged = true;
+        self.data_texture = false;
+        self.instanced = false;
+        self.cells.clear();
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.meshes = self.merged_meshes();
+    }
+
+    /// The merged mesh of every tileset drawing any tiles.
+    pub(crate) fn merged_meshes(&self) -> Vec<(ChunkKey, Mesh)> {
+        self.map
+            .tilesets
+            .iter()
+            .filter_map(|tileset| {
+                let key = merged_key(tileset.first_gid);
+                self.merged_mesh(tileset.first_gid).map(|mesh| (key, mesh))
+            })
+            .collect()
+    }
+
+    /// The mesh drawing every tile of the tileset starting at `tileset_guid`, built from the
+    /// tile layers, bottom layer first.
+    pub(crate) fn merged_mesh(&self, tileset_guid: u32) -> Option<Mesh> {
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.first_gid == tileset_guid)?;
+        let (chunks_x, chunks_y) = self.chunk_count();
+        let mut merged = ChunkVertices::default();
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            for x in 0..chunks_x {
+                for y in 0..chunks_y {
+                    let chunk = self.build_chunk(layer.layer_index, tileset, x, y, None);
+                    let vertices =
+                        match self.chunk_vertices(layer.layer_index, tileset_guid, &chunk, None) {
+                            Some(vertices) => vertices,
+                            None => continue,
+                        };
+                    let key = ChunkKey {
+                        layer_id: layer_id as u32,
+                        tileset_guid,
+                        chunk: (x, y),
+                        part: None,
+                    };
+                    let depth =
+                        self.layer_depth(layer_id) + self.chunk_translation(Vec3::zero(), &key).z();
+                    merged.append(vertices, depth);
+                }
+            }
+        }
+        if merged.positions.is_empty() {
+            None
+        } else {
+            Some(merged.into_mesh())
+        }
+    }
+
+    /// The `layer_id` the chunk `key` is drawn at. Merged meshes carry the depth of their
+    /// layers in their vertices.
+    pub fn chunk_depth(&self, key: &ChunkKey) -> f32 {
+        if key.layer_id == MERGED_LAYER {
+            0.0
+        } else {
+            self.layer_depth(key.layer_id as usize)
+        }
+    }
+}
+
+/// The key of the merged mesh of the tileset starting at `tileset_guid`.
+pub(crate) fn merged_key(tileset_guid: u32) -> ChunkKey {
+    ChunkKey {
+        layer_id: MERGED_LAYER,
+        tileset_guid,
+        chunk: (0, 0),
+        part: None,
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{ChunkKey, Map, MapSettings};
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task, TaskPool},
+};
+use futures_lite::future;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// How many chunk meshes `mesh_chunks_in_background` starts building for each map a frame.
+pub const BACKGROUND_MESHES_PER_FRAME: usize = 64;
+
+/// `MapSettings::background_meshing`, shared with the map loader, which runs off the main
+/// thread, so loaded maps are meshed in the background from their first build.
+#[derive(Debug, Clone, Default)]
+pub struct LoaderMeshing(pub(crate) Arc<AtomicBool>);
+
+/// The mesh of a chunk, and its cell texture on data texture maps.
+type MeshedChunk = Option<(Mesh, Option<Texture>)>;
+
+/// Chunk meshes being built on the `AsyncComputeTaskPool`, from a copy of the map taken when
+/// they were started.
+#[derive(Default)]
+pub struct MeshingTasks {
+    /// The copy chunks are meshed from, and the `tiles_revision` it was taken at.
+    source: Option<(Arc<Map>, u32)>,
+    tasks: Vec<(ChunkKey, Task<MeshedChunk>)>,
+}
+
+impl MeshingTasks {
+    /// How many chunks are being meshed.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+impl std::fmt::Debug for MeshingTasks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MeshingTasks({})", self.tasks.len())
+    }
+}
+
+impl Map {
+    /// Leaves the chunk meshes of the map's builds, such as by `MapSettings` or
+    /// `set_chunk_size`, to `mesh_chunks_in_background` instead of building them all at once.
+    /// Chunks then show up as their tasks finish over the next frames. Merged and streamed maps
+    /// mesh as before.
+    ///
+    /// Maps loaded by the `AssetServer` take this from `MapSettings::background_meshing`, so
+    /// their first build is meshed in the background too.
+    pub fn set_background_meshing(&mut self, background_meshing: bool) {
+        self.background_meshing = background_meshing;
+    }
+
+    /// How many chunks are still waiting for or being meshed by `mesh_chunks_in_background`.
+    pub fn unmeshed_chunks(&self) -> usize {
+        self.unmeshed.len() + self.meshing.len()
+    }
+
+    /// Every chunk of the map's layers drawing any tiles, for `mesh_chunks_in_background`.
+    pub(crate) fn queue_unmeshed(&mut self) {
+        self.unmeshed.clear();
+        self.meshing = Default::default();
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            for tileset_layer in layer.tileset_layers.iter() {
+                for (x, chunks_y) in tileset_layer.chunks.iter().enumerate() {
+                    for (y, chunk) in chunks_y.iter().enumerate() {
+                        if chunk.gids.iter().all(|gid| *gid == 0) {
+                            continue;
+                        }
+                        self.unmeshed.push(ChunkKey {
+                            layer_id: layer_id as u32,
+                            tileset_guid: tileset_layer.tileset_guid,
+                            chunk: (x, y),
+                            part: None,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// The mesh of an unmeshed chunk, and its cell texture on data texture maps.
+    fn mesh_chunk(&self, key: ChunkKey) -> MeshedChunk {
+        let layer = self.layers.get(key.layer_id as usize)?;
+        let tileset_layer = layer
+            .tileset_layers
+            .iter()
+            .find(|tileset_layer| tileset_layer.tileset_guid == key.tileset_guid)?;
+        let chunk = &tileset_layer.chunks[key.chunk.0][key.chunk.1];
+        let mesh = self.chunk_mesh(layer.layer_index, key.tileset_guid, chunk, None)?;
+        let cells = if self.data_texture {
+            self.data_texture_cells(layer.layer_index, key.tileset_guid, chunk)
+        } else {
+            None
+        };
+        Some((mesh, cells))
+    }
+
+    /// Starts meshing up to `count` unmeshed chunks, a task each on `pool`. The tasks mesh
+    /// from a copy of the map, taken again once its tiles have been edited.
+    pub(crate) fn start_meshing(&mut self, pool: &TaskPool, count: usize) {
+        let count = count.min(self.unmeshed.len());
+        if count == 0 {
+            return;
+        }
+        let source = match &self.meshing.source {
+            Some((source, revision)) if *revision == self.tiles_revision => source.clone(),
+            _ => {
+                let source = Arc::new(self.meshing_copy());
+                self.meshing.source = Some((source.clone(), self.tiles_revision));
+                source
+            }
+        };
+        for key in self.unmeshed.drain(..count).collect::<Vec<_>>() {
+            let source = source.clone();
+            let task = pool.spawn(async move { source.mesh_chunk(key) });
+            self.meshing.tasks.push((key, task));
+        }
+    }
+
+    /// Hands the chunks of finished meshing tasks to `rebuild_dirty_chunks` to be added and
+    /// spawned. Tasks meshing tiles that have since been edited are dropped, and their chunks
+    /// queued again.
+    pub(crate) fn finish_meshing(&mut self) {
+        let stale = match &self.meshing.source {
+            Some((_, revision)) => *revision != self.tiles_revision,
+            None => false,
+        };
+        if stale {
+            let tasks = std::mem::take(&mut self.meshing).tasks;
+            let keys = tasks.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+            self.unmeshed.splice(0..0, keys);
+            return;
+        }
+        for (key, mut task) in std::mem::take(&mut self.meshing.tasks) {
+            match future::block_on(future::poll_once(&mut task)) {
+                Some(Some((mesh, cells))) => {
+                    self.meshed.push((key, mesh));
+                    if let Some(cells) = cells {
+                        self.cells.push((key, cells));
+                    }
+                }
+                Some(None) => {}
+                None => self.meshing.tasks.push((key, task)),
+            }
+        }
+        if self.unmeshed_chunks() == 0 {
+            self.meshing.source = None;
+        }
+    }
+
+    /// What the chunk meshes are built from, for meshing tasks. Meshes, cell textures and
+    /// object lookups are left out.
+    #[allow(deprecated)]
+    fn meshing_copy(&self) -> Map {
+        Map {
+            map: self.map.clone(),
+            meshes: Vec::new(),
+            layers: self.layers.clone(),
+            tile_size: self.tile_size,
+            map_folder: self.map_folder.clone(),
+            image_resolver: self.image_resolver.clone(),
+            image_folder: self.image_folder.clone(),
+            tileset_sources: self.tileset_sources.clone(),
+            tile_offsets: self.tile_offsets.clone(),
+            wang_sets: self.wang_sets.clone(),
+            empty_tiles: self.empty_tiles.clone(),
+            gid_overrides: self.gid_overrides.clone(),
+            tile_uvs: self.tile_uvs.clone(),
+            tileset_assets: self.tileset_assets.clone(),
+            tileset_textures: Default::default(),
+            tile_acoustics: self.tile_acoustics.clone(),
+            tile_footsteps: self.tile_footsteps.clone(),
+            tile_animations: self.tile_animations.clone(),
+            animation_rows: self.animation_rows.clone(),
+            animation_frames: self.animation_frames.clone(),
+            shorelines: self.shorelines.clone(),
+            layer_offsets: self.layer_offsets.clone(),
+            render_order: self.render_order,
+            image_layer_repeats: self.image_layer_repeats.clone(),
+            object_draw_orders: self.object_draw_orders.clone(),
+            object_files: Default::default(),
+            file_properties: Default::default(),
+            object_index: Default::default(),
+            generation: self.generation,
+            objects_revision: self.objects_revision,
+            tiles_revision: self.tiles_revision,
+            chunk_meshes: Default::default(),
+            dirty_chunks: Default::default(),
+            chunk_splits: Default::default(),
+            baked_nav: None,
+            source_released: self.source_released,
+            atlas: self.atlas.clone(),
+            chunk_size: self.chunk_size,
+            y_sort: self.y_sort,
+            layer_depths: self.layer_depths.clone(),
+            tile_tints: self.tile_tints.clone(),
+            merged: self.merged,
+            data_texture: self.data_texture,
+            instanced: self.instanced,
+            cells: Vec::new(),
+            chunk_cells: Default::default(),
+            streamed: self.streamed,
+            scale: self.scale,
+            occluding_tiles: self.occluding_tiles.clone(),
+            emissive_tiles: self.emissive_tiles.clone(),
+            skip_covered: self.skip_covered,
+            background_meshing: self.background_meshing,
+            unmeshed: Vec::new(),
+            meshing: Default::default(),
+            meshed: Vec::new(),
+        }
+    }
+}
+
+/// Collects the chunk meshes finished on the `AsyncComputeTaskPool` for each processed map
+/// meshed in the background, see `Map::set_background_meshing`, and starts building up to
+/// `BACKGROUND_MESHES_PER_FRAME` more.
+pub fn mesh_chunks_in_background(pool: Res<AsyncComputeTaskPool>, mut maps: ResMut<Assets<Map>>) {
+    // Chunks are spawned with the materials made when the map is processed.
+    let waiting = maps
+        .iter()
+        .filter(|(_, map)| {
+            map.unmeshed_chunks() > 0
+                && (!map.tileset_assets.is_empty() || map.map.tilesets.is_empty())
+        })
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for handle in waiting {
+        profile_span!("mesh_chunks_in_background");
+        let map = maps.get_mut(&handle).unwrap();
+        map.finish_meshing();
+        map.start_meshing(&pool, BACKGROUND_MESHES_PER_FRAME);
+    }
+}
+
+/// Keeps `LoaderMeshing` up to date with `MapSettings`.
+pub fn share_background_meshing(settings: Res<MapSettings>, meshing: Res<LoaderMeshing>) {
+    meshing
+        .0
+        .store(settings.background_meshing, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MapLoadOptions;
+
+    fn map(background_meshing: bool) -> Map {
+        let options = MapLoadOptions {
+            background_meshing,
+            ..Default::default()
+        };
+        Map::try_from_bytes(include_bytes!("../assets/ortho-map.tmx"), options).unwrap()
+    }
+
+    #[test]
+    fn background_meshing_builds_the_same_chunks_over_several_frames() {
+        let built = map(false);
+        let mut map = map(true);
+        assert!(map.meshes.is_empty());
+        assert_eq!(map.unmeshed_chunks(), built.meshes.len());
+
+        let pool = TaskPool::new();
+        while map.unmeshed_chunks() > 0 {
+            map.finish_meshing();
+            map.start_meshing(&pool, 1);
+        }
+        let mut meshed = map.meshed.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        let mut expected = built.meshes.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        meshed.sort_by_key(|key| (key.layer_id, key.tileset_guid, key.chunk));
+        expected.sort_by_key(|key| (key.layer_id, key.tileset_guid, key.chunk));
+        assert_eq!(meshed, expected);
+    }
+
+    #[test]
+    fn editing_tiles_requeues_chunks_being_meshed() {
+        let mut map = map(true);
+        let chunks = map.unmeshed_chunks();
+        map.start_meshing(&TaskPool::new(), chunks);
+        assert_eq!(map.meshing.len(), chunks);
+
+        map.tiles_revision += 1;
+        map.finish_meshing();
+        assert!(map.meshing.is_empty());
+        assert!(map.meshed.is_empty());
+        assert_eq!(map.unmeshed.len(), chunks);
+    }
+}
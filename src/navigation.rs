@@ -0,0 +1,461 @@
+use crate::{LayerMask, Map, TileRect};
+use bevy::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    hash::Hash,
+};
+
+/// Which tiles of a map can be walked on.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NavGrid {
+    pub width: u32,
+    pub height: u32,
+    walkable: Vec<bool>,
+}
+
+impl NavGrid {
+    /// A grid of `width` by `height` walkable tiles.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            walkable: vec![true; (width * height) as usize],
+        }
+    }
+
+    /// Whether the tile at `x`, `y` can be walked on; tiles off the grid can't.
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as u32) < self.width
+            && (y as u32) < self.height
+            && self.walkable[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    pub fn set_walkable(&mut self, x: u32, y: u32, walkable: bool) {
+        if x < self.width && y < self.height {
+            self.walkable[(y * self.width + x) as usize] = walkable;
+        }
+    }
+
+    /// The shortest path of walkable tiles from `from` to `to`, moving between tiles that share
+    /// an edge, including both ends.
+    pub fn find_path(&self, from: (u32, u32), to: (u32, u32)) -> Option<Vec<(u32, u32)>> {
+        if !self.is_walkable(from.0 as i32, from.1 as i32)
+            || !self.is_walkable(to.0 as i32, to.1 as i32)
+        {
+            return None;
+        }
+        let distance = |a: (u32, u32), b: (u32, u32)| {
+            (a.0 as i32 - b.0 as i32).abs() as u32 + (a.1 as i32 - b.1 as i32).abs() as u32
+        };
+        find_path(
+            from,
+            to,
+            |(x, y)| {
+                let (x, y) = (x as i32, y as i32);
+                [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+                    .iter()
+                    .filter(|(x, y)| self.is_walkable(*x, *y))
+                    .map(|(x, y)| (*x as u32, *y as u32))
+                    .collect()
+            },
+            |tile| distance(tile, to),
+        )
+    }
+
+    /// The connected areas of walkable tiles, moving between tiles that share an edge.
+    pub fn regions(&self) -> NavRegions {
+        let mut labels = vec![0; self.walkable.len()];
+        let mut count = 0;
+        for start in 0..self.walkable.len() {
+            if !self.walkable[start] || labels[start] != 0 {
+                continue;
+            }
+            count += 1;
+            labels[start] = count;
+            let mut open = vec![start];
+            while let Some(index) = open.pop() {
+                let (x, y) = (
+                    (index as u32 % self.width) as i32,
+                    (index as u32 / self.width) as i32,
+                );
+                for (x, y) in [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].iter() {
+                    if self.is_walkable(*x, *y) {
+                        let next = (*y as u32 * self.width + *x as u32) as usize;
+                        if labels[next] == 0 {
+                            labels[next] = count;
+                            open.push(next);
+                        }
+                    }
+                }
+            }
+        }
+        NavRegions {
+            width: self.width,
+            height: self.height,
+            labels,
+            count,
+        }
+    }
+
+    /// The blocked tiles merged into as few rectangles as a greedy row by row sweep finds, for
+    /// static colliders that don't need one body per tile.
+    pub fn merged_colliders(&self) -> Vec<TileRect> {
+        let mut covered = vec![false; self.walkable.len()];
+        let blocked_free = |covered: &[bool], x: u32, y: u32| {
+            let index = (y * self.width + x) as usize;
+            !self.walkable[index] && !covered[index]
+        };
+        let mut colliders = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !blocked_free(&covered, x, y) {
+                    continue;
+                }
+                let mut width = 1;
+                while x + width < self.width && blocked_free(&covered, x + width, y) {
+                    width += 1;
+                }
+                let mut height = 1;
+                while y + height < self.height
+                    && (x..x + width).all(|x| blocked_free(&covered, x, y + height))
+                {
+                    height += 1;
+                }
+                for cover_y in y..y + height {
+                    for cover_x in x..x + width {
+                        covered[(cover_y * self.width + cover_x) as usize] = true;
+                    }
+                }
+                colliders.push(TileRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+        }
+        colliders
+    }
+}
+
+/// Connected areas of walkable tiles of a `NavGrid`, such as rooms or islands, from
+/// `NavGrid::regions`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NavRegions {
+    pub width: u32,
+    pub height: u32,
+    /// The region of each tile, numbered from 1, or 0 for blocked tiles.
+    pub(crate) labels: Vec<u32>,
+    pub(crate) count: u32,
+}
+
+impl NavRegions {
+    /// The region of the tile at `x`, `y`, or `None` for blocked tiles and tiles off the grid.
+    pub fn region_at(&self, x: i32, y: i32) -> Option<u32> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        match self.labels[(y as u32 * self.width + x as u32) as usize] {
+            0 => None,
+            region => Some(region),
+        }
+    }
+
+    /// Number of regions.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Whether a path can lead from one tile to the other.
+    pub fn connected(&self, a: (u32, u32), b: (u32, u32)) -> bool {
+        let region = self.region_at(a.0 as i32, a.1 as i32);
+        region.is_some() && region == self.region_at(b.0 as i32, b.1 as i32)
+    }
+}
+
+/// A map's nav grid along with what's derived from it, from `Map::nav_data`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavData {
+    /// The layers the data was computed from.
+    pub mask: LayerMask,
+    pub grid: NavGrid,
+    pub colliders: Vec<TileRect>,
+    pub regions: NavRegions,
+}
+
+impl NavGrid {
+    /// Computes the colliders and regions of the grid.
+    pub fn nav_data(self, mask: LayerMask) -> NavData {
+        NavData {
+            mask,
+            colliders: self.merged_colliders(),
+            regions: self.regions(),
+            grid: self,
+        }
+    }
+}
+
+impl Map {
+    /// A nav grid where tiles are blocked by any tile on the layers in `mask` whose tileset
+    /// tile has a `solid` bool property set or the class `wall`.
+    pub fn nav_grid(&self, mask: LayerMask) -> NavGrid {
+        match &self.baked_nav {
+            Some(baked) if baked.mask == mask => return baked.grid.clone(),
+            _ => {}
+        }
+        let mut solid = HashSet::new();
+        for tileset in self.map.tilesets.iter() {
+            for tile in tileset.tiles.iter() {
+                let solid_property = match tile.properties.get("solid") {
+                    Some(tiled::PropertyValue::BoolValue(value)) => Some(*value),
+                    _ => None,
+                };
+                let wall = tile.tile_type.as_deref() == Some("wall");
+                if solid_property.unwrap_or(wall) {
+                    solid.insert(tileset.first_gid + tile.id);
+                }
+            }
+        }
+
+        let mut grid = NavGrid::new(self.map.width, self.map.height);
+        for layer in (0..self.map.layers.len()).filter(|layer| mask.contains(*layer)) {
+            for y in 0..self.map.height {
+                for x in 0..self.map.width {
+                    if let Some(tile) = self.tile(layer, x, y) {
+                        if solid.contains(&tile.gid) {
+                            grid.set_walkable(x, y, false);
+                        }
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// The nav grid of the layers in `mask` with its colliders and regions. Maps baked with
+    /// nav data for the same layers return it as baked until their tiles are edited.
+    pub fn nav_data(&self, mask: LayerMask) -> NavData {
+        match &self.baked_nav {
+            Some(baked) if baked.mask == mask => baked.clone(),
+            _ => self.nav_grid(mask).nav_data(mask),
+        }
+    }
+}
+
+/// A tile of one of the maps of a `NavWorld`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorldTile {
+    /// Index into `NavWorld::maps`.
+    pub map: usize,
+    pub x: u32,
+    pub y: u32,
+}
+
+/// A map placed in a `NavWorld`.
+#[derive(Debug, Clone)]
+pub struct WorldMap {
+    pub map: Handle<Map>,
+    pub grid: NavGrid,
+    /// Where the map's top left tile is in world tiles: the `x` and `y` of its entry in the
+    /// `.world` file divided by the tile size.
+    pub origin: (i32, i32),
+}
+
+/// Maps laid out side by side, as in a Tiled `.world` file, that paths can cross.
+///
+/// Walkable tiles on the edge of one map lead into the touching tiles of the maps next to it.
+/// Portals join tiles anywhere else, such as doors and stairs between maps that don't touch.
+#[derive(Debug, Clone, Default)]
+pub struct NavWorld {
+    pub maps: Vec<WorldMap>,
+    /// One-way links between tiles; add one each way for two-way portals.
+    pub portals: Vec<(WorldTile, WorldTile)>,
+}
+
+impl NavWorld {
+    /// Places a map's nav grid at `origin` in world tiles, returning its index.
+    pub fn add_map(&mut self, map: Handle<Map>, grid: NavGrid, origin: (i32, i32)) -> usize {
+        self.maps.push(WorldMap { map, grid, origin });
+        self.maps.len() - 1
+    }
+
+    pub fn add_portal(&mut self, from: WorldTile, to: WorldTile) {
+        self.portals.push((from, to));
+    }
+
+    /// The walkable tile at a position in world tiles, on the first map covering it.
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<WorldTile> {
+        self.maps.iter().enumerate().find_map(|(index, map)| {
+            let (local_x, local_y) = (x - map.origin.0, y - map.origin.1);
+            if map.grid.is_walkable(local_x, local_y) {
+                Some(WorldTile {
+                    map: index,
+                    x: local_x as u32,
+                    y: local_y as u32,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    fn world_position(&self, tile: WorldTile) -> (i32, i32) {
+        let origin = self.maps[tile.map].origin;
+        (origin.0 + tile.x as i32, origin.1 + tile.y as i32)
+    }
+
+    /// The shortest path from `from` to `to` as waypoints on each map it crosses, including
+    /// both ends.
+    pub fn find_path(&self, from: WorldTile, to: WorldTile) -> Option<Vec<WorldTile>> {
+        let walkable = |tile: WorldTile| {
+            self.maps.get(tile.map).map_or(false, |map| {
+                map.grid.is_walkable(tile.x as i32, tile.y as i32)
+            })
+        };
+        if !walkable(from) || !walkable(to) {
+            return None;
+        }
+        let mut portals = HashMap::<WorldTile, Vec<WorldTile>>::new();
+        for (from, to) in self.portals.iter() {
+            if walkable(*to) {
+                portals.entry(*from).or_insert_with(Vec::new).push(*to);
+            }
+        }
+        let goal = self.world_position(to);
+        // Portals can shortcut any distance, so with them the search can't estimate ahead.
+        let estimate = portals.is_empty();
+
+        find_path(
+            from,
+            to,
+            |tile| {
+                let map = &self.maps[tile.map];
+                let (x, y) = (tile.x as i32, tile.y as i32);
+                let mut next = Vec::new();
+                for (x, y) in [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)].iter() {
+                    if map.grid.is_walkable(*x, *y) {
+                        next.push(WorldTile {
+                            map: tile.map,
+                            x: *x as u32,
+                            y: *y as u32,
+                        });
+                    } else if *x < 0
+                        || *y < 0
+                        || *x >= map.grid.width as i32
+                        || *y >= map.grid.height as i32
+                    {
+                        // Off the edge of this map, onto whichever map is there.
+                        let world = (map.origin.0 + x, map.origin.1 + y);
+                        next.extend(self.tile_at(world.0, world.1));
+                    }
+                }
+                if let Some(targets) = portals.get(&tile) {
+                    next.extend(targets.iter().copied());
+                }
+                next
+            },
+            |tile| {
+                if !estimate {
+                    return 0;
+                }
+                let position = self.world_position(tile);
+                ((position.0 - goal.0).abs() + (position.1 - goal.1).abs()) as u32
+            },
+        )
+    }
+}
+
+/// A* search where every step costs one.
+fn find_path<N, F, H>(from: N, to: N, mut neighbours: F, heuristic: H) -> Option<Vec<N>>
+where
+    N: Copy + Eq + Hash + Ord,
+    F: FnMut(N) -> Vec<N>,
+    H: Fn(N) -> u32,
+{
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut cost = HashMap::new();
+    cost.insert(from, 0);
+    open.push(Reverse((heuristic(from), from)));
+
+    while let Some(Reverse((_, current))) = open.pop() {
+        if current == to {
+            let mut path = vec![current];
+            let mut node = current;
+            while let Some(previous) = came_from.get(&node) {
+                node = *previous;
+                path.push(node);
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let next_cost = cost[&current] + 1;
+        for next in neighbours(current) {
+            if cost.get(&next).map_or(true, |cost| next_cost < *cost) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((next_cost + heuristic(next), next)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks `path` runs from `from` to `to` over walkable tiles sharing an edge.
+    fn assert_walks(grid: &NavGrid, path: &[(u32, u32)], from: (u32, u32), to: (u32, u32)) {
+        assert_eq!(path.first(), Some(&from));
+        assert_eq!(path.last(), Some(&to));
+        for tile in path {
+            assert!(grid.is_walkable(tile.0 as i32, tile.1 as i32));
+        }
+        for step in path.windows(2) {
+            let (a, b) = (step[0], step[1]);
+            let distance = (a.0 as i32 - b.0 as i32).abs() + (a.1 as i32 - b.1 as i32).abs();
+            assert_eq!(distance, 1);
+        }
+    }
+
+    #[test]
+    fn open_grid_path_is_straight() {
+        let grid = NavGrid::new(8, 8);
+        let path = grid.find_path((1, 3), (6, 3)).unwrap();
+        assert_eq!(path, (1..=6).map(|x| (x, 3)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let mut grid = NavGrid::new(8, 8);
+        // A wall down x = 4 with a gap at the bottom.
+        for y in 0..7 {
+            grid.set_walkable(4, y, false);
+        }
+        let path = grid.find_path((1, 1), (6, 1)).unwrap();
+        assert_walks(&grid, &path, (1, 1), (6, 1));
+        assert!(path.contains(&(4, 7)));
+        // 5 across plus down to row 7 and back up.
+        assert_eq!(path.len(), 5 + 6 * 2 + 1);
+    }
+
+    #[test]
+    fn unreachable_target_has_no_path() {
+        let mut grid = NavGrid::new(8, 8);
+        for y in 0..8 {
+            grid.set_walkable(4, y, false);
+        }
+        assert_eq!(grid.find_path((1, 1), (6, 1)), None);
+        assert_eq!(grid.find_path((1, 1), (4, 1)), None);
+    }
+
+    #[test]
+    fn path_to_the_start_is_the_start() {
+        let grid = NavGrid::new(8, 8);
+        assert_eq!(grid.find_path((2, 5), (2, 5)), Some(vec![(2, 5)]));
+    }
+}
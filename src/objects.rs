@@ -0,0 +1,405 @@
+use crate::{ChunkComponents, Map, MapAnchor, TileMapChunk, YSort};
+use bevy::{prelude::*, sprite::Rect};
+use glam::Vec2;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+/// Marks an entity spawned for an object of a map entity.
+#[derive(Debug, Clone)]
+pub struct TiledObject {
+    pub map: Handle<Map>,
+    pub map_entity: Entity,
+    /// The Tiled object id.
+    pub id: u32,
+}
+
+/// Sent for every entity spawned for an object, including when its map is reloaded, so
+/// gameplay components can be attached to it.
+#[derive(Debug, Clone)]
+pub struct ObjectSpawnedEvent {
+    pub map_handle: Handle<Map>,
+    pub entity: Entity,
+    pub name: String,
+    /// Tiled's object "type".
+    pub class: String,
+}
+
+/// The files an object's `file` properties point at, keyed by property name.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectFiles {
+    /// Paths relative to the asset folder.
+    pub paths: HashMap<String, PathBuf>,
+    /// Handles of the files, loaded when the map entity has `PreloadObjectFiles(true)`.
+    pub handles: HashMap<String, HandleUntyped>,
+}
+
+/// Whether object `file` properties are loaded through the `AssetServer` when their entities
+/// are spawned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PreloadObjectFiles(pub bool);
+
+/// Identifies an object by its object group and its index within that group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    pub group: usize,
+    pub index: usize,
+}
+
+/// A uniform grid over map-local world space bucketing every object by its bounds.
+///
+/// Built once when the map is loaded so per-frame rect queries only touch nearby cells.
+#[derive(Debug, Default)]
+pub struct ObjectIndex {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<ObjectId>>,
+    bounds: HashMap<ObjectId, Rect>,
+    by_class: HashMap<String, Vec<ObjectId>>,
+    by_id: HashMap<u32, ObjectId>,
+}
+
+impl ObjectIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(1.0),
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, id: ObjectId, object_id: u32, class: &str, bounds: Rect) {
+        let (min, max) = self.cell_range(&bounds);
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                self.cells.entry((x, y)).or_insert_with(Vec::new).push(id);
+            }
+        }
+        self.bounds.insert(id, bounds);
+        self.by_id.insert(object_id, id);
+        if !class.is_empty() {
+            let ids = self
+                .by_class
+                .entry(class.to_string())
+                .or_insert_with(Vec::new);
+            ids.push(id);
+            ids.sort();
+        }
+    }
+
+    /// Object ids whose bounds overlap `rect`, in group then index order.
+    pub fn query(&self, rect: &Rect) -> Vec<ObjectId> {
+        let (min, max) = self.cell_range(rect);
+        let mut found = HashSet::new();
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                if let Some(ids) = self.cells.get(&(x, y)) {
+                    found.extend(
+                        ids.iter()
+                            .filter(|id| overlaps(&self.bounds[id], rect))
+                            .copied(),
+                    );
+                }
+            }
+        }
+        let mut found = found.into_iter().collect::<Vec<_>>();
+        found.sort();
+        found
+    }
+
+    /// Object ids with the given class (Tiled's object "type"), in group then index order.
+    pub fn class(&self, class: &str) -> &[ObjectId] {
+        self.by_class
+            .get(class)
+            .map(|ids| ids.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Where the object with the given Tiled object id is stored.
+    pub fn id(&self, object_id: u32) -> Option<ObjectId> {
+        self.by_id.get(&object_id).copied()
+    }
+
+    pub fn bounds(&self, id: ObjectId) -> Option<&Rect> {
+        self.bounds.get(&id)
+    }
+
+    fn cell_range(&self, rect: &Rect) -> ((i32, i32), (i32, i32)) {
+        let cell = |v: Vec2| {
+            (
+                (v.x() / self.cell_size).floor() as i32,
+                (v.y() / self.cell_size).floor() as i32,
+            )
+        };
+        (cell(rect.min), cell(rect.max))
+    }
+}
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.min.x() <= b.max.x()
+        && a.max.x() >= b.min.x()
+        && a.min.y() <= b.max.y()
+        && a.max.y() >= b.min.y()
+}
+
+/// Bounds of an object in Tiled's object pixel space (y down), before projection.
+pub(crate) fn object_pixel_bounds(object: &tiled::Object) -> (Vec2, Vec2) {
+    // Tile objects are anchored at their bottom left corner.
+    let origin = if object.gid != 0 {
+        Vec2::new(object.x, object.y - object.height)
+    } else {
+        Vec2::new(object.x, object.y)
+    };
+    let points = match &object.shape {
+        tiled::ObjectShape::Polyline { points } | tiled::ObjectShape::Polygon { points } => {
+            points.clone()
+        }
+        tiled::ObjectShape::Point(_, _) => vec![(0.0, 0.0)],
+        tiled::ObjectShape::Rect { width, height }
+        | tiled::ObjectShape::Ellipse { width, height } => {
+            vec![(0.0, 0.0), (*width, *height)]
+        }
+    };
+    let mut min = Vec2::new(std::f32::MAX, std::f32::MAX);
+    let mut max = Vec2::new(std::f32::MIN, std::f32::MIN);
+    for (x, y) in points {
+        let point = origin + Vec2::new(x, y);
+        min = min.min(point);
+        max = max.max(point);
+    }
+    (min, max)
+}
+
+/// Rotation of an object's entity; Tiled rotates clockwise in degrees.
+pub fn object_rotation(object: &tiled::Object) -> Quat {
+    Quat::from_rotation_z(-object.rotation.to_radians())
+}
+
+/// Spawns, moves and despawns object entities so they match their map's objects.
+///
+/// Runs whenever a map's `objects_revision` differs from the last one synced for a map entity,
+/// respawning every object when the map itself was reloaded.
+/// Tile objects also get a quad drawing their tile, stacked by their group's draw order, and
+/// objects with `file` properties get `ObjectFiles`. An `ObjectSpawnedEvent` is sent for each
+/// entity spawned.
+pub fn sync_map_objects(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut spawned_events: ResMut<Events<ObjectSpawnedEvent>>,
+    mut synced: Local<HashMap<Entity, (u32, u32)>>,
+    mut map_query: Query<(
+        Entity,
+        &Handle<Map>,
+        &MapAnchor,
+        &Transform,
+        &HashMap<u32, Handle<ColorMaterial>>,
+        &PreloadObjectFiles,
+    )>,
+    mut object_query: Query<(
+        Entity,
+        &TiledObject,
+        &mut Transform,
+        Option<&mut TileMapChunk>,
+        Option<&Handle<Mesh>>,
+    )>,
+) {
+    let mut stale_maps = Vec::new();
+    for (map_entity, map_handle, anchor, origin, materials_map, preload) in &mut map_query.iter() {
+        if let Some(map) = maps.get(map_handle) {
+            // Tile objects need the tileset materials, which are created with the chunks.
+            let materials_ready = map
+                .map
+                .tilesets
+                .iter()
+                .all(|tileset| materials_map.contains_key(&tileset.first_gid));
+            let revision = (map.generation, map.objects_revision);
+            if materials_ready && synced.get(&map_entity) != Some(&revision) {
+                let translation = map.translation(anchor, origin.translation());
+                // A reloaded map gets all its objects respawned.
+                let reloaded = synced
+                    .get(&map_entity)
+                    .map_or(false, |(generation, _)| *generation != map.generation);
+                stale_maps.push((
+                    map_entity,
+                    *map_handle,
+                    translation,
+                    materials_map.clone(),
+                    *preload,
+                    reloaded,
+                ));
+            }
+        }
+    }
+
+    for (map_entity, map_handle, translation, materials_map, preload, reloaded) in stale_maps {
+        let map = maps.get(&map_handle).unwrap();
+        let mut existing = HashMap::new();
+        // Meshes of the tile objects of a reloaded map, overwritten in place by the objects
+        // respawned with the same ids.
+        let mut spawned_meshes = HashMap::new();
+        for (entity, object, mut transform, chunk, mesh) in &mut object_query.iter() {
+            if object.map_entity != map_entity {
+                continue;
+            }
+            if reloaded {
+                commands.despawn(entity);
+                if let Some(mesh) = mesh {
+                    spawned_meshes.insert(object.id, *mesh);
+                }
+                continue;
+            }
+            match map.object_id(object.id) {
+                Some(id) => {
+                    let tiled_object = map.object(id).unwrap();
+                    let pos = map.object_to_world(Vec2::new(tiled_object.x, tiled_object.y));
+                    transform.set_translation(map.local_to_world(translation, pos));
+                    transform.set_rotation(object_rotation(tiled_object));
+                    if let Some(mut chunk) = chunk {
+                        chunk.layer_id = map.object_depth(id);
+                    }
+                    existing.insert(object.id, entity);
+                }
+                None => {
+                    commands.despawn(entity);
+                    if let Some(mesh) = mesh {
+                        meshes.remove(mesh);
+                    }
+                }
+            }
+        }
+
+        for (group, object_group) in map.map.object_groups.iter().enumerate() {
+            for (index, object) in object_group.objects.iter().enumerate() {
+                if existing.contains_key(&object.id) {
+                    continue;
+                }
+                let pos = map.object_to_world(Vec2::new(object.x, object.y));
+                let transform = Transform::from_translation_rotation(
+                    map.local_to_world(translation, pos),
+                    object_rotation(object),
+                );
+                let tiled_object = TiledObject {
+                    map: map_handle,
+                    map_entity,
+                    id: object.id,
+                };
+                match map.tile_object_mesh(object) {
+                    Some((tileset_guid, mesh)) if object.visible => {
+                        let mesh = match spawned_meshes.remove(&object.id) {
+                            Some(handle) => {
+                                meshes.set(handle, mesh);
+                                handle
+                            }
+                            None => meshes.add(mesh),
+                        };
+                        commands
+                            .spawn(ChunkComponents {
+                                chunk: TileMapChunk {
+                                    layer_id: map.object_depth(ObjectId { group, index }),
+                                    ..Default::default()
+                                },
+                                material: materials_map[&tileset_guid],
+                                mesh,
+                                transform,
+                                ..Default::default()
+                            })
+                            .with(tiled_object);
+                        // Sorted among the tiles by where they stand, rather than by group.
+                        if map.y_sort {
+                            commands.with(YSort::new(map_entity, map.object_y_sort_layer(group)));
+                        }
+                    }
+                    _ => {
+                        commands.spawn((tiled_object, transform, GlobalTransform::default()));
+                    }
+                }
+                if let Some(paths) = map.object_files.get(&object.id) {
+                    let handles = if preload.0 {
+                        paths
+                            .iter()
+                            .filter_map(|(name, path)| {
+                                let handle = asset_server.load_untyped(path).ok()?;
+                                Some((name.clone(), handle))
+                            })
+                            .collect()
+                    } else {
+                        HashMap::new()
+                    };
+                    commands.with(ObjectFiles {
+                        paths: paths.clone(),
+                        handles,
+                    });
+                }
+                if let Some(entity) = commands.current_entity() {
+                    spawned_events.send(ObjectSpawnedEvent {
+                        map_handle,
+                        entity,
+                        name: object.name.clone(),
+                        class: object.obj_type.clone(),
+                    });
+                }
+            }
+        }
+        // Whatever is left belongs to objects the reloaded map no longer draws.
+        for (_, mesh) in spawned_meshes {
+            meshes.remove(&mesh);
+        }
+        synced.insert(map_entity, (map.generation, map.objects_revision));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(index: usize) -> ObjectId {
+        ObjectId { group: 0, index }
+    }
+
+    fn rect(min: (f32, f32), max: (f32, f32)) -> Rect {
+        Rect {
+            min: Vec2::new(min.0, min.1),
+            max: Vec2::new(max.0, max.1),
+        }
+    }
+
+    #[test]
+    fn objects_spanning_cells_are_found_from_each_once() {
+        let mut index = ObjectIndex::new(10.0);
+        index.insert(id(0), 1, "", rect((5.0, 5.0), (25.0, 15.0)));
+        assert_eq!(index.query(&rect((0.0, 0.0), (6.0, 6.0))), vec![id(0)]);
+        assert_eq!(index.query(&rect((22.0, 12.0), (23.0, 13.0))), vec![id(0)]);
+        assert_eq!(index.query(&rect((0.0, 0.0), (30.0, 30.0))), vec![id(0)]);
+        // In a cell the object touches, but beside it.
+        assert!(index.query(&rect((26.0, 16.0), (29.0, 19.0))).is_empty());
+    }
+
+    #[test]
+    fn negative_coordinates_use_their_own_cells() {
+        let mut index = ObjectIndex::new(10.0);
+        index.insert(id(0), 1, "", rect((-15.0, -15.0), (-12.0, -12.0)));
+        index.insert(id(1), 2, "", rect((-2.0, -2.0), (2.0, 2.0)));
+        assert_eq!(
+            index.query(&rect((-14.0, -14.0), (-13.0, -13.0))),
+            vec![id(0)]
+        );
+        assert!(index.query(&rect((-9.0, -9.0), (-3.0, -3.0))).is_empty());
+        assert!(index.query(&rect((12.0, 12.0), (15.0, 15.0))).is_empty());
+        assert_eq!(index.query(&rect((-1.0, -1.0), (-0.5, -0.5))), vec![id(1)]);
+        assert_eq!(index.query(&rect((0.5, 0.5), (1.0, 1.0))), vec![id(1)]);
+        assert_eq!(
+            index.query(&rect((-20.0, -20.0), (0.0, 0.0))),
+            vec![id(0), id(1)]
+        );
+    }
+
+    #[test]
+    fn query_edges_on_a_cell_boundary_touch_objects_past_it() {
+        let mut index = ObjectIndex::new(10.0);
+        index.insert(id(0), 1, "", rect((20.0, 0.0), (25.0, 5.0)));
+        assert_eq!(index.query(&rect((10.0, 0.0), (20.0, 5.0))), vec![id(0)]);
+        assert!(index.query(&rect((10.0, 0.0), (19.9, 5.0))).is_empty());
+        assert_eq!(index.query(&rect((25.0, 5.0), (30.0, 10.0))), vec![id(0)]);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{loader::TiledMapLoader, Map, PropertiesExt};
+use std::collections::HashSet;
+
+/// Reads the gids of tiles whose image fills their cell without any transparent pixels, from
+/// the `occludes` bool property of the tile or else of its tileset.
+pub(crate) fn read_occluding_tiles(map: &tiled::Map) -> HashSet<u32> {
+    let mut occluding = HashSet::new();
+    for tileset in map.tilesets.iter() {
+        let whole = tileset
+            .properties
+            .bool_property("occludes")
+            .unwrap_or(false);
+        for id in 0..tileset.tilecount.unwrap_or(0) {
+            let tile = tileset.tiles.iter().find(|tile| tile.id == id);
+            let occludes = tile
+                .and_then(|tile| tile.properties.bool_property("occludes"))
+                .unwrap_or(whole);
+            if occludes {
+                occluding.insert(tileset.first_gid + id);
+            }
+        }
+    }
+    occluding
+}
+
+impl Map {
+    /// Leaves tiles out of the chunk meshes where an opaque tile on a layer drawn in front
+    /// covers them, cutting overdraw on maps stacking several full layers. Tiles are opaque
+    /// when their tile, or whole tileset, has the `occludes` property set.
+    ///
+    /// Only orthogonal maps that aren't y-sorted skip tiles, and only under tiles the size of
+    /// the map's grid that aren't tinted see-through, on layers with full opacity and the same
+    /// offset. Hiding the covering layer, as `LayerVariant` or `SeeThrough` can, shows the gap
+    /// until the map is rebuilt. Like `set_chunk_size`, this is meant for maps that aren't in
+    /// `Assets<Map>` yet; loaded maps get `MapSettings::skip_covered_tiles`.
+    pub fn set_skip_covered_tiles(&mut self, skip_covered: bool) {
+        if skip_covered == self.skip_covered || self.is_prebuilt() {
+            return;
+        }
+        self.skip_covered = skip_covered;
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.build_layers();
+    }
+
+    /// Whether the tile at `x`, `y` of `map.layers[layer]` is hidden behind an opaque tile of a
+    /// layer drawn in front, and so left out of its chunk mesh.
+    pub fn is_tile_covered(&self, layer: usize, x: u32, y: u32) -> bool {
+        if !self.skip_covered
+            || self.map.orientation != tiled::Orientation::Orthogonal
+            || self.y_sort
+        {
+            return false;
+        }
+        let depth = self.tile_layer_depth(layer);
+        let offset = self.layer_offset(layer);
+        (0..self.map.layers.len()).any(|above| {
+            let covering = &self.map.layers[above];
+            if above == layer
+                || !covering.visible
+                || covering.opacity < 1.0
+                || self.layer_offset(above) != offset
+                || self.tile_layer_depth(above) <= depth
+            {
+                return false;
+            }
+            let gid = match self.tile(above, x, y) {
+                Some(tile) => self.unanimated_gid(above, tile.gid),
+                None => return false,
+            };
+            self.occludes(gid) && self.tint_vertex(above, x, y)[3] >= 1.0
+        })
+    }
+
+    /// Whether `gid` (flip flags included) draws an opaque tile that fills its cell exactly.
+    /// Animated tiles occlude when every frame does.
+    fn occludes(&self, gid: u32) -> bool {
+        let gid = TiledMapLoader::remove_tile_flags(gid);
+        let fills_cell = self.tileset_for_gid(gid).map_or(false, |tileset| {
+            (tileset.tile_width, tileset.tile_height) == (self.map.tile_width, self.map.tile_height)
+                && !self.tile_offsets.contains_key(&tileset.first_gid)
+        });
+        if !fills_cell || !self.occluding_tiles.contains(&gid) {
+            return false;
+        }
+        self.tile_animations.get(&gid).map_or(true, |frames| {
+            frames
+                .iter()
+                .all(|(frame, _)| self.occluding_tiles.contains(frame))
+        })
+    }
+
+    /// z of the chunks of `map.layers[layer]`, as `layer_depth` gives once the chunks are
+    /// built, from the visible layers below it.
+    fn tile_layer_depth(&self, layer: usize) -> f32 {
+        let layer_id = self.map.layers[..layer]
+            .iter()
+            .filter(|layer| layer.visible)
+            .count();
+        let tiled_layer = &self.map.layers[layer];
+        self.depth_override(&tiled_layer.name, &tiled_layer.properties)
+            .unwrap_or_else(|| self.stack_depth(layer_id as f32))
+    }
+
+    /// Marks the chunks drawing the tiles of the layers behind `layer` at `x`, `y` dirty, for
+    /// an edit that may have covered or uncovered them.
+    pub(crate) fn invalidate_covered(&mut self, layer: usize, x: u32, y: u32) {
+        if !self.skip_covered {
+            return;
+        }
+        let depth = self.tile_layer_depth(layer);
+        for below in 0..self.map.layers.len() {
+            if below == layer || self.tile_layer_depth(below) >= depth {
+                continue;
+            }
+            if let Some(gid) = self.tile(below, x, y).map(|tile| tile.gid) {
+                self.invalidate_tile(below, x, y, &[gid]);
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    layer_pipelines::layer_of, ChunkPool, LayerChanges, LodChunk, Map, TiledChunk, TiledImageLayer,
+    TiledObject,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        renderer::{RenderResource, RenderResources},
+        shader::ShaderDefs,
+    },
+};
+use std::collections::HashMap;
+
+/// Palettes a map entity's layers are drawn with, for palette swaps such as by area, season or
+/// a damage flash. Layers with a palette have tileset images whose red channel holds an index
+/// into a palette texture one texel tall, and are drawn with the palette's colors instead.
+/// Changing the palettes swaps them on the next frame.
+///
+/// Indices are read exactly only with `MapSettings::nearest_filtering`, since filtering blends
+/// neighboring indices.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LayerPalettes {
+    /// Draws every layer without a palette of its own.
+    pub map: Option<Handle<Texture>>,
+    /// Palettes of tile layers, object groups and image layers, by layer name.
+    pub layers: HashMap<String, Handle<Texture>>,
+}
+
+impl LayerPalettes {
+    pub fn map(mut self, palette: Handle<Texture>) -> Self {
+        self.map = Some(palette);
+        self
+    }
+
+    pub fn layer(mut self, name: impl Into<String>, palette: Handle<Texture>) -> Self {
+        self.layers.insert(name.into(), palette);
+        self
+    }
+
+    /// The palette a layer named `name` is drawn with, if any.
+    pub fn palette(&self, name: &str) -> Option<Handle<Texture>> {
+        self.layers.get(name).copied().or(self.map)
+    }
+}
+
+/// The palette a chunk is drawn with, see `LayerPalettes`. Kept up to date by
+/// `apply_layer_palettes`.
+#[derive(Debug, Clone, Default, RenderResources, ShaderDefs)]
+pub struct TilePalette {
+    #[shader_def]
+    pub palette: Option<Handle<Texture>>,
+}
+
+/// Hands the chunks, tile objects and image layers of map entities the palettes set for them
+/// in their `LayerPalettes`. Level of detail chunks are drawn from colors already, so they
+/// don't get one.
+pub fn apply_layer_palettes(
+    maps: Res<Assets<Map>>,
+    pool: Res<ChunkPool>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut changes: Local<LayerChanges<LayerPalettes>>,
+    palettes_query: Query<(Entity, &LayerPalettes)>,
+    mut query: Query<(
+        Entity,
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&TiledImageLayer>,
+        Option<&LodChunk>,
+        &mut TilePalette,
+    )>,
+) {
+    let palettes = palettes_query
+        .iter()
+        .iter()
+        .map(|(entity, palettes)| (entity, (*palettes).clone()))
+        .collect::<Vec<_>>();
+    changes.update(&map_events, palettes);
+    for (entity, chunk, object, image_layer, lod, mut palette) in &mut query.iter() {
+        if !changes.is_stale(&pool, entity, chunk, object, image_layer) {
+            continue;
+        }
+        let (map_handle, map_entity) = match (object, image_layer) {
+            (Some(object), _) => (&object.map, Some(object.map_entity)),
+            (None, Some(image_layer)) => (&image_layer.map, Some(image_layer.map_entity)),
+            (None, None) => (&chunk.map, chunk.map_entity),
+        };
+        let palettes = map_entity
+            .and_then(|entity| palettes_query.get::<LayerPalettes>(entity).ok())
+            .filter(|_| lod.is_none());
+        let layer = maps
+            .get(map_handle)
+            .and_then(|map| layer_of(map, chunk, object, image_layer));
+        let drawn = match (palettes, layer) {
+            (Some(palettes), Some((name, _))) => palettes.palette(name),
+            // Merged meshes draw every layer, so only the map's palette applies.
+            (Some(palettes), None) if chunk.key.tileset_guid != 0 => palettes.map,
+            _ => None,
+        };
+        if palette.palette != drawn {
+            palette.palette = drawn;
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{Map, WangSet};
+use glam::Vec2;
+use std::collections::HashSet;
+
+impl Map {
+    /// Draws a polyline through `points` (in tile coordinates) into `layer` as a path of wang
+    /// edge tiles, such as a road, river or wall.
+    ///
+    /// Every tile the line passes through gets the tile of `wang_set` whose edges are `color`
+    /// towards its path neighbours. Neighbouring tiles already drawn with `color` edges count as
+    /// path too and are re-tiled, so paths crossing or touching existing ones get junctions.
+    /// Tiles without a matching wang tile are left alone. Returns the number of tiles set.
+    pub fn rasterize_path(
+        &mut self,
+        layer: usize,
+        points: &[Vec2],
+        wang_set: &WangSet,
+        color: u8,
+    ) -> usize {
+        let (width, height) = (self.map.width as i32, self.map.height as i32);
+        let mut path = HashSet::new();
+        for segment in points.windows(2) {
+            for (x, y) in trace(segment[0], segment[1]) {
+                if x >= 0 && y >= 0 && x < width && y < height {
+                    path.insert((x, y));
+                }
+            }
+        }
+        if points.len() == 1 {
+            let (x, y) = (points[0].x().floor() as i32, points[0].y().floor() as i32);
+            if x >= 0 && y >= 0 && x < width && y < height {
+                path.insert((x, y));
+            }
+        }
+
+        let is_path = |map: &Map, x: i32, y: i32| {
+            if path.contains(&(x, y)) {
+                return true;
+            }
+            if x < 0 || y < 0 {
+                return false;
+            }
+            map.tile(layer, x as u32, y as u32)
+                .and_then(|tile| wang_set.tile(tile.gid))
+                .map_or(false, |tile| tile.edges().contains(&color))
+        };
+
+        let mut affected = path.iter().copied().collect::<HashSet<_>>();
+        for (x, y) in path.iter() {
+            for (nx, ny) in neighbours(*x, *y).iter() {
+                if is_path(self, *nx, *ny) {
+                    affected.insert((*nx, *ny));
+                }
+            }
+        }
+        let mut affected = affected.into_iter().collect::<Vec<_>>();
+        affected.sort();
+
+        let mut placements = Vec::new();
+        for (x, y) in affected {
+            let mut edges = [0; 4];
+            for (edge, (nx, ny)) in edges.iter_mut().zip(neighbours(x, y).iter()) {
+                if is_path(self, *nx, *ny) {
+                    *edge = color;
+                }
+            }
+            if let Some(gid) = wang_set.edge_tile(edges) {
+                placements.push((x as u32, y as u32, gid));
+            }
+        }
+
+        placements
+            .into_iter()
+            .filter(|(x, y, gid)| self.set_tile(layer, *x, *y, *gid))
+            .count()
+    }
+
+    /// Draws a polyline or polygon object into `layer` with `rasterize_path`.
+    ///
+    /// Returns `None` if there's no object with this id or it isn't a polyline or polygon.
+    pub fn rasterize_object_path(
+        &mut self,
+        layer: usize,
+        object_id: u32,
+        wang_set: &WangSet,
+        color: u8,
+    ) -> Option<usize> {
+        let object = self.object_by_id(object_id)?;
+        let mut points = match &object.shape {
+            tiled::ObjectShape::Polyline { points } | tiled::ObjectShape::Polygon { points } => {
+                points.clone()
+            }
+            _ => return None,
+        };
+        if let (tiled::ObjectShape::Polygon { .. }, Some(first)) =
+            (&object.shape, points.first().copied())
+        {
+            points.push(first);
+        }
+
+        let tile_height = self.map.tile_height as f32;
+        let tile_size = match self.map.orientation {
+            // Isometric objects measure both axes in tile heights.
+            tiled::Orientation::Isometric => Vec2::new(tile_height, tile_height),
+            _ => Vec2::new(self.map.tile_width as f32, tile_height),
+        };
+        let origin = Vec2::new(object.x, object.y);
+        let points = points
+            .into_iter()
+            .map(|(x, y)| (origin + Vec2::new(x, y)) / tile_size)
+            .collect::<Vec<_>>();
+        Some(self.rasterize_path(layer, &points, wang_set, color))
+    }
+}
+
+/// Top, right, bottom and left neighbours, matching `WangTile::edges`.
+fn neighbours(x: i32, y: i32) -> [(i32, i32); 4] {
+    [(x, y - 1), (x + 1, y), (x, y + 1), (x - 1, y)]
+}
+
+/// Tiles a segment passes through, stepping one axis at a time so consecutive tiles share an
+/// edge.
+pub(crate) fn trace(from: Vec2, to: Vec2) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = (from.x().floor() as i32, from.y().floor() as i32);
+    let (end_x, end_y) = (to.x().floor() as i32, to.y().floor() as i32);
+    let delta = to - from;
+    let (step_x, step_y) = (delta.x().signum() as i32, delta.y().signum() as i32);
+    let axis = |delta: f32, from: f32, tile: i32| {
+        if delta > 0.0 {
+            ((tile as f32 + 1.0 - from) / delta, 1.0 / delta)
+        } else if delta < 0.0 {
+            ((from - tile as f32) / -delta, -1.0 / delta)
+        } else {
+            (std::f32::INFINITY, std::f32::INFINITY)
+        }
+    };
+    let (mut next_x, step_t_x) = axis(delta.x(), from.x(), x);
+    let (mut next_y, step_t_y) = axis(delta.y(), from.y(), y);
+
+    let steps = (end_x - x).abs() + (end_y - y).abs();
+    let mut tiles = vec![(x, y)];
+    for _ in 0..steps {
+        if next_x < next_y {
+            x += step_x;
+            next_x += step_t_x;
+        } else {
+            y += step_y;
+            next_y += step_t_y;
+        }
+        tiles.push((x, y));
+    }
+    tiles
+}
@@ -0,0 +1,299 @@
+use crate::{Map, MapAnchor};
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+    window::CursorMoved,
+};
+use glam::{Vec2, Vec4};
+use std::collections::HashSet;
+
+/// A set of tile layers, by index into `tiled::Map::layers`.
+///
+/// Only the first 64 layers fit in a mask. Layers from 64 on are in none, not even `ALL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerMask(pub u64);
+
+impl LayerMask {
+    pub const ALL: LayerMask = LayerMask(std::u64::MAX);
+    pub const NONE: LayerMask = LayerMask(0);
+
+    /// The mask of `layers`, leaving out those from 64 on.
+    pub fn from_layers(layers: &[usize]) -> Self {
+        LayerMask(
+            layers
+                .iter()
+                .filter(|layer| **layer < 64)
+                .fold(0, |mask, layer| mask | (1 << layer)),
+        )
+    }
+
+    pub fn contains(&self, layer: usize) -> bool {
+        layer < 64 && self.0 & (1 << layer) != 0
+    }
+}
+
+impl Default for LayerMask {
+    fn default() -> Self {
+        LayerMask::ALL
+    }
+}
+
+/// A tile found by `Map::pick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileHit {
+    /// Index into `tiled::Map::layers`.
+    pub layer: usize,
+    pub x: u32,
+    pub y: u32,
+    pub gid: u32,
+}
+
+/// Converts a cursor position in window pixels (origin bottom left) into world space.
+pub fn screen_to_world(
+    cursor: Vec2,
+    window_size: Vec2,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Vec2 {
+    let ndc = (cursor / window_size) * 2.0 - Vec2::new(1.0, 1.0);
+    let ndc_to_world = *camera_transform.value() * camera.projection_matrix.inverse();
+    let world = ndc_to_world * Vec4::new(ndc.x(), ndc.y(), 0.0, 1.0);
+    Vec2::new(world.x(), world.y())
+}
+
+impl Map {
+    /// The topmost non-empty tile under a world position, for a map placed at `translation`.
+    ///
+    /// Only visible layers in `mask` with a non-zero opacity are considered, each shifted by its
+    /// layer offset.
+    pub fn pick_world(&self, world: Vec2, translation: Vec3, mask: LayerMask) -> Option<TileHit> {
+        let local = self.world_to_local(translation, world);
+        for (layer_index, layer) in self.map.layers.iter().enumerate().rev() {
+            if !mask.contains(layer_index) || !layer.visible || layer.opacity <= 0.0 {
+                continue;
+            }
+            let offset = self.layer_offset(layer_index);
+            let tile = self.local_to_tile(local - Vec2::new(offset.x(), -offset.y()));
+            if tile.x() < 0.0 || tile.y() < 0.0 {
+                continue;
+            }
+            let (x, y) = (tile.x() as u32, tile.y() as u32);
+            if let Some(hit) = self.layer_hit(layer_index, x, y) {
+                return Some(hit);
+            }
+        }
+        None
+    }
+
+    /// The topmost non-empty tile at `x`, `y` across the layers in `mask`, ignoring offsets.
+    pub fn top_tile(&self, x: u32, y: u32, mask: LayerMask) -> Option<TileHit> {
+        (0..self.map.layers.len())
+            .rev()
+            .filter(|layer| mask.contains(*layer))
+            .filter(|layer| {
+                self.map.layers[*layer].visible && self.map.layers[*layer].opacity > 0.0
+            })
+            .find_map(|layer| self.layer_hit(layer, x, y))
+    }
+
+    fn layer_hit(&self, layer: usize, x: u32, y: u32) -> Option<TileHit> {
+        match self.tile(layer, x, y) {
+            Some(tile) if tile.gid != 0 => Some(TileHit {
+                layer,
+                x,
+                y,
+                gid: tile.gid,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The topmost non-empty tile under the cursor, see `pick_world`.
+    pub fn pick(
+        &self,
+        cursor: Vec2,
+        window_size: Vec2,
+        camera: (&Camera, &GlobalTransform),
+        translation: Vec3,
+        mask: LayerMask,
+    ) -> Option<TileHit> {
+        let world = screen_to_world(cursor, window_size, camera.0, camera.1);
+        self.pick_world(world, translation, mask)
+    }
+}
+
+/// Maintains `HoveredTile` and `SelectedTiles` from the mouse over the 2d camera.
+///
+/// Clicking selects the tile under the cursor, dragging selects a rectangle of tiles, and
+/// holding shift adds to the current selection. Clicking where there's no tile clears it.
+#[derive(Default)]
+pub struct TilePickingPlugin;
+
+impl Plugin for TilePickingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TilePickingSettings>()
+            .init_resource::<HoveredTile>()
+            .init_resource::<SelectedTiles>()
+            .add_event::<HoveredTileChanged>()
+            .add_event::<SelectionChanged>()
+            .add_system(update_tile_picking.system());
+    }
+}
+
+pub struct TilePickingSettings {
+    pub mask: LayerMask,
+    pub select_button: MouseButton,
+}
+
+impl Default for TilePickingSettings {
+    fn default() -> Self {
+        Self {
+            mask: LayerMask::ALL,
+            select_button: MouseButton::Left,
+        }
+    }
+}
+
+/// The tile under the cursor, if any.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HoveredTile(pub Option<(Handle<Map>, TileHit)>);
+
+/// The selected tiles, all from one map.
+#[derive(Debug, Default, Clone)]
+pub struct SelectedTiles {
+    pub map: Option<Handle<Map>>,
+    pub tiles: HashSet<TileHit>,
+}
+
+/// Sent when `HoveredTile` changes.
+#[derive(Debug, Clone)]
+pub struct HoveredTileChanged {
+    pub previous: HoveredTile,
+    pub current: HoveredTile,
+}
+
+/// Sent when `SelectedTiles` changes, including when it's cleared.
+#[derive(Debug, Clone)]
+pub struct SelectionChanged;
+
+#[derive(Default)]
+pub struct TilePickingState {
+    cursor_reader: EventReader<CursorMoved>,
+    cursor: Option<Vec2>,
+    drag_start: Option<(Handle<Map>, TileHit)>,
+    /// Selection before the current drag, kept when extending with shift.
+    base_selection: HashSet<TileHit>,
+}
+
+pub fn update_tile_picking(
+    mut state: Local<TilePickingState>,
+    settings: Res<TilePickingSettings>,
+    cursor_events: Res<Events<CursorMoved>>,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    maps: Res<Assets<Map>>,
+    mut hovered: ResMut<HoveredTile>,
+    mut selected: ResMut<SelectedTiles>,
+    mut hover_events: ResMut<Events<HoveredTileChanged>>,
+    mut selection_events: ResMut<Events<SelectionChanged>>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &OrthographicProjection)>,
+    mut map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    if let Some(event) = state.cursor_reader.latest(&cursor_events) {
+        state.cursor = Some(event.position);
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+
+    let mut hit = None;
+    if let Some(cursor) = state.cursor {
+        let mut cameras = camera_query.iter();
+        if let Some((camera, camera_transform, _)) = cameras.iter().next() {
+            let world = screen_to_world(cursor, window_size, camera, camera_transform);
+            for (map_handle, anchor, origin) in &mut map_query.iter() {
+                if let Some(map) = maps.get(map_handle) {
+                    let translation = map.translation(anchor, origin.translation());
+                    if let Some(tile) = map.pick_world(world, translation, settings.mask) {
+                        hit = Some((*map_handle, tile));
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let current = HoveredTile(hit);
+    if *hovered != current {
+        hover_events.send(HoveredTileChanged {
+            previous: hovered.clone(),
+            current: current.clone(),
+        });
+        *hovered = current;
+    }
+
+    let extend = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    if mouse.just_pressed(settings.select_button) {
+        state.drag_start = hit;
+        state.base_selection = if extend && selected.map == hit.map(|(map, _)| map) {
+            selected.tiles.clone()
+        } else {
+            HashSet::new()
+        };
+        // Clicking where there's no tile deselects, unless extending the selection.
+        if hit.is_none() && !extend && (selected.map.is_some() || !selected.tiles.is_empty()) {
+            selected.map = None;
+            selected.tiles.clear();
+            selection_events.send(SelectionChanged);
+        }
+    }
+    if !mouse.pressed(settings.select_button) && !mouse.just_released(settings.select_button) {
+        state.drag_start = None;
+        return;
+    }
+
+    let (map_handle, start) = match state.drag_start {
+        Some(drag_start) => drag_start,
+        None => return,
+    };
+    // Keep the last rectangle when the cursor leaves the map mid drag.
+    let end = match hit {
+        Some((handle, tile)) if handle == map_handle => tile,
+        _ => return,
+    };
+    let map = match maps.get(&map_handle) {
+        Some(map) => map,
+        None => return,
+    };
+
+    let mut tiles = state.base_selection.clone();
+    for x in start.x.min(end.x)..=start.x.max(end.x) {
+        for y in start.y.min(end.y)..=start.y.max(end.y) {
+            if let Some(tile) = map.top_tile(x, y, settings.mask) {
+                tiles.insert(tile);
+            }
+        }
+    }
+    if selected.map != Some(map_handle) || selected.tiles != tiles {
+        selected.map = Some(map_handle);
+        selected.tiles = tiles;
+        selection_events.send(SelectionChanged);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_past_64_are_left_out_of_masks() {
+        let mask = LayerMask::from_layers(&[1, 63, 64, 200]);
+        assert_eq!(mask, LayerMask::from_layers(&[1, 63]));
+        assert!(mask.contains(63));
+        assert!(!mask.contains(64));
+        assert!(!LayerMask::ALL.contains(200));
+    }
+}
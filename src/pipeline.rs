@@ -1,4 +1,7 @@
-use crate::TileMapChunk;
+use crate::{
+    BlendMode, TileAnimations, TileDataTexture, TileEmission, TileLighting, TileMapChunk,
+    TilePalette,
+};
 use bevy::{
     prelude::*,
     render::{
@@ -15,6 +18,16 @@ use bevy::{
 
 pub const TILE_MAP_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
     Handle::from_u128(35719948083365670583462682848847416493);
+pub const TILE_MAP_ADDITIVE_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
+    Handle::from_u128(35719948083365670583462682848847416494);
+pub const TILE_MAP_MULTIPLY_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
+    Handle::from_u128(35719948083365670583462682848847416495);
+pub const TILE_MAP_INSTANCED_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
+    Handle::from_u128(35719948083365670583462682848847416496);
+pub const TILE_MAP_INSTANCED_ADDITIVE_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
+    Handle::from_u128(35719948083365670583462682848847416497);
+pub const TILE_MAP_INSTANCED_MULTIPLY_PIPELINE_HANDLE: Handle<PipelineDescriptor> =
+    Handle::from_u128(35719948083365670583462682848847416498);
 
 pub fn build_tile_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
     PipelineDescriptor {
@@ -64,8 +77,71 @@ pub fn build_tile_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescript
     }
 }
 
+/// The tile map pipeline drawing chunks of instanced maps, see `Map::set_instanced`, with a
+/// vertex shader placing each tile from its instance data.
+pub fn build_instanced_tile_map_pipeline(
+    pipeline: &PipelineDescriptor,
+    shaders: &mut Assets<Shader>,
+) -> PipelineDescriptor {
+    let mut pipeline = pipeline.clone();
+    pipeline.shader_stages.vertex = shaders.add(Shader::from_glsl(
+        ShaderStage::Vertex,
+        include_str!("tile_map_instanced.vert"),
+    ));
+    pipeline
+}
+
+/// The tile map pipeline with its color blending replaced for layers blended with `blend`.
+/// Blended layers stay out of the depth buffer, so they never hide what's drawn after them.
+pub fn build_blended_tile_map_pipeline(
+    pipeline: &PipelineDescriptor,
+    blend: BlendMode,
+) -> PipelineDescriptor {
+    let (color_blend, alpha_blend) = match blend {
+        BlendMode::Alpha => return pipeline.clone(),
+        BlendMode::Additive => (
+            BlendDescriptor {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            BlendDescriptor {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        ),
+        BlendMode::Multiply => (
+            BlendDescriptor {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::SrcColor,
+                operation: BlendOperation::Add,
+            },
+            BlendDescriptor {
+                src_factor: BlendFactor::Zero,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        ),
+    };
+    let mut pipeline = pipeline.clone();
+    for color_state in pipeline.color_states.iter_mut() {
+        color_state.color_blend = color_blend.clone();
+        color_state.alpha_blend = alpha_blend.clone();
+    }
+    if let Some(depth_stencil_state) = pipeline.depth_stencil_state.as_mut() {
+        depth_stencil_state.depth_write_enabled = false;
+    }
+    pipeline
+}
+
 pub mod node {
     pub const TILE_MAP_CHUNK: &'static str = "tile_map_chunk";
+    pub const TILE_ANIMATIONS: &'static str = "tile_animations";
+    pub const TILE_DATA_TEXTURE: &'static str = "tile_data_texture";
+    pub const TILE_LIGHTING: &'static str = "tile_lighting";
+    pub const TILE_PALETTE: &'static str = "tile_palette";
+    pub const TILE_EMISSION: &'static str = "tile_emission";
 }
 
 pub trait TileMapRenderGraphBuilder {
@@ -80,13 +156,59 @@ impl TileMapRenderGraphBuilder for RenderGraph {
         );
         self.add_node_edge(node::TILE_MAP_CHUNK, base::node::MAIN_PASS)
             .unwrap();
+        self.add_system_node(
+            node::TILE_ANIMATIONS,
+            RenderResourcesNode::<TileAnimations>::new(false),
+        );
+        self.add_node_edge(node::TILE_ANIMATIONS, base::node::MAIN_PASS)
+            .unwrap();
+        self.add_system_node(
+            node::TILE_DATA_TEXTURE,
+            RenderResourcesNode::<TileDataTexture>::new(false),
+        );
+        self.add_node_edge(node::TILE_DATA_TEXTURE, base::node::MAIN_PASS)
+            .unwrap();
+        self.add_system_node(
+            node::TILE_LIGHTING,
+            RenderResourcesNode::<TileLighting>::new(false),
+        );
+        self.add_node_edge(node::TILE_LIGHTING, base::node::MAIN_PASS)
+            .unwrap();
+        self.add_system_node(
+            node::TILE_PALETTE,
+            RenderResourcesNode::<TilePalette>::new(false),
+        );
+        self.add_node_edge(node::TILE_PALETTE, base::node::MAIN_PASS)
+            .unwrap();
+        self.add_system_node(
+            node::TILE_EMISSION,
+            RenderResourcesNode::<TileEmission>::new(false),
+        );
+        self.add_node_edge(node::TILE_EMISSION, base::node::MAIN_PASS)
+            .unwrap();
 
         let mut pipelines = resources.get_mut::<Assets<PipelineDescriptor>>().unwrap();
         let mut shaders = resources.get_mut::<Assets<Shader>>().unwrap();
+        let pipeline = build_tile_map_pipeline(&mut shaders);
+        let instanced = build_instanced_tile_map_pipeline(&pipeline, &mut shaders);
+        pipelines.set(
+            TILE_MAP_INSTANCED_ADDITIVE_PIPELINE_HANDLE,
+            build_blended_tile_map_pipeline(&instanced, BlendMode::Additive),
+        );
+        pipelines.set(
+            TILE_MAP_INSTANCED_MULTIPLY_PIPELINE_HANDLE,
+            build_blended_tile_map_pipeline(&instanced, BlendMode::Multiply),
+        );
+        pipelines.set(TILE_MAP_INSTANCED_PIPELINE_HANDLE, instanced);
+        pipelines.set(
+            TILE_MAP_ADDITIVE_PIPELINE_HANDLE,
+            build_blended_tile_map_pipeline(&pipeline, BlendMode::Additive),
+        );
         pipelines.set(
-            TILE_MAP_PIPELINE_HANDLE,
-            build_tile_map_pipeline(&mut shaders),
+            TILE_MAP_MULTIPLY_PIPELINE_HANDLE,
+            build_blended_tile_map_pipeline(&pipeline, BlendMode::Multiply),
         );
+        pipelines.set(TILE_MAP_PIPELINE_HANDLE, pipeline);
         self
     }
 }
@@ -0,0 +1,91 @@
+use crate::{MapSettings, TiledChunk};
+use bevy::{prelude::*, render::camera::OrthographicProjection};
+use std::collections::HashMap;
+
+/// How the 2d camera's zoom is treated by `snap_chunks_to_pixels`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelScaling {
+    /// Any zoom. Chunks still land on whole screen pixels, but texels can cover uneven counts
+    /// of them.
+    Free,
+    /// The camera's zoom is rounded so every tileset texel covers a whole number of screen
+    /// pixels, at least one, for the crispest pixel art.
+    Integer,
+}
+
+impl Default for PixelScaling {
+    fn default() -> Self {
+        PixelScaling::Free
+    }
+}
+
+/// Snaps chunk entities to whole screen pixels of the first 2d camera as it moves, so pixel
+/// art doesn't shimmer, optionally rounding the camera's zoom too. Pair with
+/// `MapSettings::nearest_filtering`.
+#[derive(Debug, Clone, Default)]
+pub struct PixelPerfect {
+    pub enabled: bool,
+    pub scaling: PixelScaling,
+}
+
+/// The translations of the chunks `snap_chunks_to_pixels` moved, put back by
+/// `unsnap_chunks` before anything else reads them.
+#[derive(Debug, Default)]
+pub struct SnappedChunks(HashMap<Entity, Vec3>);
+
+/// Puts the chunks snapped last frame back where they were placed.
+pub fn unsnap_chunks(mut snapped: ResMut<SnappedChunks>, mut query: Query<&mut Transform>) {
+    for (entity, translation) in snapped.0.drain() {
+        if let Ok(mut transform) = query.get_mut::<Transform>(entity) {
+            transform.set_translation(translation);
+        }
+    }
+}
+
+/// Moves every chunk entity to the nearest whole screen pixel from the first 2d camera, and
+/// with `PixelScaling::Integer` rounds the camera's zoom first. Texels are `MapSettings::scale`
+/// world units wide.
+pub fn snap_chunks_to_pixels(
+    settings: Res<PixelPerfect>,
+    map_settings: Res<MapSettings>,
+    mut snapped: ResMut<SnappedChunks>,
+    mut camera_query: Query<(&Camera, &OrthographicProjection, &mut Transform)>,
+    mut chunk_query: Query<(Entity, &TiledChunk, &mut Transform)>,
+) {
+    if !settings.enabled {
+        return;
+    }
+    // Read off the camera's own transform, which is already where it moved to this frame.
+    let (camera, zoom) = {
+        let mut cameras = camera_query.iter();
+        let mut transform = match cameras.iter().next() {
+            Some((_, _, transform)) => transform,
+            None => return,
+        };
+        // World units per screen pixel.
+        let zoom = transform.value().x_axis().truncate().length();
+        if zoom <= std::f32::EPSILON {
+            return;
+        }
+        let texel = map_settings.scale;
+        let rounded = texel / (texel / zoom).round().max(1.0);
+        if settings.scaling == PixelScaling::Integer && (rounded - zoom).abs() > std::f32::EPSILON {
+            transform.apply_scale(rounded / zoom);
+            (transform.translation().truncate(), rounded)
+        } else {
+            (transform.translation().truncate(), zoom)
+        }
+    };
+
+    for (entity, _, mut transform) in &mut chunk_query.iter() {
+        let translation = transform.translation();
+        let offset = (translation.truncate() - camera) / zoom;
+        let snapped_translation = (camera
+            + Vec2::new(offset.x().round(), offset.y().round()) * zoom)
+            .extend(translation.z());
+        if snapped_translation != translation {
+            snapped.0.insert(entity, translation);
+            transform.set_translation(snapped_translation);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{ChunkComponents, TiledChunk};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Chunk entities kept after their chunk went away, such as streamed out or despawned with its
+/// map, to draw the next chunk spawned instead of despawning one entity and spawning another.
+///
+/// Only plain chunk entities spawned through the pool are kept, hidden and with no mesh, up to
+/// `capacity` of them. Components added to chunk entities besides `ChunkComponents` stay with
+/// them when they're reused.
+#[derive(Debug)]
+pub struct ChunkPool {
+    /// How many entities are kept at most; ones released past it are despawned.
+    pub capacity: usize,
+    free: Vec<Entity>,
+    /// Entities spawned through the pool, and how many chunks each has been spawned for.
+    spawned: HashMap<Entity, u32>,
+}
+
+impl Default for ChunkPool {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            free: Vec::new(),
+            spawned: HashMap::new(),
+        }
+    }
+}
+
+impl ChunkPool {
+    /// Spawns `chunk` on a pooled entity if there is one, or else a new one.
+    pub fn spawn(&mut self, commands: &mut Commands, chunk: ChunkComponents) -> Entity {
+        match self.free.pop() {
+            Some(entity) => {
+                commands.insert(entity, chunk);
+                *self.spawned.entry(entity).or_insert(0) += 1;
+                entity
+            }
+            None => {
+                let entity = commands.spawn(chunk).current_entity().unwrap();
+                self.spawned.insert(entity, 1);
+                entity
+            }
+        }
+    }
+
+    /// Hides `entity` and keeps it for the next chunk spawned, or despawns it if it wasn't
+    /// spawned through the pool or the pool is full. Its mesh and material are left to the
+    /// caller.
+    pub fn release(&mut self, commands: &mut Commands, entity: Entity) {
+        if !self.spawned.contains_key(&entity) || self.free.len() >= self.capacity {
+            self.spawned.remove(&entity);
+            commands.despawn(entity);
+            return;
+        }
+        // Without a map or mesh it's skipped by the chunk systems and draws nothing, even if
+        // something shows it again.
+        commands.insert(
+            entity,
+            (
+                TiledChunk::default(),
+                Handle::<Mesh>::default(),
+                Draw {
+                    is_visible: false,
+                    is_transparent: true,
+                    ..Default::default()
+                },
+            ),
+        );
+        self.free.push(entity);
+    }
+
+    /// How many chunks `entity` has been spawned for, so systems can tell a reused entity
+    /// from the chunk it drew before.
+    pub(crate) fn spawns(&self, entity: Entity) -> u32 {
+        self.spawned.get(&entity).copied().unwrap_or(0)
+    }
+
+    /// How many entities are waiting to be reused.
+    pub fn pooled(&self) -> usize {
+        self.free.len()
+    }
+}
@@ -0,0 +1,102 @@
+use crate::Map;
+use bevy::{asset::LoadState, prelude::*};
+use std::collections::HashMap;
+
+/// How far along loading a map entity's map is.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MapLoadProgress {
+    /// Whether the map file has been parsed and its tilesets resolved.
+    pub map_loaded: bool,
+    /// Whether the map's chunk meshes and materials have been spawned.
+    pub chunks_spawned: bool,
+    /// Tileset and image layer images finished loading, including ones that failed.
+    pub images_loaded: usize,
+    /// Images the map draws, known once the map is loaded.
+    pub images: usize,
+    /// Whether the map failed to load, see `MapLoadFailed`. Failed maps count as done.
+    pub failed: bool,
+}
+
+impl MapLoadProgress {
+    /// Progress from 0 to 1, counting the map, its chunks and each image as one step.
+    pub fn fraction(&self) -> f32 {
+        if self.failed {
+            return 1.0;
+        }
+        let done = self.map_loaded as usize + self.chunks_spawned as usize + self.images_loaded;
+        done as f32 / (2 + self.images) as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.failed || self.map_loaded && self.chunks_spawned && self.images_loaded == self.images
+    }
+}
+
+/// Loading progress of the maps of every map entity, for loading screens.
+#[derive(Debug, Default)]
+pub struct LoadingProgress {
+    pub maps: HashMap<Handle<Map>, MapLoadProgress>,
+}
+
+impl LoadingProgress {
+    /// Progress of every map together, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        if self.maps.is_empty() {
+            return 1.0;
+        }
+        let (done, steps) = self.maps.values().fold((0, 0), |(done, steps), progress| {
+            if progress.failed {
+                return (done + 1, steps + 1);
+            }
+            (
+                done + progress.map_loaded as usize
+                    + progress.chunks_spawned as usize
+                    + progress.images_loaded,
+                steps + 2 + progress.images,
+            )
+        });
+        done as f32 / steps as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.maps.values().all(MapLoadProgress::is_done)
+    }
+}
+
+/// Updates `LoadingProgress` for the map of each map entity.
+pub fn update_loading_progress(
+    asset_server: Res<AssetServer>,
+    maps: Res<Assets<Map>>,
+    mut loading: ResMut<LoadingProgress>,
+    mut query: Query<(&Handle<Map>, &HashMap<u32, Handle<ColorMaterial>>)>,
+) {
+    for (map_handle, materials_map) in &mut query.iter() {
+        let mut progress = MapLoadProgress::default();
+        if let Some(map) = maps.get(map_handle) {
+            progress.map_loaded = true;
+            // Tileset materials are created in the same pass that spawns the chunks.
+            progress.chunks_spawned = map
+                .map
+                .tilesets
+                .iter()
+                .all(|tileset| materials_map.contains_key(&tileset.first_gid));
+            let images = map.image_dependencies();
+            progress.images = images.len();
+            progress.images_loaded = images
+                .iter()
+                .filter(|path| {
+                    let texture: Option<Handle<Texture>> = asset_server.get_handle(path.as_path());
+                    match texture.and_then(|texture| asset_server.get_load_state(texture)) {
+                        Some(LoadState::Loaded(_)) | Some(LoadState::Failed(_)) => true,
+                        _ => false,
+                    }
+                })
+                .count();
+        } else if let Some(LoadState::Failed(_)) = asset_server.get_load_state(*map_handle) {
+            progress.failed = true;
+        }
+        if loading.maps.get(map_handle) != Some(&progress) {
+            loading.maps.insert(*map_handle, progress);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::PropertyOwner;
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// A custom property with Tiled's types mapped onto bevy ones.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+    String(String),
+    Color(Color),
+}
+
+impl From<&tiled::PropertyValue> for Property {
+    fn from(value: &tiled::PropertyValue) -> Self {
+        match value {
+            tiled::PropertyValue::BoolValue(value) => Property::Bool(*value),
+            tiled::PropertyValue::IntValue(value) => Property::Int(*value),
+            tiled::PropertyValue::FloatValue(value) => Property::Float(*value),
+            tiled::PropertyValue::StringValue(value) => Property::String(value.clone()),
+            tiled::PropertyValue::ColorValue(value) => Property::Color(argb_to_color(*value)),
+        }
+    }
+}
+
+/// Converts a color property, which Tiled stores as `#AARRGGBB`. Maps loaded from `.tmx`
+/// files have the alpha of colors written as `#RRGGBB` set to opaque, see
+/// `make_rgb_colors_opaque`.
+pub fn argb_to_color(argb: u32) -> Color {
+    let channel = |shift: u32| ((argb >> shift) & 0xFF) as f32 / 255.0;
+    Color::rgba(channel(16), channel(8), channel(0), channel(24))
+}
+
+/// Gives the `rgb_colors` properties, written as `#RRGGBB`, an opaque alpha byte. The tiled
+/// crate reads them with a zero one, which can't be told apart from `#00RRGGBB` afterwards.
+pub(crate) fn make_rgb_colors_opaque(
+    map: &mut tiled::Map,
+    rgb_colors: &HashMap<PropertyOwner, HashSet<String>>,
+) {
+    for (owner, names) in rgb_colors.iter() {
+        let properties = match owner_properties(map, *owner) {
+            Some(properties) => properties,
+            None => continue,
+        };
+        for name in names.iter() {
+            if let Some(tiled::PropertyValue::ColorValue(value)) = properties.get_mut(name) {
+                *value |= 0xFF00_0000;
+            }
+        }
+    }
+}
+
+fn owner_properties(map: &mut tiled::Map, owner: PropertyOwner) -> Option<&mut tiled::Properties> {
+    let tileset = |map: &mut tiled::Map, first_gid| {
+        map.tilesets
+            .iter_mut()
+            .find(|tileset| tileset.first_gid == first_gid)
+    };
+    match owner {
+        PropertyOwner::Map => Some(&mut map.properties),
+        PropertyOwner::Layer(index) => map.layers.get_mut(index).map(|layer| &mut layer.properties),
+        PropertyOwner::ImageLayer(index) => map
+            .image_layers
+            .get_mut(index)
+            .map(|layer| &mut layer.properties),
+        PropertyOwner::ObjectGroup(index) => map
+            .object_groups
+            .get_mut(index)
+            .map(|group| &mut group.properties),
+        PropertyOwner::Object(id) => map
+            .object_groups
+            .iter_mut()
+            .flat_map(|group| group.objects.iter_mut())
+            .find(|object| object.id == id)
+            .map(|object| &mut object.properties),
+        PropertyOwner::Tileset(first_gid) => {
+            tileset(map, first_gid).map(|tileset| &mut tileset.properties)
+        }
+        PropertyOwner::Tile(first_gid, id) => tileset(map, first_gid)?
+            .tiles
+            .iter_mut()
+            .find(|tile| tile.id == id)
+            .map(|tile| &mut tile.properties),
+    }
+}
+
+/// Typed access to a map, layer, tile or object's custom properties.
+pub trait PropertiesExt {
+    fn property(&self, name: &str) -> Option<Property>;
+
+    fn bool_property(&self, name: &str) -> Option<bool> {
+        match self.property(name)? {
+            Property::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// An int property, or a float property truncated.
+    fn int_property(&self, name: &str) -> Option<i32> {
+        match self.property(name)? {
+            Property::Int(value) => Some(value),
+            Property::Float(value) => Some(value as i32),
+            _ => None,
+        }
+    }
+
+    /// A float property, or an int property widened.
+    fn float_property(&self, name: &str) -> Option<f32> {
+        match self.property(name)? {
+            Property::Float(value) => Some(value),
+            Property::Int(value) => Some(value as f32),
+            _ => None,
+        }
+    }
+
+    fn string_property(&self, name: &str) -> Option<String> {
+        match self.property(name)? {
+            Property::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn color_property(&self, name: &str) -> Option<Color> {
+        match self.property(name)? {
+            Property::Color(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl PropertiesExt for tiled::Properties {
+    fn property(&self, name: &str) -> Option<Property> {
+        self.get(name).map(Property::from)
+    }
+}
+
+/// The custom properties of a map, added to its map entity once the map is processed.
+#[derive(Debug, Clone, Default)]
+pub struct MapProperties(pub tiled::Properties);
+
+impl PropertiesExt for MapProperties {
+    fn property(&self, name: &str) -> Option<Property> {
+        self.0.property(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Map;
+
+    const MAP: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="1" height="1" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="1">
+ <properties>
+  <property name="rgb" type="color" value="#ff8000"/>
+  <property name="transparent" type="color" value="#00ff8000"/>
+  <property name="argb" type="color" value="#80ff8000"/>
+ </properties>
+ <layer id="1" name="ground" width="1" height="1">
+  <data encoding="csv">0</data>
+ </layer>
+</map>
+"##;
+
+    #[test]
+    fn rgb_colors_are_opaque_and_argb_colors_keep_their_alpha() {
+        let map = Map::try_from_bytes(MAP.as_bytes(), Default::default()).unwrap();
+        let properties = &map.map.properties;
+        let rgb = properties.color_property("rgb").unwrap();
+        let transparent = properties.color_property("transparent").unwrap();
+        let argb = properties.color_property("argb").unwrap();
+        assert_eq!(rgb, Color::rgba(1.0, 128.0 / 255.0, 0.0, 1.0));
+        assert_eq!(transparent, Color::rgba(1.0, 128.0 / 255.0, 0.0, 0.0));
+        assert_eq!(argb, Color::rgba(1.0, 128.0 / 255.0, 0.0, 128.0 / 255.0));
+    }
+}
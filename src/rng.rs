@@ -0,0 +1,64 @@
+/// SplitMix64, a tiny generator whose output is the same on every platform and crate version.
+#[derive(Debug, Clone)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    /// A generator for one tile, so results don't depend on the order tiles are visited in.
+    pub fn for_tile(seed: u64, x: u32, y: u32) -> Self {
+        let mut rng = SplitMix64(seed ^ ((x as u64) << 32 | y as u64));
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// The seed behind every random choice made for maps, such as scattering and procedural
+/// generation.
+///
+/// Each use draws from its own named stream, so adding a new use of randomness doesn't shift the
+/// results of existing ones. Everything derived from it is computed the same on every platform,
+/// so identical seeds reproduce identical maps for networked and replay-based games.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapRng {
+    seed: u64,
+}
+
+impl MapRng {
+    pub fn new(seed: u64) -> Self {
+        MapRng { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// The seed of a named stream, to pass to `Map::scatter` or a generator.
+    pub fn stream(&self, name: &str) -> u64 {
+        // FNV-1a, rather than std's hasher whose output isn't guaranteed to be stable.
+        let mut hash = 0xCBF2_9CE4_8422_2325u64;
+        for byte in name.bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+        SplitMix64::new(self.seed ^ hash).next_u64()
+    }
+}
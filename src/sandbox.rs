@@ -0,0 +1,187 @@
+use crate::{animation::animation_rows, tmx::read_map_size, Map, MapError, MapLoadOptions};
+use std::{collections::HashSet, path::PathBuf};
+
+/// Limits on maps from untrusted sources, such as player uploads, see
+/// `Map::from_untrusted_bytes`.
+///
+/// Maps over a size limit are rejected. Content that isn't allowed is stripped instead, so a
+/// map with one bad tileset still loads without it.
+#[derive(Debug, Clone)]
+pub struct SandboxProfile {
+    /// Size of the `.tmx` file in bytes.
+    pub max_bytes: usize,
+    /// Width and height of the map in tiles.
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Tile, object and image layers together.
+    pub max_layers: usize,
+    pub max_objects: usize,
+    /// Tileset images maps may draw, relative to the asset folder, or `None` for any. Tilesets
+    /// drawing anything else are stripped along with their tiles.
+    pub allowed_tileset_images: Option<HashSet<PathBuf>>,
+    /// Image layers load whatever image they name, so they're stripped unless allowed.
+    pub allow_image_layers: bool,
+    /// Object `file` properties load whatever file they name, so they're stripped unless
+    /// allowed.
+    pub allow_object_files: bool,
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        Self {
+            max_bytes: 4 * 1024 * 1024,
+            max_width: 1024,
+            max_height: 1024,
+            max_layers: 32,
+            max_objects: 4096,
+            allowed_tileset_images: None,
+            allow_image_layers: false,
+            allow_object_files: false,
+        }
+    }
+}
+
+/// Content `Map::from_untrusted_bytes` stripped from a map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stripped {
+    /// A tileset drawing an image that isn't allowed, by name, along with its tiles and tile
+    /// objects.
+    Tileset(String),
+    /// An image layer, by name.
+    ImageLayer(String),
+    /// The `file` properties of an object, by object id.
+    ObjectFiles(u32),
+}
+
+impl Map {
+    /// Builds a map from a `.tmx` file that can't be trusted, such as one uploaded by a
+    /// player, enforcing `profile`. Returns the map along with whatever was stripped from it.
+    ///
+    /// The map is parsed without a path, so external tilesets are rejected rather than read
+    /// from the filesystem. Set `image_resolver` on the map before adding it to `Assets<Map>`
+    /// if its images are somewhere else.
+    pub fn from_untrusted_bytes(
+        bytes: &[u8],
+        profile: &SandboxProfile,
+    ) -> Result<(Map, Vec<Stripped>), MapError> {
+        if bytes.len() > profile.max_bytes {
+            return Err(MapError::Rejected(format!(
+                "{} bytes, at most {} allowed",
+                bytes.len(),
+                profile.max_bytes
+            )));
+        }
+        let (width, height) = read_map_size(bytes)
+            .ok_or_else(|| MapError::Parse("missing map width or height".to_string()))?;
+        if width > profile.max_width || height > profile.max_height {
+            return Err(MapError::Rejected(format!(
+                "{}x{} tiles, at most {}x{} allowed",
+                width, height, profile.max_width, profile.max_height
+            )));
+        }
+
+        let mut map = Map::parse(bytes, MapLoadOptions::default())?;
+        let layer_count =
+            map.map.layers.len() + map.map.object_groups.len() + map.map.image_layers.len();
+        if layer_count > profile.max_layers {
+            return Err(MapError::Rejected(format!(
+                "{} layers, at most {} allowed",
+                layer_count, profile.max_layers
+            )));
+        }
+        let object_count = map
+            .map
+            .object_groups
+            .iter()
+            .map(|group| group.objects.len())
+            .sum::<usize>();
+        if object_count > profile.max_objects {
+            return Err(MapError::Rejected(format!(
+                "{} objects, at most {} allowed",
+                object_count, profile.max_objects
+            )));
+        }
+
+        let stripped = map.sanitize(profile);
+        map.build()?;
+        Ok((map, stripped))
+    }
+
+    /// Strips the content `profile` doesn't allow.
+    fn sanitize(&mut self, profile: &SandboxProfile) -> Vec<Stripped> {
+        let mut stripped = Vec::new();
+        if let Some(allowed) = &profile.allowed_tileset_images {
+            let disallowed = self
+                .map
+                .tilesets
+                .iter()
+                .filter(|tileset| {
+                    self.tileset_image_path(tileset)
+                        .map_or(true, |path| !allowed.contains(&PathBuf::from(path)))
+                })
+                .map(|tileset| (tileset.first_gid, tileset.name.clone()))
+                .collect::<Vec<_>>();
+            for (first_gid, name) in disallowed {
+                self.strip_tileset(first_gid);
+                stripped.push(Stripped::Tileset(name));
+            }
+        }
+        if !profile.allow_image_layers {
+            for layer in self.map.image_layers.drain(..) {
+                stripped.push(Stripped::ImageLayer(layer.name));
+            }
+            self.image_layer_repeats.clear();
+        }
+        if !profile.allow_object_files {
+            let mut ids = self
+                .object_files
+                .drain()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            ids.sort();
+            stripped.extend(ids.into_iter().map(Stripped::ObjectFiles));
+        }
+        stripped
+    }
+
+    /// Removes the tileset starting at `first_gid`, clearing its tiles and tile objects.
+    fn strip_tileset(&mut self, first_gid: u32) {
+        // Gids up to the next tileset belong to this one, whatever its tile count says.
+        let end = self
+            .map
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.first_gid)
+            .filter(|gid| *gid > first_gid)
+            .min()
+            .unwrap_or(std::u32::MAX);
+        let in_tileset = |gid: u32| gid >= first_gid && gid < end;
+
+        for layer in self.map.layers.iter_mut() {
+            if let tiled::LayerData::Finite(rows) = &mut layer.tiles {
+                for tile in rows.iter_mut().flat_map(|row| row.iter_mut()) {
+                    if in_tileset(tile.gid) {
+                        *tile = tiled::LayerTile::new(0);
+                    }
+                }
+            }
+        }
+        for group in self.map.object_groups.iter_mut() {
+            group.objects.retain(|object| !in_tileset(object.gid));
+        }
+        self.objects_changed();
+
+        self.map
+            .tilesets
+            .retain(|tileset| tileset.first_gid != first_gid);
+        self.tileset_sources.remove(&first_gid);
+        self.tile_offsets.remove(&first_gid);
+        self.tile_uvs.remove(&first_gid);
+        self.wang_sets.retain(|set| set.first_gid != first_gid);
+        self.tile_acoustics.retain(|gid, _| !in_tileset(*gid));
+        self.tile_footsteps.retain(|gid, _| !in_tileset(*gid));
+        self.tile_animations.retain(|gid, _| !in_tileset(*gid));
+        self.animation_frames.retain(|gid, _| !in_tileset(*gid));
+        self.animation_rows = animation_rows(&self.tile_animations);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{rng::SplitMix64, Map};
+
+impl Map {
+    /// Fills `layer` (an index into `map.layers`) with decoration tiles picked from `gid_weights`.
+    ///
+    /// Each tile for which `mask` returns true gets a decoration with probability `density`.
+    /// The outcome for a tile depends only on `seed` and its position, so the same seed always
+    /// produces the same decorations; take it from a `MapRng` stream. Returns the number of
+    /// tiles placed.
+    pub fn scatter<F>(
+        &mut self,
+        layer: usize,
+        gid_weights: &[(u32, f32)],
+        density: f32,
+        seed: u64,
+        mask: F,
+    ) -> usize
+    where
+        F: Fn(&Map, u32, u32) -> bool,
+    {
+        let total_weight: f32 = gid_weights.iter().map(|(_, weight)| weight.max(0.0)).sum();
+        if layer >= self.map.layers.len() || total_weight <= 0.0 {
+            return 0;
+        }
+
+        let mut placements = Vec::new();
+        for y in 0..self.map.height {
+            for x in 0..self.map.width {
+                if !mask(self, x, y) {
+                    continue;
+                }
+                let mut rng = SplitMix64::for_tile(seed, x, y);
+                if rng.next_f32() >= density {
+                    continue;
+                }
+                let mut pick = rng.next_f32() * total_weight;
+                let gid = gid_weights
+                    .iter()
+                    .find(|(_, weight)| {
+                        pick -= weight.max(0.0);
+                        pick < 0.0
+                    })
+                    .or_else(|| gid_weights.last())
+                    .map(|(gid, _)| *gid)
+                    .unwrap();
+                placements.push((x, y, gid));
+            }
+        }
+
+        for (x, y, gid) in placements.iter() {
+            self.set_tile(layer, *x, *y, *gid);
+        }
+        placements.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DECORATIONS: &[(u32, f32)] = &[(5, 1.0), (6, 2.0)];
+
+    fn map() -> Map {
+        Map::try_from_bytes(
+            include_bytes!("../assets/ortho-map.tmx"),
+            Default::default(),
+        )
+        .unwrap()
+    }
+
+    fn gids(map: &Map) -> Vec<u32> {
+        (0..map.map.height)
+            .flat_map(|y| (0..map.map.width).map(move |x| (x, y)))
+            .map(|(x, y)| map.tile(0, x, y).unwrap().gid)
+            .collect()
+    }
+
+    fn scattered(seed: u64) -> Vec<u32> {
+        let mut map = map();
+        map.scatter(0, DECORATIONS, 0.3, seed, |_, _, _| true);
+        gids(&map)
+    }
+
+    #[test]
+    fn same_seed_places_the_same_tiles() {
+        assert_eq!(scattered(11), scattered(11));
+        assert_ne!(scattered(11), scattered(12));
+    }
+
+    #[test]
+    fn masked_tiles_get_nothing() {
+        let mut map = map();
+        let before = gids(&map);
+        let placed = map.scatter(0, DECORATIONS, 1.0, 11, |_, x, _| x < 10);
+        assert_eq!(placed, 10 * map.map.height as usize);
+        let width = map.map.width as usize;
+        for (i, (before, after)) in before.iter().zip(gids(&map)).enumerate() {
+            if i % width >= 10 {
+                assert_eq!(*before, after);
+            } else {
+                assert!(DECORATIONS.iter().any(|(gid, _)| *gid == after));
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{LayerMask, Map, MapAnchor, TileMapChunk, TiledChunk};
+use bevy::prelude::*;
+use glam::Vec2;
+
+/// Cuts a soft transparent hole in occluding layers of a map entity wherever a
+/// `SeeThroughTarget` is behind them, so it stays visible behind walls and roofs.
+#[derive(Debug, Clone)]
+pub struct SeeThrough {
+    /// The occluding layers, by index into `map.layers`.
+    pub layers: Vec<usize>,
+    /// Radius of the hole in map pixels.
+    pub radius: f32,
+    /// Width of the hole's soft edge in map pixels.
+    pub softness: f32,
+    /// Opacity of occluding tiles in the middle of the hole.
+    pub opacity: f32,
+}
+
+impl Default for SeeThrough {
+    fn default() -> Self {
+        Self {
+            layers: Vec::new(),
+            radius: 48.0,
+            softness: 16.0,
+            opacity: 0.2,
+        }
+    }
+}
+
+/// Marks an entity that occluding layers are cut away around. Only the first one behind an
+/// occluding tile gets a hole.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SeeThroughTarget;
+
+/// Moves the see-through hole of each `SeeThrough` map entity to the target behind its
+/// occluding layers, or closes it when none is.
+pub fn apply_see_through(
+    maps: Res<Assets<Map>>,
+    mut target_query: Query<(&SeeThroughTarget, &GlobalTransform)>,
+    mut map_query: Query<(&Handle<Map>, &SeeThrough, &MapAnchor, &Transform)>,
+    mut chunk_query: Query<(&TiledChunk, &mut TileMapChunk)>,
+) {
+    let targets = target_query
+        .iter()
+        .iter()
+        .map(|(_, transform)| transform.translation().truncate())
+        .collect::<Vec<_>>();
+
+    for (map_handle, see_through, anchor, origin) in &mut map_query.iter() {
+        let map = match maps.get(map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let translation = map.translation(anchor, origin.translation());
+        let mask = LayerMask::from_layers(&see_through.layers);
+        let hole = targets
+            .iter()
+            .find(|target| map.pick_world(**target, translation, mask).is_some())
+            .map(|target| map.world_to_local(translation, *target));
+        let (radius, hole_center) = match hole {
+            Some(hole_center) => (see_through.radius, hole_center),
+            None => (0.0, Vec2::zero()),
+        };
+
+        for (chunk, mut tile_map_chunk) in &mut chunk_query.iter() {
+            if chunk.map != *map_handle || chunk.key.tileset_guid == 0 {
+                continue;
+            }
+            let layer_index = match map.layers.get(chunk.key.layer_id as usize) {
+                Some(layer) => layer.layer_index,
+                None => continue,
+            };
+            if !see_through.layers.contains(&layer_index) {
+                continue;
+            }
+            // Only touch chunks whose hole moved, so unchanged uniforms aren't uploaded again.
+            if tile_map_chunk.hole_radius != radius
+                || tile_map_chunk.hole_center != hole_center
+                || tile_map_chunk.hole_softness != see_through.softness
+                || tile_map_chunk.hole_opacity != see_through.opacity
+            {
+                tile_map_chunk.hole_radius = radius;
+                tile_map_chunk.hole_center = hole_center;
+                tile_map_chunk.hole_softness = see_through.softness;
+                tile_map_chunk.hole_opacity = see_through.opacity;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{Map, TileRect, WangSet};
+use std::collections::HashSet;
+
+/// Shoreline autotiles drawn over the water of a terrain layer wherever it meets land.
+///
+/// Each shore cell gets the corner wang tile whose corners touching land are `land_color` and
+/// the rest `water_color`. Giving those tiles an `<animation>` in Tiled animates the shore.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shoreline {
+    /// Layer holding the terrain, an index into `map.layers`.
+    pub terrain_layer: usize,
+    /// Layer the shoreline is drawn into, usually the one above `terrain_layer`. Cells off the
+    /// shore are cleared.
+    pub shore_layer: usize,
+    /// Gids of the terrain layer that are water. Cells with any other tile are land.
+    pub water: HashSet<u32>,
+    pub wang_set: WangSet,
+    pub water_color: u8,
+    pub land_color: u8,
+}
+
+impl Shoreline {
+    fn is_land(&self, map: &Map, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        map.tile(self.terrain_layer, x as u32, y as u32)
+            .map_or(false, |tile| {
+                tile.gid != 0 && !self.water.contains(&tile.gid)
+            })
+    }
+
+    /// The gid of the shore tile for the cell at `x`, `y`, or 0 if it isn't on the shore.
+    fn shore_gid(&self, map: &Map, x: i32, y: i32) -> u32 {
+        let water = map
+            .tile(self.terrain_layer, x as u32, y as u32)
+            .map_or(false, |tile| self.water.contains(&tile.gid));
+        if !water {
+            return 0;
+        }
+        // A corner touches land if any of the four cells meeting there is land.
+        let corner = |dx: i32, dy: i32| {
+            let land = [(0, 0), (dx, 0), (0, dy), (dx, dy)]
+                .iter()
+                .any(|(ox, oy)| self.is_land(map, x + ox, y + oy));
+            if land {
+                self.land_color
+            } else {
+                self.water_color
+            }
+        };
+        let corners = [corner(-1, -1), corner(1, -1), corner(1, 1), corner(-1, 1)];
+        if corners.iter().all(|color| *color == self.water_color) {
+            return 0;
+        }
+        self.wang_set.corner_tile(corners).unwrap_or(0)
+    }
+}
+
+impl Map {
+    /// Draws `shoreline` along its whole terrain layer and keeps it up to date as the terrain is
+    /// edited with `set_tile`. Returns the number of shore tiles changed.
+    pub fn add_shoreline(&mut self, shoreline: Shoreline) -> usize {
+        let (width, height) = (self.map.width, self.map.height);
+        self.shorelines.push(shoreline);
+        self.retile_shoreline(
+            self.shorelines.len() - 1,
+            TileRect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        )
+    }
+
+    /// Re-tiles the shorelines of `layer` around `rect`, such as after editing its terrain
+    /// directly. Returns the number of shore tiles changed.
+    pub fn update_shorelines(&mut self, layer: usize, rect: TileRect) -> usize {
+        // Changing a cell changes the corners of the cells around it.
+        let x = rect.x.saturating_sub(1);
+        let y = rect.y.saturating_sub(1);
+        let rect = TileRect {
+            x,
+            y,
+            width: rect.x + rect.width + 1 - x,
+            height: rect.y + rect.height + 1 - y,
+        };
+        (0..self.shorelines.len())
+            .filter(|index| self.shorelines[*index].terrain_layer == layer)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|index| self.retile_shoreline(index, rect))
+            .sum()
+    }
+
+    fn retile_shoreline(&mut self, index: usize, rect: TileRect) -> usize {
+        let shoreline = &self.shorelines[index];
+        let x_end = (rect.x + rect.width).min(self.map.width);
+        let y_end = (rect.y + rect.height).min(self.map.height);
+        let mut placements = Vec::new();
+        for y in rect.y..y_end {
+            for x in rect.x..x_end {
+                let gid = shoreline.shore_gid(self, x as i32, y as i32);
+                let current = self.tile(shoreline.shore_layer, x, y).map(|tile| tile.gid);
+                if current.map_or(false, |current| current != gid) {
+                    placements.push((x, y, gid));
+                }
+            }
+        }
+
+        let shore_layer = shoreline.shore_layer;
+        placements
+            .into_iter()
+            .filter(|(x, y, gid)| self.write_tile(shore_layer, *x, *y, *gid))
+            .count()
+    }
+}
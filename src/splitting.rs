@@ -0,0 +1,157 @@
+use crate::{loader::part_size, ChunkKey, Map};
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// A chunk of one of `Map::layers`, by layer id and chunk position.
+pub type ChunkId = (u32, (usize, usize));
+
+/// When chunks are split into parts so edits only rebuild the part they touch, and merged
+/// back into a single mesh to save draw calls once edits stop. Off by default, for maps
+/// painted on every frame such as by an in-game editor.
+#[derive(Debug, Clone)]
+pub struct ChunkSplitting {
+    pub enabled: bool,
+    /// Frames a second with edits to a chunk that split it. However many tiles a frame writes,
+    /// such as a scatter or a brush stroke, it counts once.
+    pub split_rate: f32,
+    /// Seconds without edits after which a split chunk is merged again.
+    pub merge_delay: f32,
+}
+
+impl Default for ChunkSplitting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            split_rate: 4.0,
+            merge_delay: 2.0,
+        }
+    }
+}
+
+/// How often each chunk of a map is edited, and which ones are split.
+#[derive(Debug, Default)]
+pub struct ChunkSplits {
+    /// Chunks edited since the last update.
+    edits: HashSet<ChunkId>,
+    /// Recent frames with edits, decaying by about two thirds every second.
+    heat: HashMap<ChunkId, f32>,
+    /// Split chunks and when they were last edited, in seconds since startup.
+    split: HashMap<ChunkId, f64>,
+}
+
+impl ChunkSplits {
+    pub fn is_split(&self, chunk: ChunkId) -> bool {
+        self.split.contains_key(&chunk)
+    }
+
+    pub(crate) fn edited(&mut self, chunk: ChunkId) {
+        self.edits.insert(chunk);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.edits.is_empty() && self.heat.is_empty() && self.split.is_empty()
+    }
+}
+
+impl Map {
+    /// How many parts the chunk at `chunk` splits into along each axis, fewer than
+    /// `CHUNK_PARTS` for chunks cut short by the map's edge or too small to split that far.
+    pub fn chunk_parts(&self, chunk: (usize, usize)) -> (usize, usize) {
+        let (width, height) = self.chunk_size;
+        let (part_width, part_height) = part_size(self.chunk_size);
+        let tiles = |size: u32, index: usize, chunk_size: usize| {
+            (size as usize)
+                .saturating_sub(index * chunk_size)
+                .min(chunk_size)
+                .max(1)
+        };
+        let tiles_x = tiles(self.map.width, chunk.0, width);
+        let tiles_y = tiles(self.map.height, chunk.1, height);
+        (
+            (tiles_x + part_width - 1) / part_width,
+            (tiles_y + part_height - 1) / part_height,
+        )
+    }
+
+    /// Splits chunks edited faster than `split_rate` and merges split ones that haven't been
+    /// edited for `merge_delay`, marking their meshes dirty. `now` is in seconds since startup
+    /// and `delta` is the time since the last update.
+    pub fn update_chunk_splits(&mut self, settings: &ChunkSplitting, now: f64, delta: f32) {
+        let splits = &mut self.chunk_splits;
+        let decay = (-delta).exp();
+        for heat in splits.heat.values_mut() {
+            *heat *= decay;
+        }
+        let mut changed = Vec::new();
+        for chunk in splits.edits.drain() {
+            *splits.heat.entry(chunk).or_insert(0.0) += 1.0;
+            if let Some(last_edit) = splits.split.get_mut(&chunk) {
+                *last_edit = now;
+            }
+        }
+        for (chunk, heat) in splits.heat.iter() {
+            if settings.enabled && *heat >= settings.split_rate && !splits.split.contains_key(chunk)
+            {
+                splits.split.insert(*chunk, now);
+                changed.push(*chunk);
+            }
+        }
+        splits.heat.retain(|_, heat| *heat >= 0.01);
+        let merge_delay = settings.merge_delay as f64;
+        let merged = splits
+            .split
+            .iter()
+            .filter(|(_, last_edit)| !settings.enabled || now - **last_edit >= merge_delay)
+            .map(|(chunk, _)| *chunk)
+            .collect::<Vec<_>>();
+        for chunk in merged {
+            splits.split.remove(&chunk);
+            changed.push(chunk);
+        }
+
+        // Rebuilding both the whole chunk and its parts swaps which of them has a mesh.
+        for (layer_id, chunk) in changed {
+            let parts = self.chunk_parts(chunk);
+            let layer = match self.layers.get(layer_id as usize) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            for tileset_layer in layer.tileset_layers.iter() {
+                let key = ChunkKey {
+                    layer_id,
+                    tileset_guid: tileset_layer.tileset_guid,
+                    chunk,
+                    part: None,
+                };
+                self.dirty_chunks.insert(key);
+                for part_x in 0..parts.0 {
+                    for part_y in 0..parts.1 {
+                        self.dirty_chunks.insert(ChunkKey {
+                            part: Some((part_x, part_y)),
+                            ..key
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits and merges the chunks of maps being edited, see `ChunkSplitting`.
+pub fn split_hot_chunks(
+    time: Res<Time>,
+    settings: Res<ChunkSplitting>,
+    mut maps: ResMut<Assets<Map>>,
+) {
+    // Maps that aren't being edited are left alone, since `get_mut` marks them modified.
+    let busy = maps
+        .iter()
+        .filter(|(_, map)| !map.chunk_splits.is_idle())
+        .map(|(handle, _)| handle)
+        .collect::<Vec<_>>();
+    for map_handle in busy {
+        if let Some(map) = maps.get_mut(&map_handle) {
+            map.update_chunk_splits(&settings, time.seconds_since_startup, time.delta_seconds);
+        }
+    }
+}
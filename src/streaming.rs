@@ -0,0 +1,314 @@
+use crate::{loader::part_size, ChunkComponents, ChunkKey, ChunkPool, Map, Tileset, MERGED_LAYER};
+use bevy::prelude::*;
+use glam::Vec2;
+use std::collections::{HashMap, HashSet};
+
+/// Spawns the chunks of the maps in `MapStreaming` near `StreamingAnchor`s, and despawns them
+/// again as the anchors move away. Streamed maps don't need map entities.
+#[derive(Default)]
+pub struct MapStreamingPlugin;
+
+impl Plugin for MapStreamingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Before the maps are processed, so no meshes are built for them up front.
+        app.init_resource::<MapStreaming>()
+            .add_system_to_stage(stage::PRE_UPDATE, mark_streamed_maps.system())
+            .add_system(stream_map_chunks.system());
+    }
+}
+
+/// A map placed in the world for streaming.
+#[derive(Debug, Clone)]
+pub struct StreamedMap {
+    pub map: Handle<Map>,
+    /// Translation of the map's chunks, as for a map entity that isn't centered.
+    pub offset: Vec3,
+}
+
+/// The maps to stream and how close anchors have to be for their chunks to be spawned.
+#[derive(Debug, Clone)]
+pub struct MapStreaming {
+    pub maps: Vec<StreamedMap>,
+    /// Distance in world units from an anchor to the middle of a chunk within which the chunk
+    /// is built and spawned.
+    pub radius: f32,
+    /// Distance beyond which a spawned chunk is despawned and its mesh dropped. Kept above
+    /// `radius` so chunks along the edge aren't rebuilt every time an anchor wobbles.
+    pub evict_radius: f32,
+}
+
+impl Default for MapStreaming {
+    fn default() -> Self {
+        Self {
+            maps: Vec::new(),
+            radius: 2048.0,
+            evict_radius: 2560.0,
+        }
+    }
+}
+
+/// Marks an entity, usually the camera, that map chunks are streamed in around.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StreamingAnchor;
+
+#[derive(Default)]
+pub struct MapStreamingState {
+    map_event_reader: EventReader<AssetEvent<Map>>,
+    /// Spawned chunk entities, by map and chunk.
+    spawned: HashMap<(Handle<Map>, ChunkKey), Entity>,
+    /// Chunks in range that draw nothing, until their map is edited.
+    empty: HashSet<(Handle<Map>, ChunkKey)>,
+    /// The generation of each map when its chunks were spawned.
+    generations: HashMap<Handle<Map>, u32>,
+}
+
+impl Map {
+    /// Map-local position of the middle of the chunk `key`.
+    fn chunk_center(&self, key: &ChunkKey) -> Vec2 {
+        if key.layer_id == MERGED_LAYER {
+            // Merged meshes cover the whole map.
+            return self.tile_to_local(Vec2::new(
+                self.map.width as f32 / 2.0,
+                self.map.height as f32 / 2.0,
+            ));
+        }
+        let (width, height) = self.chunk_size;
+        let ((size_x, size_y), x, y) = match key.part {
+            Some((part_x, part_y)) => {
+                let (part_width, part_height) = part_size(self.chunk_size);
+                (
+                    (part_width, part_height),
+                    key.chunk.0 * width + part_x * part_width,
+                    key.chunk.1 * height + part_y * part_height,
+                )
+            }
+            None => ((width, height), key.chunk.0 * width, key.chunk.1 * height),
+        };
+        self.tile_to_local(Vec2::new(
+            x as f32 + size_x as f32 / 2.0,
+            y as f32 + size_y as f32 / 2.0,
+        ))
+    }
+
+    /// Builds chunk meshes only as `stream_map_chunks` needs them, rather than every one when
+    /// the map is processed, so huge maps only take memory for the chunks around the anchors.
+    /// Merged maps are drawn whole and keep their meshes.
+    pub fn set_streamed(&mut self, streamed: bool) {
+        let streamed = streamed && !self.merged;
+        if streamed == self.streamed {
+            return;
+        }
+        self.streamed = streamed;
+        self.chunk_splits = Default::default();
+        self.dirty_chunks.clear();
+        self.build_layers();
+    }
+
+    /// The chunks `stream_map_chunks` can spawn: every chunk of a streamed map drawing any of
+    /// its tileset's tiles, or the chunks with a mesh.
+    fn streamable_chunks(&self) -> Vec<ChunkKey> {
+        if !self.streamed {
+            return self.chunk_meshes.keys().copied().collect();
+        }
+        let mut keys = Vec::new();
+        for (layer_id, layer) in self.layers.iter().enumerate() {
+            for tileset_layer in layer.tileset_layers.iter() {
+                for (x, chunks_y) in tileset_layer.chunks.iter().enumerate() {
+                    for (y, chunk) in chunks_y.iter().enumerate() {
+                        // Released maps have no chunk tiles left to go by.
+                        if chunk.gids.is_empty() && !self.source_released {
+                            continue;
+                        }
+                        keys.push(ChunkKey {
+                            layer_id: layer_id as u32,
+                            tileset_guid: tileset_layer.tileset_guid,
+                            chunk: (x, y),
+                            part: None,
+                        });
+                    }
+                }
+            }
+        }
+        keys
+    }
+
+    /// Builds the mesh of the chunk `key` of a streamed map from its tile layer, with its cell
+    /// texture if the map draws from data textures. `None` if it draws nothing.
+    fn build_streamed_chunk(&self, key: &ChunkKey) -> Option<(Mesh, Option<Texture>)> {
+        let layer = self.layers.get(key.layer_id as usize)?;
+        let tileset = self
+            .map
+            .tilesets
+            .iter()
+            .find(|tileset| tileset.first_gid == key.tileset_guid)?;
+        let chunk = self.build_chunk(layer.layer_index, tileset, key.chunk.0, key.chunk.1, None);
+        let mesh = self.chunk_mesh(layer.layer_index, key.tileset_guid, &chunk, None)?;
+        let cells = if self.data_texture {
+            self.data_texture_cells(layer.layer_index, key.tileset_guid, &chunk)
+        } else {
+            None
+        };
+        Some((mesh, cells))
+    }
+}
+
+/// Streams the maps listed in `MapStreaming` as they're added or reloaded, see
+/// `Map::set_streamed`.
+pub fn mark_streamed_maps(
+    streaming: Res<MapStreaming>,
+    mut map_event_reader: Local<EventReader<AssetEvent<Map>>>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut maps: ResMut<Assets<Map>>,
+) {
+    let mut added = Vec::new();
+    for event in map_event_reader.iter(&map_events) {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            added.push(*handle);
+        }
+    }
+    for handle in added {
+        if !streaming.maps.iter().any(|streamed| streamed.map == handle) {
+            continue;
+        }
+        // `get_mut` fires `Modified`, so only maps that need it are touched.
+        let unmarked = maps.get(&handle).map_or(false, |map| {
+            !map.streamed && !map.merged && map.chunk_meshes.is_empty()
+        });
+        if unmarked {
+            maps.get_mut(&handle).unwrap().set_streamed(true);
+        }
+    }
+}
+
+/// Spawns the chunks of streamed maps within `MapStreaming::radius` of a `StreamingAnchor`,
+/// building their meshes first, and despawns them beyond `MapStreaming::evict_radius`, dropping
+/// their meshes.
+pub fn stream_map_chunks(
+    mut commands: Commands,
+    mut chunk_pool: ResMut<ChunkPool>,
+    mut state: Local<MapStreamingState>,
+    streaming: Res<MapStreaming>,
+    map_events: Res<Events<AssetEvent<Map>>>,
+    mut maps: ResMut<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut textures: ResMut<Assets<Texture>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut anchor_query: Query<(&StreamingAnchor, &GlobalTransform)>,
+) {
+    let state = &mut *state;
+    // Edits can fill chunks that were empty.
+    for event in state.map_event_reader.iter(&map_events) {
+        if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+            state.empty.retain(|(map, _)| map != handle);
+        }
+    }
+    let anchors = anchor_query
+        .iter()
+        .iter()
+        .map(|(_, transform)| transform.translation().truncate())
+        .collect::<Vec<_>>();
+    let evict_radius = streaming.evict_radius.max(streaming.radius);
+
+    let mut kept = HashSet::new();
+    for streamed in streaming.maps.iter() {
+        let map = match maps.get(&streamed.map) {
+            Some(map) => map,
+            None => continue,
+        };
+        // Reloading a map despawns every chunk drawing it, streamed or not.
+        if state.generations.insert(streamed.map, map.generation) != Some(map.generation) {
+            let map_handle = streamed.map;
+            state.spawned.retain(|(handle, _), _| *handle != map_handle);
+            state.empty.retain(|(handle, _)| *handle != map_handle);
+        }
+
+        let mut entering = Vec::new();
+        for key in map.streamable_chunks() {
+            let center = map.local_to_world(streamed.offset, map.chunk_center(&key));
+            let distance = anchors
+                .iter()
+                .map(|anchor| (center.truncate() - *anchor).length())
+                .fold(f32::INFINITY, f32::min);
+            let id = (streamed.map, key);
+            if state.spawned.contains_key(&id) {
+                if distance <= evict_radius {
+                    kept.insert(id);
+                }
+            } else if distance <= streaming.radius && !state.empty.contains(&id) {
+                entering.push(key);
+            }
+        }
+
+        for key in entering {
+            let id = (streamed.map, key);
+            let map = maps.get(&streamed.map).unwrap();
+            let mesh = match map.chunk_meshes.get(&key).copied() {
+                Some(mesh) => mesh,
+                None => match map.build_streamed_chunk(&key) {
+                    Some((mesh, cells)) => {
+                        let mesh = meshes.add(mesh);
+                        let cells = cells.map(|cells| textures.add(cells));
+                        let map = maps.get_mut(&streamed.map).unwrap();
+                        map.chunk_meshes.insert(key, mesh);
+                        if let Some(cells) = cells {
+                            map.chunk_cells.insert(key, cells);
+                        }
+                        mesh
+                    }
+                    None => {
+                        state.empty.insert(id);
+                        continue;
+                    }
+                },
+            };
+            let map = maps.get(&streamed.map).unwrap();
+            let material = map
+                .tileset_assets
+                .get(&key.tileset_guid)
+                .and_then(|tileset| tilesets.get(tileset))
+                .map(|tileset| tileset.material);
+            let material = match material {
+                Some(material) => material,
+                None => continue,
+            };
+            let entity = chunk_pool.spawn(
+                &mut commands,
+                ChunkComponents::new(
+                    streamed.map,
+                    None,
+                    key,
+                    map.chunk_depth(&key),
+                    mesh,
+                    material,
+                    map.chunk_translation(streamed.offset, &key),
+                ),
+            );
+            state.spawned.insert(id, entity);
+            kept.insert(id);
+        }
+    }
+
+    let spawned = &mut state.spawned;
+    spawned.retain(|(map_handle, key), entity| {
+        if kept.contains(&(*map_handle, *key)) {
+            return true;
+        }
+        // Chunks that emptied out were already despawned along with their mesh.
+        let (still_drawn, streamed) = maps.get(map_handle).map_or((false, false), |map| {
+            (map.chunk_meshes.contains_key(key), map.streamed)
+        });
+        if still_drawn {
+            chunk_pool.release(&mut commands, *entity);
+        }
+        if still_drawn && streamed {
+            let map = maps.get_mut(map_handle).unwrap();
+            if let Some(mesh) = map.chunk_meshes.remove(key) {
+                meshes.remove(&mesh);
+            }
+            if let Some(cells) = map.chunk_cells.remove(key) {
+                textures.remove(&cells);
+            }
+        }
+        false
+    });
+}
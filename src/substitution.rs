@@ -0,0 +1,97 @@
+use crate::Map;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Alternate tileset art, such as a high contrast accessibility mode, swapped in across every
+/// loaded map without duplicating map files.
+///
+/// Changes are applied when `enabled` is toggled and to maps loaded afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct TilesetSubstitution {
+    pub enabled: bool,
+    /// Replacement images keyed by tileset name, relative to the asset folder. Each must have
+    /// the same layout as the tileset's own image.
+    pub images: HashMap<String, String>,
+    /// Gids drawn in place of others while enabled.
+    pub gids: HashMap<u32, u32>,
+}
+
+#[derive(Default)]
+pub struct TilesetSubstitutionState {
+    /// The map generation and whether the substitution was enabled when last applied to each
+    /// map entity.
+    applied: HashMap<Entity, (u32, bool)>,
+    /// The textures tileset materials had before being substituted.
+    original_textures: HashMap<Handle<ColorMaterial>, Option<Handle<Texture>>>,
+    textures: HashMap<String, Handle<Texture>>,
+}
+
+/// Swaps the textures of tileset materials and the gids drawn by maps to match
+/// `TilesetSubstitution`.
+pub fn apply_tileset_substitution(
+    mut state: Local<TilesetSubstitutionState>,
+    substitution: Res<TilesetSubstitution>,
+    asset_server: Res<AssetServer>,
+    mut maps: ResMut<Assets<Map>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &Handle<Map>, &HashMap<u32, Handle<ColorMaterial>>)>,
+) {
+    let state = &mut *state;
+    for (entity, map_handle, materials_map) in &mut query.iter() {
+        let generation = match maps.get(map_handle) {
+            // Materials are created once the map has been processed.
+            Some(map)
+                if map
+                    .map
+                    .tilesets
+                    .iter()
+                    .all(|tileset| materials_map.contains_key(&tileset.first_gid)) =>
+            {
+                map.generation
+            }
+            _ => continue,
+        };
+        let applied = (generation, substitution.enabled);
+        if state.applied.get(&entity) == Some(&applied) {
+            continue;
+        }
+        let map = maps.get_mut(map_handle).unwrap();
+
+        for tileset in map.map.tilesets.iter() {
+            let material_handle = materials_map[&tileset.first_gid];
+            let material = match materials.get_mut(&material_handle) {
+                Some(material) => material,
+                None => continue,
+            };
+            let image = substitution.images.get(&tileset.name);
+            match image {
+                Some(image) if substitution.enabled => {
+                    let texture = match state.textures.get(image) {
+                        Some(texture) => *texture,
+                        None => match asset_server.load(image.as_str()) {
+                            Ok(texture) => *state.textures.entry(image.clone()).or_insert(texture),
+                            Err(_) => continue,
+                        },
+                    };
+                    state
+                        .original_textures
+                        .entry(material_handle)
+                        .or_insert(material.texture);
+                    material.texture = Some(texture);
+                }
+                _ => {
+                    if let Some(texture) = state.original_textures.remove(&material_handle) {
+                        material.texture = texture;
+                    }
+                }
+            }
+        }
+
+        map.set_gid_overrides(if substitution.enabled {
+            substitution.gids.clone()
+        } else {
+            HashMap::new()
+        });
+        state.applied.insert(entity, applied);
+    }
+}
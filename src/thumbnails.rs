@@ -0,0 +1,242 @@
+use crate::{lod::texel, Map, Tileset};
+use bevy::{prelude::*, render::texture::TextureFormat};
+
+/// A texture a map is rendered into by `render_map_thumbnails`.
+#[derive(Debug, Clone)]
+struct MapThumbnail {
+    map: Handle<Map>,
+    size: (u32, u32),
+    texture: Handle<Texture>,
+    /// The map generation and `tiles_revision` last rendered, or `None` until the map and its
+    /// tilesets load.
+    revision: Option<(u32, u32)>,
+}
+
+/// Renders maps into textures, for minimaps or level select thumbnails. The textures are
+/// rendered on the CPU, since Bevy 0.2 can't render to a texture, once the map and its
+/// tileset images load, and again whenever tiles are edited.
+#[derive(Debug, Default)]
+pub struct MapThumbnails {
+    thumbnails: Vec<MapThumbnail>,
+}
+
+impl MapThumbnails {
+    /// A texture of `width` by `height` pixels the whole of `map` is drawn into, transparent
+    /// until it's rendered. It can be drawn with a `ColorMaterial` like any other texture.
+    pub fn render(
+        &mut self,
+        map: Handle<Map>,
+        width: u32,
+        height: u32,
+        textures: &mut Assets<Texture>,
+    ) -> Handle<Texture> {
+        let texture = textures.add(Texture::new(
+            Vec2::new(1.0, 1.0),
+            vec![0; 4],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        self.thumbnails.push(MapThumbnail {
+            map,
+            size: (width.max(1), height.max(1)),
+            texture,
+            revision: None,
+        });
+        texture
+    }
+
+    /// Stops keeping `texture` up to date with its map.
+    pub fn remove(&mut self, texture: &Handle<Texture>) {
+        self.thumbnails
+            .retain(|thumbnail| thumbnail.texture != *texture);
+    }
+}
+
+impl Map {
+    /// Draws the tile layers of the map, over its background color, into a `width` by `height`
+    /// texture stretched over `world_bounds`. Image layers and objects aren't drawn, and
+    /// animated tiles show the frame they're on.
+    ///
+    /// `None` until every tileset image the map draws with has loaded. Tilesets drawn with a
+    /// placeholder, or with images that aren't 8 bit RGBA, are left out.
+    pub fn render_thumbnail(
+        &self,
+        width: u32,
+        height: u32,
+        tilesets: &Assets<Tileset>,
+        textures: &Assets<Texture>,
+    ) -> Option<Texture> {
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        let background = self.background_color().map_or([0.0; 4], |color| {
+            let [r, g, b, a]: [f32; 4] = color.into();
+            [r * 255.0, g * 255.0, b * 255.0, a * 255.0]
+        });
+        let mut pixels = vec![background; width * height];
+        let bounds = self.world_bounds();
+        let scale = Vec2::new(
+            width as f32 / (bounds.max.x() - bounds.min.x()).max(1.0),
+            height as f32 / (bounds.max.y() - bounds.min.y()).max(1.0),
+        );
+
+        let mut layer_ids = (0..self.layers.len()).collect::<Vec<_>>();
+        layer_ids.sort_by(|a, b| {
+            self.layer_depth(*a)
+                .partial_cmp(&self.layer_depth(*b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let (chunks_x, chunks_y) = self.chunk_count();
+        for layer_id in layer_ids {
+            let layer_index = self.layers[layer_id].layer_index;
+            for tileset in self.map.tilesets.iter() {
+                let source = match self.chunk_source_texture(tileset.first_gid, tilesets) {
+                    Some(source) => source,
+                    None => continue,
+                };
+                let source = textures.get(&source)?;
+                match source.format {
+                    TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+                    _ => continue,
+                }
+                for x in 0..chunks_x {
+                    for y in 0..chunks_y {
+                        let chunk = self.build_chunk(layer_index, tileset, x, y, None);
+                        let vertices =
+                            match self.chunk_vertices(layer_index, tileset.first_gid, &chunk, None)
+                            {
+                                Some(vertices) => vertices,
+                                None => continue,
+                            };
+                        // Image space, y down.
+                        let points = vertices
+                            .positions
+                            .iter()
+                            .map(|[x, y, _]| {
+                                Vec2::new(
+                                    (x - bounds.min.x()) * scale.x(),
+                                    (bounds.max.y() - y) * scale.y(),
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        for triangle in vertices.indices.chunks(3) {
+                            let corners = [
+                                triangle[0] as usize,
+                                triangle[1] as usize,
+                                triangle[2] as usize,
+                            ];
+                            fill_triangle(
+                                &mut pixels,
+                                (width, height),
+                                [points[corners[0]], points[corners[1]], points[corners[2]]],
+                                |weights| {
+                                    let mut uv = Vec2::zero();
+                                    let mut tint = [0.0; 4];
+                                    for (corner, weight) in corners.iter().zip(weights.iter()) {
+                                        let [u, v] = vertices.uvs[*corner];
+                                        uv += Vec2::new(u, v) * *weight;
+                                        for (channel, value) in
+                                            vertices.colors[*corner].iter().enumerate()
+                                        {
+                                            tint[channel] += value * weight;
+                                        }
+                                    }
+                                    let color = texel(source, uv.x(), uv.y());
+                                    [
+                                        color[0] * tint[0],
+                                        color[1] * tint[1],
+                                        color[2] * tint[2],
+                                        color[3] * tint[3],
+                                    ]
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let data = pixels
+            .iter()
+            .flat_map(|pixel| {
+                pixel
+                    .iter()
+                    .map(|channel| channel.max(0.0).min(255.0) as u8)
+            })
+            .collect();
+        Some(Texture::new(
+            Vec2::new(width as f32, height as f32),
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        ))
+    }
+}
+
+/// The z of the cross product of `a` and `b`.
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x() * b.y() - a.y() * b.x()
+}
+
+/// Blends `shade` over every pixel whose center falls in `triangle`, given the barycentric
+/// weights of its corners there.
+fn fill_triangle(
+    pixels: &mut [[f32; 4]],
+    (width, height): (usize, usize),
+    triangle: [Vec2; 3],
+    shade: impl Fn([f32; 3]) -> [f32; 4],
+) {
+    let [a, b, c] = triangle;
+    let area = cross(b - a, c - a);
+    if area.abs() <= std::f32::EPSILON {
+        return;
+    }
+    let min = a.min(b).min(c);
+    let max = a.max(b).max(c);
+    let (first_x, first_y) = (min.x().max(0.0) as usize, min.y().max(0.0) as usize);
+    let (last_x, last_y) = (
+        (max.x().ceil().max(0.0) as usize).min(width),
+        (max.y().ceil().max(0.0) as usize).min(height),
+    );
+    for y in first_y..last_y {
+        for x in first_x..last_x {
+            let point = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let weights = [
+                cross(c - b, point - b) / area,
+                cross(a - c, point - c) / area,
+                cross(b - a, point - a) / area,
+            ];
+            if weights.iter().any(|weight| *weight < 0.0) {
+                continue;
+            }
+            let color = shade(weights);
+            let pixel = &mut pixels[y * width + x];
+            let alpha = color[3] / 255.0;
+            for channel in 0..3 {
+                pixel[channel] = color[channel] * alpha + pixel[channel] * (1.0 - alpha);
+            }
+            pixel[3] = color[3] + pixel[3] * (1.0 - alpha);
+        }
+    }
+}
+
+/// Renders the textures of `MapThumbnails` once their maps are ready, and again when a map is
+/// reloaded or its tiles change.
+pub fn render_map_thumbnails(
+    mut thumbnails: ResMut<MapThumbnails>,
+    maps: Res<Assets<Map>>,
+    tilesets: Res<Assets<Tileset>>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    for thumbnail in thumbnails.thumbnails.iter_mut() {
+        let map = match maps.get(&thumbnail.map) {
+            Some(map) => map,
+            None => continue,
+        };
+        let revision = (map.generation, map.tiles_revision);
+        if thumbnail.revision == Some(revision) {
+            continue;
+        }
+        let (width, height) = thumbnail.size;
+        if let Some(texture) = map.render_thumbnail(width, height, &tilesets, &textures) {
+            textures.set(thumbnail.texture, texture);
+            thumbnail.revision = Some(revision);
+        }
+    }
+}
@@ -1,6 +1,9 @@
 #version 450
 
 layout(location = 0) in vec2 v_Uv;
+layout(location = 1) in float v_Alpha;
+layout(location = 2) flat in float v_Animation;
+layout(location = 3) in vec4 v_Color;
 
 layout(location = 0) out vec4 o_Target;
 
@@ -13,12 +16,149 @@ layout(set = 1, binding = 1) uniform texture2D ColorMaterial_texture;
 layout(set = 1, binding = 2) uniform sampler ColorMaterial_texture_sampler;
 # endif
 
+layout(set = 2, binding = 1) uniform TileMapChunk {
+    float layer_id;
+    float hole_radius;
+    vec2 hole_center;
+    float hole_softness;
+    float hole_opacity;
+    float time;
+    float scale;
+    vec4 ambient;
+};
+
+# ifdef TILEANIMATIONS_FRAMES
+layout(set = 2, binding = 2) uniform texture2D TileAnimations_frames;
+layout(set = 2, binding = 3) uniform sampler TileAnimations_frames_sampler;
+# endif
+
+# ifdef TILEDATATEXTURE_CELLS
+layout(set = 2, binding = 4) uniform texture2D TileDataTexture_cells;
+layout(set = 2, binding = 5) uniform sampler TileDataTexture_cells_sampler;
+# endif
+
+# ifdef TILELIGHTING_NORMAL_MAP
+layout(set = 2, binding = 6) uniform texture2D TileLighting_normal_map;
+layout(set = 2, binding = 7) uniform sampler TileLighting_normal_map_sampler;
+layout(set = 2, binding = 8) uniform TileLighting_light_direction {
+    vec4 LightDirection;
+};
+layout(set = 2, binding = 9) uniform TileLighting_light_color {
+    vec4 LightColor;
+};
+layout(set = 2, binding = 10) uniform TileLighting_ambient {
+    vec4 Ambient;
+};
+# endif
+
+# ifdef TILEPALETTE_PALETTE
+layout(set = 2, binding = 11) uniform texture2D TilePalette_palette;
+layout(set = 2, binding = 12) uniform sampler TilePalette_palette_sampler;
+
+// Undoes the sRGB decoding of the tileset texture, so palette indices come back as stored.
+float srgb_encode(float linear) {
+    return linear <= 0.0031308 ? linear * 12.92 : 1.055 * pow(linear, 1.0 / 2.4) - 0.055;
+}
+# endif
+
+# ifdef TILEEMISSION_EMISSIVE_MAP
+layout(set = 2, binding = 13) uniform texture2D TileEmission_emissive_map;
+layout(set = 2, binding = 14) uniform sampler TileEmission_emissive_map_sampler;
+layout(set = 2, binding = 15) uniform TileEmission_intensity {
+    float Intensity;
+};
+# endif
+
 void main() {
+    vec2 uv = v_Uv;
+    float animation = v_Animation;
+    vec4 tint = v_Color;
+# ifdef TILEDATATEXTURE_CELLS
+    // The chunk is one quad with uv in cells. Each third of the cell texture holds a texel per
+    // cell: the UVs of its tile, its tint and its animation, see Map::data_texture_cells.
+    ivec2 cells = textureSize(sampler2D(TileDataTexture_cells, TileDataTexture_cells_sampler), 0);
+    cells.y /= 3;
+    ivec2 cell = clamp(ivec2(floor(uv)), ivec2(0), cells - 1);
+    vec2 local = clamp(uv - vec2(cell), 0.0, 1.0);
+    vec4 rect = texelFetch(
+        sampler2D(TileDataTexture_cells, TileDataTexture_cells_sampler), cell, 0);
+    if (rect == vec4(0.0)) {
+        discard;
+    }
+    tint = texelFetch(
+        sampler2D(TileDataTexture_cells, TileDataTexture_cells_sampler),
+        cell + ivec2(0, cells.y), 0);
+    animation = texelFetch(
+        sampler2D(TileDataTexture_cells, TileDataTexture_cells_sampler),
+        cell + ivec2(0, cells.y * 2), 0).x;
+    uv = mix(rect.xy, rect.zw, local);
+# endif
+# ifdef TILEANIMATIONS_FRAMES
+    // Row animation - 1 of the frame texture holds a texel per frame: the UV offset to the
+    // frame, the millisecond it ends at and the length of the animation.
+    if (animation > 0.5) {
+        int row = int(animation + 0.5) - 1;
+        int frames = textureSize(
+            sampler2D(TileAnimations_frames, TileAnimations_frames_sampler), 0).x;
+        vec4 frame = texelFetch(
+            sampler2D(TileAnimations_frames, TileAnimations_frames_sampler),
+            ivec2(0, row), 0);
+        float millis = mod(time * 1000.0, max(frame.w, 1.0));
+        for (int i = 1; i < frames && millis >= frame.z; i++) {
+            frame = texelFetch(
+                sampler2D(TileAnimations_frames, TileAnimations_frames_sampler),
+                ivec2(i, row), 0);
+        }
+        uv += frame.xy;
+    }
+# endif
+
     vec4 color = Color;
 # ifdef COLORMATERIAL_TEXTURE
-    color *= texture(
+    vec4 texel = texture(
         sampler2D(ColorMaterial_texture, ColorMaterial_texture_sampler),
-        v_Uv);
+        uv);
+# ifdef TILEPALETTE_PALETTE
+    // Red holds an index into the palette, alpha stays the tile's.
+    int entries = textureSize(sampler2D(TilePalette_palette, TilePalette_palette_sampler), 0).x;
+    int index = clamp(int(srgb_encode(texel.r) * 255.0 + 0.5), 0, entries - 1);
+    texel = vec4(
+        texelFetch(sampler2D(TilePalette_palette, TilePalette_palette_sampler), ivec2(index, 0), 0).rgb,
+        texel.a);
+# endif
+    color *= texel;
+# endif
+    color *= tint;
+# ifdef TILEEMISSION_EMISSIVE_MAP
+    vec3 unlit = color.rgb;
+# endif
+# ifdef TILELIGHTING_NORMAL_MAP
+    // The normal map shares the tileset image's layout, with z out of the screen.
+    vec3 normal = texture(
+        sampler2D(TileLighting_normal_map, TileLighting_normal_map_sampler),
+        uv).xyz * 2.0 - 1.0;
+    float diffuse = max(dot(normalize(normal), LightDirection.xyz), 0.0);
+    color.rgb *= Ambient.rgb + LightColor.rgb * diffuse;
+# endif
+    // The MapAmbient tint, white on layers that ignore it.
+    color.rgb *= ambient.rgb;
+# ifdef TILEEMISSION_EMISSIVE_MAP
+    // The emissive map shares the tileset image's layout too. Glowing pixels keep their own
+    // color however dark the light, scaled by the glow intensity.
+    vec3 glow = texture(
+        sampler2D(TileEmission_emissive_map, TileEmission_emissive_map_sampler),
+        uv).rgb;
+    color.rgb = max(color.rgb, unlit * glow * Intensity);
+# endif
+    color.a *= v_Alpha;
+    // Keep transparent pixels out of the depth buffer, so sprites behind a tall tile's
+    // empty corners still show.
+    if (color.a <= 0.0) {
+        discard;
+    }
+# ifdef TILEBLENDING_MULTIPLY
+    // Multiplied layers ignore alpha, so see-through pixels fade to white instead.
+    color.rgb = mix(vec3(1.0), color.rgb, color.a);
 # endif
     o_Target = color;
 }
@@ -1,12 +1,126 @@
+use crate::{Map, MapSettings, TiledChunk, TiledImageLayer, TiledObject, MAP_SCALE};
 use bevy::core::Byteable;
-use bevy::render::renderer::{RenderResource, RenderResources};
+use bevy::prelude::*;
+use bevy::render::{
+    renderer::{RenderResource, RenderResources},
+    shader::ShaderDefs,
+    texture::{AddressMode, FilterMode, SamplerDescriptor},
+};
+use glam::Vec2;
+use std::collections::HashSet;
 
 #[repr(C)]
-#[derive(Default, RenderResources, RenderResource)]
+#[derive(RenderResources, RenderResource)]
 #[render_resources(from_self)]
 pub struct TileMapChunk {
     pub layer_id: f32,
+    /// Radius of a see-through hole in map-local pixels, or 0 for none.
+    pub hole_radius: f32,
+    /// Center of the hole in map-local pixels.
+    pub hole_center: Vec2,
+    /// Width of the hole's soft edge in map-local pixels.
+    pub hole_softness: f32,
+    /// Opacity of tiles in the middle of the hole.
+    pub hole_opacity: f32,
+    /// Seconds animated tiles are played at, see `advance_tile_animations`.
+    pub time: f32,
+    /// World units each map pixel is drawn across, kept in line with `Map::scale` by
+    /// `sync_map_scales`.
+    pub scale: f32,
+    /// What colors are multiplied by, kept in line with `MapAmbient` by `apply_map_ambient`.
+    pub ambient: Color,
+}
+
+impl Default for TileMapChunk {
+    fn default() -> Self {
+        Self {
+            layer_id: 0.0,
+            hole_radius: 0.0,
+            hole_center: Vec2::zero(),
+            hole_softness: 0.0,
+            hole_opacity: 0.0,
+            time: 0.0,
+            scale: MAP_SCALE,
+            ambient: Color::WHITE,
+        }
+    }
 }
 
 // SAFE: sprite is repr(C) and only consists of byteables
 unsafe impl Byteable for TileMapChunk {}
+
+/// Draws chunks, tile objects and image layers at the `Map::scale` of their map.
+pub fn sync_map_scales(
+    maps: Res<Assets<Map>>,
+    mut query: Query<(
+        &TiledChunk,
+        Option<&TiledObject>,
+        Option<&TiledImageLayer>,
+        &mut TileMapChunk,
+    )>,
+) {
+    for (chunk, object, image_layer, mut tile_map_chunk) in &mut query.iter() {
+        let map = object
+            .map(|object| &object.map)
+            .or_else(|| image_layer.map(|image_layer| &image_layer.map))
+            .unwrap_or(&chunk.map);
+        if let Some(map) = maps.get(map) {
+            if tile_map_chunk.scale != map.scale {
+                tile_map_chunk.scale = map.scale;
+            }
+        }
+    }
+}
+
+/// The sampler tileset and image layer textures are drawn with: nearest filtering for pixel
+/// art, or linear, clamped to the image either way.
+pub fn tile_sampler(nearest_filtering: bool) -> SamplerDescriptor {
+    let filter = if nearest_filtering {
+        FilterMode::Nearest
+    } else {
+        FilterMode::Linear
+    };
+    SamplerDescriptor {
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter: filter,
+        ..Default::default()
+    }
+}
+
+/// Gives the textures of every chunk, tile object and image layer the `tile_sampler` asked
+/// for by `MapSettings::nearest_filtering`, as they load and whenever the setting changes.
+pub fn apply_tile_sampling(
+    settings: Res<MapSettings>,
+    materials: Res<Assets<ColorMaterial>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut query: Query<(&TiledChunk, &Handle<ColorMaterial>)>,
+) {
+    let sampler = tile_sampler(settings.nearest_filtering);
+    let mut checked = HashSet::new();
+    for (_, material) in &mut query.iter() {
+        let texture = match materials
+            .get(material)
+            .and_then(|material| material.texture)
+        {
+            Some(texture) if checked.insert(texture) => texture,
+            _ => continue,
+        };
+        // Only touched when it differs, since every change re-uploads the texture.
+        let stale = textures.get(&texture).map_or(false, |texture| {
+            let current = &texture.sampler;
+            current.mag_filter != sampler.mag_filter
+                || current.min_filter != sampler.min_filter
+                || current.address_mode_u != sampler.address_mode_u
+                || current.address_mode_v != sampler.address_mode_v
+        });
+        if stale {
+            if let Some(texture) = textures.get_mut(&texture) {
+                texture.sampler = tile_sampler(settings.nearest_filtering);
+            }
+        }
+    }
+}
@@ -3,8 +3,13 @@
 layout(location = 0) in vec3 Vertex_Position;
 layout(location = 1) in vec3 Vertex_Normal;
 layout(location = 2) in vec2 Vertex_Uv;
+layout(location = 3) in float Vertex_Animation;
+layout(location = 4) in vec4 Vertex_Color;
 
 layout(location = 0) out vec2 v_Uv;
+layout(location = 1) out float v_Alpha;
+layout(location = 2) flat out float v_Animation;
+layout(location = 3) out vec4 v_Color;
 
 layout(set = 0, binding = 0) uniform Camera {
     mat4 ViewProj;
@@ -16,11 +21,27 @@ layout(set = 2, binding = 0) uniform Transform {
 
 layout(set = 2, binding = 1) uniform TileMapChunk {
     float layer_id;
+    float hole_radius;
+    vec2 hole_center;
+    float hole_softness;
+    float hole_opacity;
+    float time;
+    float scale;
+    vec4 ambient;
 };
 
 void main() {
     v_Uv = Vertex_Uv;
-    vec3 position = Vertex_Position * vec3(4.0, 4.0, 1.0);
-    position.z = layer_id;
+    v_Animation = Vertex_Animation;
+    v_Color = Vertex_Color;
+    v_Alpha = 1.0;
+    if (hole_radius > 0.0) {
+        float distance = length(Vertex_Position.xy - hole_center);
+        float edge = smoothstep(hole_radius, hole_radius + max(hole_softness, 0.001), distance);
+        v_Alpha = mix(hole_opacity, 1.0, edge);
+    }
+    vec3 position = Vertex_Position * vec3(scale, scale, 1.0);
+    // Tiles of y-sorted maps are nudged in front of their layer, see Map::y_sort_depth.
+    position.z = layer_id + Vertex_Position.z;
     gl_Position = ViewProj * Model * vec4(position, 1.0);
 }
\ No newline at end of file
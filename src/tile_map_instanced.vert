@@ -0,0 +1,53 @@
+#version 450
+
+// One instance per tile, see Map::set_instanced.
+layout(location = 0) in vec4 I_Vertex_Rect;
+layout(location = 1) in vec4 I_Vertex_Uv;
+layout(location = 2) in vec4 I_Vertex_Color;
+layout(location = 3) in float I_Vertex_Depth;
+layout(location = 4) in float I_Vertex_Animation;
+
+layout(location = 0) out vec2 v_Uv;
+layout(location = 1) out float v_Alpha;
+layout(location = 2) flat out float v_Animation;
+layout(location = 3) out vec4 v_Color;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 ViewProj;
+};
+
+layout(set = 2, binding = 0) uniform Transform {
+    mat4 Model;
+};
+
+layout(set = 2, binding = 1) uniform TileMapChunk {
+    float layer_id;
+    float hole_radius;
+    vec2 hole_center;
+    float hole_softness;
+    float hole_opacity;
+    float time;
+    float scale;
+    vec4 ambient;
+};
+
+// Corners of the tile's quad by vertex index, bottom left first and clockwise, as in the
+// quads of chunk meshes.
+const vec2 CORNERS[4] = vec2[4](vec2(0.0, 0.0), vec2(0.0, 1.0), vec2(1.0, 1.0), vec2(1.0, 0.0));
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexIndex];
+    vec2 local = mix(I_Vertex_Rect.xy, I_Vertex_Rect.zw, corner);
+    // Uvs run down the tileset image, positions up the map.
+    v_Uv = mix(I_Vertex_Uv.xy, I_Vertex_Uv.zw, vec2(corner.x, 1.0 - corner.y));
+    v_Animation = I_Vertex_Animation;
+    v_Color = I_Vertex_Color;
+    v_Alpha = 1.0;
+    if (hole_radius > 0.0) {
+        float distance = length(local - hole_center);
+        float edge = smoothstep(hole_radius, hole_radius + max(hole_softness, 0.001), distance);
+        v_Alpha = mix(hole_opacity, 1.0, edge);
+    }
+    vec3 position = vec3(local * scale, layer_id + I_Vertex_Depth);
+    gl_Position = ViewProj * Model * vec4(position, 1.0);
+}
\ No newline at end of file
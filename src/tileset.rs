@@ -0,0 +1,367 @@
+use crate::{tmx::read_tsx_extras, Map, MapError, PropertiesExt, TilesetReader, WangSet};
+use bevy::{asset::LoadState, prelude::*, render::texture::TextureFormat};
+use glam::Vec2;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Tileset textures waiting to load so their `trans` color can be keyed out.
+#[derive(Debug, Default)]
+pub struct TransparentColorKeys {
+    pending: HashMap<Handle<Texture>, [u8; 3]>,
+}
+
+impl TransparentColorKeys {
+    /// Makes pixels of `color` in `texture` transparent once it's loaded.
+    pub fn insert(&mut self, texture: Handle<Texture>, color: &tiled::Colour) {
+        self.pending
+            .insert(texture, [color.red, color.green, color.blue]);
+    }
+
+    /// Whether `texture` is still waiting to be keyed.
+    pub(crate) fn is_pending(&self, texture: Handle<Texture>) -> bool {
+        self.pending.contains_key(&texture)
+    }
+}
+
+/// Sets the alpha of every pixel matching `rgb` to zero. Only 8 bit RGBA textures are keyed.
+pub fn key_transparent_color(texture: &mut Texture, rgb: [u8; 3]) {
+    match texture.format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+        _ => return,
+    }
+    for pixel in texture.data.chunks_exact_mut(4) {
+        if pixel[..3] == rgb {
+            pixel[3] = 0;
+        }
+    }
+}
+
+/// Keys out the transparent colors of tileset textures as they finish loading.
+pub fn apply_transparent_color_keys(
+    mut keys: ResMut<TransparentColorKeys>,
+    mut textures: ResMut<Assets<Texture>>,
+) {
+    let mut keyed = Vec::new();
+    for (handle, rgb) in keys.pending.iter() {
+        if let Some(texture) = textures.get_mut(handle) {
+            key_transparent_color(texture, *rgb);
+            keyed.push(*handle);
+        }
+    }
+    for handle in keyed {
+        keys.pending.remove(&handle);
+    }
+}
+
+/// Sent when a tileset image can't be loaded. Its chunks are drawn with a magenta checker
+/// until the image loads.
+#[derive(Debug, Clone)]
+pub struct MissingTexture {
+    pub map: Handle<Map>,
+    /// Path of the image, relative to the asset folder.
+    pub path: String,
+}
+
+#[derive(Debug)]
+struct WatchedTexture {
+    map: Handle<Map>,
+    path: String,
+    texture: Handle<Texture>,
+    /// Size of the placeholder and of its checker squares, in pixels.
+    size: (u32, u32, u32),
+    placeholder: Option<Handle<Texture>>,
+}
+
+/// Tileset materials whose textures are still loading, so ones that fail can show a
+/// placeholder until the image appears.
+#[derive(Debug, Default)]
+pub struct MissingTextures {
+    watched: HashMap<Handle<ColorMaterial>, WatchedTexture>,
+}
+
+impl MissingTextures {
+    /// Watches `material` until `texture`, loaded from `path`, finishes loading. `size` is the
+    /// image's width and height and `tile_size` that of the tileset's tiles, in pixels.
+    pub fn watch(
+        &mut self,
+        material: Handle<ColorMaterial>,
+        map: Handle<Map>,
+        path: String,
+        texture: Handle<Texture>,
+        size: (u32, u32),
+        tile_size: u32,
+    ) {
+        self.watched.insert(
+            material,
+            WatchedTexture {
+                map,
+                path,
+                texture,
+                size: (size.0, size.1, (tile_size / 2).max(1)),
+                placeholder: None,
+            },
+        );
+    }
+}
+
+/// A magenta and black checker of `cell` pixel squares.
+pub fn placeholder_texture(width: u32, height: u32, cell: u32) -> Texture {
+    let (width, height, cell) = (width.max(1), height.max(1), cell.max(1));
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            if (x / cell + y / cell) % 2 == 0 {
+                data.extend_from_slice(&[255, 0, 255, 255]);
+            } else {
+                data.extend_from_slice(&[0, 0, 0, 255]);
+            }
+        }
+    }
+    Texture::new(
+        Vec2::new(width as f32, height as f32),
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Draws a placeholder in place of tileset textures that failed to load, sending a
+/// `MissingTexture` for each, and swaps the real texture back in if it loads later, such as
+/// through asset hot reloading.
+pub fn apply_missing_textures(
+    asset_server: Res<AssetServer>,
+    mut missing: ResMut<MissingTextures>,
+    mut events: ResMut<Events<MissingTexture>>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut loaded = Vec::new();
+    for (material_handle, watched) in missing.watched.iter_mut() {
+        if textures.get(&watched.texture).is_some() {
+            if let Some(material) = materials.get_mut(material_handle) {
+                // Leave materials alone that something else, like a substitution, has retextured.
+                if material.texture == watched.placeholder {
+                    material.texture = Some(watched.texture);
+                }
+            }
+            if let Some(placeholder) = watched.placeholder {
+                textures.remove(&placeholder);
+            }
+            loaded.push(*material_handle);
+            continue;
+        }
+        if watched.placeholder.is_some() {
+            continue;
+        }
+        if let Some(LoadState::Failed(_)) = asset_server.get_load_state(watched.texture) {
+            let (width, height, cell) = watched.size;
+            let placeholder = textures.add(placeholder_texture(width, height, cell));
+            if let Some(material) = materials.get_mut(material_handle) {
+                if material.texture == Some(watched.texture) {
+                    material.texture = Some(placeholder);
+                }
+            }
+            watched.placeholder = Some(placeholder);
+            events.send(MissingTexture {
+                map: watched.map,
+                path: watched.path.clone(),
+            });
+        }
+    }
+    for material_handle in loaded {
+        missing.watched.remove(&material_handle);
+    }
+}
+
+/// A tileset image as an asset shared by every map that draws it, so they use one texture,
+/// material and UV table rather than one each.
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub name: String,
+    /// Path of the image, relative to the asset folder.
+    pub image_path: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// The image, or `None` if it couldn't be requested and a placeholder is drawn instead.
+    pub texture: Option<Handle<Texture>>,
+    pub material: Handle<ColorMaterial>,
+    /// UVs of each tile, as (start u, start v, end u, end v).
+    pub uvs: Arc<Vec<Vec4>>,
+    /// The normal map drawn with the image, see `Map::tileset_normal_map_path`.
+    pub normal_map: Option<Handle<Texture>>,
+    /// The emissive map drawn with the image, see `Map::tileset_emissive_map_path`, or else one
+    /// made from the `emissive` properties of its tiles.
+    pub emissive_map: Option<Handle<Texture>>,
+}
+
+impl Tileset {
+    /// UVs of the `tile`th tile, as (start u, start v, end u, end v).
+    pub fn tile_uv(&self, tile: u32) -> Option<Vec4> {
+        self.uvs.get(tile as usize).copied()
+    }
+}
+
+/// How much a tileset's UVs are scaled by, from its `uv_scale_x` and `uv_scale_y` float
+/// properties.
+///
+/// Tiled measures tiles against the image it was given, so a tileset drawn from a texture
+/// padded out to other dimensions, such as a block compressed or power of two copy of the
+/// image, scales its UVs by `image size / texture size` to match. Such textures are handed to
+/// a map with `Map::set_tileset_texture` or `MapSettings::tileset_textures`.
+pub fn tileset_uv_scale(tileset: &tiled::Tileset) -> Vec2 {
+    Vec2::new(
+        tileset
+            .properties
+            .float_property("uv_scale_x")
+            .unwrap_or(1.0),
+        tileset
+            .properties
+            .float_property("uv_scale_y")
+            .unwrap_or(1.0),
+    )
+}
+
+/// UVs of every tile of a tileset, as (start u, start v, end u, end v).
+pub(crate) fn tile_uv_table(tileset: &tiled::Tileset) -> Vec<Vec4> {
+    // Tiles of image collections can have ids past the tile count.
+    let ids = tileset.tiles.iter().map(|tile| tile.id + 1).max();
+    let count = tileset.tilecount.unwrap_or(0).max(ids.unwrap_or(0));
+    (0..count).map(|tile| Map::tile_uv(tileset, tile)).collect()
+}
+
+/// Tilesets that draw the same image the same way share a `Tileset`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SharedTilesetKey {
+    image_path: String,
+    tile_size: (u32, u32),
+    margin: u32,
+    spacing: u32,
+    /// Bits of `tileset_uv_scale`.
+    uv_scale: (u32, u32),
+}
+
+/// The `Tileset` assets shared between maps, created as maps using them are processed.
+#[derive(Debug, Default)]
+pub struct SharedTilesets {
+    tilesets: HashMap<SharedTilesetKey, Handle<Tileset>>,
+}
+
+impl SharedTilesets {
+    /// The shared tileset drawing `tileset` from the image at `image_path`, if any map has
+    /// created it.
+    pub fn get(&self, tileset: &tiled::Tileset, image_path: &str) -> Option<Handle<Tileset>> {
+        self.tilesets
+            .get(&SharedTilesetKey::new(tileset, image_path))
+            .copied()
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        tileset: &tiled::Tileset,
+        image_path: &str,
+        handle: Handle<Tileset>,
+    ) {
+        self.tilesets
+            .insert(SharedTilesetKey::new(tileset, image_path), handle);
+    }
+}
+
+impl SharedTilesetKey {
+    fn new(tileset: &tiled::Tileset, image_path: &str) -> Self {
+        Self {
+            image_path: image_path.to_string(),
+            tile_size: (tileset.tile_width, tileset.tile_height),
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            uv_scale: {
+                let scale = tileset_uv_scale(tileset);
+                (scale.x().to_bits(), scale.y().to_bits())
+            },
+        }
+    }
+}
+
+/// Where a tileset was declared, which decides what its image sources are relative to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TilesetSource {
+    /// Declared inline in the TMX; image sources are relative to the map file.
+    Embedded,
+    /// Loaded from a `.tsx` file (path relative to the map); image sources are relative to it.
+    External(PathBuf),
+}
+
+impl TilesetSource {
+    /// Resolves an image `source` attribute against the folder holding the map file.
+    pub fn resolve_image(&self, map_folder: &Path, image_source: &str) -> PathBuf {
+        match self {
+            TilesetSource::Embedded => map_folder.join(image_source),
+            TilesetSource::External(tsx_path) => match tsx_path.parent() {
+                Some(tsx_folder) => map_folder.join(tsx_folder).join(image_source),
+                None => map_folder.join(image_source),
+            },
+        }
+    }
+}
+
+/// Per tileset data the tiled crate doesn't parse.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct TilesetExtras {
+    /// `<tileoffset>` in pixels, y down.
+    pub tile_offset: Vec2,
+    pub wang_sets: Vec<WangSet>,
+    /// Names of the color properties written as `#RRGGBB`, with the id of the tile they
+    /// belong to or `None` for the tileset's own.
+    pub rgb_colors: Vec<(Option<u32>, String)>,
+}
+
+/// Reads the `.tsx` files of the map's external tilesets, keyed by first gid, with `reader`
+/// or else from the filesystem next to the map at `path`. There's no synchronous file access
+/// on the web, so there they're only read by a `reader`.
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+pub(crate) fn read_external_tilesets(
+    sources: &HashMap<u32, TilesetSource>,
+    map_folder: &Path,
+    path: Option<&Path>,
+    reader: Option<&TilesetReader>,
+) -> Result<HashMap<u32, Vec<u8>>, MapError> {
+    let mut tilesets = HashMap::new();
+    for (first_gid, source) in sources.iter() {
+        let tsx_path = match source {
+            TilesetSource::External(tsx_path) => map_folder.join(tsx_path),
+            TilesetSource::Embedded => continue,
+        };
+        let tsx = match reader {
+            Some(reader) => reader.read(&tsx_path),
+            #[cfg(not(target_arch = "wasm32"))]
+            None if path.is_some() => std::fs::read(&tsx_path),
+            // Left for the tiled crate to reject.
+            None => continue,
+        };
+        let tsx = tsx.map_err(|err| {
+            MapError::Parse(format!("failed to read tileset {:?}: {}", tsx_path, err))
+        })?;
+        tilesets.insert(*first_gid, tsx);
+    }
+    Ok(tilesets)
+}
+
+/// Adds the extras of external tilesets, read from their `.tsx` files by
+/// `read_external_tilesets`, to those of the embedded ones, keyed by first gid.
+pub(crate) fn read_tileset_extras(
+    mut extras: HashMap<u32, TilesetExtras>,
+    external: &HashMap<u32, Vec<u8>>,
+) -> HashMap<u32, TilesetExtras> {
+    for (first_gid, tsx) in external.iter() {
+        if let Some(tileset_extras) = read_tsx_extras(tsx) {
+            extras.insert(*first_gid, tileset_extras);
+        }
+    }
+    for (first_gid, tileset_extras) in extras.iter_mut() {
+        for wang_set in tileset_extras.wang_sets.iter_mut() {
+            wang_set.first_gid = *first_gid;
+        }
+    }
+    extras
+}
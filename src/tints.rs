@@ -0,0 +1,63 @@
+use crate::Map;
+use bevy::{
+    prelude::*,
+    render::mesh::{VertexAttribute, VertexAttributeValues},
+};
+
+/// The `Vertex_Color` attribute of the tile pipeline, which the tile's texture is multiplied by.
+pub(crate) fn tint_attribute(colors: Vec<[f32; 4]>) -> VertexAttribute {
+    VertexAttribute {
+        name: "Vertex_Color".into(),
+        values: VertexAttributeValues::Float4(colors),
+    }
+}
+
+impl Map {
+    /// The tint of the tile at `x`, `y` of `layer` (an index into `map.layers`), white unless
+    /// set with `set_tile_tint`.
+    pub fn tile_tint(&self, layer: usize, x: u32, y: u32) -> Color {
+        self.tile_tints
+            .get(&(layer, x, y))
+            .copied()
+            .unwrap_or(Color::WHITE)
+    }
+
+    /// Multiplies the color of the tile at `x`, `y` of `layer` (an index into `map.layers`) by
+    /// `tint`, such as to highlight a selected tile, flash a damaged one or color territory,
+    /// and marks its chunk dirty. White clears the tint. Returns `false` for cells outside the
+    /// tile layers.
+    pub fn set_tile_tint(&mut self, layer: usize, x: u32, y: u32, tint: Color) -> bool {
+        if self.tile(layer, x, y).is_none() {
+            return false;
+        }
+        let previous = if tint == Color::WHITE {
+            self.tile_tints.remove(&(layer, x, y))
+        } else {
+            self.tile_tints.insert((layer, x, y), tint)
+        };
+        if previous.unwrap_or(Color::WHITE) != tint {
+            let gid = self.tile(layer, x, y).map_or(0, |tile| tile.gid);
+            self.invalidate_tile(layer, x, y, &[gid]);
+            self.invalidate_covered(layer, x, y);
+        }
+        true
+    }
+
+    /// Clears the tints of every tile of `layer`.
+    pub fn clear_tile_tints(&mut self, layer: usize) {
+        let tinted = self
+            .tile_tints
+            .keys()
+            .filter(|(tinted_layer, _, _)| *tinted_layer == layer)
+            .copied()
+            .collect::<Vec<_>>();
+        for (layer, x, y) in tinted {
+            self.set_tile_tint(layer, x, y, Color::WHITE);
+        }
+    }
+
+    /// The `Vertex_Color` of the tile at `x`, `y` of `layer`.
+    pub(crate) fn tint_vertex(&self, layer: usize, x: u32, y: u32) -> [f32; 4] {
+        self.tile_tint(layer, x, y).into()
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{
+    parse_wang_id, tileset::TilesetExtras, ImageLayerRepeat, ObjectDrawOrder, PropertyOwner,
+    RenderOrder, TilesetSource, WangSet, WangTile,
+};
+use glam::Vec2;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::PathBuf,
+};
+use xml::{
+    attribute::{Attribute, OwnedAttribute},
+    name::Name,
+    reader::{EventReader, XmlEvent},
+    writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent},
+};
+
+/// What the tiled crate drops from a `.tmx` file, read in a single pass over its XML. Anything
+/// else tiled 0.9 doesn't parse belongs here rather than in a pass of its own.
+#[derive(Debug, Default)]
+pub(crate) struct TmxExtras {
+    /// The `renderorder` of `<map>`.
+    pub render_order: RenderOrder,
+    /// The `offsetx`/`offsety` of each tile layer, in the same order as `tiled::Map::layers`.
+    pub layer_offsets: Vec<Vec2>,
+    /// The `repeatx`/`repeaty` of each image layer, in the same order as
+    /// `tiled::Map::image_layers`.
+    pub image_layer_repeats: Vec<ImageLayerRepeat>,
+    /// The `draworder` of each object group, in the same order as `tiled::Map::object_groups`.
+    pub object_draw_orders: Vec<ObjectDrawOrder>,
+    /// Where each top level tileset was declared, keyed by first gid. The tiled crate merges
+    /// external tilesets into the map without remembering where they came from.
+    pub tileset_sources: HashMap<u32, TilesetSource>,
+    /// Extras of the tilesets embedded in the map, keyed by first gid.
+    pub tileset_extras: HashMap<u32, TilesetExtras>,
+    /// The `file` properties of every object outside tilesets, keyed by object id then
+    /// property name.
+    pub object_files: HashMap<u32, HashMap<String, String>>,
+    /// Names of the `file` properties of everything else that has properties.
+    pub file_properties: HashMap<PropertyOwner, HashSet<String>>,
+    /// Whether any property is typed `file`, including ones without an owner above, such as
+    /// those of collision shapes, so the tiled crate has to be handed the file rewritten by
+    /// `file_properties_as_strings`.
+    pub file_typed: bool,
+    /// Names of the color properties written as `#RRGGBB` rather than `#AARRGGBB`, by owner.
+    /// Those of tilesets and tiles are in `tileset_extras`.
+    pub rgb_colors: HashMap<PropertyOwner, HashSet<String>>,
+}
+
+fn attribute<'a>(attributes: &'a [OwnedAttribute], key: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|attribute| attribute.name.local_name == key)
+        .map(|attribute| attribute.value.as_str())
+}
+
+/// Whether `<property>` attributes are a color written as `#RRGGBB`, which the tiled crate
+/// reads with a zero alpha byte just like a fully transparent `#00RRGGBB`.
+fn is_rgb_color(attributes: &[OwnedAttribute]) -> bool {
+    attribute(attributes, "type") == Some("color")
+        && attribute(attributes, "value")
+            .map_or(false, |value| value.trim_start_matches('#').len() == 6)
+}
+
+/// Collects the extras of `<tileset>` elements declared at `tileset_depth`: depth 2 in a map,
+/// or 1 for the root of a `.tsx` file.
+struct TilesetScanner {
+    tileset_depth: usize,
+    tilesets: Vec<(Option<u32>, TilesetExtras)>,
+    /// Whether the last tileset is still open.
+    open: bool,
+    /// The id of the open `<tile>` of the last tileset.
+    tile: Option<u32>,
+}
+
+impl TilesetScanner {
+    fn new(tileset_depth: usize) -> Self {
+        Self {
+            tileset_depth,
+            tilesets: Vec::new(),
+            open: false,
+            tile: None,
+        }
+    }
+
+    fn start(&mut self, element: &str, attributes: &[OwnedAttribute], depth: usize) {
+        let tileset_depth = self.tileset_depth;
+        if depth == tileset_depth && element == "tileset" {
+            let first_gid = attribute(attributes, "firstgid").and_then(|value| value.parse().ok());
+            self.tilesets.push((first_gid, TilesetExtras::default()));
+            self.open = true;
+            return;
+        }
+        let extras = match self.tilesets.last_mut() {
+            Some((_, extras)) if self.open && depth > tileset_depth => extras,
+            _ => return,
+        };
+        let parsed = |key: &str| attribute(attributes, key).and_then(|value| value.parse().ok());
+        if element == "property" && is_rgb_color(attributes) {
+            // <tileset><properties><property>, or <tileset><tile><properties><property>.
+            let tile = match (depth - tileset_depth, self.tile) {
+                (2, _) => None,
+                (3, Some(tile)) => Some(tile),
+                _ => return,
+            };
+            if let Some(name) = attribute(attributes, "name") {
+                extras.rgb_colors.push((tile, name.to_string()));
+            }
+        } else if depth == tileset_depth + 1 && element == "tile" {
+            self.tile = parsed("id");
+        } else if depth == tileset_depth + 1 && element == "tileoffset" {
+            let x: Option<f32> = parsed("x");
+            let y: Option<f32> = parsed("y");
+            extras.tile_offset = Vec2::new(x.unwrap_or(0.0), y.unwrap_or(0.0));
+        } else if depth == tileset_depth + 2 && element == "wangset" {
+            extras.wang_sets.push(WangSet {
+                name: attribute(attributes, "name")
+                    .unwrap_or_default()
+                    .to_string(),
+                ..Default::default()
+            });
+        } else if depth == tileset_depth + 3 {
+            let wang_set = match extras.wang_sets.last_mut() {
+                Some(wang_set) => wang_set,
+                None => return,
+            };
+            match element {
+                // Tiled 1.5+ and the corner colors of older versions.
+                "wangcolor" | "wangcornercolor" => {
+                    let name = attribute(attributes, "name").unwrap_or_default();
+                    wang_set.colors.push(name.to_string());
+                }
+                "wangtile" => {
+                    let tile_id = parsed("tileid");
+                    let wang_id = attribute(attributes, "wangid").and_then(parse_wang_id);
+                    if let (Some(tile_id), Some(wang_id)) = (tile_id, wang_id) {
+                        wang_set.tiles.push(WangTile { tile_id, wang_id });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn end(&mut self, element: &str, depth: usize) {
+        if depth == self.tileset_depth && element == "tileset" {
+            self.open = false;
+        } else if depth == self.tileset_depth + 1 && element == "tile" {
+            self.tile = None;
+        }
+    }
+}
+
+/// What an element at `depth` owns properties as, given the owner of its parent.
+fn property_owner(
+    element: &str,
+    attributes: &[OwnedAttribute],
+    depth: usize,
+    parent: Option<PropertyOwner>,
+    extras: &TmxExtras,
+) -> Option<PropertyOwner> {
+    let parsed = |key: &str| attribute(attributes, key).and_then(|value| value.parse().ok());
+    match (depth, element, parent) {
+        (1, "map", _) => Some(PropertyOwner::Map),
+        // Owners are found before the element itself is read, so these counts are its index.
+        (2, "layer", _) => Some(PropertyOwner::Layer(extras.layer_offsets.len())),
+        (2, "imagelayer", _) => Some(PropertyOwner::ImageLayer(extras.image_layer_repeats.len())),
+        (2, "objectgroup", _) => Some(PropertyOwner::ObjectGroup(extras.object_draw_orders.len())),
+        (2, "tileset", _) => parsed("firstgid").map(PropertyOwner::Tileset),
+        (3, "object", Some(PropertyOwner::ObjectGroup(_))) => {
+            parsed("id").map(PropertyOwner::Object)
+        }
+        (3, "tile", Some(PropertyOwner::Tileset(first_gid))) => {
+            parsed("id").map(|id| PropertyOwner::Tile(first_gid, id))
+        }
+        _ => None,
+    }
+}
+
+/// Reads what the tiled crate drops from the `.tmx` file in `bytes`.
+pub(crate) fn read_tmx_extras(bytes: &[u8]) -> TmxExtras {
+    let mut extras = TmxExtras::default();
+    // <map> is depth 1, so its layers and tilesets are depth 2.
+    let mut tilesets = TilesetScanner::new(2);
+    // The property owner of each open element.
+    let mut owners: Vec<Option<PropertyOwner>> = Vec::new();
+    for event in EventReader::new(bytes) {
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                let depth = owners.len() + 1;
+                let element = name.local_name.as_str();
+                tilesets.start(element, &attributes, depth);
+                let parent = owners.last().copied().flatten();
+                owners.push(property_owner(element, &attributes, depth, parent, &extras));
+                let value = |key: &str| attribute(&attributes, key);
+                let float = |key: &str| {
+                    value(key)
+                        .and_then(|value| value.parse::<f32>().ok())
+                        .unwrap_or(0.0)
+                };
+                let flag =
+                    |key: &str| value(key).map_or(false, |value| value == "1" || value == "true");
+                match (depth, element) {
+                    (1, "map") => {
+                        extras.render_order = value("renderorder")
+                            .and_then(RenderOrder::from_attribute)
+                            .unwrap_or_default();
+                    }
+                    (2, "layer") => {
+                        extras
+                            .layer_offsets
+                            .push(Vec2::new(float("offsetx"), float("offsety")));
+                    }
+                    (2, "imagelayer") => {
+                        extras.image_layer_repeats.push(ImageLayerRepeat {
+                            x: flag("repeatx"),
+                            y: flag("repeaty"),
+                        });
+                    }
+                    (2, "objectgroup") => {
+                        extras.object_draw_orders.push(
+                            value("draworder")
+                                .and_then(ObjectDrawOrder::from_attribute)
+                                .unwrap_or_default(),
+                        );
+                    }
+                    (2, "tileset") => {
+                        if let Some(first_gid) = value("firstgid").and_then(|gid| gid.parse().ok())
+                        {
+                            let source = match value("source") {
+                                Some(path) => TilesetSource::External(PathBuf::from(path)),
+                                None => TilesetSource::Embedded,
+                            };
+                            extras.tileset_sources.insert(first_gid, source);
+                        }
+                    }
+                    _ => {}
+                }
+                if element != "property" {
+                    continue;
+                }
+                let file = value("type") == Some("file");
+                extras.file_typed |= file;
+                // <property> sits in <properties>, which sits in its owner.
+                let owner = match owners.len().checked_sub(3).map(|owner| owners[owner]) {
+                    Some(Some(owner)) => owner,
+                    _ => continue,
+                };
+                let name = match value("name") {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                if is_rgb_color(&attributes) {
+                    // The tileset scanner has those of tilesets and tiles, external ones too.
+                    if let PropertyOwner::Tileset(_) | PropertyOwner::Tile(..) = owner {
+                        continue;
+                    }
+                    extras
+                        .rgb_colors
+                        .entry(owner)
+                        .or_insert_with(HashSet::new)
+                        .insert(name);
+                    continue;
+                }
+                if !file {
+                    continue;
+                }
+                if let PropertyOwner::Object(id) = owner {
+                    extras
+                        .object_files
+                        .entry(id)
+                        .or_insert_with(HashMap::new)
+                        .insert(name.clone(), value("value").unwrap_or("").into());
+                }
+                extras
+                    .file_properties
+                    .entry(owner)
+                    .or_insert_with(HashSet::new)
+                    .insert(name);
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                tilesets.end(&name.local_name, owners.len());
+                owners.pop();
+            }
+            Ok(_) => {}
+            // The tiled parser already reported malformed XML.
+            Err(_) => break,
+        }
+    }
+    for (first_gid, tileset_extras) in tilesets.tilesets {
+        if let Some(first_gid) = first_gid {
+            extras.tileset_extras.insert(first_gid, tileset_extras);
+        }
+    }
+    extras
+}
+
+/// Writes `event` as it was read, with the `file` type of `<property>` elements rewritten to
+/// `string`, and `first_gid` added to the element it starts.
+fn write_event<W: Write>(
+    writer: &mut EventWriter<W>,
+    event: &XmlEvent,
+    first_gid: Option<&str>,
+) -> xml::writer::Result<()> {
+    let (name, attributes, namespace) = match event {
+        XmlEvent::StartElement {
+            name,
+            attributes,
+            namespace,
+        } if first_gid.is_some() || name.local_name == "property" => (name, attributes, namespace),
+        event => {
+            return match event.as_writer_event() {
+                Some(event) => writer.write(event),
+                None => Ok(()),
+            }
+        }
+    };
+    let mut attributes = attributes
+        .iter()
+        .map(|attribute| {
+            let value = match attribute.name.local_name.as_str() {
+                "type" if name.local_name == "property" && attribute.value == "file" => "string",
+                _ => attribute.value.as_str(),
+            };
+            Attribute::new(attribute.name.borrow(), value)
+        })
+        .collect::<Vec<_>>();
+    if let Some(first_gid) = first_gid {
+        attributes.push(Attribute::new(Name::local("firstgid"), first_gid));
+    }
+    writer.write(WriterEvent::StartElement {
+        name: name.borrow(),
+        attributes: Cow::Owned(attributes),
+        namespace: Cow::Borrowed(namespace),
+    })
+}
+
+/// Writes the root `<tileset>` of the `.tsx` file in `tsx`, as a tileset embedded at
+/// `first_gid`.
+fn write_tsx<W: Write>(writer: &mut EventWriter<W>, tsx: &[u8], first_gid: &str) -> Option<()> {
+    let mut depth = 0;
+    for event in EventReader::new(tsx) {
+        let event = event.ok()?;
+        let root = match &event {
+            XmlEvent::StartElement { .. } => {
+                depth += 1;
+                depth == 1
+            }
+            XmlEvent::EndElement { .. } => {
+                depth -= 1;
+                false
+            }
+            // The XML declaration, and anything else outside the tileset.
+            _ if depth == 0 => continue,
+            _ => false,
+        };
+        let first_gid = if root { Some(first_gid) } else { None };
+        write_event(writer, &event, first_gid).ok()?;
+    }
+    Some(())
+}
+
+/// Rewrites a `.tmx` file into one the tiled crate reads without touching the filesystem:
+/// the external tilesets in `tilesets`, keyed by first gid, are embedded, and since the tiled
+/// crate rejects properties of type `file`, they're handed to it as strings. Everything else
+/// is copied as it was read. `None` for XML the tiled crate would reject anyway.
+pub(crate) fn prepare_for_tiled(bytes: &[u8], tilesets: &HashMap<u32, Vec<u8>>) -> Option<Vec<u8>> {
+    let mut rewritten = Vec::with_capacity(bytes.len());
+    {
+        let mut writer = EmitterConfig::new().create_writer(&mut rewritten);
+        let mut depth = 0;
+        // Whether the reference to an external tileset that was embedded is being skipped.
+        let mut skipping = false;
+        for event in EventReader::new(bytes) {
+            let event = event.ok()?;
+            match &event {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    depth += 1;
+                    if skipping {
+                        continue;
+                    }
+                    if depth == 2 && name.local_name == "tileset" {
+                        let first_gid = attribute(attributes, "firstgid");
+                        let tsx = first_gid
+                            .and_then(|first_gid| first_gid.parse().ok())
+                            .and_then(|first_gid| tilesets.get(&first_gid));
+                        if let (Some(first_gid), Some(tsx)) = (first_gid, tsx) {
+                            write_tsx(&mut writer, tsx, first_gid)?;
+                            skipping = true;
+                            continue;
+                        }
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    depth -= 1;
+                    if skipping {
+                        skipping = depth > 1;
+                        continue;
+                    }
+                }
+                _ if skipping => continue,
+                _ => {}
+            }
+            write_event(&mut writer, &event, None).ok()?;
+        }
+    }
+    Some(rewritten)
+}
+
+/// The `width` and `height` of the `map` element, read before the tiled crate decodes any
+/// layer data.
+pub(crate) fn read_map_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    for event in EventReader::new(bytes) {
+        if let Ok(XmlEvent::StartElement {
+            name, attributes, ..
+        }) = event
+        {
+            if name.local_name != "map" {
+                break;
+            }
+            let parsed =
+                |key: &str| attribute(&attributes, key).and_then(|value| value.parse().ok());
+            return Some((parsed("width")?, parsed("height")?));
+        }
+    }
+    None
+}
+
+/// Reads the extras of the tileset of a `.tsx` file.
+pub(crate) fn read_tsx_extras(bytes: &[u8]) -> Option<TilesetExtras> {
+    let mut tilesets = TilesetScanner::new(1);
+    let mut depth = 0;
+    for event in EventReader::new(bytes) {
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                depth += 1;
+                tilesets.start(&name.local_name, &attributes, depth);
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                tilesets.end(&name.local_name, depth);
+                depth -= 1;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    tilesets
+        .tilesets
+        .into_iter()
+        .next()
+        .map(|(_, extras)| extras)
+}
@@ -0,0 +1,525 @@
+//! The stable API, version 1.
+//!
+//! Everything here keeps its shape across Bevy upgrades and renderer rewrites, unlike the
+//! component bundles and render pipeline setup the rest of the crate exposes. Breaking changes
+//! go into a new version module, with this one kept alongside it for a while. Custom render
+//! pipelines are tied to Bevy's renderer, so `LayerPipelines` is only on the bundles outside
+//! this module.
+//!
+//! ```ignore
+//! use bevy_tiled::v1::*;
+//!
+//! App::build()
+//!     .add_plugin(TiledPlugin::new(TiledSettings::default().retain_source(false)))
+//!     .add_startup_system(setup.system());
+//!
+//! fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+//!     let map: TiledMapHandle = asset_server.load("ortho-map.tmx").unwrap();
+//!     commands.spawn_tiled_map(TiledMapBundle::new(map).anchor(MapAnchor::Center));
+//! }
+//! ```
+
+use crate::{
+    ChunkCulling, ChunkSplitting, DespawnTiledMap, Map, MapSettings, PreloadObjectFiles,
+    TiledMapComponents, TiledMapPlugin, CHUNK_SIZE, MAP_SCALE,
+};
+use bevy::prelude::*;
+use glam::Vec2;
+use std::collections::HashMap;
+
+pub use crate::{
+    LayerDepths, LayerMask, LayerPalettes, MapAnchor, MapBackground, MapError, MapLoadFailed,
+    MapReadyEvent, ObjectSpawnedEvent, Property, TileHit, TiledObject,
+};
+
+/// Version of the API in this module.
+pub const API_VERSION: u32 = 1;
+
+/// A map asset, as loaded from a `.tmx` file by the `AssetServer` and held by map entities.
+/// The map type behind it isn't part of this API; maps are read through `TiledMapView`.
+pub type TiledMapHandle = Handle<Map>;
+
+/// The loaded maps, for `TiledMapView::new`.
+pub type TiledMaps = Assets<Map>;
+
+/// Adds the map plugin with `settings`.
+#[derive(Debug, Clone, Default)]
+pub struct TiledPlugin {
+    settings: TiledSettings,
+}
+
+impl TiledPlugin {
+    pub fn new(settings: TiledSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl Plugin for TiledPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let settings = &self.settings;
+        app.add_plugin(TiledMapPlugin)
+            .add_resource(MapSettings {
+                retain_source: settings.retain_source,
+                chunk_size: settings.chunk_size,
+                atlas_tilesets: settings.atlas_tilesets,
+                atlas_extrusion: settings.atlas_extrusion,
+                y_sort: settings.y_sort,
+                layer_depths: settings.layer_depths.clone(),
+                nearest_filtering: settings.nearest_filtering,
+                merge_layers: settings.merge_layers,
+                data_texture: settings.data_texture,
+                instanced: settings.instanced,
+                skip_covered_tiles: settings.skip_covered_tiles,
+                scale: settings.scale,
+                background_meshing: settings.background_meshing,
+            })
+            .add_resource(ChunkSplitting {
+                enabled: settings.split_edited_chunks,
+                ..Default::default()
+            })
+            .add_resource(ChunkCulling {
+                enabled: settings.cull_chunks,
+                ..Default::default()
+            });
+    }
+}
+
+/// How maps are loaded and drawn, set through its builder methods.
+#[derive(Debug, Clone)]
+pub struct TiledSettings {
+    /// Whether maps keep the tile data their meshes were built from once drawn. Tiles can be
+    /// read and edited either way.
+    retain_source: bool,
+    /// Width and height of the chunks maps are drawn in, in tiles. Bigger chunks draw with
+    /// fewer calls, smaller ones cull and stream more finely.
+    chunk_size: (usize, usize),
+    /// Whether maps with several tilesets draw them from one packed texture.
+    atlas_tilesets: bool,
+    /// Pixels tiles are extruded by in the atlas, hiding seams between tiles at fractional
+    /// zoom.
+    atlas_extrusion: u32,
+    /// Whether chunks edited on many frames in a row are split up, so edits rebuild less. Off
+    /// by default.
+    split_edited_chunks: bool,
+    /// Whether chunks outside the camera view are hidden.
+    cull_chunks: bool,
+    /// Whether tiles further down a map are drawn in front of those above them, so sprites
+    /// placed with `TiledMapView::y_sorted_z` can walk behind tall tiles.
+    y_sort: bool,
+    /// Where layers are drawn along z.
+    layer_depths: LayerDepths,
+    /// Whether textures are drawn with nearest filtering, for pixel art.
+    nearest_filtering: bool,
+    /// Whether maps are drawn with one mesh per tileset across all of their layers, for maps
+    /// that are never edited.
+    merge_layers: bool,
+    /// Whether chunks are drawn from a texture of their tiles rather than a quad per tile, for
+    /// huge or often edited orthogonal maps.
+    data_texture: bool,
+    /// Whether chunks are drawn as a GPU instance per tile rather than a quad per tile, for
+    /// huge or often edited maps.
+    instanced: bool,
+    /// Whether tiles hidden behind tiles with the `occludes` property are left out of the
+    /// chunk meshes.
+    skip_covered_tiles: bool,
+    /// World units each map pixel is drawn across.
+    scale: f32,
+    /// Whether chunks are meshed over the next frames rather than all at once.
+    background_meshing: bool,
+}
+
+impl Default for TiledSettings {
+    fn default() -> Self {
+        Self {
+            retain_source: true,
+            chunk_size: (CHUNK_SIZE, CHUNK_SIZE),
+            atlas_tilesets: false,
+            atlas_extrusion: 0,
+            split_edited_chunks: false,
+            cull_chunks: true,
+            y_sort: false,
+            layer_depths: LayerDepths::default(),
+            nearest_filtering: false,
+            merge_layers: false,
+            data_texture: false,
+            instanced: false,
+            skip_covered_tiles: false,
+            scale: MAP_SCALE,
+            background_meshing: false,
+        }
+    }
+}
+
+impl TiledSettings {
+    pub fn retain_source(mut self, retain_source: bool) -> Self {
+        self.retain_source = retain_source;
+        self
+    }
+
+    pub fn chunk_size(mut self, width: usize, height: usize) -> Self {
+        self.chunk_size = (width.max(1), height.max(1));
+        self
+    }
+
+    pub fn atlas_tilesets(mut self, atlas_tilesets: bool) -> Self {
+        self.atlas_tilesets = atlas_tilesets;
+        self
+    }
+
+    pub fn atlas_extrusion(mut self, atlas_extrusion: u32) -> Self {
+        self.atlas_extrusion = atlas_extrusion;
+        self
+    }
+
+    pub fn split_edited_chunks(mut self, split_edited_chunks: bool) -> Self {
+        self.split_edited_chunks = split_edited_chunks;
+        self
+    }
+
+    pub fn cull_chunks(mut self, cull_chunks: bool) -> Self {
+        self.cull_chunks = cull_chunks;
+        self
+    }
+
+    pub fn y_sort(mut self, y_sort: bool) -> Self {
+        self.y_sort = y_sort;
+        self
+    }
+
+    pub fn layer_depths(mut self, layer_depths: LayerDepths) -> Self {
+        self.layer_depths = layer_depths;
+        self
+    }
+
+    pub fn nearest_filtering(mut self, nearest_filtering: bool) -> Self {
+        self.nearest_filtering = nearest_filtering;
+        self
+    }
+
+    pub fn merge_layers(mut self, merge_layers: bool) -> Self {
+        self.merge_layers = merge_layers;
+        self
+    }
+
+    pub fn data_texture(mut self, data_texture: bool) -> Self {
+        self.data_texture = data_texture;
+        self
+    }
+
+    pub fn instanced(mut self, instanced: bool) -> Self {
+        self.instanced = instanced;
+        self
+    }
+
+    pub fn skip_covered_tiles(mut self, skip_covered_tiles: bool) -> Self {
+        self.skip_covered_tiles = skip_covered_tiles;
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn background_meshing(mut self, background_meshing: bool) -> Self {
+        self.background_meshing = background_meshing;
+        self
+    }
+}
+
+/// A map entity to spawn with `TiledCommands::spawn_tiled_map`, set up through its builder
+/// methods.
+#[derive(Debug, Clone)]
+pub struct TiledMapBundle {
+    map: TiledMapHandle,
+    transform: Transform,
+    /// Which point of the map is placed on `transform`.
+    anchor: MapAnchor,
+    background: MapBackground,
+    /// Whether the files named by object `file` properties are loaded along with the map.
+    preload_object_files: bool,
+    /// Palettes drawing the map or some of its layers, for palette swaps.
+    palettes: LayerPalettes,
+}
+
+impl TiledMapBundle {
+    pub fn new(map: TiledMapHandle) -> Self {
+        Self {
+            map,
+            transform: Transform::default(),
+            anchor: MapAnchor::default(),
+            background: MapBackground::default(),
+            preload_object_files: false,
+            palettes: LayerPalettes::default(),
+        }
+    }
+
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Centers the map on `transform`, or places its origin there again.
+    #[deprecated(note = "use `anchor(MapAnchor::Center)`")]
+    pub fn centered(self, centered: bool) -> Self {
+        self.anchor(if centered {
+            MapAnchor::Center
+        } else {
+            MapAnchor::Origin
+        })
+    }
+
+    pub fn anchor(mut self, anchor: MapAnchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn background(mut self, background: MapBackground) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn preload_object_files(mut self, preload_object_files: bool) -> Self {
+        self.preload_object_files = preload_object_files;
+        self
+    }
+
+    pub fn palettes(mut self, palettes: LayerPalettes) -> Self {
+        self.palettes = palettes;
+        self
+    }
+}
+
+impl From<TiledMapBundle> for TiledMapComponents {
+    fn from(bundle: TiledMapBundle) -> Self {
+        Self {
+            map_asset: bundle.map,
+            origin: bundle.transform,
+            anchor: bundle.anchor,
+            background: bundle.background,
+            preload_files: PreloadObjectFiles(bundle.preload_object_files),
+            palettes: bundle.palettes,
+            ..Default::default()
+        }
+    }
+}
+
+/// Spawns and despawns map entities.
+pub trait TiledCommands {
+    /// Spawns a map entity, which becomes the `current_entity`. Its chunks and objects are
+    /// spawned once the map has loaded, followed by a `MapReadyEvent`.
+    fn spawn_tiled_map(&mut self, bundle: TiledMapBundle) -> &mut Self;
+
+    /// Despawns a map entity spawned with `spawn_tiled_map`, along with its chunks, objects and
+    /// image layers, next frame.
+    fn despawn_tiled_map(&mut self, entity: Entity) -> &mut Self;
+}
+
+impl TiledCommands for Commands {
+    fn spawn_tiled_map(&mut self, bundle: TiledMapBundle) -> &mut Self {
+        self.spawn(TiledMapComponents::from(bundle))
+    }
+
+    fn despawn_tiled_map(&mut self, entity: Entity) -> &mut Self {
+        self.insert_one(entity, DespawnTiledMap)
+    }
+}
+
+/// A loaded map as placed by a map entity, for looking up tiles and objects by world position.
+#[derive(Debug, Clone, Copy)]
+pub struct TiledMapView<'a> {
+    map: &'a Map,
+    translation: Vec3,
+}
+
+impl<'a> TiledMapView<'a> {
+    /// The view of a map entity's components, or `None` until its map has loaded.
+    pub fn new(
+        maps: &'a TiledMaps,
+        map: &TiledMapHandle,
+        anchor: &MapAnchor,
+        transform: &Transform,
+    ) -> Option<Self> {
+        let map = maps.get(map)?;
+        Some(Self {
+            map,
+            translation: map.translation(anchor, transform.translation()),
+        })
+    }
+
+    /// Map width and height in tiles.
+    pub fn size(&self) -> (u32, u32) {
+        (self.map.map.width, self.map.map.height)
+    }
+
+    /// The tile under a world position, whether or not anything is drawn there.
+    pub fn tile_at(&self, world: Vec2) -> Option<(u32, u32)> {
+        let local = self.map.world_to_local(self.translation, world);
+        let tile = self.map.world_to_tile(local)?;
+        let (width, height) = self.size();
+        if tile.x() < 0.0 || tile.y() < 0.0 {
+            return None;
+        }
+        let (x, y) = (tile.x() as u32, tile.y() as u32);
+        if x < width && y < height {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// World position of the middle of a tile.
+    pub fn tile_center(&self, x: u32, y: u32) -> Vec3 {
+        let local = self
+            .map
+            .tile_to_local(Vec2::new(x as f32 + 0.5, y as f32 + 0.5));
+        self.map.local_to_world(self.translation, local)
+    }
+
+    /// The gid of a tile of a layer, by index into the map's tile layers, or 0 for none.
+    pub fn gid(&self, layer: usize, x: u32, y: u32) -> u32 {
+        self.map.tile(layer, x, y).map_or(0, |tile| tile.gid)
+    }
+
+    /// The z to draw a sprite standing at a world position on a tile layer, by index into the
+    /// map's tile layers, so it sorts with the layer's tiles when `TiledSettings::y_sort` is set.
+    /// `None` for hidden layers, which aren't drawn.
+    pub fn y_sorted_z(&self, layer: usize, world: Vec2) -> Option<f32> {
+        let layer_id = self
+            .map
+            .layers
+            .iter()
+            .position(|drawn| drawn.layer_index == layer)?;
+        Some(self.map.y_sorted_z(self.translation, layer_id, world))
+    }
+
+    /// The topmost drawn tile under a world position, across the layers in `mask`.
+    pub fn pick(&self, world: Vec2, mask: LayerMask) -> Option<TileHit> {
+        self.map.pick_world(world, self.translation, mask)
+    }
+
+    /// The object with a Tiled object id.
+    pub fn object(&self, id: u32) -> Option<TiledObjectView> {
+        self.map
+            .object_by_id(id)
+            .map(|object| self.object_view(object))
+    }
+
+    /// Objects of a class, as set by their `type` in Tiled.
+    pub fn objects_of_class(&self, class: &str) -> impl Iterator<Item = TiledObjectView> + 'a {
+        let view = *self;
+        self.map
+            .objects_by_class(class)
+            .map(move |object| view.object_view(object))
+    }
+
+    fn object_view(&self, object: &tiled::Object) -> TiledObjectView {
+        let local = self.map.object_to_world(Vec2::new(object.x, object.y));
+        TiledObjectView {
+            id: object.id,
+            name: object.name.clone(),
+            class: object.obj_type.clone(),
+            position: self.map.local_to_world(self.translation, local),
+            size: Vec2::new(object.width, object.height),
+            properties: object
+                .properties
+                .iter()
+                .map(|(name, value)| (name.clone(), Property::from(value)))
+                .collect(),
+        }
+    }
+}
+
+/// An object of a map, as looked up through `TiledMapView`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TiledObjectView {
+    /// The object's id in Tiled.
+    pub id: u32,
+    pub name: String,
+    /// As set by its `type` in Tiled.
+    pub class: String,
+    /// World position of the object, where its entity is spawned.
+    pub position: Vec3,
+    /// Width and height as set in Tiled, in map pixels.
+    pub size: Vec2,
+    pub properties: HashMap<String, Property>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2 by 2 map with a hidden layer under a ground and a walls layer.
+    fn map() -> Map {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="4" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="2" columns="2">
+  <image source="tiles.png" width="32" height="16"/>
+ </tileset>
+ <layer id="1" name="hidden" width="2" height="2" visible="0">
+  <data encoding="csv">1,1,1,1</data>
+ </layer>
+ <layer id="2" name="ground" width="2" height="2">
+  <data encoding="csv">1,1,1,1</data>
+ </layer>
+ <layer id="3" name="walls" width="2" height="2">
+  <data encoding="csv">2,0,0,2</data>
+ </layer>
+</map>
+"#;
+        let mut map = Map::try_from_bytes(tmx.as_bytes(), Default::default()).unwrap();
+        map.set_y_sort(true);
+        map
+    }
+
+    #[test]
+    fn y_sorted_z_indexes_layers_like_gid() {
+        let map = map();
+        let view = TiledMapView {
+            map: &map,
+            translation: Vec3::zero(),
+        };
+        let world = Vec2::new(8.0, -8.0);
+        assert_eq!(view.gid(2, 0, 0), 2);
+        // The walls are the second layer drawn, after the ground.
+        assert_eq!(
+            view.y_sorted_z(2, world),
+            Some(map.y_sorted_z(Vec3::zero(), 1, world))
+        );
+        assert!(view.y_sorted_z(2, world) > view.y_sorted_z(1, world));
+        assert_eq!(view.y_sorted_z(0, world), None);
+    }
+
+    #[test]
+    fn objects_are_viewed_in_world_space() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="2" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="2" nextobjectid="3">
+ <objectgroup id="1" name="spawns">
+  <object id="1" name="door" type="exit" x="16" y="8" width="16" height="8">
+   <properties>
+    <property name="locked" type="bool" value="true"/>
+   </properties>
+  </object>
+  <object id="2" name="chest" type="loot" x="0" y="0"/>
+ </objectgroup>
+</map>
+"#;
+        let map = Map::try_from_bytes(tmx.as_bytes(), Default::default()).unwrap();
+        let translation = Vec3::new(100.0, 50.0, 0.0);
+        let view = TiledMapView {
+            map: &map,
+            translation,
+        };
+        let door = view.object(1).unwrap();
+        assert_eq!((door.name.as_str(), door.class.as_str()), ("door", "exit"));
+        assert_eq!(
+            door.position,
+            map.local_to_world(translation, map.object_to_world(Vec2::new(16.0, 8.0)))
+        );
+        assert_eq!(door.size, Vec2::new(16.0, 8.0));
+        assert_eq!(door.properties.get("locked"), Some(&Property::Bool(true)));
+        let exits = view.objects_of_class("exit").collect::<Vec<_>>();
+        assert_eq!(exits, vec![door]);
+        assert!(view.object(3).is_none());
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{Map, TiledChunk, TiledImageLayer};
+use bevy::prelude::*;
+
+/// Picks between layers named `<name>_<variant>`, such as `Signs_en` and `Signs_ja`, so text
+/// baked into tiles can be localized by authoring one layer per language.
+#[derive(Debug, Clone, Default)]
+pub struct LayerVariant {
+    /// Known variant suffixes, e.g. `["en", "ja"]`. Only layers ending in `_` and one of these
+    /// are variants; every other layer is always shown.
+    pub variants: Vec<String>,
+    /// The variant shown. Layers of the other variants are hidden.
+    pub selected: Option<String>,
+}
+
+impl LayerVariant {
+    /// The variant suffix of a layer name, if it has one of the known ones.
+    pub fn variant_of<'a>(&self, layer_name: &'a str) -> Option<&'a str> {
+        let (_, suffix) = layer_name.split_at(layer_name.rfind('_')? + 1);
+        if self.variants.iter().any(|variant| variant == suffix) {
+            Some(suffix)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a layer with this name is shown.
+    pub fn shows(&self, layer_name: &str) -> bool {
+        match self.variant_of(layer_name) {
+            Some(variant) => self.selected.as_deref() == Some(variant),
+            None => true,
+        }
+    }
+}
+
+/// Shows the chunks and image layers of the selected layer variant and hides the others.
+pub fn apply_layer_variant(
+    variant: Res<LayerVariant>,
+    maps: Res<Assets<Map>>,
+    mut chunk_query: Query<(&TiledChunk, &mut Draw)>,
+    mut image_layer_query: Query<(&TiledImageLayer, &mut Draw)>,
+) {
+    for (chunk, mut draw) in &mut chunk_query.iter() {
+        // The background quad isn't a layer.
+        if chunk.key.tileset_guid == 0 {
+            continue;
+        }
+        let layer = maps
+            .get(&chunk.map)
+            .and_then(|map| {
+                map.layers
+                    .get(chunk.key.layer_id as usize)
+                    .map(|layer| (map, layer))
+            })
+            .and_then(|(map, layer)| map.map.layers.get(layer.layer_index));
+        if let Some(layer) = layer {
+            let visible = variant.shows(&layer.name);
+            if draw.is_visible != visible {
+                draw.is_visible = visible;
+            }
+        }
+    }
+
+    for (image_layer, mut draw) in &mut image_layer_query.iter() {
+        let layer = maps
+            .get(&image_layer.map)
+            .and_then(|map| map.map.image_layers.get(image_layer.index));
+        if let Some(layer) = layer {
+            let visible = variant.shows(&layer.name);
+            if draw.is_visible != visible {
+                draw.is_visible = visible;
+            }
+        }
+    }
+}
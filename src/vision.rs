@@ -0,0 +1,353 @@
+use crate::{
+    animation::animation_attribute, paths::trace, tints::tint_attribute, ChunkComponents, ChunkKey,
+    LayerMask, Map, MapAnchor, TileMapChunk, TiledChunk,
+};
+use bevy::{
+    prelude::*,
+    render::{mesh::VertexAttribute, pipeline::PrimitiveTopology},
+};
+use glam::Vec2;
+use std::collections::HashSet;
+
+/// A cone of vision over a map, such as a guard's, in tile coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VisionCone {
+    /// Where the observer stands, in fractional tile coordinates.
+    pub origin: Vec2,
+    /// Which way the observer faces, in tile coordinates with y down. Needn't be normalized.
+    pub direction: Vec2,
+    /// Full width of the cone in radians. Anything from `2π` up sees all around.
+    pub angle: f32,
+    /// How far the observer sees, in tiles.
+    pub range: f32,
+}
+
+impl Default for VisionCone {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::zero(),
+            direction: Vec2::new(1.0, 0.0),
+            angle: std::f32::consts::FRAC_PI_2,
+            range: 8.0,
+        }
+    }
+}
+
+impl VisionCone {
+    /// Whether `point` is inside the cone, ignoring anything in the way.
+    pub fn contains(&self, point: Vec2) -> bool {
+        let offset = point - self.origin;
+        let distance = offset.length();
+        if distance > self.range {
+            return false;
+        }
+        if distance == 0.0 || self.angle >= std::f32::consts::PI * 2.0 {
+            return true;
+        }
+        let direction = self.direction.normalize();
+        offset.normalize().dot(direction) >= (self.angle / 2.0).cos()
+    }
+}
+
+impl Map {
+    /// Gids of tiles that block sight.
+    fn opaque_gids(&self) -> HashSet<u32> {
+        let mut opaque = HashSet::new();
+        for tileset in self.map.tilesets.iter() {
+            for tile in tileset.tiles.iter() {
+                let opaque_property = match tile.properties.get("opaque") {
+                    Some(tiled::PropertyValue::BoolValue(value)) => Some(*value),
+                    _ => None,
+                };
+                let wall = tile.tile_type.as_deref() == Some("wall");
+                if opaque_property.unwrap_or(wall) {
+                    opaque.insert(tileset.first_gid + tile.id);
+                }
+            }
+        }
+        opaque
+    }
+
+    fn is_opaque(&self, opaque: &HashSet<u32>, mask: LayerMask, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        (0..self.map.layers.len())
+            .filter(|layer| mask.contains(*layer))
+            .any(|layer| {
+                self.tile(layer, x as u32, y as u32)
+                    .map_or(false, |tile| opaque.contains(&tile.gid))
+            })
+    }
+
+    /// Whether nothing opaque on the layers in `mask` stands between the tiles at `from` and
+    /// `to`, excluding both.
+    fn line_of_sight(
+        &self,
+        opaque: &HashSet<u32>,
+        mask: LayerMask,
+        from: Vec2,
+        to: (i32, i32),
+    ) -> bool {
+        let target = Vec2::new(to.0 as f32 + 0.5, to.1 as f32 + 0.5);
+        trace(from, target)
+            .into_iter()
+            .skip(1)
+            .take_while(|tile| *tile != to)
+            .all(|(x, y)| !self.is_opaque(opaque, mask, x, y))
+    }
+
+    /// The tiles whose centers are inside `cone` and in sight of its origin, as (x, y).
+    ///
+    /// Tiles on the layers in `mask` whose tileset tile has an `opaque` bool property set or the
+    /// class `wall` hide what's behind them, but are seen themselves, so the walls of a room
+    /// show up.
+    pub fn visible_tiles(&self, cone: &VisionCone, mask: LayerMask) -> HashSet<(u32, u32)> {
+        let opaque = self.opaque_gids();
+        let min_x = (cone.origin.x() - cone.range).floor().max(0.0) as u32;
+        let min_y = (cone.origin.y() - cone.range).floor().max(0.0) as u32;
+        let max_x = ((cone.origin.x() + cone.range).ceil().max(0.0) as u32).min(self.map.width);
+        let max_y = ((cone.origin.y() + cone.range).ceil().max(0.0) as u32).min(self.map.height);
+
+        let mut visible = HashSet::new();
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let center = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if cone.contains(center)
+                    && self.line_of_sight(&opaque, mask, cone.origin, (x as i32, y as i32))
+                {
+                    visible.insert((x, y));
+                }
+            }
+        }
+        visible
+    }
+
+    /// Whether `target`, in fractional tile coordinates, is inside `cone` and in sight of its
+    /// origin, with the same blocking as `visible_tiles`.
+    pub fn can_see(&self, cone: &VisionCone, target: Vec2, mask: LayerMask) -> bool {
+        let tile = (target.x().floor() as i32, target.y().floor() as i32);
+        cone.contains(target) && self.line_of_sight(&self.opaque_gids(), mask, cone.origin, tile)
+    }
+
+    /// A mesh covering `tiles`, in the map-local space of the chunk meshes.
+    pub(crate) fn tiles_mesh(&self, tiles: &HashSet<(u32, u32)>) -> Mesh {
+        let mut tiles = tiles.iter().copied().collect::<Vec<_>>();
+        tiles.sort();
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for (x, y) in tiles {
+            let (x, y) = (x as f32, y as f32);
+            let start = positions.len() as u32;
+            for corner in [(x, y + 1.0), (x, y), (x + 1.0, y), (x + 1.0, y + 1.0)].iter() {
+                let corner = self.tile_to_local(Vec2::new(corner.0, corner.1));
+                positions.push([corner.x(), corner.y(), 0.0]);
+            }
+            indices.extend_from_slice(&[start, start + 2, start + 1, start, start + 3, start + 2]);
+        }
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs = vec![[0.0, 0.0]; positions.len()];
+        let animations = vec![0.0; positions.len()];
+        let colors = vec![[1.0; 4]; positions.len()];
+        Mesh {
+            primitive_topology: PrimitiveTopology::TriangleList,
+            attributes: vec![
+                VertexAttribute::position(positions),
+                VertexAttribute::normal(normals),
+                VertexAttribute::uv(uvs),
+                animation_attribute(animations),
+                tint_attribute(colors),
+            ],
+            indices: Some(indices),
+        }
+    }
+}
+
+/// Draws the tiles a `VisionCone` sees over a map entity's layers, for debugging. Spawn it on
+/// an entity of its own and move `cone` along with the observer.
+#[derive(Debug, Clone)]
+pub struct VisionConeDebug {
+    /// The map entity the cone is drawn over.
+    pub map: Entity,
+    pub cone: VisionCone,
+    pub mask: LayerMask,
+    /// Color of the drawn tiles, read when they're first drawn.
+    pub color: Color,
+    drawn: Option<(VisionCone, u32, Handle<Mesh>)>,
+}
+
+impl VisionConeDebug {
+    pub fn new(map: Entity, cone: VisionCone) -> Self {
+        Self {
+            map,
+            cone,
+            mask: LayerMask::ALL,
+            color: Color::rgba(1.0, 0.9, 0.2, 0.35),
+            drawn: None,
+        }
+    }
+}
+
+/// Redraws each `VisionConeDebug` whose cone or map changed.
+pub fn draw_vision_cones(
+    mut commands: Commands,
+    maps: Res<Assets<Map>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut debug_query: Query<(Entity, &mut VisionConeDebug)>,
+    mut transform_query: Query<(&VisionConeDebug, &mut Transform, &mut TileMapChunk)>,
+    map_query: Query<(&Handle<Map>, &MapAnchor, &Transform)>,
+) {
+    for (entity, mut debug) in &mut debug_query.iter() {
+        let map_handle = match map_query.get::<Handle<Map>>(debug.map) {
+            Ok(map_handle) => *map_handle,
+            Err(_) => continue,
+        };
+        let map = match maps.get(&map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let up_to_date = debug.drawn.as_ref().map_or(false, |(cone, generation, _)| {
+            *cone == debug.cone && *generation == map.generation
+        });
+        if up_to_date {
+            continue;
+        }
+
+        let mesh = map.tiles_mesh(&map.visible_tiles(&debug.cone, debug.mask));
+        let mesh_handle = match &debug.drawn {
+            Some((_, _, mesh_handle)) => {
+                meshes.set(*mesh_handle, mesh);
+                *mesh_handle
+            }
+            None => {
+                let mesh_handle = meshes.add(mesh);
+                commands.insert(
+                    entity,
+                    ChunkComponents {
+                        tiled_chunk: TiledChunk {
+                            map: Handle::default(),
+                            key: ChunkKey::default(),
+                            map_entity: None,
+                        },
+                        material: materials.add(debug.color.into()),
+                        mesh: mesh_handle,
+                        ..Default::default()
+                    },
+                );
+                mesh_handle
+            }
+        };
+        debug.drawn = Some((debug.cone, map.generation, mesh_handle));
+    }
+
+    // Follow the map entity, drawn over every layer of its map.
+    for (debug, mut transform, mut chunk) in &mut transform_query.iter() {
+        let (map_handle, anchor, origin) = match (
+            map_query.get::<Handle<Map>>(debug.map),
+            map_query.get::<MapAnchor>(debug.map),
+            map_query.get::<Transform>(debug.map),
+        ) {
+            (Ok(map_handle), Ok(anchor), Ok(origin)) => (map_handle, anchor, origin),
+            _ => continue,
+        };
+        let map = match maps.get(&map_handle) {
+            Some(map) => map,
+            None => continue,
+        };
+        let translation = map.translation(&anchor, origin.translation());
+        if transform.translation() != translation {
+            transform.set_translation(translation);
+        }
+        let layer_id = map.stack_depth(map.layers.len() as f32);
+        if chunk.layer_id != layer_id {
+            chunk.layer_id = layer_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 9 by 9 map of floor with a wall layer running down x = 4.
+    fn walled_map() -> Map {
+        let csv = |gid: &dyn Fn(u32) -> u32| {
+            (0..9)
+                .map(|_| {
+                    (0..9)
+                        .map(|x| gid(x).to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>()
+                .join(",\n")
+        };
+        let tmx = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.4" orientation="orthogonal" renderorder="right-down" width="9" height="9" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="1">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16" tilecount="2" columns="2">
+  <image source="tiles.png" width="32" height="16"/>
+  <tile id="1">
+   <properties>
+    <property name="opaque" type="bool" value="true"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="floor" width="9" height="9">
+  <data encoding="csv">{}</data>
+ </layer>
+ <layer id="2" name="walls" width="9" height="9">
+  <data encoding="csv">{}</data>
+ </layer>
+</map>
+"#,
+            csv(&|_| 1),
+            csv(&|x| if x == 4 { 2 } else { 0 }),
+        );
+        Map::try_from_bytes(tmx.as_bytes(), Default::default()).unwrap()
+    }
+
+    fn all_around(origin: Vec2, range: f32) -> VisionCone {
+        VisionCone {
+            origin,
+            angle: std::f32::consts::PI * 2.0,
+            range,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn walls_hide_the_tiles_behind_them() {
+        let map = walled_map();
+        let cone = all_around(Vec2::new(1.5, 4.5), 8.0);
+        let visible = map.visible_tiles(&cone, LayerMask::ALL);
+        assert!(visible.contains(&(3, 4)));
+        // The wall itself is seen.
+        assert!(visible.contains(&(4, 4)));
+        assert!(!visible.contains(&(5, 4)));
+        assert!(!visible.contains(&(7, 2)));
+        assert!(!map.can_see(&cone, Vec2::new(6.5, 4.5), LayerMask::ALL));
+
+        // Leaving the wall layer out of the mask sees through it.
+        let floor = LayerMask::from_layers(&[0]);
+        assert!(map.visible_tiles(&cone, floor).contains(&(7, 4)));
+        assert!(map.can_see(&cone, Vec2::new(6.5, 4.5), floor));
+    }
+
+    #[test]
+    fn nothing_is_seen_beyond_the_range() {
+        let map = walled_map();
+        let cone = all_around(Vec2::new(1.5, 4.5), 2.0);
+        let visible = map.visible_tiles(&cone, LayerMask::ALL);
+        for (x, y) in visible.iter() {
+            let center = Vec2::new(*x as f32 + 0.5, *y as f32 + 0.5);
+            assert!((center - cone.origin).length() <= 2.0);
+        }
+        assert!(visible.contains(&(1, 6)));
+        assert!(visible.contains(&(3, 4)));
+        assert!(!visible.contains(&(1, 7)));
+        assert!(!visible.contains(&(3, 6)));
+        assert!(!map.can_see(&cone, Vec2::new(1.5, 7.5), LayerMask::ALL));
+    }
+}
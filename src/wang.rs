@@ -0,0 +1,110 @@
+/// Colors of a wang tile in Tiled's order: top, top right, right, bottom right, bottom,
+/// bottom left, left, top left. Colors are 1-based, 0 means unset.
+pub type WangId = [u8; 8];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WangTile {
+    /// Tile id within the tileset.
+    pub tile_id: u32,
+    pub wang_id: WangId,
+}
+
+impl WangTile {
+    /// Corner colors as [top left, top right, bottom right, bottom left].
+    pub fn corners(&self) -> [u8; 4] {
+        [
+            self.wang_id[7],
+            self.wang_id[1],
+            self.wang_id[3],
+            self.wang_id[5],
+        ]
+    }
+
+    /// Edge colors as [top, right, bottom, left].
+    pub fn edges(&self) -> [u8; 4] {
+        [
+            self.wang_id[0],
+            self.wang_id[2],
+            self.wang_id[4],
+            self.wang_id[6],
+        ]
+    }
+}
+
+/// A wang set of a tileset, used for auto-tiling transitions between terrains.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WangSet {
+    pub name: String,
+    /// First gid of the tileset the set belongs to.
+    pub first_gid: u32,
+    /// Color names; color `n` is `colors[n - 1]`.
+    pub colors: Vec<String>,
+    pub tiles: Vec<WangTile>,
+}
+
+impl WangSet {
+    /// The 1-based index of the color with this name.
+    pub fn color(&self, name: &str) -> Option<u8> {
+        self.colors
+            .iter()
+            .position(|color| color == name)
+            .map(|index| index as u8 + 1)
+    }
+
+    /// Gids of every tile whose corners are [top left, top right, bottom right, bottom left].
+    pub fn corner_tiles<'a>(&'a self, corners: [u8; 4]) -> impl Iterator<Item = u32> + 'a {
+        self.tiles
+            .iter()
+            .filter(move |tile| tile.corners() == corners)
+            .map(move |tile| self.first_gid + tile.tile_id)
+    }
+
+    /// The gid of the first tile whose corners are [top left, top right, bottom right,
+    /// bottom left].
+    pub fn corner_tile(&self, corners: [u8; 4]) -> Option<u32> {
+        self.corner_tiles(corners).next()
+    }
+
+    /// Gids of every tile whose edges are [top, right, bottom, left].
+    pub fn edge_tiles<'a>(&'a self, edges: [u8; 4]) -> impl Iterator<Item = u32> + 'a {
+        self.tiles
+            .iter()
+            .filter(move |tile| tile.edges() == edges)
+            .map(move |tile| self.first_gid + tile.tile_id)
+    }
+
+    /// The gid of the first tile whose edges are [top, right, bottom, left].
+    pub fn edge_tile(&self, edges: [u8; 4]) -> Option<u32> {
+        self.edge_tiles(edges).next()
+    }
+
+    /// The wang tile drawn by `gid`, if it's part of this set.
+    pub fn tile(&self, gid: u32) -> Option<&WangTile> {
+        let tile_id = gid.checked_sub(self.first_gid)?;
+        self.tiles.iter().find(|tile| tile.tile_id == tile_id)
+    }
+}
+
+/// Parses a `wangid` attribute.
+///
+/// Tiled 1.5+ writes eight comma separated colors; older versions pack them into a
+/// `0xCECECECE` hex number with the top edge in the lowest nibble.
+pub fn parse_wang_id(value: &str) -> Option<WangId> {
+    let mut wang_id = [0; 8];
+    if value.contains(',') {
+        let colors = value
+            .split(',')
+            .map(|color| color.trim().parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        if colors.len() != 8 {
+            return None;
+        }
+        wang_id.copy_from_slice(&colors);
+    } else {
+        let packed = u32::from_str_radix(value.trim_start_matches("0x"), 16).ok()?;
+        for (i, color) in wang_id.iter_mut().enumerate() {
+            *color = ((packed >> (4 * i)) & 0xF) as u8;
+        }
+    }
+    Some(wang_id)
+}
@@ -0,0 +1,100 @@
+use crate::{Map, MapAnchor, PropertiesExt, TileMapChunk};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// Keeps an entity's z in line with the tiles around it on a y-sorted map, see
+/// `Map::set_y_sort`, so it draws behind what stands further down the map and in front of what
+/// stands further up. Works on sprites and on anything drawn with the tile pipeline, such as
+/// tile objects, which get one on y-sorted maps.
+#[derive(Debug, Clone, Copy)]
+pub struct YSort {
+    /// The map entity of the map the entity stands in.
+    pub map_entity: Entity,
+    /// The tile layer, indexing `Map::layers`, whose tiles the entity sorts among.
+    pub layer: usize,
+    /// World units from the entity's translation down to where it stands, such as half the
+    /// height of a sprite drawn around its middle.
+    pub foot_offset: f32,
+}
+
+impl YSort {
+    pub fn new(map_entity: Entity, layer: usize) -> Self {
+        Self {
+            map_entity,
+            layer,
+            foot_offset: 0.0,
+        }
+    }
+
+    pub fn foot_offset(mut self, foot_offset: f32) -> Self {
+        self.foot_offset = foot_offset;
+        self
+    }
+}
+
+impl Map {
+    /// The tile layer, indexing `layers`, the tile objects of an object group sort among on
+    /// y-sorted maps: the one named by the group's `y_sort_layer` property, or else the top one.
+    pub fn object_y_sort_layer(&self, group: usize) -> usize {
+        let named = self
+            .map
+            .object_groups
+            .get(group)
+            .and_then(|group| group.properties.string_property("y_sort_layer"))
+            .and_then(|name| {
+                self.layers
+                    .iter()
+                    .position(|layer| self.map.layers[layer.layer_index].name == name)
+            });
+        named.unwrap_or_else(|| self.layers.len().saturating_sub(1))
+    }
+}
+
+/// Sets the z of every `YSort` entity from where it stands with `Map::y_sorted_z`, the formula
+/// y-sorted tiles are drawn with. Entities drawn with the tile pipeline get it as the
+/// `layer_id` of their `TileMapChunk` instead, since the shader adds that to their z.
+pub fn apply_y_sort(
+    maps: Res<Assets<Map>>,
+    mut map_query: Query<(Entity, &Handle<Map>, &MapAnchor, &Transform)>,
+    mut sorted_query: Query<(&YSort, &mut Transform, Option<&mut TileMapChunk>)>,
+) {
+    let mut placements = HashMap::new();
+    for (entity, map_handle, anchor, transform) in &mut map_query.iter() {
+        if let Some(map) = maps.get(map_handle) {
+            placements.insert(
+                entity,
+                (
+                    *map_handle,
+                    map.translation(anchor, transform.translation()),
+                ),
+            );
+        }
+    }
+
+    for (y_sort, mut transform, chunk) in &mut sorted_query.iter() {
+        let (map, translation) = match placements
+            .get(&y_sort.map_entity)
+            .and_then(|(map_handle, translation)| Some((maps.get(map_handle)?, *translation)))
+        {
+            Some(placement) => placement,
+            None => continue,
+        };
+        let foot = transform.translation().truncate() - Vec2::new(0.0, y_sort.foot_offset);
+        let z = map.y_sorted_z(translation, y_sort.layer, foot);
+        match chunk {
+            Some(mut chunk) => {
+                let layer_id = z - transform.translation().z();
+                if chunk.layer_id != layer_id {
+                    chunk.layer_id = layer_id;
+                }
+            }
+            None => {
+                if transform.translation().z() != z {
+                    let mut translation = transform.translation();
+                    translation.set_z(z);
+                    transform.set_translation(translation);
+                }
+            }
+        }
+    }
+}